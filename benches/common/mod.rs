@@ -0,0 +1,125 @@
+// each benchmark is built on its own and uses only part of this
+#![allow(dead_code)]
+
+use anyhow::Result;
+use chrono::{Duration, TimeZone, Utc};
+use feedreader::db::{self, Page, Sort, Storage};
+use feedreader::memory::Memory;
+use feedreader::search::Language;
+use feedreader::Article;
+use std::env;
+
+/// Builds an rss document with `count` items whose links start at `offset`, published an hour apart.
+pub fn feed(prefix: &str, offset: usize, count: usize) -> String {
+    let start = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+    let items: String = (offset..offset + count)
+        .map(|i| {
+            format!(
+                "<item><title>Article {i}</title><link>https://bench.example/{prefix}/{i}</link>\
+                <author>bench@example.com (Bench)</author><pubDate>{}</pubDate>\
+                <category>bench</category><description>&lt;p&gt;{}&lt;/p&gt;</description></item>",
+                (start + Duration::hours(i as i64)).to_rfc2822(),
+                "lorem ipsum dolor sit amet ".repeat(40),
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\"?><rss version=\"2.0\"><channel><title>Bench</title>\
+        <link>https://bench.example/</link><description>bench</description>{items}</channel></rss>"
+    )
+}
+
+/// Parses a document from `feed` into articles the way a refresh does.
+pub fn articles(document: &str) -> Vec<Article> {
+    feed_rs::parser::parse(document.as_bytes())
+        .unwrap()
+        .entries
+        .iter()
+        .map(|e| e.into())
+        .collect()
+}
+
+/// Stores `count` articles. Their links are fixed, so reruns against postgres find them already
+/// stored.
+pub async fn seed(store: &Store, count: usize) {
+    for offset in (0..count).step_by(500) {
+        let articles = articles(&feed("seed", offset, 500.min(count - offset)));
+        store.add_articles(articles).await.unwrap();
+    }
+}
+
+/// Where the storage benchmarks run, in memory unless BENCH_POSTGRES_HOST names a postgres.
+pub enum Store {
+    Memory(Memory),
+    Postgres(Storage),
+}
+
+impl Store {
+    /// Connects to the postgres named by the BENCH_POSTGRES_* variables, or starts out empty in
+    /// memory when BENCH_POSTGRES_HOST is unset. Use a throwaway database, the benchmarks insert
+    /// articles into it.
+    pub async fn from_env() -> Store {
+        match env::var("BENCH_POSTGRES_HOST") {
+            Ok(host) => Store::Postgres(storage(host).await),
+            Err(_) => Store::Memory(Memory::new(db::Quota::default(), db::DEFAULT_PAGE_SIZE)),
+        }
+    }
+
+    /// Which backend the benchmarks ran against, so their results are kept apart.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Store::Memory(_) => "memory",
+            Store::Postgres(_) => "postgres",
+        }
+    }
+
+    pub async fn add_articles(&self, articles: Vec<Article>) -> Result<Vec<Article>> {
+        match self {
+            Store::Memory(m) => m.add_articles(articles.into_iter()).await,
+            Store::Postgres(p) => p.add_articles(articles.into_iter()).await,
+        }
+    }
+
+    pub async fn get_unread_articles(
+        &self,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        match self {
+            Store::Memory(m) => m.get_unread_articles(sort, pagination).await,
+            Store::Postgres(p) => p.get_unread_articles(sort, pagination).await,
+        }
+    }
+
+    pub async fn search_articles(&self, query: &str) -> Result<Page<Article>> {
+        let query = query.to_string();
+        match self {
+            Store::Memory(m) => m.search_articles(query, Language::English).await,
+            Store::Postgres(p) => p.search_articles(query, Language::English).await,
+        }
+    }
+}
+
+async fn storage(host: String) -> Storage {
+    let username = env::var("BENCH_POSTGRES_USERNAME").unwrap_or("postgres".to_string());
+    let password = env::var("BENCH_POSTGRES_PASSWORD").unwrap_or_default();
+    let port = env::var("BENCH_POSTGRES_PORT")
+        .unwrap_or("5432".to_string())
+        .parse()
+        .unwrap();
+    let dbname = env::var("BENCH_POSTGRES_DB").unwrap_or("feedreader_bench".to_string());
+
+    let store = db::connection(
+        username.as_str(),
+        password.as_str(),
+        host.as_str(),
+        port,
+        dbname.as_str(),
+        db::Quota::default(),
+        db::Breaker::default(),
+    )
+    .await
+    .unwrap();
+    store.migrate().await.unwrap();
+    store
+}
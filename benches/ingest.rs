@@ -0,0 +1,47 @@
+mod common;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for count in [10, 100, 1000] {
+        let document = common::feed("parse", 0, count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &document, |b, d| {
+            b.iter(|| common::articles(black_box(d)))
+        });
+    }
+    group.finish();
+}
+
+fn ingest(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let store = rt.block_on(common::Store::from_env());
+
+    // every batch needs links that have not been stored by this or an earlier run
+    let run = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let prefix = format!("ingest/{}", run);
+    let offset = AtomicUsize::new(0);
+
+    let mut group = c.benchmark_group(format!("ingest/{}", store.name()));
+    for count in [10, 100] {
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.to_async(&rt).iter_batched(
+                || {
+                    let start = offset.fetch_add(count, Ordering::Relaxed);
+                    common::articles(&common::feed(&prefix, start, count))
+                },
+                |articles| async { store.add_articles(articles).await.unwrap() },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, ingest);
+criterion_main!(benches);
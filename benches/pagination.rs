@@ -0,0 +1,57 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+use feedreader::db::{self, Cursor, Ordering, Sort, SortKey};
+
+/// Unread articles kept in the benchmark database, enough to page well past the first page.
+const SEED: usize = 5000;
+
+fn pagination(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let store = rt.block_on(common::Store::from_env());
+
+    rt.block_on(common::seed(&store, SEED));
+    let middle = Cursor::after(
+        Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap() + Duration::hours(SEED as i64 / 2),
+        "",
+    );
+
+    let newest = Sort::default();
+    let oldest = Sort {
+        key: SortKey::Published,
+        ordering: Ordering::Ascending,
+        page_size: None,
+        page: None,
+    };
+
+    let mut group = c.benchmark_group(format!("pagination/{}", store.name()));
+    group.bench_function("first page", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .get_unread_articles(newest, db::START.to_string())
+                .await
+                .unwrap()
+        })
+    });
+    group.bench_function("middle page", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .get_unread_articles(newest, middle.clone())
+                .await
+                .unwrap()
+        })
+    });
+    group.bench_function("first page ascending", |b| {
+        b.to_async(&rt).iter(|| async {
+            store
+                .get_unread_articles(oldest, db::START.to_string())
+                .await
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pagination);
+criterion_main!(benches);
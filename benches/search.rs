@@ -0,0 +1,32 @@
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Articles searched through, the same ones the pagination benchmark pages through.
+const SEED: usize = 5000;
+
+fn search(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let store = rt.block_on(common::Store::from_env());
+    rt.block_on(common::seed(&store, SEED));
+
+    let mut group = c.benchmark_group(format!("search/{}", store.name()));
+    // every seeded article has these words, the results are cut at the search limit
+    group.bench_function("common words", |b| {
+        b.to_async(&rt)
+            .iter(|| async { store.search_articles("lorem ipsum").await.unwrap() })
+    });
+    // only the title of article 2500 has it
+    group.bench_function("rare word", |b| {
+        b.to_async(&rt)
+            .iter(|| async { store.search_articles("2500").await.unwrap() })
+    });
+    group.bench_function("no match", |b| {
+        b.to_async(&rt)
+            .iter(|| async { store.search_articles("nothing").await.unwrap() })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
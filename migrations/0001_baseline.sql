@@ -0,0 +1,182 @@
+-- the schema as it was created before migrations were versioned, written to bring databases
+-- of any earlier release up to it
+CREATE TABLE IF NOT EXISTS feeds (
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    site_url TEXT NOT NULL,
+    feed_url TEXT NOT NULL UNIQUE,
+    date_added TEXT NOT NULL,
+    last_updated TEXT NOT NULL,
+    poll_interval INTEGER NOT NULL DEFAULT 0,
+    next_poll TEXT NOT NULL DEFAULT '',
+    last_modified TEXT NOT NULL DEFAULT '',
+    build_marker TEXT NOT NULL DEFAULT '',
+    muted BOOLEAN NOT NULL DEFAULT false
+);
+
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS poll_interval INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS next_poll TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS last_modified TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS build_marker TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS muted BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS search_language TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS keep_content BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS push BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS refresh_interval INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS etag TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS fetch_error TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS hints JSONB NOT NULL DEFAULT '{"ttl": 0, "skip_hours": [], "skip_days": []}';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS retry_after TEXT NOT NULL DEFAULT '';
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS erroring BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE IF NOT EXISTS articles (
+    id TEXT NOT NULL,
+    feed TEXT NOT NULL,
+    title TEXT NOT NULL,
+    link TEXT NOT NULL UNIQUE,
+    author TEXT NOT NULL,
+    published TEXT NOT NULL,
+    read BOOLEAN NOT NULL,
+    favorited BOOLEAN NOT NULL,
+    read_date TEXT NOT NULL,
+    summary TEXT NOT NULL DEFAULT '',
+    content TEXT NOT NULL DEFAULT '',
+    word_count INTEGER NOT NULL DEFAULT 0,
+    reading_minutes INTEGER NOT NULL DEFAULT 0,
+    canonical_link TEXT NOT NULL DEFAULT '',
+    fingerprint TEXT NOT NULL DEFAULT '',
+    sources TEXT[] NOT NULL DEFAULT '{}',
+    date_added TEXT NOT NULL DEFAULT '',
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    hidden BOOLEAN NOT NULL DEFAULT false,
+    note TEXT NOT NULL DEFAULT '',
+    pinned BOOLEAN NOT NULL DEFAULT false,
+    raw JSONB NOT NULL DEFAULT '{}',
+    language TEXT NOT NULL DEFAULT '',
+    progress INTEGER NOT NULL DEFAULT 0,
+    content_hash TEXT NOT NULL DEFAULT ''
+);
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS summary TEXT NOT NULL DEFAULT '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS content TEXT NOT NULL DEFAULT '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS word_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS reading_minutes INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS canonical_link TEXT NOT NULL DEFAULT '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS fingerprint TEXT NOT NULL DEFAULT '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS sources TEXT[] NOT NULL DEFAULT '{}';
+UPDATE articles SET canonical_link = link WHERE canonical_link = '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS date_added TEXT NOT NULL DEFAULT '';
+UPDATE articles SET date_added = published WHERE date_added = '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS hidden BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS note TEXT NOT NULL DEFAULT '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS pinned BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS raw JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS language TEXT NOT NULL DEFAULT '';
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS progress INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS content_hash TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE IF NOT EXISTS filters (
+    id TEXT PRIMARY KEY,
+    pattern TEXT NOT NULL,
+    regex BOOLEAN NOT NULL,
+    field TEXT NOT NULL,
+    action TEXT NOT NULL,
+    date_added TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS subscriptions (
+    id TEXT PRIMARY KEY,
+    tag TEXT NOT NULL,
+    webhook TEXT NOT NULL,
+    date_added TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reports (
+    period TEXT PRIMARY KEY,
+    generated TEXT NOT NULL,
+    data JSONB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS digests (
+    period TEXT PRIMARY KEY,
+    sent TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS enclosures (
+    article TEXT NOT NULL,
+    url TEXT NOT NULL,
+    mime TEXT NOT NULL DEFAULT '',
+    length BIGINT NOT NULL DEFAULT 0,
+    duration INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (article, url)
+);
+
+ALTER TABLE enclosures ADD COLUMN IF NOT EXISTS duration INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE enclosures ADD COLUMN IF NOT EXISTS blob TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE IF NOT EXISTS blobs (
+    hash TEXT PRIMARY KEY,
+    mime TEXT NOT NULL DEFAULT '',
+    size BIGINT NOT NULL DEFAULT 0,
+    date_added TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS views (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    filter TEXT NOT NULL,
+    feed TEXT NOT NULL DEFAULT '',
+    tag TEXT NOT NULL DEFAULT '',
+    keyword TEXT NOT NULL DEFAULT '',
+    date_added TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS images (
+    url TEXT PRIMARY KEY,
+    blob TEXT NOT NULL,
+    date_added TEXT NOT NULL,
+    last_used TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS push_subscriptions (
+    endpoint TEXT PRIMARY KEY,
+    p256dh TEXT NOT NULL,
+    auth TEXT NOT NULL,
+    date_added TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS feed_tombstones (
+    feed_url TEXT PRIMARY KEY,
+    date_removed TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS scrape_rules (
+    feed TEXT PRIMARY KEY,
+    item TEXT NOT NULL,
+    title TEXT NOT NULL,
+    link TEXT NOT NULL,
+    date TEXT NOT NULL DEFAULT '',
+    date_added TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    feed TEXT NOT NULL,
+    state TEXT NOT NULL,
+    error TEXT NOT NULL DEFAULT '',
+    date_added TEXT NOT NULL,
+    started TEXT NOT NULL DEFAULT '',
+    finished TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS fetch_log (
+    feed TEXT NOT NULL,
+    date TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    bytes BIGINT NOT NULL DEFAULT 0,
+    duration_ms BIGINT NOT NULL DEFAULT 0,
+    added INTEGER NOT NULL DEFAULT 0,
+    error TEXT NOT NULL DEFAULT ''
+);
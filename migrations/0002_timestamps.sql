@@ -0,0 +1,24 @@
+-- dates were stored as rfc3339 text, with '-1' for never and '' when a feed gave no date.
+-- anything that does not parse becomes null, articles without a publish date fall back to
+-- when they were added so they still sort and page alongside the rest
+CREATE FUNCTION pg_temp.to_timestamp(value TEXT) RETURNS TIMESTAMPTZ AS $$
+BEGIN
+    IF value IN ('', '-1') THEN
+        RETURN NULL;
+    END IF;
+    RETURN value::TIMESTAMPTZ;
+EXCEPTION WHEN OTHERS THEN
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE feeds ALTER COLUMN last_updated DROP NOT NULL;
+ALTER TABLE feeds ALTER COLUMN date_added TYPE TIMESTAMPTZ USING COALESCE(pg_temp.to_timestamp(date_added), now());
+ALTER TABLE feeds ALTER COLUMN last_updated TYPE TIMESTAMPTZ USING pg_temp.to_timestamp(last_updated);
+
+ALTER TABLE articles ALTER COLUMN date_added DROP DEFAULT;
+ALTER TABLE articles ALTER COLUMN date_added TYPE TIMESTAMPTZ USING COALESCE(pg_temp.to_timestamp(date_added), pg_temp.to_timestamp(published), now());
+ALTER TABLE articles ALTER COLUMN date_added SET DEFAULT now();
+ALTER TABLE articles ALTER COLUMN published TYPE TIMESTAMPTZ USING COALESCE(pg_temp.to_timestamp(published), date_added);
+ALTER TABLE articles ALTER COLUMN read_date DROP NOT NULL;
+ALTER TABLE articles ALTER COLUMN read_date TYPE TIMESTAMPTZ USING pg_temp.to_timestamp(read_date);
@@ -0,0 +1,9 @@
+-- feeds and articles were created without keys, so every lookup by id and every page of an
+-- article list scanned the whole table. the indexes follow the pagination queries, which
+-- filter on read or favorited and order by when articles were published or read
+ALTER TABLE feeds ADD PRIMARY KEY (id);
+ALTER TABLE articles ADD PRIMARY KEY (id);
+
+CREATE INDEX IF NOT EXISTS articles_read_published ON articles (read, published);
+CREATE INDEX IF NOT EXISTS articles_favorited_published ON articles (favorited, published);
+CREATE INDEX IF NOT EXISTS articles_read_read_date ON articles (read, read_date);
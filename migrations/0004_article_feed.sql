@@ -0,0 +1,9 @@
+-- articles named their feed by its display name only. feed_id ties them to the feed itself,
+-- articles of a feed that is deleted keep the name they were stored with. Names are not unique,
+-- so only articles of a name a single feed has are tied here. The rest keep a null feed_id and
+-- the schema check at startup lists them with the statement that ties them to a feed
+ALTER TABLE articles ADD COLUMN feed_id TEXT REFERENCES feeds (id) ON DELETE SET NULL;
+UPDATE articles SET feed_id = feeds.id
+    FROM (SELECT name, min(id) AS id FROM feeds GROUP BY name HAVING count(*) = 1) AS feeds
+    WHERE articles.feed = feeds.name;
+CREATE INDEX IF NOT EXISTS articles_feed_id ON articles (feed_id);
@@ -0,0 +1,27 @@
+-- articles are stemmed once when stored instead of on every search. Each is stemmed with its
+-- feed's search language, or the default, which the application sets from SEARCH_LANGUAGE on start
+CREATE FUNCTION default_search_language() RETURNS regconfig AS $$
+    SELECT 'english'::regconfig
+$$ LANGUAGE sql STABLE;
+
+CREATE FUNCTION article_search_vector(title TEXT, summary TEXT, feed TEXT) RETURNS tsvector AS $$
+    SELECT to_tsvector(
+        COALESCE((SELECT NULLIF(search_language, '')::regconfig FROM feeds WHERE id = feed), default_search_language()),
+        title || ' ' || summary
+    )
+$$ LANGUAGE sql STABLE;
+
+ALTER TABLE articles ADD COLUMN search_vector TSVECTOR;
+UPDATE articles SET search_vector = article_search_vector(title, summary, feed_id);
+
+CREATE FUNCTION articles_search_vector() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector := article_search_vector(NEW.title, NEW.summary, NEW.feed_id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER articles_search_vector BEFORE INSERT OR UPDATE OF title, summary, feed_id ON articles
+    FOR EACH ROW EXECUTE FUNCTION articles_search_vector();
+
+CREATE INDEX IF NOT EXISTS articles_search_vector ON articles USING GIN (search_vector);
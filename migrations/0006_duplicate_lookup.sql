@@ -0,0 +1,4 @@
+-- every new article is checked against stored ones with the same canonical link or fingerprint,
+-- without these each check reads the whole articles table
+CREATE INDEX IF NOT EXISTS articles_canonical_link ON articles (canonical_link);
+CREATE INDEX IF NOT EXISTS articles_fingerprint ON articles (fingerprint) WHERE fingerprint <> '';
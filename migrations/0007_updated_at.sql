@@ -0,0 +1,3 @@
+-- when a refresh last found the publisher had edited an article's title, author or text,
+-- null for articles never edited since they were stored
+ALTER TABLE articles ADD COLUMN updated_at TIMESTAMPTZ;
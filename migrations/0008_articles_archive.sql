@@ -0,0 +1,8 @@
+-- read articles old enough are moved here to keep articles and its indexes small. It has every
+-- column of articles and gets the same ones added in later migrations, rows are copied between
+-- the two by column name so the order they were added in does not matter
+CREATE TABLE IF NOT EXISTS articles_archive (LIKE articles INCLUDING DEFAULTS INCLUDING CONSTRAINTS);
+ALTER TABLE articles_archive ADD PRIMARY KEY (id);
+ALTER TABLE articles_archive ADD UNIQUE (link);
+ALTER TABLE articles_archive ADD FOREIGN KEY (feed_id) REFERENCES feeds (id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS articles_archive_read_date ON articles_archive (read_date, id);
@@ -0,0 +1,44 @@
+-- unread articles per feed, kept up to date as articles change instead of counted on every page.
+-- Articles whose feed was deleted are counted under ''. The triggers run once per statement with
+-- the changed rows, so marking a whole feed read or storing a batch of articles is one update
+CREATE TABLE IF NOT EXISTS feed_counts (
+    feed TEXT PRIMARY KEY,
+    unread BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO feed_counts (feed, unread)
+    SELECT COALESCE(feed_id, ''), COUNT(*) FROM articles WHERE NOT read AND NOT hidden GROUP BY 1
+    ON CONFLICT (feed) DO UPDATE SET unread = EXCLUDED.unread;
+
+CREATE FUNCTION articles_feed_counts() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO feed_counts (feed, unread)
+            SELECT COALESCE(feed_id, ''), COUNT(*) FROM new_rows WHERE NOT read AND NOT hidden GROUP BY 1
+            ON CONFLICT (feed) DO UPDATE SET unread = feed_counts.unread + EXCLUDED.unread;
+    ELSIF TG_OP = 'DELETE' THEN
+        INSERT INTO feed_counts (feed, unread)
+            SELECT COALESCE(feed_id, ''), -COUNT(*) FROM old_rows WHERE NOT read AND NOT hidden GROUP BY 1
+            ON CONFLICT (feed) DO UPDATE SET unread = feed_counts.unread + EXCLUDED.unread;
+    ELSE
+        INSERT INTO feed_counts (feed, unread)
+            SELECT feed, SUM(delta) FROM (
+                SELECT COALESCE(feed_id, '') AS feed, 1 AS delta FROM new_rows WHERE NOT read AND NOT hidden
+                UNION ALL
+                SELECT COALESCE(feed_id, ''), -1 FROM old_rows WHERE NOT read AND NOT hidden
+            ) AS changes GROUP BY feed HAVING SUM(delta) <> 0
+            ON CONFLICT (feed) DO UPDATE SET unread = feed_counts.unread + EXCLUDED.unread;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER articles_feed_counts_insert AFTER INSERT ON articles
+    REFERENCING NEW TABLE AS new_rows
+    FOR EACH STATEMENT EXECUTE FUNCTION articles_feed_counts();
+CREATE TRIGGER articles_feed_counts_update AFTER UPDATE ON articles
+    REFERENCING OLD TABLE AS old_rows NEW TABLE AS new_rows
+    FOR EACH STATEMENT EXECUTE FUNCTION articles_feed_counts();
+CREATE TRIGGER articles_feed_counts_delete AFTER DELETE ON articles
+    REFERENCING OLD TABLE AS old_rows
+    FOR EACH STATEMENT EXECUTE FUNCTION articles_feed_counts();
@@ -0,0 +1,10 @@
+-- the last document fetched for each feed, gzipped and cut at FEED_PAYLOAD_MAX_BYTES, to see what
+-- a publisher sent when it does not parse. Nothing is written unless FEED_PAYLOAD_MAX_BYTES is set
+CREATE TABLE IF NOT EXISTS feed_payloads (
+    feed TEXT PRIMARY KEY REFERENCES feeds (id) ON DELETE CASCADE,
+    fetched TIMESTAMPTZ NOT NULL,
+    content_type TEXT NOT NULL DEFAULT '',
+    size BIGINT NOT NULL,
+    truncated BOOLEAN NOT NULL DEFAULT false,
+    body BYTEA NOT NULL
+);
@@ -0,0 +1,48 @@
+-- feeds and articles were identified by their url base64 encoded, which made for long ids that
+-- give away the url. They become the first 16 bytes of the url's sha256 in hex, as computed by
+-- short_id in src/lib.rs. Articles identified by their guid had the sha256 of their feed's url
+-- and guid as id. Their ids now hash the feed's id instead, which cannot be done here since the
+-- guid was not stored, so they are cut to the same length as Article::legacy_guid_id computes
+-- them and a refresh that finds the entry again moves it to its guid id
+CREATE FUNCTION pg_temp.short_id(key TEXT) RETURNS TEXT AS $$
+    SELECT encode(substring(sha256(convert_to(key, 'UTF8')) FROM 1 FOR 16), 'hex')
+$$ LANGUAGE sql IMMUTABLE;
+
+-- base64 ids of links are padded, only the unpadded guid hashes are 43 characters long
+CREATE FUNCTION pg_temp.article_id(id TEXT, link TEXT) RETURNS TEXT AS $$
+    SELECT CASE
+        WHEN id ~ '^[A-Za-z0-9_-]{43}$'
+            THEN encode(substring(decode(translate(id, '-_', '+/') || '=', 'base64') FROM 1 FOR 16), 'hex')
+        ELSE pg_temp.short_id(link)
+    END
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE TEMPORARY TABLE feed_ids ON COMMIT DROP AS
+    SELECT id AS old, pg_temp.short_id(feed_url) AS new FROM feeds;
+CREATE TEMPORARY TABLE article_ids ON COMMIT DROP AS
+    SELECT id AS old, pg_temp.article_id(id, link) AS new FROM articles
+    UNION ALL
+    SELECT id, pg_temp.article_id(id, link) FROM articles_archive;
+
+-- the keys are put back once every reference to a feed was rewritten
+ALTER TABLE articles DROP CONSTRAINT articles_feed_id_fkey;
+ALTER TABLE articles_archive DROP CONSTRAINT articles_archive_feed_id_fkey;
+ALTER TABLE feed_payloads DROP CONSTRAINT feed_payloads_feed_fkey;
+
+UPDATE feeds SET id = f.new FROM feed_ids AS f WHERE feeds.id = f.old;
+UPDATE articles SET id = a.new FROM article_ids AS a WHERE articles.id = a.old;
+UPDATE articles SET feed_id = f.new FROM feed_ids AS f WHERE articles.feed_id = f.old;
+UPDATE articles_archive SET id = a.new FROM article_ids AS a WHERE articles_archive.id = a.old;
+UPDATE articles_archive SET feed_id = f.new FROM feed_ids AS f WHERE articles_archive.feed_id = f.old;
+UPDATE enclosures SET article = a.new FROM article_ids AS a WHERE enclosures.article = a.old;
+UPDATE fetch_log SET feed = f.new FROM feed_ids AS f WHERE fetch_log.feed = f.old;
+UPDATE jobs SET feed = f.new FROM feed_ids AS f WHERE jobs.feed = f.old;
+UPDATE scrape_rules SET feed = f.new FROM feed_ids AS f WHERE scrape_rules.feed = f.old;
+UPDATE feed_payloads SET feed = f.new FROM feed_ids AS f WHERE feed_payloads.feed = f.old;
+
+ALTER TABLE articles ADD FOREIGN KEY (feed_id) REFERENCES feeds (id) ON DELETE SET NULL;
+ALTER TABLE articles_archive ADD FOREIGN KEY (feed_id) REFERENCES feeds (id) ON DELETE SET NULL;
+ALTER TABLE feed_payloads ADD FOREIGN KEY (feed) REFERENCES feeds (id) ON DELETE CASCADE;
+
+-- the triggers moved the counts along with the articles, the emptied rows under the old ids go
+DELETE FROM feed_counts WHERE feed IN (SELECT old FROM feed_ids);
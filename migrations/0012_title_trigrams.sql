@@ -0,0 +1,13 @@
+-- trigram index for searching titles with typos and partial words. pg_trgm ships with postgres
+-- but not with every install, and creating it may take more privileges than the app has, in
+-- either case titles are searched with ILIKE instead
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'pg_trgm') THEN
+        CREATE EXTENSION IF NOT EXISTS pg_trgm;
+        CREATE INDEX IF NOT EXISTS articles_title_trgm ON articles USING GIN (title gin_trgm_ops);
+    END IF;
+EXCEPTION WHEN insufficient_privilege THEN
+    RAISE NOTICE 'could not create pg_trgm, titles are searched with ILIKE';
+END
+$$;
@@ -0,0 +1,22 @@
+-- clients of the Fever api keep ids as integers, so every feed and article gets a number too.
+-- Articles count up in the order they were stored, which lets clients ask for the ones newer
+-- than the last they saw. The archived ones are the oldest and are numbered first, they keep
+-- their number as they move between the two tables
+ALTER TABLE feeds ADD COLUMN IF NOT EXISTS seq BIGSERIAL;
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS seq BIGSERIAL;
+ALTER TABLE articles_archive ADD COLUMN IF NOT EXISTS seq BIGINT;
+
+CREATE TEMPORARY TABLE article_seqs ON COMMIT DROP AS
+    SELECT id, row_number() OVER (ORDER BY archived DESC, date_added, id) AS seq FROM (
+        SELECT id, date_added, true AS archived FROM articles_archive
+        UNION ALL
+        SELECT id, date_added, false FROM articles
+    ) AS a;
+UPDATE articles_archive SET seq = s.seq FROM article_seqs AS s WHERE articles_archive.id = s.id;
+UPDATE articles SET seq = s.seq FROM article_seqs AS s WHERE articles.id = s.id;
+SELECT setval(pg_get_serial_sequence('articles', 'seq'), (SELECT COALESCE(max(seq), 0) + 1 FROM article_seqs), false);
+ALTER TABLE articles_archive ALTER COLUMN seq SET NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS feeds_seq ON feeds (seq);
+CREATE UNIQUE INDEX IF NOT EXISTS articles_seq ON articles (seq);
+CREATE UNIQUE INDEX IF NOT EXISTS articles_archive_seq ON articles_archive (seq);
@@ -0,0 +1,14 @@
+-- sources listed the names of the other feeds an article was found in, which stop matching once
+-- a feed is renamed and are ambiguous when feeds share a name. They become the ids of those
+-- feeds, names that do not lead to a single feed are dropped
+CREATE TEMPORARY TABLE source_ids ON COMMIT DROP AS
+    SELECT name, min(id) AS id FROM feeds GROUP BY name HAVING count(*) = 1;
+
+UPDATE articles SET sources = ARRAY(
+    SELECT s.id FROM unnest(articles.sources) WITH ORDINALITY AS u(name, n)
+    JOIN source_ids AS s ON s.name = u.name ORDER BY u.n
+) WHERE sources <> '{}';
+UPDATE articles_archive SET sources = ARRAY(
+    SELECT s.id FROM unnest(articles_archive.sources) WITH ORDINALITY AS u(name, n)
+    JOIN source_ids AS s ON s.name = u.name ORDER BY u.n
+) WHERE sources <> '{}';
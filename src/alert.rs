@@ -0,0 +1,144 @@
+use super::{client, Article};
+use anyhow::Result;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+use std::env;
+use std::sync::Arc;
+
+/// A push service new articles of watched feeds are announced on. Each is enabled by setting its
+/// environment variables.
+pub enum Service {
+    /// ALERT_NTFY_URL, the topic url, and ALERT_NTFY_TOKEN for protected topics
+    Ntfy { url: String, token: Option<String> },
+    /// ALERT_PUSHOVER_TOKEN, ALERT_PUSHOVER_USER
+    Pushover { token: String, user: String },
+    /// ALERT_GOTIFY_URL, ALERT_GOTIFY_TOKEN
+    Gotify { url: String, token: String },
+}
+
+impl Service {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Service::Ntfy { .. } => "ntfy",
+            Service::Pushover { .. } => "pushover",
+            Service::Gotify { .. } => "gotify",
+        }
+    }
+
+    async fn send(&self, client: &Client, article: &Article) -> Result<()> {
+        let link = article.canonical_link.as_str();
+        let req = match self {
+            Service::Ntfy { url, token } => {
+                let req = client
+                    .post(url.as_str())
+                    .header("Title", article.feed.as_str())
+                    .header("Click", link)
+                    .body(article.title.clone());
+                match token {
+                    Some(t) => req.bearer_auth(t),
+                    None => req,
+                }
+            }
+            Service::Pushover { token, user } => client
+                .post("https://api.pushover.net/1/messages.json")
+                .form(&[
+                    ("token", token.as_str()),
+                    ("user", user.as_str()),
+                    ("title", article.feed.as_str()),
+                    ("message", article.title.as_str()),
+                    ("url", link),
+                ]),
+            Service::Gotify { url, token } => {
+                let body = serde_json::to_string(&GotifyMessage {
+                    title: article.feed.as_str(),
+                    message: format!("{}\n{}", article.title, link),
+                })?;
+                client
+                    .post(format!("{}/message", url.trim_end_matches('/')))
+                    .header("X-Gotify-Key", token.as_str())
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+            }
+        };
+        req.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct GotifyMessage<'a> {
+    title: &'a str,
+    message: String,
+}
+
+/// The push services configured for this instance and `ALERT_FEEDS`, the comma separated names
+/// of the feeds whose new articles are announced on them.
+#[derive(Clone, Default)]
+pub struct Alerts {
+    services: Arc<Vec<Service>>,
+    feeds: Arc<Vec<String>>,
+}
+
+impl Alerts {
+    pub fn from_env() -> Result<Self> {
+        let mut services = vec![];
+
+        if let Ok(url) = env::var("ALERT_NTFY_URL") {
+            services.push(Service::Ntfy {
+                url,
+                token: env::var("ALERT_NTFY_TOKEN").ok(),
+            });
+        }
+
+        if let (Ok(token), Ok(user)) = (
+            env::var("ALERT_PUSHOVER_TOKEN"),
+            env::var("ALERT_PUSHOVER_USER"),
+        ) {
+            services.push(Service::Pushover { token, user });
+        }
+
+        if let (Ok(url), Ok(token)) = (env::var("ALERT_GOTIFY_URL"), env::var("ALERT_GOTIFY_TOKEN"))
+        {
+            services.push(Service::Gotify { url, token });
+        }
+
+        let feeds: Vec<String> = env::var("ALERT_FEEDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|f| f.trim().to_lowercase())
+            .filter(|f| !f.is_empty())
+            .collect();
+        if !services.is_empty() && feeds.is_empty() {
+            return Err(anyhow::Error::msg(
+                "a push service is configured but ALERT_FEEDS names no feed to watch",
+            ));
+        }
+
+        Ok(Alerts {
+            services: Arc::new(services),
+            feeds: Arc::new(feeds),
+        })
+    }
+
+    /// Announces the newly stored articles of watched feeds on every service. A service that
+    /// fails is logged and skipped so the others still get the article.
+    pub async fn dispatch(&self, articles: &[Article]) {
+        if self.services.is_empty() {
+            return;
+        }
+
+        let client = client::shared();
+        // articles muted during ingest are not worth an alert
+        for a in articles
+            .iter()
+            .filter(|a| !a.read && !a.hidden && self.feeds.contains(&a.feed.to_lowercase()))
+        {
+            for s in self.services.iter() {
+                if let Err(e) = s.send(&client, a).await {
+                    println!("could not alert on {}: {}", s.name(), e);
+                }
+            }
+        }
+    }
+}
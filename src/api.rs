@@ -0,0 +1,236 @@
+use super::openapi::{Documented, Negotiated, Paged};
+use super::{
+    db, paged_json, prefetch, reject_anyhow, search, ArticleBaseTemplate, ArticleListTemplate,
+    ArticlePage, ArticlesQuery, FeedPage, PageQuery, SearchOptions, SortOptions,
+};
+use chrono::Utc;
+use rweb::filters::BoxedFilter;
+use rweb::*;
+use std::sync::Arc;
+
+/// Where the json article lists live, the links to their other pages point here.
+const ARTICLES: &str = "/api/v1/articles";
+const FEEDS: &str = "/api/v1/feeds";
+
+/// Whether `accept` ranks application/json above text/html, by quality and then by order.
+/// Anything else, including no header at all, gets html as before.
+pub fn prefers_json(accept: Option<&str>) -> bool {
+    let accept = match accept {
+        Some(a) => a,
+        None => return false,
+    };
+    // quality and whether it is json of the range preferred so far
+    let mut best = (-1.0_f32, false);
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let q = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.parse().ok())
+            .unwrap_or(1.0);
+        let json = match media.as_str() {
+            "application/json" => true,
+            "text/html" | "text/*" | "*/*" => false,
+            _ => continue,
+        };
+        if q > best.0 {
+            best = (q, json);
+        }
+    }
+    best.1 && best.0 > 0.0
+}
+
+/// [prefers_json] of the request's `Accept` header, for handlers to take with `#[filter]`.
+pub fn accepts_json() -> impl Filter<Extract = (bool,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept").map(|a: Option<String>| prefers_json(a.as_deref()))
+}
+
+/// The answer of a route that can reply either way, caches are told it depends on `Accept`.
+pub fn vary<T>(reply: impl Reply + 'static) -> Documented<Negotiated<T>> {
+    Documented::new(warp::reply::with_header(
+        reply,
+        http::header::VARY,
+        "Accept",
+    ))
+}
+
+/// A page of an article list as json, with links to the pages around it at `path`.
+fn list_json(
+    path: &str,
+    filter: &str,
+    sort: db::Sort,
+    body: ArticlePage,
+) -> Result<Documented<Paged<ArticlePage>>, Rejection> {
+    let sort = sort.query();
+    let mut params = vec![("filter", filter)];
+    params.extend(sort.split('&').filter_map(|p| p.split_once('=')));
+    paged_json(&body, path, &params, &body.cursor)
+}
+
+/// An article list page, as json when `json`.
+pub fn articles(
+    json: bool,
+    page: ArticleBaseTemplate,
+) -> Result<Documented<Negotiated<Paged<ArticlePage>>>, Rejection> {
+    if !json {
+        return Ok(vary(page));
+    }
+    let body = ArticlePage {
+        pinned: page.pinned,
+        articles: page.articles,
+        cursor: page.cursor,
+    };
+    Ok(vary(list_json(
+        ARTICLES,
+        &page.article_filter,
+        page.sort,
+        body,
+    )?))
+}
+
+/// A page of the list `filter` names as rendered for scrolling, as json when `json`.
+pub fn list(
+    json: bool,
+    filter: &str,
+    list: ArticleListTemplate,
+) -> Result<Documented<Negotiated<Paged<ArticlePage>>>, Rejection> {
+    if !json {
+        return Ok(vary(list));
+    }
+    let body = ArticlePage {
+        pinned: match list.cursor.has_prev {
+            true => vec![],
+            false => list.pinned,
+        },
+        articles: list.articles,
+        cursor: list.cursor,
+    };
+    Ok(vary(list_json(ARTICLES, filter, list.sort, body)?))
+}
+
+/// A page of an article list, `filter` takes the same names as the `article_filter` header and
+/// defaults to unread. Pinned articles come with the first page only.
+pub async fn article_page(
+    store: &dyn db::Store,
+    pages: &prefetch::Pages,
+    o: ArticlesQuery,
+    path: &str,
+) -> Result<Documented<Paged<ArticlePage>>, Rejection> {
+    let filter = o.filter.unwrap_or_else(|| db::Filter::Unread.to_string());
+    let options = SortOptions {
+        sort: o.sort,
+        dir: o.dir,
+        page_size: o.page_size,
+        page: None,
+    };
+    let sort = super::list_sort(store, &filter, &options)
+        .await
+        .map_err(reject_anyhow)?;
+    let pagination = o.pagination.unwrap_or_else(|| sort.start().to_string());
+    let list = super::list_page(store, pages, &filter, sort, pagination)
+        .await
+        .map_err(reject_anyhow)?;
+
+    let body = ArticlePage {
+        pinned: match list.cursor.has_prev {
+            true => vec![],
+            false => list.pinned,
+        },
+        articles: list.articles,
+        cursor: list.cursor,
+    };
+    list_json(path, &filter, sort, body)
+}
+
+/// A page of the feeds, by name.
+pub async fn feed_page(
+    store: &dyn db::Store,
+    pagination: Option<String>,
+    path: &str,
+) -> Result<Documented<Paged<FeedPage>>, Rejection> {
+    let pagination = pagination.unwrap_or_else(|| db::START.to_string());
+    let page = store.get_feeds(pagination).await.map_err(reject_anyhow)?;
+
+    let body = FeedPage {
+        feeds: page.items,
+        cursor: page.cursor,
+    };
+    paged_json(&body, path, &[], &body.cursor)
+}
+
+/// A page of an article list.
+#[get("/api/v1/articles")]
+async fn get_articles(
+    options: Query<ArticlesQuery>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<Documented<Paged<ArticlePage>>, Rejection> {
+    article_page(store.as_ref(), &pages, options.into_inner(), ARTICLES).await
+}
+
+/// How many articles each list holds.
+#[get("/api/v1/articles/counts")]
+async fn article_counts(#[data] store: Arc<dyn db::Store>) -> Result<Json<db::Counts>, Rejection> {
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(counts.into())
+}
+
+/// An article.
+#[get("/api/v1/articles/{article_id}")]
+async fn get_article(
+    article_id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<super::Article>, Rejection> {
+    let article = store
+        .get_article_by_id(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(article.into())
+}
+
+/// A page of the feeds.
+#[get("/api/v1/feeds")]
+async fn get_feeds(
+    options: Query<PageQuery>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Documented<Paged<FeedPage>>, Rejection> {
+    feed_page(store.as_ref(), options.into_inner().pagination, FEEDS).await
+}
+
+/// Articles matching `q`. Search results are ranked rather than paged, they all come on one
+/// page.
+#[get("/api/v1/search")]
+async fn search_articles(
+    options: Query<SearchOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] language: search::Language,
+) -> Result<Json<ArticlePage>, Rejection> {
+    let page = super::search_page(store.as_ref(), options.into_inner().q, language)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(ArticlePage {
+        pinned: vec![],
+        articles: page.items,
+        cursor: page.cursor,
+    }
+    .into())
+}
+
+/// The json aliases of the article and feed lists, the counts, an article and search.
+pub fn routes(
+    store: Arc<dyn db::Store>,
+    pages: prefetch::Pages,
+    language: search::Language,
+) -> BoxedFilter<(Box<dyn Reply>,)> {
+    // counts before the article route, which would take "counts" for an id
+    article_counts(store.clone())
+        .or(get_articles(store.clone(), pages))
+        .or(get_article(store.clone()))
+        .or(get_feeds(store.clone()))
+        .or(search_articles(store, language))
+        .map(|r| Box::new(r) as Box<dyn Reply>)
+        .boxed()
+}
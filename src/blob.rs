@@ -0,0 +1,302 @@
+use super::{client, db, proxy};
+use anyhow::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, StatusCode};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Blobs younger than this are never collected, so one stored just before the row that
+/// references it is written survives.
+const GC_GRACE_HOURS: i64 = 24;
+
+/// Images larger than this are left on the publisher's server.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Tables and columns that hold blob hashes. A blob referenced by none of them is garbage.
+pub const REFERENCES: &[(&str, &str)] = &[("enclosures", "blob"), ("images", "blob")];
+
+/// Put in place of a hash for images that could not be cached, so they are not tried again.
+pub const UNCACHEABLE: &str = "-";
+
+/// What postgres knows about a stored blob.
+#[derive(Clone)]
+pub struct Blob {
+    pub hash: String,
+    pub mime: String,
+    pub size: i64,
+    pub date_added: String,
+}
+
+impl Blob {
+    pub fn new(hash: String, mime: String, size: usize) -> Self {
+        Blob {
+            hash,
+            mime,
+            size: size as i64,
+            date_added: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+impl From<&tokio_postgres::Row> for Blob {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Blob {
+            hash: row.get(0),
+            mime: row.get(1),
+            size: row.get(2),
+            date_added: row.get(3),
+        }
+    }
+}
+
+/// Where media such as cached images is kept, outside of postgres. Blobs are addressed by the
+/// sha256 of their bytes, so a file fetched for several articles is only stored once.
+#[derive(Clone)]
+pub enum Store {
+    /// BLOB_DIR, defaults to ./blobs
+    Disk { root: PathBuf },
+    /// BLOB_S3_ENDPOINT, BLOB_S3_BUCKET, BLOB_S3_REGION, BLOB_S3_ACCESS_KEY, BLOB_S3_SECRET_KEY.
+    /// Any S3 compatible service, addressed path style.
+    S3 {
+        client: Client,
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+    },
+}
+
+pub fn hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Blobs are spread over directories named after the first two characters of their hash.
+fn key(hash: &str) -> Result<String> {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow::Error::msg(format!("bad blob hash: {}", hash)));
+    }
+    Ok(format!("{}/{}", &hash[..2], hash))
+}
+
+impl Store {
+    pub fn from_env() -> Result<Store> {
+        let endpoint = match env::var("BLOB_S3_ENDPOINT") {
+            Ok(e) => e,
+            Err(_) => {
+                return Ok(Store::Disk {
+                    root: env::var("BLOB_DIR").unwrap_or("blobs".to_string()).into(),
+                })
+            }
+        };
+        let var = |name: &str| {
+            env::var(name).map_err(|_| anyhow::Error::msg(format!("{} must be set", name)))
+        };
+        Ok(Store::S3 {
+            client: client::shared(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: var("BLOB_S3_BUCKET")?,
+            region: env::var("BLOB_S3_REGION").unwrap_or("us-east-1".to_string()),
+            access_key: var("BLOB_S3_ACCESS_KEY")?,
+            secret_key: var("BLOB_S3_SECRET_KEY")?,
+        })
+    }
+
+    /// Stores the bytes unless they are there already and returns their hash.
+    pub async fn put(&self, bytes: &[u8]) -> Result<String> {
+        let hash = hash(bytes);
+        let key = key(&hash)?;
+        match self {
+            Store::Disk { root } => {
+                let path = root.join(&key);
+                if tokio::fs::metadata(&path).await.is_ok() {
+                    return Ok(hash);
+                }
+                tokio::fs::create_dir_all(root.join(&hash[..2])).await?;
+                // written aside and renamed so a reader never sees half a file
+                let partial = path.with_extension("partial");
+                tokio::fs::write(&partial, bytes).await?;
+                tokio::fs::rename(&partial, &path).await?;
+            }
+            Store::S3 { .. } => {
+                self.s3(Method::PUT, &key, bytes.to_vec())
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(hash)
+    }
+
+    pub async fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let key = key(hash)?;
+        match self {
+            Store::Disk { root } => match tokio::fs::read(root.join(key)).await {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Store::S3 { .. } => {
+                let response = self.s3(Method::GET, &key, vec![]).await?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                Ok(Some(response.error_for_status()?.bytes().await?.to_vec()))
+            }
+        }
+    }
+
+    pub async fn delete(&self, hash: &str) -> Result<()> {
+        let key = key(hash)?;
+        match self {
+            Store::Disk { root } => match tokio::fs::remove_file(root.join(key)).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            },
+            Store::S3 { .. } => {
+                self.s3(Method::DELETE, &key, vec![])
+                    .await?
+                    .error_for_status()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Sends a request for an object signed with AWS signature version 4.
+    async fn s3(&self, method: Method, key: &str, body: Vec<u8>) -> Result<reqwest::Response> {
+        let (client, endpoint, bucket, region, access_key, secret_key) = match self {
+            Store::S3 {
+                client,
+                endpoint,
+                bucket,
+                region,
+                access_key,
+                secret_key,
+            } => (client, endpoint, bucket, region, access_key, secret_key),
+            Store::Disk { .. } => return Err(anyhow::Error::msg("not an s3 store")),
+        };
+
+        let url = reqwest::Url::parse(format!("{}/{}/{}", endpoint, bucket, key).as_str())?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload = format!("{:x}", Sha256::digest(&body));
+
+        let canonical = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method,
+            url.path(),
+            host,
+            payload,
+            timestamp,
+            payload
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, region);
+        let to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+            timestamp,
+            scope,
+            Sha256::digest(canonical.as_bytes())
+        );
+        let mut signing_key = format!("AWS4{}", secret_key).into_bytes();
+        for part in [date.as_str(), region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part.as_bytes())?;
+        }
+        let signature = hmac(&signing_key, to_sign.as_bytes())?
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        Ok(client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload)
+            .header("x-amz-date", timestamp)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    access_key, scope, signature
+                ),
+            )
+            .body(body)
+            .send()
+            .await?)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Copies up to `limit` image enclosures that have not been cached yet into the blob store, so
+/// thumbnails keep showing when the publisher removes or hotlink protects them.
+pub async fn cache_images(store: &dyn db::Store, blobs: &Store, limit: i64) -> Result<usize> {
+    let mut cached = 0;
+    for url in store.get_uncached_images(limit).await? {
+        // enclosure urls come from the feed, fetched like the proxy does so a feed cannot point
+        // us at a private address
+        let hash = match proxy::fetch_public(&url).await {
+            Ok((bytes, mime)) => {
+                let hash = blobs.put(&bytes).await?;
+                store
+                    .add_blob(Blob::new(hash.clone(), mime, bytes.len()))
+                    .await?;
+                cached += 1;
+                hash
+            }
+            Err(e) => {
+                println!("could not cache image {}: {}", url, e);
+                UNCACHEABLE.to_string()
+            }
+        };
+        store.set_enclosure_blob(url, hash).await?;
+    }
+    Ok(cached)
+}
+
+pub(crate) async fn fetch_image(client: &Client, url: &str) -> Result<(Vec<u8>, String)> {
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let mime = resp
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if !mime.starts_with("image/") {
+        return Err(anyhow::Error::msg(format!("not an image: {}", mime)));
+    }
+    if resp.content_length().unwrap_or_default() as usize > MAX_IMAGE_BYTES {
+        return Err(anyhow::Error::msg("image too large"));
+    }
+    // the length may be missing or wrong, so the body is counted as it arrives too
+    let mut bytes = Vec::new();
+    let mut chunks = resp.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        bytes.extend_from_slice(&chunk?);
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(anyhow::Error::msg("image too large"));
+        }
+    }
+    Ok((bytes, mime))
+}
+
+/// Removes blobs nothing references any more, returning how many were removed.
+pub async fn collect(store: &dyn db::Store, blobs: &Store, now: DateTime<Utc>) -> Result<usize> {
+    let before =
+        (now - Duration::hours(GC_GRACE_HOURS)).to_rfc3339_opts(SecondsFormat::Millis, true);
+    let orphans = store.get_orphaned_blobs(before).await?;
+    for hash in orphans.iter() {
+        blobs.delete(hash).await?;
+    }
+    store.delete_blobs(orphans.clone()).await?;
+    Ok(orphans.len())
+}
@@ -0,0 +1,139 @@
+use super::client;
+use super::politeness;
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How many redirects are followed before giving up on a link.
+const MAX_HOPS: usize = 5;
+/// How long a host is trusted to serve its links without redirects before it is checked again.
+const DIRECT_HOST_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// The most hosts remembered as direct, the longest remembered are forgotten first.
+const MAX_DIRECT_HOSTS: usize = 10_000;
+
+/// Hosts whose links were served without a redirect, with when that was seen. Their articles
+/// skip the lookup on later refreshes.
+static DIRECT_HOSTS: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+fn client() -> Client {
+    CLIENT
+        .get_or_init(|| {
+            client::builder()
+                .redirect(Policy::none())
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_else(|e| panic!("could not build http client: {}", e))
+        })
+        .clone()
+}
+
+fn is_direct(host: &str) -> bool {
+    let hosts = DIRECT_HOSTS.lock().unwrap();
+    hosts
+        .get(host)
+        .is_some_and(|seen| seen.elapsed() < DIRECT_HOST_TTL)
+}
+
+fn remember_direct(host: String) {
+    let mut hosts = DIRECT_HOSTS.lock().unwrap();
+    if hosts.len() >= MAX_DIRECT_HOSTS {
+        hosts.retain(|_, seen| seen.elapsed() < DIRECT_HOST_TTL);
+    }
+    if hosts.len() >= MAX_DIRECT_HOSTS {
+        let oldest = hosts
+            .iter()
+            .min_by_key(|(_, seen)| **seen)
+            .map(|(h, _)| h.clone());
+        if let Some(oldest) = oldest {
+            hosts.remove(&oldest);
+        }
+    }
+    hosts.insert(host, Instant::now());
+}
+
+/// Query parameters added by newsletters, aggregators and analytics that never change the target page.
+const TRACKING_PARAMS: [&str; 10] = [
+    "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid", "igshid", "ref", "ref_src", "_hsenc",
+];
+
+fn is_tracking_param(name: &str) -> bool {
+    name.starts_with("utm_") || TRACKING_PARAMS.contains(&name)
+}
+
+/// Strips tracking parameters and fragments from a link so the same post syndicated by
+/// several feeds resolves to a single url. Links that do not parse are returned untouched.
+pub fn strip_tracking(link: &str) -> String {
+    let mut url = match Url::parse(link) {
+        Ok(u) => u,
+        Err(_) => return link.to_string(),
+    };
+
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| !is_tracking_param(k))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+
+    match kept.is_empty() {
+        true => url.set_query(None),
+        false => {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+    url.set_fragment(None);
+
+    url.to_string()
+}
+
+/// Follows a link's redirect chain to the page it finally lands on, then strips tracking
+/// parameters from it. Aggregators wrap links in redirects, so this is what identifies a post.
+/// Any lookup failure keeps the last url reached. Each hop waits its turn with `hosts`, like
+/// feed fetches do.
+pub async fn resolve(hosts: &politeness::Hosts, link: &str) -> String {
+    let mut url = match Url::parse(link) {
+        Ok(u) => u,
+        Err(_) => return strip_tracking(link),
+    };
+
+    let host = url.host_str().unwrap_or_default().to_string();
+    if is_direct(&host) {
+        return strip_tracking(link);
+    }
+
+    let client = client();
+    for hop in 0..MAX_HOPS {
+        let resp = {
+            let _permit = hosts.wait(url.as_str()).await;
+            match client.head(url.clone()).send().await {
+                Ok(r) => r,
+                Err(_) => break,
+            }
+        };
+
+        let next = match resp.status().is_redirection() {
+            true => resp
+                .headers()
+                .get(LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .and_then(|l| url.join(l).ok()),
+            false => None,
+        };
+
+        match next {
+            Some(n) => url = n,
+            None => {
+                if hop == 0 && resp.status().is_success() {
+                    remember_direct(host);
+                }
+                break;
+            }
+        }
+    }
+
+    strip_tracking(url.as_str())
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use rweb::filters::BoxedFilter;
+use rweb::*;
+use std::fmt;
+
+#[cfg(feature = "chaos")]
+use rand_core::{OsRng, RngCore};
+#[cfg(feature = "chaos")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "chaos")]
+use std::sync::Mutex;
+#[cfg(feature = "chaos")]
+use std::time::Duration;
+
+/// A failure made up on purpose, told apart from real ones so retries treat it as transient.
+#[derive(Debug)]
+pub struct Injected(&'static str);
+
+impl fmt::Display for Injected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "injected {} failure", self.0)
+    }
+}
+
+impl std::error::Error for Injected {}
+
+/// Faults injected into database calls and feed fetches, set through `/chaos` in builds with the
+/// `chaos` feature to check that alerting, retries and the circuit breaker behave before they
+/// are needed. Percentages are the chance of each call failing.
+#[cfg(feature = "chaos")]
+#[derive(Deserialize, Serialize, Clone, Copy, Schema)]
+#[serde(default)]
+pub struct Faults {
+    db_latency_ms: u64,
+    db_error_percent: u32,
+    fetch_latency_ms: u64,
+    fetch_error_percent: u32,
+}
+
+#[cfg(feature = "chaos")]
+impl Faults {
+    const NONE: Faults = Faults {
+        db_latency_ms: 0,
+        db_error_percent: 0,
+        fetch_latency_ms: 0,
+        fetch_error_percent: 0,
+    };
+}
+
+#[cfg(feature = "chaos")]
+impl Default for Faults {
+    fn default() -> Self {
+        Faults::NONE
+    }
+}
+
+#[cfg(feature = "chaos")]
+static FAULTS: Mutex<Faults> = Mutex::new(Faults::NONE);
+
+#[cfg(feature = "chaos")]
+async fn inject(latency_ms: u64, error_percent: u32, what: &'static str) -> Result<()> {
+    if latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+    }
+    match OsRng.next_u32() % 100 < error_percent {
+        true => Err(Injected(what).into()),
+        false => Ok(()),
+    }
+}
+
+/// Called before every database call, delays it and fails it as configured.
+#[cfg(feature = "chaos")]
+pub async fn db() -> Result<()> {
+    let f = *FAULTS.lock().unwrap();
+    inject(f.db_latency_ms, f.db_error_percent, "database").await
+}
+
+/// Called before every feed fetch attempt, delays it and fails it as configured.
+#[cfg(feature = "chaos")]
+pub async fn fetch() -> Result<()> {
+    let f = *FAULTS.lock().unwrap();
+    inject(f.fetch_latency_ms, f.fetch_error_percent, "fetch").await
+}
+
+#[cfg(not(feature = "chaos"))]
+pub async fn db() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(feature = "chaos"))]
+pub async fn fetch() -> Result<()> {
+    Ok(())
+}
+
+/// The faults injected.
+#[cfg(feature = "chaos")]
+#[get("/chaos")]
+async fn get_faults() -> Result<Json<Faults>, Rejection> {
+    Ok(Json::from(*FAULTS.lock().unwrap()))
+}
+
+/// Replaces the injected faults, anything left out is turned off.
+#[cfg(feature = "chaos")]
+#[put("/chaos")]
+async fn set_faults(faults: Json<Faults>) -> Result<Json<Faults>, Rejection> {
+    let faults = faults.into_inner();
+    println!(
+        "injecting faults: {}",
+        serde_json::to_string(&faults).unwrap()
+    );
+    *FAULTS.lock().unwrap() = faults;
+    Ok(Json::from(faults))
+}
+
+/// Stops injecting faults.
+#[cfg(feature = "chaos")]
+#[delete("/chaos")]
+async fn clear_faults() -> Result<Json<Faults>, Rejection> {
+    println!("no longer injecting faults");
+    *FAULTS.lock().unwrap() = Faults::NONE;
+    Ok(Json::from(Faults::NONE))
+}
+
+#[cfg(feature = "chaos")]
+pub fn routes() -> BoxedFilter<(Box<dyn Reply>,)> {
+    get_faults()
+        .or(set_faults())
+        .or(clear_faults())
+        .map(|r| Box::new(r) as Box<dyn Reply>)
+        .boxed()
+}
+
+/// Without the `chaos` feature there is nothing to route to.
+#[cfg(not(feature = "chaos"))]
+pub fn routes() -> BoxedFilter<(Box<dyn Reply>,)> {
+    warp::any()
+        .and_then(|| async { Err::<Box<dyn Reply>, Rejection>(warp::reject::not_found()) })
+        .boxed()
+}
@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Tracks consecutive database failures. Once `threshold` of them happen in a row the circuit
+/// opens and calls fail fast until it is closed again by a successful probe.
+pub struct Circuit {
+    threshold: u32,
+    failures: AtomicU32,
+    open: AtomicBool,
+}
+
+impl Circuit {
+    pub fn new(threshold: u32) -> Self {
+        Circuit {
+            threshold: threshold.max(1),
+            failures: AtomicU32::new(0),
+            open: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Acquire)
+    }
+
+    pub fn success(&self) {
+        self.failures.store(0, Ordering::Release);
+    }
+
+    /// Counts a failure, returning true when it is the one that opened the circuit so the
+    /// caller knows to start probing.
+    pub fn failure(&self) -> bool {
+        let failures = self.failures.fetch_add(1, Ordering::AcqRel) + 1;
+        failures >= self.threshold && self.trip()
+    }
+
+    /// Opens the circuit straight away, for failures that are certain to repeat such as a closed
+    /// connection. Returns true when it was closed before.
+    pub fn trip(&self) -> bool {
+        !self.open.swap(true, Ordering::AcqRel)
+    }
+
+    pub fn close(&self) {
+        self.failures.store(0, Ordering::Release);
+        self.open.store(false, Ordering::Release);
+    }
+}
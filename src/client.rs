@@ -0,0 +1,70 @@
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder};
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const DEFAULT_CONTACT_URL: &str = "https://github.com/kdwils/feedreader-rs";
+const DEFAULT_CONNECT_TIMEOUT_SECONDS: u64 = 10;
+const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+static SHARED: OnceLock<Client> = OnceLock::new();
+
+/// The client every outgoing request is made with, so publishers see who is asking and a server
+/// that never answers cannot hold a refresh or a request handler forever.
+pub fn shared() -> Client {
+    SHARED
+        .get_or_init(|| {
+            builder()
+                .build()
+                .unwrap_or_else(|e| panic!("could not build http client: {}", e))
+        })
+        .clone()
+}
+
+/// A client configured like the shared one, for callers that need to change part of it such as
+/// following redirects by hand.
+///
+/// - `HTTP_USER_AGENT` replaces the whole User-Agent, by default it names feedreader, its
+///   version and `HTTP_CONTACT_URL` (this repository) for publishers to get in touch.
+/// - `HTTP_CONNECT_TIMEOUT_SECONDS` (10) bounds connecting, `HTTP_TIMEOUT_SECONDS` (30) the
+///   whole request including reading the body.
+/// - `HTTP_MAX_REDIRECTS` (10) is how many redirects are followed, zero follows none.
+pub fn builder() -> ClientBuilder {
+    let user_agent = env::var("HTTP_USER_AGENT").unwrap_or_else(|_| {
+        format!(
+            "feedreader/{} (+{})",
+            env!("CARGO_PKG_VERSION"),
+            env::var("HTTP_CONTACT_URL").unwrap_or(DEFAULT_CONTACT_URL.to_string())
+        )
+    });
+    let redirect = match max_redirects() {
+        0 => Policy::none(),
+        n => Policy::limited(n),
+    };
+
+    Client::builder()
+        .user_agent(user_agent)
+        .connect_timeout(Duration::from_secs(var(
+            "HTTP_CONNECT_TIMEOUT_SECONDS",
+            DEFAULT_CONNECT_TIMEOUT_SECONDS,
+        )))
+        .timeout(Duration::from_secs(var(
+            "HTTP_TIMEOUT_SECONDS",
+            DEFAULT_TIMEOUT_SECONDS,
+        )))
+        .redirect(redirect)
+}
+
+/// How many redirects a client follows, for those that follow them with a policy of their own.
+pub fn max_redirects() -> usize {
+    var("HTTP_MAX_REDIRECTS", DEFAULT_MAX_REDIRECTS)
+}
+
+fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
@@ -1,29 +1,133 @@
-use super::{AddFeed, Article, Feed};
+use super::blob::{self, Blob};
+use super::chaos;
+use super::circuit::Circuit;
+use super::enclosure::Enclosure;
+use super::export::{self, Column};
+use super::fetch::{Attempt, Payload};
+use super::fever;
+use super::greader;
+use super::migrate::{self, Migration};
+use super::mute::{Mute, Mutes};
+use super::notify::Subscription;
+use super::polling;
+use super::push::PushSubscription;
+use super::queue::{Job, State};
+use super::report::{Count, Report};
+use super::rules::{Imported, Rules};
+use super::sanitize::Raw;
+use super::schema::{self, Discrepancy, Found};
+use super::score::Signals;
+use super::scrape::Rule;
+use super::search::Language;
+use super::view::View;
+use super::{AddFeed, Article, BulkAction, Feed};
 use anyhow::Result;
-use futures::lock::Mutex;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use futures::lock::{Mutex, MutexGuard};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use rweb::Schema;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::str::FromStr;
+use std::sync::atomic::{self, AtomicU64};
 use std::sync::Arc;
 
-use tokio_postgres::{Client, Config, NoTls, Row};
+use tokio_postgres::tls::NoTlsStream;
+use tokio_postgres::types::{Json, ToSql};
+use tokio_postgres::{
+    Client, Config, Connection, GenericClient, NoTls, Row, Socket, Statement, ToStatement,
+    Transaction,
+};
 
-pub static MAX_DATE: &str = "9999-12-31";
+/// The cursor of the first page of a list.
+pub static START: &str = "start";
 
-const LIMIT: usize = 4;
-const LIMIT_UPPER_BOUND: usize = LIMIT + 1;
-const LIMIT_LOWER_BOUND: usize = LIMIT - 1;
+/// Reads a date handed around as text, such as a pagination cursor, into a timestamp column
+/// value. Besides rfc3339 this takes bare dates like `2023-01-31`. Anything else, including the
+/// `-1` used for never, is stored as null.
+pub(crate) fn timestamp(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| DateTime::from_naive_utc_and_offset(dt, Utc))
+}
+
+/// The text form of a timestamp column, `missing` when it is null.
+pub(crate) fn text(dt: Option<DateTime<Utc>>, missing: &str) -> String {
+    match dt {
+        Some(dt) => dt.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        None => missing.to_string(),
+    }
+}
+
+/// The registrable domain of a feed's site_url, so publications hosted on one platform group
+/// together. Two-letter country suffixes such as co.uk keep a third label, ip addresses are kept whole.
+const FEED_DOMAIN: &str = r"(SELECT CASE
+    WHEN host ~ '^[0-9.]+$' THEN host
+    WHEN host ~ '\.(co|com|org|net|ac|gov|edu)\.[a-z]{2}$' THEN substring(host from '([^.]+\.[^.]+\.[^.]+)$')
+    ELSE COALESCE(substring(host from '([^.]+\.[^.]+)$'), host)
+END FROM (SELECT lower(COALESCE(substring(site_url from '^[a-zA-Z]+://([^/:?#]+)'), '')) AS host) AS h)";
+
+/// Articles along with the current name of their feed as `feed_name`, so renaming a feed carries
+/// over to its articles. Articles of a feed that was deleted keep the name they were stored with.
+/// `sources` holds feed ids, `source_names` the current names of those feeds still around.
+const ARTICLES: &str = "(SELECT articles.*, COALESCE(feeds.name, articles.feed) AS feed_name, ARRAY(SELECT source.name FROM unnest(articles.sources) WITH ORDINALITY AS s(id, n) JOIN feeds AS source ON source.id = s.id ORDER BY s.n) AS source_names FROM articles LEFT JOIN feeds ON feeds.id = articles.feed_id) AS articles";
+
+/// Rows on a page unless `PAGE_SIZE` or the request asks for another size.
+pub const DEFAULT_PAGE_SIZE: usize = 4;
+/// The most rows a single page may hold.
+pub const MAX_PAGE_SIZE: usize = 100;
+/// Search results are ranked rather than paged, only the best matches are shown.
+pub(crate) const SEARCH_LIMIT: usize = 50;
+/// How many articles the random view resurfaces at a time.
+pub(crate) const SAMPLE_LIMIT: usize = 10;
+/// Rows stored by one multi-row insert, postgres takes at most 65535 parameters a statement.
+const INSERT_BATCH: usize = 1000;
+/// Articles moved to the archive at a time, so a first run over a large table does not hold its
+/// locks for long.
+pub(crate) const ARCHIVE_BATCH: i64 = 1000;
+/// Articles read per query by an export.
+const EXPORT_BATCH: i64 = 1000;
+/// How many days apart two feeds may publish the same story for it to count as syndicated,
+/// further apart a matching title and author is more likely a recurring post.
+pub(crate) const DUPLICATE_WINDOW_DAYS: i64 = 7;
+/// How a stored article is brought up to date when a refresh finds the publisher edited it, as
+/// the conflict clause of an insert. Articles stored before there were content hashes are left
+/// to the rehash in [Storage::add_articles].
+const UPSERT: &str = "title = EXCLUDED.title, author = EXCLUDED.author, summary = EXCLUDED.summary, content = EXCLUDED.content, word_count = EXCLUDED.word_count, reading_minutes = EXCLUDED.reading_minutes, fingerprint = EXCLUDED.fingerprint, raw = EXCLUDED.raw, language = EXCLUDED.language, content_hash = EXCLUDED.content_hash, updated_at = now()
+WHERE articles.content_hash <> '' AND (articles.content_hash <> EXCLUDED.content_hash OR articles.author <> EXCLUDED.author)";
+/// Prepared statements kept per connection, the cache starts over once it is full.
+const STATEMENT_CACHE_SIZE: usize = 256;
+
+/// Article totals shown alongside every article list.
+#[derive(Serialize, Default, Schema)]
+pub struct Counts {
+    pub unread: i64,
+    pub read_today: i64,
+    pub favorites: i64,
+}
 
 pub enum Filter {
     Unread,
     Favorite,
     Read,
+    Hidden,
 }
 
-impl ToString for Filter {
-    fn to_string(&self) -> String {
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Filter::Read => "read".to_string(),
-            Filter::Favorite => "favorite".to_string(),
-            Filter::Unread => "unread".to_string(),
+            Filter::Read => write!(f, "read"),
+            Filter::Favorite => write!(f, "favorite"),
+            Filter::Unread => write!(f, "unread"),
+            Filter::Hidden => write!(f, "hidden"),
         }
     }
 }
@@ -35,56 +139,558 @@ impl FromStr for Filter {
             "unread" => Ok(Filter::Unread),
             "favorite" => Ok(Filter::Favorite),
             "read" => Ok(Filter::Read),
+            "hidden" => Ok(Filter::Hidden),
             _ => Err(anyhow::Error::msg(format!("bad filter type: {}", s))),
         }
     }
 }
 
-enum Ordering {
-    Ascending,
-    Descending,
-}
+impl Filter {
+    fn condition(&self) -> &'static str {
+        match self {
+            Filter::Unread => "read = false AND hidden = false",
+            Filter::Favorite => "favorited = true AND hidden = false",
+            Filter::Read => "read = true AND hidden = false",
+            Filter::Hidden => "hidden = true",
+        }
+    }
 
-impl ToString for Ordering {
-    fn to_string(&self) -> String {
-        match *self {
-            Ordering::Ascending => "ASC".to_string(),
-            Ordering::Descending => "DESC".to_string(),
+    /// History is ordered by when articles were read, everything else by when they were published.
+    pub fn default_sort(&self) -> Sort {
+        let key = match self {
+            Filter::Read => SortKey::ReadDate,
+            _ => SortKey::Published,
+        };
+        Sort {
+            key,
+            ordering: Ordering::Descending,
+            page_size: None,
+            page: None,
         }
     }
 }
 
-enum PaginationField {
-    Id,
+#[derive(Clone, Copy)]
+pub enum SortKey {
     Published,
+    Added,
     ReadDate,
+    /// Ranked by `score::Signals`, only available for unread articles.
+    Score,
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortKey::Published => write!(f, "published"),
+            SortKey::Added => write!(f, "added"),
+            SortKey::ReadDate => write!(f, "read"),
+            SortKey::Score => write!(f, "score"),
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<SortKey> {
+        match s {
+            "published" => Ok(SortKey::Published),
+            "added" => Ok(SortKey::Added),
+            "read" => Ok(SortKey::ReadDate),
+            "score" => Ok(SortKey::Score),
+            _ => Err(anyhow::Error::msg(format!("bad sort key: {}", s))),
+        }
+    }
+}
+
+impl SortKey {
+    /// Scores are not stored, ties between equal scores are broken by when articles were published.
+    fn column(&self) -> &'static str {
+        match self {
+            SortKey::Published | SortKey::Score => "published",
+            SortKey::Added => "date_added",
+            SortKey::ReadDate => "read_date",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Sort {
+    pub key: SortKey,
+    pub ordering: Ordering,
+    /// Rows per page, `None` for the page size the storage was configured with.
+    pub page_size: Option<usize>,
+    /// Page number counted from 1 when paging by number, `None` to page by cursor.
+    pub page: Option<usize>,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Filter::Unread.default_sort()
+    }
+}
+
+impl Sort {
+    /// The cursor value that selects the first page for this sort. Scored pages are paged by offset.
+    pub fn start(&self) -> &'static str {
+        match self.key {
+            SortKey::Score => "0",
+            _ => START,
+        }
+    }
+
+    /// Query string that reproduces this sort on follow up requests.
+    pub fn query(&self) -> String {
+        let dir = match self.ordering {
+            Ordering::Ascending => "asc",
+            Ordering::Descending => "desc",
+        };
+        let mut query = format!("sort={}&dir={}", self.key, dir);
+        if let Some(size) = self.page_size {
+            query = format!("{}&page_size={}", query, size);
+        }
+        if let Some(page) = self.page {
+            query = format!("{}&page={}", query, page);
+        }
+        query
+    }
+
+    /// Query string of page `number` of this sort.
+    pub fn page_query(&self, number: &usize) -> String {
+        Sort {
+            page: Some(*number),
+            ..*self
+        }
+        .query()
+    }
+}
+
+/// Optional restrictions on which articles are streamed out of storage or shown by a saved view.
+#[derive(Default)]
+pub struct ArticleQuery {
+    pub filter: Option<Filter>,
+    pub feed: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// An article tag, matched exactly.
+    pub tag: Option<String>,
+    /// Text the title contains, ignoring case.
+    pub title: Option<String>,
+}
+
+impl ArticleQuery {
+    /// The restrictions as sql conditions along with their parameters, which are numbered
+    /// from `first` so they can follow parameters of the surrounding query.
+    fn conditions(&self, first: usize) -> (Vec<String>, Vec<&(dyn ToSql + Sync)>) {
+        let mut conditions = vec![];
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+        let restrictions: [(&str, Option<&(dyn ToSql + Sync)>); 5] = [
+            ("feed_name = $", self.feed.as_ref().map(|p| p as _)),
+            ("published >= $", self.since.as_ref().map(|p| p as _)),
+            ("published < $", self.until.as_ref().map(|p| p as _)),
+            ("$ = ANY(tags)", self.tag.as_ref().map(|p| p as _)),
+            (
+                "strpos(lower(title), lower($)) > 0",
+                self.title.as_ref().map(|p| p as _),
+            ),
+        ];
+        for (condition, param) in restrictions {
+            if let Some(p) = param {
+                params.push(p);
+                let placeholder = format!("${}", first + params.len() - 1);
+                conditions.push(condition.replace('$', placeholder.as_str()));
+            }
+        }
+
+        if let Some(filter) = &self.filter {
+            conditions.push(filter.condition().to_string());
+        }
+        (conditions, params)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Ordering {
+    Ascending,
+    Descending,
 }
 
-impl PaginationField {
-    fn index(self) -> usize {
+impl Ordering {
+    fn reverse(&self) -> Ordering {
         match self {
-            PaginationField::Id => 0,
-            PaginationField::Published => 5,
-            PaginationField::ReadDate => 8,
+            Ordering::Ascending => Ordering::Descending,
+            Ordering::Descending => Ordering::Ascending,
+        }
+    }
+
+    /// comparison used to select rows after the cursor in this direction
+    fn after(&self) -> &'static str {
+        match self {
+            Ordering::Ascending => ">",
+            Ordering::Descending => "<",
+        }
+    }
+
+    /// comparison used to select rows before the cursor in this direction, the row the cursor
+    /// points at included
+    fn before(&self) -> &'static str {
+        match self {
+            Ordering::Ascending => "<=",
+            Ordering::Descending => ">=",
+        }
+    }
+}
+
+impl FromStr for Ordering {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Ordering> {
+        match s {
+            "asc" => Ok(Ordering::Ascending),
+            "desc" => Ok(Ordering::Descending),
+            _ => Err(anyhow::Error::msg(format!("bad sort direction: {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Ordering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Ordering::Ascending => write!(f, "ASC"),
+            Ordering::Descending => write!(f, "DESC"),
         }
     }
 }
 
-pub struct Page {
+pub struct Page<T> {
     pub cursor: Cursor,
-    pub items: Vec<Row>,
+    pub items: Vec<T>,
+    /// Items shown above the first page regardless of the sort.
+    pub pinned: Vec<T>,
+    /// Where the page sits among the rest when paging by number.
+    pub numbers: Option<PageNumbers>,
 }
 
-impl Page {
-    fn new(next: Vec<Row>, prev: Vec<Row>, curr: String, paginated_field: PaginationField) -> Page {
+impl<T> Page<T> {
+    /// The page of `next` following the cursor `curr`, `prev` holds the items before it closest
+    /// first. Both are queried with one item more than `limit`, `key` tells where an item sorts.
+    pub(crate) fn new(
+        next: Vec<T>,
+        prev: Vec<T>,
+        curr: String,
+        limit: usize,
+        key: impl Fn(&T) -> Key,
+    ) -> Page<T> {
+        Page {
+            cursor: Cursor::new(next.as_slice(), prev.as_slice(), curr, limit, key),
+            items: Cursor::items(next, limit),
+            pinned: vec![],
+            numbers: None,
+        }
+    }
+
+    /// Page `number` of `total` items, `limit` to a page. There is no cursor to follow, it only
+    /// tells whether earlier pages exist so pinned items lead the first page alone.
+    pub(crate) fn numbered(
+        items: Vec<T>,
+        curr: String,
+        number: usize,
+        limit: usize,
+        total: usize,
+    ) -> Page<T> {
+        Page {
+            cursor: Cursor {
+                has_prev: number > 1,
+                curr,
+                ..Default::default()
+            },
+            items,
+            pinned: vec![],
+            numbers: Some(PageNumbers {
+                page: number,
+                pages: total.div_ceil(limit).max(1),
+                total,
+            }),
+        }
+    }
+
+    /// A single page of everything in `items`, for results that are ranked rather than paged.
+    pub(crate) fn all(items: Vec<T>) -> Page<T> {
+        Page {
+            cursor: Cursor::default(),
+            items,
+            pinned: vec![],
+            numbers: None,
+        }
+    }
+
+    /// The same page with every item converted by `f`.
+    pub fn map<U>(self, f: impl Fn(&T) -> U) -> Page<U> {
         Page {
-            cursor: Cursor::new(next.as_slice(), prev, curr, paginated_field.index()),
-            items: Cursor::items(next),
+            cursor: self.cursor,
+            items: self.items.iter().map(&f).collect(),
+            pinned: self.pinned.iter().map(&f).collect(),
+            numbers: self.numbers,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct PageNumbers {
+    pub page: usize,
+    pub pages: usize,
+    /// Rows on all pages together.
+    pub total: usize,
+}
+
+impl PageNumbers {
+    /// Pages to link to: the first and last and the two either side of this one, `None` where
+    /// pages are left out in between.
+    pub fn links(&self) -> Vec<Option<PageLink>> {
+        let mut links = vec![];
+        let mut last = 0;
+        for number in 1..=self.pages {
+            if number != 1 && number != self.pages && number.abs_diff(self.page) > 2 {
+                continue;
+            }
+            if number > last + 1 {
+                links.push(None);
+            }
+            links.push(Some(PageLink {
+                number,
+                current: number == self.page,
+            }));
+            last = number;
+        }
+        links
+    }
+}
+
+pub struct PageLink {
+    pub number: usize,
+    pub current: bool,
+}
+
+/// Rows matching `condition` on the `comparison` side of the cursor in `$1` and `$2`, all of
+/// them when the cursor is null. Rows without a value to sort by are left out of the pages.
+fn keyset_condition(condition: &str, column: &str, comparison: &str) -> String {
+    format!(
+        "{} AND {} IS NOT NULL AND ($1::timestamptz IS NULL OR ({}, id) {} ($1, $2::text))",
+        condition, column, column, comparison
+    )
+}
+
+/// Pages through the rows of `from` matching `condition` ordered by `column` and then id, so
+/// rows sharing a timestamp keep their place, starting after the `pagination` cursor. The
+/// cursor is `$1` and `$2`, any `params` the condition uses follow it.
+#[allow(clippy::too_many_arguments)]
+async fn keyset_page(
+    conn: &Client,
+    from: &str,
+    condition: &str,
+    params: &[&(dyn ToSql + Sync)],
+    column: &str,
+    ordering: Ordering,
+    limit: usize,
+    pagination: String,
+) -> Result<Page<Row>> {
+    let key = Key::decode(pagination.as_str())?;
+    let (at, id) = match &key {
+        Some(k) => (Some(k.at), Some(k.id.clone())),
+        None => (None, None),
+    };
+    let params: Vec<&(dyn ToSql + Sync)> = [&at as &(dyn ToSql + Sync), &id]
+        .into_iter()
+        .chain(params.iter().copied())
+        .collect();
+
+    let next_query = format!(
+        "SELECT * FROM {} WHERE {} ORDER BY {} {}, id {} LIMIT {}",
+        from,
+        keyset_condition(condition, column, ordering.after()),
+        column,
+        ordering,
+        ordering,
+        limit + 1
+    );
+    let next = conn.query(next_query.as_str(), &params).await?;
+
+    // the rows before the page closest first, starting with the one the cursor points at
+    let prev = match key {
+        Some(_) => {
+            let prev_query = format!(
+                "SELECT * FROM {} WHERE {} ORDER BY {} {}, id {} LIMIT {}",
+                from,
+                keyset_condition(condition, column, ordering.before()),
+                column,
+                ordering.reverse(),
+                ordering.reverse(),
+                limit + 1
+            );
+            conn.query(prev_query.as_str(), &params).await?
         }
+        None => vec![],
+    };
+
+    Ok(Page::new(next, prev, pagination, limit, |r| {
+        Key::of(r, column)
+    }))
+}
+
+/// The page of `scored` items that `pagination` points at when ranked by score in the order of
+/// `sort`, `limit` to a page. `pagination` is an offset, `scored` comes newest first.
+pub(crate) fn ranked<T>(
+    mut scored: Vec<(f64, T)>,
+    sort: Sort,
+    limit: usize,
+    pagination: String,
+) -> Result<Page<T>> {
+    let offset: usize = match sort.page {
+        Some(number) => (number - 1) * limit,
+        None => pagination.parse()?,
+    };
+    // stable, so equal scores stay newest first
+    scored.sort_by(|a, b| match sort.ordering {
+        Ordering::Ascending => a.0.total_cmp(&b.0),
+        Ordering::Descending => b.0.total_cmp(&a.0),
+    });
+
+    let total = scored.len();
+    let items: Vec<T> = scored
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(_, item)| item)
+        .collect();
+
+    if let Some(number) = sort.page {
+        return Ok(Page::numbered(items, pagination, number, limit, total));
+    }
+    Ok(Page {
+        cursor: Cursor {
+            has_next: offset + limit < total,
+            has_prev: offset > 0,
+            next: (offset + limit).to_string(),
+            prev: offset.saturating_sub(limit).to_string(),
+            curr: pagination,
+        },
+        items,
+        pinned: vec![],
+        numbers: None,
+    })
+}
+
+/// When a new article was published, added and read. Articles without a usable publish date are
+/// treated as published when added.
+pub(crate) fn dates(a: &Article) -> (DateTime<Utc>, DateTime<Utc>, Option<DateTime<Utc>>) {
+    let date_added = timestamp(&a.date_added).unwrap_or_else(Utc::now);
+    let published = timestamp(&a.published).unwrap_or(date_added);
+    (published, date_added, timestamp(&a.read_date))
+}
+
+/// The feed a new article belongs to, none for articles stored without one.
+fn feed_id(a: &Article) -> Option<&str> {
+    Some(a.feed_id.as_str()).filter(|id| !id.is_empty())
+}
+
+/// Whether `b` is the story `a` tells syndicated by another feed: the same canonical url, or the
+/// same title and author, published within [DUPLICATE_WINDOW_DAYS] of each other.
+pub(crate) fn syndicated(a: &Article, b: &Article) -> bool {
+    let same = a.canonical_link == b.canonical_link
+        || (!a.fingerprint.is_empty() && a.fingerprint == b.fingerprint);
+    let apart = (dates(a).0 - dates(b).0).abs();
+    same && a.feed_id != b.feed_id && apart <= Duration::days(DUPLICATE_WINDOW_DAYS)
+}
+
+/// Placeholders of a multi-row VALUES list, `rows` rows of `columns` parameters each.
+fn values(rows: usize, columns: usize) -> String {
+    (0..rows)
+        .map(|r| {
+            let row: Vec<String> = (1..=columns)
+                .map(|c| format!("${}", r * columns + c))
+                .collect();
+            format!("({})", row.join(", "))
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Articles together with the archived ones, each under its feed's current name, for the history.
+fn history() -> String {
+    let columns = schema::columns("articles");
+    format!(
+        "(SELECT history.*, COALESCE(feeds.name, history.feed) AS feed_name, ARRAY(SELECT source.name FROM unnest(history.sources) WITH ORDINALITY AS s(id, n) JOIN feeds AS source ON source.id = s.id ORDER BY s.n) AS source_names FROM (SELECT {} FROM articles UNION ALL SELECT {} FROM {}) AS history LEFT JOIN feeds ON feeds.id = history.feed_id) AS articles",
+        columns,
+        columns,
+        schema::ARCHIVE
+    )
+}
+
+/// Articles, archived ones included, with the columns [greader::Item] is read from.
+fn greader_items() -> String {
+    format!(
+        "SELECT articles.seq, feeds.seq, articles.feed_name, feeds.site_url, articles.title, articles.author, articles.content, articles.summary, articles.link, articles.read, articles.favorited, articles.tags, articles.published, articles.date_added FROM {} LEFT JOIN feeds ON feeds.id = articles.feed_id",
+        history()
+    )
+}
+
+/// Moves the listed articles back out of the archive, so changing them works the same as for
+/// any other article.
+async fn restore<C>(client: &C, ids: &[&str]) -> Result<()>
+where
+    C: GenericClient + Sync,
+{
+    let columns = schema::columns("articles");
+    let query = format!(
+        "WITH restored AS (DELETE FROM {} WHERE id = ANY($1) RETURNING {}) INSERT INTO articles ({}) SELECT {} FROM restored",
+        schema::ARCHIVE,
+        columns,
+        columns,
+        columns
+    );
+    client.execute(query.as_str(), &[&ids]).await?;
+    Ok(())
+}
+
+/// Loads the enclosures of the given article rows.
+async fn enclosures(conn: &Client, rows: &[Row]) -> Result<BTreeMap<String, Vec<Enclosure>>> {
+    let ids: Vec<String> = rows.iter().map(|r| r.get(0)).collect();
+    let mut enclosures: BTreeMap<String, Vec<Enclosure>> = BTreeMap::new();
+    for row in conn
+        .query("SELECT * FROM enclosures WHERE article = ANY($1)", &[&ids])
+        .await?
+    {
+        enclosures
+            .entry(row.get(0))
+            .or_default()
+            .push(Enclosure::from(&row));
     }
+    Ok(enclosures)
+}
+
+/// Reads article rows into articles along with their enclosures.
+async fn articles(conn: &Client, rows: Vec<Row>) -> Result<Vec<Article>> {
+    let mut enclosures = enclosures(conn, &rows).await?;
+    Ok(rows
+        .iter()
+        .map(|r| {
+            let mut article = Article::from(r);
+            article.enclosures = enclosures.remove(&article.id).unwrap_or_default();
+            article
+        })
+        .collect())
 }
 
-#[derive(Default, Clone)]
+/// Reads a page of article rows into one of articles.
+async fn article_page(conn: &Client, page: Page<Row>) -> Result<Page<Article>> {
+    Ok(Page {
+        cursor: page.cursor,
+        items: articles(conn, page.items).await?,
+        pinned: articles(conn, page.pinned).await?,
+        numbers: page.numbers,
+    })
+}
+
+#[derive(Default, Clone, Serialize, Schema)]
 pub struct Cursor {
     pub has_next: bool,
     pub has_prev: bool,
@@ -94,18 +700,20 @@ pub struct Cursor {
 }
 
 impl Cursor {
-    fn new(next: &[Row], prev: Vec<Row>, curr: String, index: usize) -> Self {
+    /// `next` and `prev` are queried with one item more than `limit`, to tell whether another
+    /// page follows. `prev` holds the items before the page closest first.
+    fn new<T>(next: &[T], prev: &[T], curr: String, limit: usize, key: impl Fn(&T) -> Key) -> Self {
         let (hn, n) = match next.len() {
-            // next contains the elements for the next page, we only need elements up to the limit as the last is used to confirm there is another page
-            LIMIT_UPPER_BOUND => (true, next[next.len() - 1 - 1].get(index)),
-            1..=LIMIT_LOWER_BOUND => (false, next[next.len() - 1].get(index)),
+            // the item past the limit only confirms there is another page, which starts after the last item shown
+            len if len == limit + 1 => (true, key(&next[limit - 1]).encode()),
             _ => (false, "".to_string()),
         };
 
         let (hp, p) = match prev.len() {
-            LIMIT_UPPER_BOUND => (true, prev[0 + 1].get(index)),
-            1..=LIMIT_LOWER_BOUND => (true, MAX_DATE.to_string()),
-            _ => (false, "".to_string()),
+            // the previous page starts after the item before it
+            len if len == limit + 1 => (true, key(&prev[limit]).encode()),
+            0 => (false, "".to_string()),
+            _ => (true, START.to_string()),
         };
 
         Cursor {
@@ -113,14 +721,23 @@ impl Cursor {
             has_prev: hp,
             next: n,
             prev: p,
-            curr: curr,
+            curr,
         }
     }
 
-    fn items(next: Vec<Row>) -> Vec<Row> {
+    /// The cursor of the page that follows the row `id` sorted at `at`.
+    pub fn after(at: DateTime<Utc>, id: &str) -> String {
+        Key {
+            at,
+            id: id.to_string(),
+        }
+        .encode()
+    }
+
+    fn items<T>(next: Vec<T>, limit: usize) -> Vec<T> {
         let mut items = next;
         match items.len() {
-            LIMIT_UPPER_BOUND => {
+            len if len == limit + 1 => {
                 // if we have more elements than the limit, another page exists and we only need the len of LIMIT elements
                 items.pop();
                 items
@@ -130,242 +747,3249 @@ impl Cursor {
     }
 }
 
-#[derive(Clone)]
-pub struct Storage {
-    client: Arc<Mutex<Client>>,
+/// Where a page starts: the sort column and id of the row just before it, so rows sharing a
+/// timestamp are neither skipped nor repeated. Clients get it encoded and pass it back untouched.
+pub(crate) struct Key {
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) id: String,
 }
 
-impl Storage {
-    pub(crate) async fn init(&self) -> Result<()> {
-        let conn = self.client.lock().await;
-        let query = r#"
-CREATE TABLE IF NOT EXISTS feeds (
-    id TEXT NOT NULL,
-    name TEXT NOT NULL,
-    site_url TEXT NOT NULL,
-    feed_url TEXT NOT NULL UNIQUE,
-    date_added TEXT NOT NULL,
-    last_updated TEXT NOT NULL
-);
-
-CREATE TABLE IF NOT EXISTS articles (
-    id TEXT NOT NULL,
-    feed TEXT NOT NULL,
-    title TEXT NOT NULL,
-    link TEXT NOT NULL UNIQUE,
-    author TEXT NOT NULL,
-    published TEXT NOT NULL,
-    read BOOLEAN NOT NULL,
-    favorited BOOLEAN NOT NULL,
-    read_date TEXT NOT NULL
-);"#;
-        conn.batch_execute(query).await?;
-        Ok(())
-    }
-
-    pub(crate) async fn add_feed(&self, f: AddFeed) -> Result<Feed> {
-        let conn = &mut self.client.lock().await;
-        let query = "INSERT INTO FEEDS (id, name, site_url, feed_url, date_added, last_updated) VALUES ($1, $2, $3, $4, $5, $6)";
-        let tx = conn.transaction().await?;
-        let stmt = tx.prepare(query).await?;
-        let fta = Feed::new(f.feed_name, f.site_url, f.feed_url);
-        tx.execute(
-            &stmt,
-            &[
-                &fta.id,
-                &fta.name,
-                &fta.site_url,
-                &fta.feed_url,
-                &fta.date_added,
-                &fta.last_updated,
-            ],
-        )
-        .await?;
-        tx.commit().await?;
+impl Key {
+    fn of(row: &Row, column: &str) -> Key {
+        Key {
+            at: row.get(column),
+            id: row.get("id"),
+        }
+    }
 
-        Ok(fta)
+    fn encode(&self) -> String {
+        let key = format!("{}|{}", text(Some(self.at), ""), self.id);
+        URL_SAFE_NO_PAD.encode(key)
     }
 
-    pub(crate) async fn get_feed_by_id(&self, id: String) -> Result<Feed> {
-        let conn = &mut self.client.lock().await;
-        let query = "SELECT * FROM feeds WHERE id = $1";
-        let result = conn.query_one(query, &[&id]).await?;
-        Ok(Feed::from(&result))
+    /// `None` for the first page.
+    pub(crate) fn decode(cursor: &str) -> Result<Option<Key>> {
+        if cursor == START {
+            return Ok(None);
+        }
+        let bad = || anyhow::Error::msg(format!("bad cursor: {}", cursor));
+        let key = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).map_err(|_| bad())?)?;
+        let (at, id) = key.split_once('|').ok_or_else(bad)?;
+        Ok(Some(Key {
+            at: timestamp(at).ok_or_else(bad)?,
+            id: id.to_string(),
+        }))
     }
+}
 
-    pub(crate) async fn get_feeds(&self, pagination: String) -> Result<Page> {
-        let conn = &mut self.client.lock().await;
-        let next_query = format!(
-            "SELECT * FROM feeds WHERE date_added < $1 ORDER BY id {} LIMIT {}",
-            Ordering::Descending.to_string(),
-            LIMIT_UPPER_BOUND
-        );
-        let next = conn.query(next_query.as_str(), &[&pagination]).await?;
+/// What a successful refresh of a feed changed, stored at once by [Storage::apply_refresh].
+pub struct Refreshed {
+    /// The articles in the feed document, none when it had not changed.
+    pub articles: Vec<Article>,
+    /// Older articles from the feed's archive pages, stored without being reported as new.
+    pub history: Vec<Article>,
+    /// Ids articles were stored under by an earlier release, each with the id the article has
+    /// now, for [Article::legacy_guid_id].
+    pub rekeyed: Vec<(String, String)>,
+    /// The etag, last modified and build marker of the document, when one was fetched.
+    pub validators: Option<(String, String, String)>,
+    /// The feed's scheduling hints, when they changed.
+    pub hints: Option<polling::Hints>,
+    pub updated: DateTime<Utc>,
+}
 
-        let prev_query = format!("SELECT * FROM ( SELECT * FROM feeds WHERE date_added > $1 ORDER BY id {} LIMIT {} ) AS data ORDER BY date_added {}", Ordering::Ascending.to_string(), LIMIT_UPPER_BOUND, Ordering::Descending.to_string());
-        let prev = conn.query(prev_query.as_str(), &[&pagination]).await?;
+/// A feed whose recent articles mostly went unread.
+pub struct Suggestion {
+    pub id: String,
+    pub name: String,
+    pub total: i64,
+    pub unread: i64,
+}
 
-        Ok(Page::new(next, prev, pagination, PaginationField::Id))
-    }
+/// Feeds grouped under the domain of their site.
+pub struct Domain {
+    pub name: String,
+    pub feeds: Vec<String>,
+    pub muted: bool,
+}
 
-    pub(crate) async fn delete_feed(&self, id: String) -> Result<()> {
-        let conn = &mut self.client.lock().await;
-        let query = "DELETE FROM feeds WHERE id = $1";
-        let tx = conn.transaction().await?;
-        tx.execute(query, &[&id]).await?;
-        tx.commit().await?;
-        Ok(())
-    }
+/// Upper bounds on what the instance is willing to store. There are no user accounts, so they
+/// cover the whole database. A bound of `None` is unlimited. Checking a bound and adding what it
+/// limits happen under [QUOTA_LOCK], so instances sharing a database cannot both pass the check.
+#[derive(Default, Clone, Copy)]
+pub struct Quota {
+    pub max_feeds: Option<i64>,
+    pub max_articles: Option<i64>,
+}
 
-    pub(crate) async fn update_feed_last_updated(
-        &self,
-        timestamp: String,
-        id: String,
-    ) -> Result<()> {
-        let conn = &mut self.client.lock().await;
-        let tx = conn.transaction().await?;
-        let query = "UPDATE feeds SET last_updated = $1 WHERE id = $2";
-        tx.query(query, &[&timestamp, &id]).await?;
-        tx.commit().await?;
-        Ok(())
-    }
+/// When unread articles are given up on and marked read. A bound of `None` never expires anything.
+#[derive(Default, Clone, Copy)]
+pub struct Expiry {
+    pub max_age_days: Option<i64>,
+    pub max_unread_per_feed: Option<i64>,
+}
 
-    pub(crate) async fn add_articles<T>(&self, articles: T) -> Result<()>
-    where
-        T: Iterator<Item = Article>,
-    {
-        let conn = &mut self.client.lock().await;
-        let tx = conn.transaction().await?;
-        let query = "INSERT INTO articles (id, feed, title, link, author, published, read, favorited, read_date) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (link) DO NOTHING";
-        let stmt = tx.prepare(query).await?;
-        for article in articles {
-            tx.execute(
-                &stmt,
-                &[
-                    &article.id,
-                    &article.feed,
-                    &article.title,
-                    &article.link,
-                    &article.author,
-                    &article.published,
-                    &article.read,
-                    &article.favorited,
-                    &article.read_date,
-                ],
-            )
-            .await?;
-        }
+/// How long the body of a read article is kept, and how long read articles stay in the articles
+/// table before they are moved to the archive. A bound of `None` keeps them forever.
+#[derive(Default, Clone, Copy)]
+pub struct Retention {
+    pub content_days: Option<i64>,
+    pub archive_days: Option<i64>,
+}
 
-        tx.commit().await?;
-        Ok(())
-    }
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    pub(crate) resource: &'static str,
+    pub(crate) limit: i64,
+}
 
-    pub(crate) async fn get_article_by_id(&self, id: String) -> Result<Article> {
-        let conn = &mut self.client.lock().await;
-        let query = "SELECT * FROM articles WHERE id = $1";
-        let row = conn.query_one(query, &[&id]).await?;
-        Ok(Article::from(&row))
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} quota exceeded: at most {} {} may be stored",
+            self.resource, self.limit, self.resource
+        )
     }
+}
 
-    pub(crate) async fn get_unread_articles(&self, pagination: String) -> Result<Page> {
-        let conn = &mut self.client.lock().await;
+impl std::error::Error for QuotaExceeded {}
 
-        let next_query =format!("SELECT * FROM articles WHERE read = false AND published < $1 ORDER BY published {} LIMIT {}", Ordering::Descending.to_string(), LIMIT_UPPER_BOUND);
-        let next = conn.query(next_query.as_str(), &[&pagination]).await?;
+/// Key of the advisory lock held while a quota is checked, along with the resource it limits.
+const QUOTA_LOCK: i32 = 0x7175_6f74;
 
-        let prev_query = format!("SELECT * FROM ( SELECT * FROM articles WHERE read = false AND published > $1 ORDER BY published {} LIMIT {} ) AS data ORDER BY published {}", Ordering::Ascending.to_string(), LIMIT_UPPER_BOUND, Ordering::Descending.to_string());
-        let prev = conn.query(prev_query.as_str(), &[&pagination]).await?;
+/// Waits for other transactions checking the quota of `resource` to finish, the lock is released
+/// when `tx` ends.
+async fn lock_quota(tx: &Transaction<'_>, resource: &str) -> Result<()> {
+    tx.execute(
+        "SELECT pg_advisory_xact_lock($1, hashtext($2))",
+        &[&QUOTA_LOCK, &resource],
+    )
+    .await?;
+    Ok(())
+}
 
-        Ok(Page::new(
-            next,
-            prev,
-            pagination,
-            PaginationField::Published,
-        ))
-    }
+/// How long a connection attempt may take before it counts as failed.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
-    pub(crate) async fn get_read_articles(&self, pagination: String) -> Result<Page> {
-        let conn = &mut self.client.lock().await;
+/// How long a connection may sit idle before TCP keepalives start checking on it.
+const KEEPALIVE_IDLE: std::time::Duration = std::time::Duration::from_secs(60);
 
-        let next_query = format!("SELECT * FROM articles WHERE read = true AND read_date < $1 ORDER BY read_date {} LIMIT {}", Ordering::Descending.to_string(), LIMIT_UPPER_BOUND);
-        let next = conn
-            .query(next_query.as_str(), &[&pagination.clone()])
-            .await?;
+/// How long a health check ping may take before the connection counts as gone.
+const HEALTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
-        let prev_query = format!("SELECT * FROM ( SELECT * FROM articles WHERE read = true AND read_date > $1 ORDER BY read_date {} LIMIT {} ) AS data ORDER BY read_date {}", Ordering::Ascending.to_string(), LIMIT_UPPER_BOUND, Ordering::Descending.to_string());
-        let prev = conn
-            .query(prev_query.as_str(), &[&pagination.clone()])
-            .await?;
+/// When the database is treated as down. After `threshold` queries in a row fail to reach
+/// postgres, or as soon as the connection drops, calls fail fast until a background probe manages to reconnect. Probes start
+/// `probe_interval` apart and back off up to `max_probe_interval`. Every `health_interval` an idle
+/// connection is pinged, so one that silently stopped answering is replaced too.
+#[derive(Clone, Copy)]
+pub struct Breaker {
+    pub threshold: u32,
+    pub probe_interval: std::time::Duration,
+    pub max_probe_interval: std::time::Duration,
+    /// `None` turns the health check off.
+    pub health_interval: Option<std::time::Duration>,
+}
 
-        Ok(Page::new(next, prev, pagination, PaginationField::ReadDate))
+impl Default for Breaker {
+    fn default() -> Self {
+        Breaker {
+            threshold: 3,
+            probe_interval: std::time::Duration::from_secs(1),
+            max_probe_interval: std::time::Duration::from_secs(60),
+            health_interval: Some(std::time::Duration::from_secs(30)),
+        }
     }
+}
 
-    pub(crate) async fn get_favorited_articles(&self, pagination: String) -> Result<Page> {
-        let conn = &mut self.client.lock().await;
-
-        let next_query = format!("SELECT * FROM articles WHERE favorited = true AND published < $1 ORDER BY published {} LIMIT {}", Ordering::Descending.to_string(), LIMIT_UPPER_BOUND);
-        let next = conn.query(next_query.as_str(), &[&pagination]).await?;
-
-        let prev_query = format!("SELECT * FROM ( SELECT * FROM articles WHERE favorited = true AND published > $1 ORDER BY published {} LIMIT {} ) AS data ORDER BY published {}", Ordering::Ascending.to_string(), LIMIT_UPPER_BOUND, Ordering::Descending.to_string());
-        let prev = conn.query(prev_query.as_str(), &[&pagination]).await?;
+/// A storage call turned away because the database is down.
+#[derive(Debug)]
+pub struct Unavailable;
 
-        Ok(Page::new(
-            next,
-            prev,
-            pagination,
-            PaginationField::Published,
-        ))
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "database is unavailable, reconnecting in the background")
     }
+}
 
-    pub(crate) async fn mark_article_read(&self, a: Article) -> Result<()> {
-        let conn = &mut self.client.lock().await;
-        let timestamp = match a.read {
-            true => "-1".to_string(),
-            false => Article::rfc3339_timestamp(),
-        };
+impl std::error::Error for Unavailable {}
 
-        let query = "UPDATE articles SET read = NOT read, read_date = $1 WHERE id = $2";
-        let tx = conn.transaction().await?;
-        tx.execute(query, &[&timestamp, &a.clone().id]).await?;
-        tx.commit().await?;
-        Ok(())
-    }
+/// A storage call that ran out of time waiting for the connection.
+#[derive(Debug)]
+pub struct DeadlineExceeded;
 
-    pub(crate) async fn mark_article_favorite(&self, id: String) -> Result<()> {
-        let conn = &mut self.client.lock().await;
-        let query = "UPDATE articles SET favorited = NOT favorited WHERE id = $1";
-        let tx = conn.transaction().await?;
-        tx.execute(query, &[&id]).await?;
-        tx.commit().await?;
-        Ok(())
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "timed out waiting for the database")
     }
+}
 
-    pub(crate) async fn filter(self, filter: Filter, pagination: String) -> Result<Page> {
-        match filter {
-            Filter::Unread => return self.get_unread_articles(pagination).await,
-            Filter::Favorite => return self.get_favorited_articles(pagination).await,
-            Filter::Read => return self.get_read_articles(pagination).await,
+impl std::error::Error for DeadlineExceeded {}
+
+/// Whether `e` says postgres could not be reached or dropped the connection, rather than that it
+/// answered and turned the query down.
+fn connection_error(e: &tokio_postgres::Error) -> bool {
+    if e.is_closed() {
+        return true;
+    }
+    match e.code() {
+        // connection exceptions, and the server shutting down or still starting up
+        Some(code) => {
+            code.code().starts_with("08") || ["57P01", "57P02", "57P03"].contains(&code.code())
         }
+        None => std::error::Error::source(e).is_some_and(|s| s.is::<std::io::Error>()),
     }
 }
 
-pub async fn connection(username: &str, password: &str, host: &str, port: u16) -> Result<Storage> {
-    let (client, connection) = Config::new()
-        .user(username)
-        .password(password)
-        .host(host)
-        .dbname("feedreader")
-        .port(port)
-        .connect(NoTls)
-        .await?;
+/// The locked connection of a [Storage]. Queries made through it tell the circuit whether
+/// postgres answered, and a connection that closed while it was held opens the circuit.
+struct Conn<'a> {
+    client: MutexGuard<'a, Client>,
+    storage: &'a Storage,
+}
 
-    tokio::spawn(async move {
-        if let Err(error) = connection.await {
-            eprintln!("Connection error: {}", error);
+impl Conn<'_> {
+    /// Counts a query that could not reach postgres against the circuit, any answer closes the
+    /// streak.
+    fn outcome<T>(
+        &self,
+        result: Result<T, tokio_postgres::Error>,
+    ) -> Result<T, tokio_postgres::Error> {
+        match &result {
+            Err(e) if connection_error(e) => {
+                if self.storage.circuit.failure() {
+                    self.storage.probe();
+                }
+            }
+            _ => self.storage.circuit.success(),
         }
-    });
+        result
+    }
 
-    Ok(Storage {
-        client: Arc::new(Mutex::new(client)),
-    })
+    async fn query<T>(
+        &self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, tokio_postgres::Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        self.outcome(self.client.query(statement, params).await)
+    }
+
+    async fn query_one<T>(
+        &self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Row, tokio_postgres::Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        self.outcome(self.client.query_one(statement, params).await)
+    }
+
+    async fn query_opt<T>(
+        &self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, tokio_postgres::Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        self.outcome(self.client.query_opt(statement, params).await)
+    }
+
+    async fn execute<T>(
+        &self,
+        statement: &T,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, tokio_postgres::Error>
+    where
+        T: ?Sized + ToStatement,
+    {
+        self.outcome(self.client.execute(statement, params).await)
+    }
+
+    async fn batch_execute(&self, query: &str) -> Result<(), tokio_postgres::Error> {
+        self.outcome(self.client.batch_execute(query).await)
+    }
+}
+
+impl std::ops::Deref for Conn<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl std::ops::DerefMut for Conn<'_> {
+    fn deref_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+}
+
+/// Transactions go to the client directly, one that lost the connection is caught here.
+impl Drop for Conn<'_> {
+    fn drop(&mut self) {
+        if self.client.is_closed() && self.storage.circuit.trip() {
+            self.storage.probe();
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    client: Arc<Mutex<Client>>,
+    quota: Quota,
+    page_size: usize,
+    /// How long each call may take, `None` for no limit.
+    timeout: Option<std::time::Duration>,
+    /// The statement_timeout the session has in milliseconds, zero for none. Only changed while
+    /// holding the client, so it is set again only when a storage with another timeout locks it.
+    statement_timeout: Arc<AtomicU64>,
+    config: Config,
+    breaker: Breaker,
+    circuit: Arc<Circuit>,
+    /// Bumped whenever the client is replaced, so the old connection closing is not taken for an outage.
+    generation: Arc<AtomicU64>,
+    /// Statements prepared on the current client by query text, emptied when the client is replaced.
+    statements: Arc<std::sync::Mutex<HashMap<String, Statement>>>,
+    /// Read-only endpoint that listings and search go to, if one is configured.
+    replica: Option<Arc<Storage>>,
+}
+
+impl Storage {
+    /// The same storage with `size` rows on a page unless a sort asks for another size.
+    pub fn with_page_size(&self, size: usize) -> Storage {
+        Storage {
+            page_size: size.clamp(1, MAX_PAGE_SIZE),
+            ..self.clone()
+        }
+    }
+
+    /// Rows on a page sorted by `sort`.
+    fn page_size(&self, sort: Sort) -> usize {
+        sort.page_size.unwrap_or(self.page_size)
+    }
+
+    /// The same storage with calls cut off after `timeout`, once while waiting for the connection
+    /// and again for each query, which postgres cancels via statement_timeout.
+    pub fn with_timeout(&self, timeout: Option<std::time::Duration>) -> Storage {
+        Storage {
+            timeout,
+            replica: self
+                .replica
+                .as_ref()
+                .map(|r| Arc::new(r.with_timeout(timeout))),
+            ..self.clone()
+        }
+    }
+
+    /// The same storage sending article and feed listings, search and counts to `replica`,
+    /// everything else still goes to this connection. Replicas lag behind, so what was just
+    /// written can take a moment to show up in listings.
+    pub fn with_replica(&self, replica: Storage) -> Storage {
+        Storage {
+            replica: Some(Arc::new(replica.with_timeout(self.timeout))),
+            ..self.clone()
+        }
+    }
+
+    /// Where read-only calls go, the replica when there is one.
+    fn reader(&self) -> &Storage {
+        self.replica.as_deref().unwrap_or(self)
+    }
+
+    /// Locks the connection and sets the session's statement_timeout to this storage's timeout
+    /// when it has another one. Fails fast while the circuit is open. Waiting for the lock does not count against the
+    /// circuit, a call stuck behind a long refresh says nothing about the database.
+    async fn conn(&self) -> Result<Conn<'_>> {
+        if self.circuit.is_open() {
+            return Err(Unavailable.into());
+        }
+        if let Err(e) = chaos::db().await {
+            if self.circuit.failure() {
+                self.probe();
+            }
+            return Err(e);
+        }
+
+        let client = match self.timeout {
+            None => self.client.lock().await,
+            Some(t) => tokio::time::timeout(t, self.client.lock())
+                .await
+                .map_err(|_| DeadlineExceeded)?,
+        };
+        let conn = Conn {
+            client,
+            storage: self,
+        };
+        if conn.is_closed() {
+            return Err(Unavailable.into());
+        }
+
+        let timeout = self.timeout.map_or(0, |t| t.as_millis().max(1) as u64);
+        if self.statement_timeout.load(atomic::Ordering::Relaxed) != timeout {
+            conn.batch_execute(format!("SET statement_timeout = {}", timeout).as_str())
+                .await?;
+            self.statement_timeout
+                .store(timeout, atomic::Ordering::Relaxed);
+        }
+        Ok(conn)
+    }
+
+    /// The statement for `query` on `client`, prepared on first use and reused after, which saves
+    /// a round trip for every later call. `client` must be the locked connection or a transaction
+    /// on it, that is what keeps the cache in step with the connection.
+    async fn statement<C>(&self, client: &C, query: &str) -> Result<Statement>
+    where
+        C: GenericClient + Sync,
+    {
+        let cached = self.statements.lock().unwrap().get(query).cloned();
+        if let Some(statement) = cached {
+            return Ok(statement);
+        }
+        let statement = client.prepare(query).await?;
+        let mut statements = self.statements.lock().unwrap();
+        if statements.len() >= STATEMENT_CACHE_SIZE {
+            statements.clear();
+        }
+        statements.insert(query.to_string(), statement.clone());
+        Ok(statement)
+    }
+
+    /// Reconnects in the background until postgres answers again, doubling the wait after every
+    /// failed attempt, then swaps in the new connection and closes the circuit.
+    fn probe(&self) {
+        println!("database circuit opened, reconnecting");
+        let storage = self.clone();
+        tokio::spawn(async move {
+            let mut wait = storage.breaker.probe_interval;
+            loop {
+                tokio::time::sleep(wait).await;
+                match storage.config.connect(NoTls).await {
+                    Ok((client, connection)) => {
+                        let mut conn = storage.client.lock().await;
+                        *conn = client;
+                        storage.statements.lock().unwrap().clear();
+                        let generation =
+                            storage.generation.fetch_add(1, atomic::Ordering::AcqRel) + 1;
+                        storage.drive(connection, generation);
+                        // a new session starts without a statement_timeout
+                        storage
+                            .statement_timeout
+                            .store(0, atomic::Ordering::Relaxed);
+                        storage.circuit.close();
+                        println!("database reconnected, circuit closed");
+                        return;
+                    }
+                    Err(e) => {
+                        wait = (wait * 2).min(storage.breaker.max_probe_interval);
+                        println!("database probe failed, retrying in {:?}: {}", wait, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Runs the connection in the background. When it ends while still the current one the
+    /// circuit opens right away and reconnection starts, instead of waiting for a call to notice.
+    fn drive(&self, connection: Connection<Socket, NoTlsStream>, generation: u64) {
+        let storage = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                eprintln!("Connection error: {}", error);
+            }
+            if storage.generation.load(atomic::Ordering::Acquire) == generation
+                && storage.circuit.trip()
+            {
+                storage.probe();
+            }
+        });
+    }
+
+    /// Pings the connection every `interval` while it is idle and the circuit is closed. A ping
+    /// that fails or takes longer than `HEALTH_TIMEOUT` opens the circuit and starts reconnecting.
+    fn watch(&self, interval: std::time::Duration) {
+        let storage = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if storage.circuit.is_open() {
+                    continue;
+                }
+                // A connection that is in use gets checked by whoever is using it.
+                let conn = match storage.client.try_lock() {
+                    Some(conn) => conn,
+                    None => continue,
+                };
+                let ping = conn.simple_query("SELECT 1");
+                let error = match tokio::time::timeout(HEALTH_TIMEOUT, ping).await {
+                    Ok(Ok(_)) => continue,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => format!("no answer within {:?}", HEALTH_TIMEOUT),
+                };
+                drop(conn);
+                println!("database health check failed: {}", error);
+                if storage.circuit.trip() {
+                    storage.probe();
+                }
+            }
+        });
+    }
+
+    /// Applies the pending migrations like [Store::migrate], for callers outside the crate.
+    pub async fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        let conn = &mut self.conn().await?;
+        conn.batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied TEXT NOT NULL)",
+        )
+        .await?;
+
+        let tx = conn.transaction().await?;
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&migrate::LOCK])
+            .await?;
+        let applied: BTreeSet<i32> = tx
+            .query("SELECT version FROM schema_migrations", &[])
+            .await?
+            .iter()
+            .map(|r| r.get(0))
+            .collect();
+
+        let pending = migrate::pending(&applied);
+        for m in &pending {
+            tx.batch_execute(m.sql).await?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, name, applied) VALUES ($1, $2, $3)",
+                &[&m.version, &m.name, &Article::rfc3339_timestamp()],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(pending)
+    }
+
+    /// Stores new articles and returns the ones that were actually inserted. Articles already
+    /// stored under their link are left alone unless their content hash or author changed, then
+    /// they are updated in place and marked edited without counting them as new.
+    pub async fn add_articles<T>(&self, articles: T) -> Result<Vec<Article>>
+    where
+        T: Iterator<Item = Article>,
+    {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let added = self.insert_articles(&tx, articles).await?;
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    /// Stores new articles like [Storage::add_articles] inside a transaction the caller commits.
+    async fn insert_articles<T>(&self, tx: &Transaction<'_>, articles: T) -> Result<Vec<Article>>
+    where
+        T: Iterator<Item = Article>,
+    {
+        let filters = self.statement(tx, "SELECT * FROM filters").await?;
+        let mutes = tx
+            .query(&filters, &[])
+            .await?
+            .iter()
+            .map(|r| r.into())
+            .collect::<Vec<Mute>>();
+        let mutes = Mutes::new(&mutes)?;
+
+        // a link or guid listed twice is taken as it first appears
+        let mut links = HashSet::new();
+        let mut ids = HashSet::new();
+        let articles: Vec<Article> = articles
+            .filter(|a| links.insert(a.link.clone()) && ids.insert(a.id.clone()))
+            .collect();
+
+        // archived articles are not stored again, nor updated since they were read long ago
+        let links: Vec<&str> = articles.iter().map(|a| a.link.as_str()).collect();
+        let ids: Vec<&str> = articles.iter().map(|a| a.id.as_str()).collect();
+        let query = format!(
+            "SELECT link, id FROM {} WHERE link = ANY($1) OR id = ANY($2)",
+            schema::ARCHIVE
+        );
+        let archived = self.statement(tx, &query).await?;
+        let archived: HashSet<String> = tx
+            .query(&archived, &[&links, &ids])
+            .await?
+            .iter()
+            .flat_map(|r| [r.get(0), r.get(1)])
+            .collect();
+        let articles: Vec<Article> = articles
+            .into_iter()
+            .filter(|a| !archived.contains(&a.link) && !archived.contains(&a.id))
+            .collect();
+
+        // an article is already stored under its link or, when its feed changes links between
+        // fetches, under the id taken from its guid
+        let links: Vec<&str> = articles.iter().map(|a| a.link.as_str()).collect();
+        let ids: Vec<&str> = articles.iter().map(|a| a.id.as_str()).collect();
+        let lookup = self
+            .statement(
+                tx,
+                "SELECT link, id, content_hash, author FROM articles WHERE link = ANY($1) OR id = ANY($2)",
+            )
+            .await?;
+        let rows = tx.query(&lookup, &[&links, &ids]).await?;
+        let by_link: HashMap<String, &Row> = rows.iter().map(|r| (r.get(0), r)).collect();
+        let by_id: HashMap<String, &Row> = rows.iter().map(|r| (r.get(1), r)).collect();
+        let mut stored_articles = vec![];
+        let mut new = vec![];
+        for article in articles {
+            let row = by_link
+                .get(&article.link)
+                .or_else(|| by_id.get(&article.id));
+            match row {
+                Some(row) => stored_articles.push((
+                    row.get::<_, String>(1),
+                    row.get::<_, String>(2),
+                    row.get::<_, String>(3),
+                    article,
+                )),
+                None => new.push(article),
+            }
+        }
+
+        let mut rehashed = (vec![], vec![]);
+        let mut changed = vec![];
+        for (id, hash, author, article) in &stored_articles {
+            match hash.as_str() {
+                h if h == article.content_hash && *author == article.author => {}
+                // stored before hashes were, its content may have been stripped since so only
+                // the hash is filled in
+                "" if *author == article.author => {
+                    rehashed.0.push(id.as_str());
+                    rehashed.1.push(article.content_hash.as_str());
+                }
+                _ => changed.push((id.as_str(), article)),
+            }
+        }
+        if !rehashed.0.is_empty() {
+            let rehash = self
+                .statement(tx, "UPDATE articles SET content_hash = u.content_hash FROM unnest($1::TEXT[], $2::TEXT[]) AS u(id, content_hash) WHERE articles.id = u.id")
+                .await?;
+            tx.execute(&rehash, &[&rehashed.0, &rehashed.1]).await?;
+        }
+        if !changed.is_empty() {
+            let column = |f: fn(&Article) -> &str| -> Vec<&str> {
+                changed.iter().map(|(_, a)| f(a)).collect()
+            };
+            let ids: Vec<&str> = changed.iter().map(|(id, _)| *id).collect();
+            let word_counts: Vec<i32> = changed.iter().map(|(_, a)| a.word_count).collect();
+            let reading_minutes: Vec<i32> =
+                changed.iter().map(|(_, a)| a.reading_minutes).collect();
+            let raws: Vec<Json<&Raw>> = changed.iter().map(|(_, a)| Json(&a.raw)).collect();
+            let update = self
+                .statement(
+                    tx,
+                    "UPDATE articles SET title = u.title, author = u.author, summary = u.summary, content = u.content, word_count = u.word_count, reading_minutes = u.reading_minutes, fingerprint = u.fingerprint, raw = u.raw, language = u.language, content_hash = u.content_hash, updated_at = now()
+FROM unnest($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::INTEGER[], $7::INTEGER[], $8::TEXT[], $9::JSONB[], $10::TEXT[], $11::TEXT[])
+AS u(id, title, author, summary, content, word_count, reading_minutes, fingerprint, raw, language, content_hash)
+WHERE articles.id = u.id",
+                )
+                .await?;
+            tx.execute(
+                &update,
+                &[
+                    &ids,
+                    &column(|a| &a.title),
+                    &column(|a| &a.author),
+                    &column(|a| &a.summary),
+                    &column(|a| &a.content),
+                    &word_counts,
+                    &reading_minutes,
+                    &column(|a| &a.fingerprint),
+                    &raws,
+                    &column(|a| &a.language),
+                    &column(|a| &a.content_hash),
+                ],
+            )
+            .await?;
+        }
+
+        // the same story syndicated by another feed around the same time is attributed to the
+        // existing row instead of stored twice, a feed repeating a title is left alone
+        let column = |f: fn(&Article) -> &str| -> Vec<&str> { new.iter().map(f).collect() };
+        let feed_ids: Vec<Option<&str>> = new.iter().map(feed_id).collect();
+        let published: Vec<DateTime<Utc>> = new.iter().map(|a| dates(a).0).collect();
+        let lookup = self
+            .statement(
+                tx,
+                "SELECT u.n, d.id FROM unnest($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TIMESTAMPTZ[]) WITH ORDINALITY AS u(link, canonical_link, fingerprint, feed_id, published, n)
+CROSS JOIN LATERAL (SELECT id FROM articles WHERE link = u.link OR ((canonical_link = u.canonical_link OR (fingerprint <> '' AND fingerprint = u.fingerprint)) AND feed_id IS DISTINCT FROM u.feed_id AND published BETWEEN u.published - make_interval(days => $6) AND u.published + make_interval(days => $6)) LIMIT 1) AS d",
+            )
+            .await?;
+        let duplicates: HashMap<usize, String> = tx
+            .query(
+                &lookup,
+                &[
+                    &column(|a| &a.link),
+                    &column(|a| &a.canonical_link),
+                    &column(|a| &a.fingerprint),
+                    &feed_ids,
+                    &published,
+                    &(DUPLICATE_WINDOW_DAYS as i32),
+                ],
+            )
+            .await?
+            .iter()
+            .map(|r| (r.get::<_, i64>(0) as usize - 1, r.get(1)))
+            .collect();
+
+        // duplicates among the new articles are attributed to the first of them
+        let mut attributed = (vec![], vec![]);
+        let mut earlier: Vec<&Article> = vec![];
+        let mut pending = vec![];
+        for (i, mut article) in new.iter().cloned().enumerate() {
+            let syndicated = earlier
+                .iter()
+                .find(|e| syndicated(e, &article))
+                .map(|e| &e.id);
+            if let Some(id) = duplicates.get(&i).or(syndicated) {
+                attributed.0.push(id.clone());
+                attributed.1.push(article.feed_id);
+                continue;
+            }
+            earlier.push(&new[i]);
+
+            mutes.apply(&mut article);
+            pending.push(article);
+        }
+
+        // over the quota a refresh still updates what is stored, it only stops adding to it
+        if let Some(limit) = self.quota.max_articles {
+            lock_quota(tx, "articles").await?;
+            let count = self.statement(tx, "SELECT COUNT(*) FROM articles").await?;
+            let count: i64 = tx.query_one(&count, &[]).await?.get(0);
+            let allowed = (limit - count).max(0) as usize;
+            if pending.len() > allowed {
+                println!(
+                    "articles quota of {} reached, skipping {} new articles",
+                    limit,
+                    pending.len() - allowed
+                );
+                pending.truncate(allowed);
+            }
+        }
+
+        let mut added = vec![];
+        while !pending.is_empty() {
+            let batch: Vec<Article> = pending.drain(..pending.len().min(INSERT_BATCH)).collect();
+            let dates: Vec<_> = batch.iter().map(dates).collect();
+            let feed_ids: Vec<Option<&str>> = batch.iter().map(feed_id).collect();
+            let raws: Vec<Json<&Raw>> = batch.iter().map(|a| Json(&a.raw)).collect();
+
+            let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+            for (i, article) in batch.iter().enumerate() {
+                let (published, date_added, read_date) = &dates[i];
+                params.extend([
+                    &article.id as &(dyn ToSql + Sync),
+                    &article.feed,
+                    &article.title,
+                    &article.link,
+                    &article.author,
+                    published,
+                    &article.read,
+                    &article.favorited,
+                    read_date,
+                    &article.summary,
+                    &article.content,
+                    &article.word_count,
+                    &article.reading_minutes,
+                    &article.canonical_link,
+                    &article.fingerprint,
+                    date_added,
+                    &article.tags,
+                    &article.hidden,
+                    &raws[i],
+                    &article.language,
+                    &article.content_hash,
+                    &feed_ids[i],
+                ]);
+            }
+            // not cached, a statement per batch size would crowd the others out of the cache
+            let query = format!("INSERT INTO articles (id, feed, title, link, author, published, read, favorited, read_date, summary, content, word_count, reading_minutes, canonical_link, fingerprint, date_added, tags, hidden, raw, language, content_hash, feed_id) VALUES {} ON CONFLICT (link) DO UPDATE SET {} RETURNING id, updated_at IS NULL", values(batch.len(), 22), UPSERT);
+            // articles stored meanwhile under the same link come back edited rather than inserted
+            let inserted: HashSet<String> = tx
+                .query(query.as_str(), &params)
+                .await?
+                .iter()
+                .filter(|r| r.get(1))
+                .map(|r| r.get(0))
+                .collect();
+            added.extend(batch.into_iter().filter(|a| inserted.contains(&a.id)));
+        }
+
+        let enclosures: Vec<(&str, &Enclosure)> = added
+            .iter()
+            .flat_map(|a| a.enclosures.iter().map(|e| (a.id.as_str(), e)))
+            .collect();
+        if !enclosures.is_empty() {
+            let ids: Vec<&str> = enclosures.iter().map(|(id, _)| *id).collect();
+            let urls: Vec<&str> = enclosures.iter().map(|(_, e)| e.url.as_str()).collect();
+            let mimes: Vec<&str> = enclosures.iter().map(|(_, e)| e.mime.as_str()).collect();
+            let lengths: Vec<i64> = enclosures.iter().map(|(_, e)| e.length).collect();
+            let durations: Vec<i32> = enclosures.iter().map(|(_, e)| e.duration).collect();
+            let insert = self
+                .statement(
+                    tx,
+                    "INSERT INTO enclosures (article, url, mime, length, duration) SELECT * FROM unnest($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::BIGINT[], $5::INTEGER[]) ON CONFLICT DO NOTHING",
+                )
+                .await?;
+            tx.execute(&insert, &[&ids, &urls, &mimes, &lengths, &durations])
+                .await?;
+        }
+
+        // after the inserts, so duplicates of articles stored just now are attributed as well
+        if !attributed.0.is_empty() {
+            let attribute = self
+                .statement(
+                    tx,
+                    "UPDATE articles SET sources = articles.sources || a.feeds
+FROM (SELECT u.id, array_agg(DISTINCT u.feed) AS feeds FROM unnest($1::TEXT[], $2::TEXT[]) AS u(id, feed)
+JOIN articles ON articles.id = u.id WHERE articles.feed_id IS DISTINCT FROM u.feed AND NOT (u.feed = ANY(articles.sources)) GROUP BY u.id) AS a
+WHERE articles.id = a.id",
+                )
+                .await?;
+            tx.execute(&attribute, &[&attributed.0, &attributed.1])
+                .await?;
+        }
+
+        Ok(added)
+    }
+
+    /// Pages through articles matching `condition` in the order given by `sort`,
+    /// starting after the `pagination` cursor. The cursor is `$1` and `$2`, any `params` the
+    /// condition uses follow it.
+    async fn articles_page(
+        &self,
+        from: &str,
+        condition: &str,
+        params: &[&(dyn ToSql + Sync)],
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        if let SortKey::Score = sort.key {
+            return Err(anyhow::Error::msg(
+                "only unread articles can be sorted by score",
+            ));
+        }
+        let conn = &mut self.reader().conn().await?;
+        let column = sort.key.column();
+        let limit = self.page_size(sort);
+
+        // numbered pages skip ahead from where the sort starts
+        if let Some(number) = sort.page {
+            let (at, id): (Option<DateTime<Utc>>, Option<String>) = (None, None);
+            let params: Vec<&(dyn ToSql + Sync)> = [&at as &(dyn ToSql + Sync), &id]
+                .into_iter()
+                .chain(params.iter().copied())
+                .collect();
+            let matching = keyset_condition(condition, column, sort.ordering.after());
+            let query = format!(
+                "SELECT * FROM {} WHERE {} ORDER BY {} {}, id {} LIMIT {} OFFSET {}",
+                from,
+                matching,
+                column,
+                sort.ordering,
+                sort.ordering,
+                limit,
+                (number - 1) * limit
+            );
+            let count_query = format!("SELECT COUNT(*) FROM {} WHERE {}", from, matching);
+            let items = conn.query(query.as_str(), &params).await?;
+            let total: i64 = conn.query_one(count_query.as_str(), &params).await?.get(0);
+            let page = Page::numbered(items, pagination, number, limit, total as usize);
+            return article_page(conn, page).await;
+        }
+
+        let page = keyset_page(
+            conn,
+            from,
+            condition,
+            params,
+            column,
+            sort.ordering,
+            limit,
+            pagination,
+        )
+        .await?;
+        article_page(conn, page).await
+    }
+
+    /// Visible articles matching a web style query, best match first, see [Store::search_articles].
+    pub async fn search_articles(
+        &self,
+        query: String,
+        language: Language,
+    ) -> Result<Page<Article>> {
+        let conn = &mut self.reader().conn().await?;
+        let search = format!(
+            r#"SELECT articles.* FROM {}
+LEFT JOIN feeds ON feeds.id = articles.feed_id,
+LATERAL (SELECT websearch_to_tsquery(COALESCE(NULLIF(feeds.search_language, ''), $2)::regconfig, $1) AS query) AS s
+WHERE articles.hidden = false AND articles.search_vector @@ s.query
+ORDER BY ts_rank(articles.search_vector, s.query) DESC, articles.published DESC
+LIMIT {}"#,
+            ARTICLES, SEARCH_LIMIT
+        );
+        let items = conn
+            .query(search.as_str(), &[&query, &language.to_string()])
+            .await?;
+        Ok(Page::all(articles(conn, items).await?))
+    }
+
+    /// Pinned articles are kept out of the pages and returned separately so they always lead the list.
+    pub async fn get_unread_articles(
+        &self,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let condition = format!("{} AND pinned = false", Filter::Unread.condition());
+        let mut page = match sort.key {
+            SortKey::Score => {
+                self.scored_page(condition.as_str(), sort, pagination)
+                    .await?
+            }
+            _ => {
+                self.articles_page(ARTICLES, condition.as_str(), &[], sort, pagination)
+                    .await?
+            }
+        };
+
+        let conn = &mut self.reader().conn().await?;
+        let query = format!(
+            "SELECT * FROM {} WHERE {} AND pinned = true ORDER BY published DESC",
+            ARTICLES,
+            Filter::Unread.condition()
+        );
+        let pinned = conn.query(query.as_str(), &[]).await?;
+        page.pinned = articles(conn, pinned).await?;
+        Ok(page)
+    }
+
+    /// Pages through articles matching `condition` ranked by score. Scores depend on everything
+    /// read so far, so every matching row is scored on each request and `pagination` is an offset.
+    async fn scored_page(
+        &self,
+        condition: &str,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let conn = &mut self.reader().conn().await?;
+        let mut signals = Signals::default();
+        for row in conn
+            .query(
+                format!("SELECT feed_name, COUNT(*) FILTER (WHERE read = true), COUNT(*) FROM {} GROUP BY feed_name", ARTICLES).as_str(),
+                &[],
+            )
+            .await?
+        {
+            let (read, total): (i64, i64) = (row.get(1), row.get(2));
+            signals
+                .read_ratio
+                .insert(row.get(0), read as f64 / total as f64);
+        }
+        for row in conn
+            .query(
+                "SELECT title, tags FROM articles WHERE favorited = true",
+                &[],
+            )
+            .await?
+        {
+            signals.favorite(row.get(0), &row.get::<_, Vec<String>>(1));
+        }
+
+        let query = format!(
+            "SELECT * FROM {} WHERE {} ORDER BY published DESC",
+            ARTICLES, condition
+        );
+        let now = Utc::now();
+        let scored: Vec<(f64, Row)> = conn
+            .query(query.as_str(), &[])
+            .await?
+            .into_iter()
+            .map(|r| {
+                let score = signals.score(
+                    r.get("feed_name"),
+                    r.get(2),
+                    &r.get::<_, Vec<String>>(17),
+                    r.get(5),
+                    now,
+                );
+                (score, r)
+            })
+            .collect();
+        let page = ranked(scored, sort, self.page_size(sort), pagination)?;
+        article_page(conn, page).await
+    }
+
+    async fn export_batch(
+        &self,
+        select: &str,
+        q: &ArticleQuery,
+        after: &Option<(DateTime<Utc>, String)>,
+    ) -> Result<Vec<Row>> {
+        let (mut conditions, mut params) = q.conditions(1);
+        if let Some((published, id)) = after {
+            conditions.push(format!(
+                "(published, id) < (${}, ${})",
+                params.len() + 1,
+                params.len() + 2
+            ));
+            params.extend([published as &(dyn ToSql + Sync), id]);
+        }
+        let mut query = select.to_string();
+        if !conditions.is_empty() {
+            query = format!("{} WHERE {}", query, conditions.join(" AND "));
+        }
+        query = format!(
+            "{} ORDER BY published {}, id {} LIMIT {}",
+            query,
+            Ordering::Descending,
+            Ordering::Descending,
+            EXPORT_BATCH
+        );
+
+        let conn = self.conn().await?;
+        Ok(conn.query(query.as_str(), &params).await?)
+    }
+}
+
+/// What the route handlers and background jobs keep their data in. [Storage] keeps it in
+/// postgres, [crate::memory::Memory] in memory for trying things out and testing without a database.
+#[async_trait]
+pub(crate) trait Store: Send + Sync {
+    /// Applies the migrations this database has not had yet, in order and all in one
+    /// transaction, returning them. An advisory lock keeps instances started together from
+    /// applying the same migration twice.
+    async fn migrate(&self) -> Result<Vec<&'static Migration>>;
+
+    /// The versions of the migrations applied to this database, none when it was never migrated.
+    async fn applied_migrations(&self) -> Result<BTreeSet<i32>>;
+
+    /// Looks up the tables, columns and unique indexes in the current schema and reports
+    /// anything the queries rely on that is missing or has drifted.
+    async fn check_schema(&self) -> Result<Vec<Discrepancy>>;
+
+    async fn add_feed(&self, f: AddFeed) -> Result<Feed>;
+
+    /// Adds every feed not already subscribed to in one transaction, returning the ones added.
+    /// Feeds that were unsubscribed from are skipped too unless `resubscribe` is set, which
+    /// forgets they were. The whole import fails when it would go over the feed quota.
+    async fn add_feeds(&self, feeds: Vec<AddFeed>, resubscribe: bool) -> Result<Vec<Feed>>;
+
+    /// An empty language makes the feed use the configured default again.
+    async fn update_feed_search_language(
+        &self,
+        id: String,
+        language: Option<Language>,
+    ) -> Result<()>;
+
+    /// Makes `language` the one articles of feeds without their own are stemmed with when stored,
+    /// stemming those already stored again when it changed.
+    async fn set_search_language(&self, language: Language) -> Result<()>;
+
+    async fn toggle_feed_keep_content(&self, id: String) -> Result<()>;
+
+    async fn update_feed_refresh_interval(&self, id: String, seconds: i32) -> Result<()>;
+
+    /// Feeds with push on send their new articles to subscribed browsers.
+    async fn toggle_feed_push(&self, id: String) -> Result<()>;
+
+    async fn get_feed_by_id(&self, id: String) -> Result<Feed>;
+
+    /// Feeds most recently added first.
+    async fn get_feeds(&self, pagination: String) -> Result<Page<Feed>>;
+
+    /// Unsubscribes from a feed, leaving a tombstone so imports do not quietly add it back.
+    async fn delete_feed(&self, id: String) -> Result<()>;
+
+    /// Keeps the feed from being fetched before `until`, by the scheduler or by hand.
+    async fn update_feed_retry_after(&self, id: String, until: String) -> Result<()>;
+
+    /// Stores everything a successful refresh of feed `id` changed in one transaction, so the
+    /// feed never looks refreshed later than its newest stored article. Returns the articles that
+    /// were new and how many were backfilled from its history.
+    async fn apply_refresh(
+        &self,
+        id: String,
+        refreshed: Refreshed,
+    ) -> Result<(Vec<Article>, usize)>;
+
+    /// Records why the last refresh of a feed failed, an empty error once one succeeds again.
+    async fn update_feed_fetch_error(&self, id: String, error: String) -> Result<()>;
+
+    /// Counts a failed refresh and marks the feed erroring once `max` happened in a row. Returns
+    /// true when this failure is the one that did.
+    async fn add_feed_failure(&self, id: String, max: i32) -> Result<bool>;
+
+    /// Puts a feed marked erroring back on the schedule, due right away.
+    async fn enable_feed(&self, id: String) -> Result<()>;
+
+    /// Feeds with at least `min_articles` articles added since `since` of which at least
+    /// `min_unread_ratio` are still unread, worst first.
+    async fn get_unread_suggestions(
+        &self,
+        since: DateTime<Utc>,
+        min_articles: i64,
+        min_unread_ratio: f64,
+    ) -> Result<Vec<Suggestion>>;
+
+    async fn get_feed_domains(&self) -> Result<Vec<Domain>>;
+
+    async fn get_feeds_by_domain(&self, domain: String) -> Result<Vec<Feed>>;
+
+    /// Muting a domain stops polling its feeds and marks what they have left unread as read.
+    /// Unmuting resumes polling on the next refresh.
+    async fn mute_domain(&self, domain: String, muted: bool) -> Result<()>;
+
+    /// Feeds whose next scheduled poll is at or before `now`. Feeds that were never polled are always due.
+    async fn get_due_feeds(&self, now: String) -> Result<Vec<Feed>>;
+
+    async fn update_feed_schedule(
+        &self,
+        id: String,
+        poll_interval: i32,
+        next_poll: String,
+    ) -> Result<()>;
+
+    /// Publish dates of a feed's most recent articles, newest first.
+    async fn get_feed_publish_history(&self, feed: String, limit: i64) -> Result<Vec<String>>;
+
+    /// Counts unread and favorited articles, and the articles read since `today`.
+    async fn get_counts(&self, today: String) -> Result<Counts>;
+
+    /// Unread articles by feed id, for the feeds that have any. Kept up to date by triggers on
+    /// articles rather than counted here, articles whose feed was deleted are under "".
+    async fn get_unread_counts(&self) -> Result<BTreeMap<String, i64>>;
+
+    /// Corrects the unread counts where they drifted from the articles, returning for how many
+    /// feeds they had. Articles cannot change meanwhile, their triggers wait on the lock.
+    async fn recount_unread(&self) -> Result<u64>;
+
+    /// Makes the space of deleted rows in `table` reusable, so a table that is pruned regularly
+    /// stops growing.
+    async fn vacuum(&self, table: &str) -> Result<()>;
+
+    /// Updates the statistics the query planner picks plans by.
+    async fn analyze(&self) -> Result<()>;
+
+    /// Marks unread articles read once they are older than the expiry age, or once a feed has
+    /// more unread articles than the expiry allows, oldest first. Hidden, pinned and favorited
+    /// articles are neither expired nor counted against their feed. Returns how many were marked.
+    async fn auto_expire_unread(&self, expiry: Expiry, now: DateTime<Utc>) -> Result<u64>;
+
+    /// Empties the content and drops the image enclosures of articles read longer ago than the
+    /// retention allows, keeping the rows themselves so history, search and dedup still work.
+    /// Favorites, pinned articles and feeds set to keep content are left alone. Returns how many
+    /// articles were stripped.
+    async fn strip_read_content(&self, retention: Retention, now: DateTime<Utc>) -> Result<u64>;
+
+    /// Moves articles read longer ago than the retention allows to the archive, where the history
+    /// still finds them. Favorites, pinned and hidden articles stay. Returns how many were moved.
+    async fn archive_read_articles(&self, retention: Retention, now: DateTime<Utc>) -> Result<u64>;
+
+    /// The most recently stored articles, newest first.
+    async fn get_recent_articles(&self, limit: i64) -> Result<Vec<Article>>;
+
+    /// Returns which of the given links are already stored.
+    async fn get_known_links(&self, links: Vec<String>) -> Result<Vec<String>>;
+
+    async fn get_article_by_id(&self, id: String) -> Result<Article>;
+
+    /// Pinned articles are kept out of the pages and returned separately so they always lead the list.
+    async fn get_unread_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>>;
+
+    /// Visible articles whose title or summary match a web style query (quoted phrases, `or`, `-word`),
+    /// best match first. Articles are stemmed when stored, the query is stemmed alike with the feed's
+    /// search language, or `language` when the feed has none.
+    async fn search_articles(&self, query: String, language: Language) -> Result<Page<Article>>;
+
+    /// Visible articles with a title close to `query`, closest first, for searches that found
+    /// nothing word for word. With pg_trgm installed titles match despite typos and partial
+    /// words, without it they have to contain `query` as it is.
+    async fn fuzzy_search_titles(&self, query: String) -> Result<Page<Article>>;
+
+    /// A random handful of unread or favorited articles published before `before`. Older articles
+    /// are likelier to be picked, each weighted by how many newer ones are ahead of it.
+    async fn sample_articles(&self, before: DateTime<Utc>) -> Result<Page<Article>>;
+
+    /// Articles with an audio enclosure, played or not, newest first.
+    async fn get_podcast_episodes(&self, pagination: String) -> Result<Page<Article>>;
+
+    async fn get_read_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>>;
+
+    /// Articles dismissed by hand or by a hide filter, read or not.
+    async fn get_hidden_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>>;
+
+    async fn get_favorited_articles(&self, sort: Sort, pagination: String)
+        -> Result<Page<Article>>;
+
+    /// Every article `q` selects, newest first, as the values of `columns`.
+    fn stream_articles(
+        &self,
+        columns: &[Column],
+        q: ArticleQuery,
+    ) -> BoxStream<'static, Result<Vec<Value>>>;
+
+    /// Pages through the articles a saved view selects.
+    async fn get_view_articles(
+        &self,
+        q: &ArticleQuery,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>>;
+
+    async fn mark_article_read(&self, a: Article) -> Result<()>;
+
+    /// Hidden articles drop out of every list except the hidden one without being marked read.
+    async fn mark_article_hidden(&self, id: String) -> Result<()>;
+
+    async fn mark_article_pinned(&self, id: String) -> Result<()>;
+
+    async fn mark_feed_read(&self, feed: String) -> Result<()>;
+
+    async fn update_article_note(&self, id: String, note: String) -> Result<()>;
+
+    /// Remembers the paragraph the reader got to, counted from the top of the article body.
+    async fn update_article_progress(&self, id: String, progress: i32) -> Result<()>;
+
+    async fn mark_article_favorite(&self, id: String) -> Result<()>;
+
+    /// Applies one action to every listed article in a single transaction, returning how many changed.
+    async fn bulk_update_articles(&self, ids: Vec<String>, action: BulkAction) -> Result<u64>;
+
+    /// When a feed was last refreshed, for Fever clients to tell whether syncing is worth it.
+    async fn get_last_refreshed(&self) -> Result<Option<DateTime<Utc>>>;
+
+    /// Every feed by its number instead of its id, as the Fever api lists them.
+    async fn get_fever_feeds(&self) -> Result<Vec<fever::Feed>>;
+
+    /// Up to `limit` articles by their number, archived ones included. Hidden articles are only
+    /// returned when asked for by number.
+    async fn get_fever_items(&self, items: fever::Items, limit: i64) -> Result<Vec<fever::Item>>;
+
+    /// How many articles there are to sync, archived ones included and hidden ones left out.
+    async fn count_fever_items(&self) -> Result<i64>;
+
+    /// Numbers of the unread articles, or of the favorites when `saved`. Neither are ever
+    /// archived, so the archive is left out.
+    async fn get_fever_item_ids(&self, saved: bool) -> Result<Vec<i64>>;
+
+    /// The ids of the articles numbered `seqs`, archived or not. Numbers no article has are
+    /// left out.
+    async fn get_article_ids_by_seq(&self, seqs: Vec<i64>) -> Result<Vec<String>>;
+
+    /// Marks the unread articles stored before `before` read, those of the feed numbered `feed`
+    /// or of every feed when it is none, and only those tagged `tag` if there is one. Returns
+    /// how many changed.
+    async fn mark_read_before(
+        &self,
+        feed: Option<i64>,
+        tag: Option<String>,
+        before: DateTime<Utc>,
+    ) -> Result<u64>;
+
+    /// Every feed by its number, as Google Reader clients list subscriptions.
+    async fn get_greader_subscriptions(&self) -> Result<Vec<greader::Subscription>>;
+
+    /// Unread articles of the feeds that have any, by feed number.
+    async fn get_greader_unread_counts(&self) -> Result<Vec<greader::UnreadCount>>;
+
+    /// A page of the stream `s` selects, archived articles included and hidden ones left out.
+    async fn get_greader_items(&self, s: &greader::Selection) -> Result<Vec<greader::Item>>;
+
+    /// The articles numbered `seqs`, whatever state they are in.
+    async fn get_greader_items_by_seq(&self, seqs: Vec<i64>) -> Result<Vec<greader::Item>>;
+
+    /// Lists every tag with the number of articles carrying it.
+    async fn get_tags(&self) -> Result<Vec<Count>>;
+
+    /// Renames a tag on every article. Renaming onto a tag that is already in use is refused,
+    /// that is what [Store::merge_tag] is for.
+    async fn rename_tag(&self, tag: String, name: String) -> Result<()>;
+
+    /// Moves every article tagged `tag` over to `into`, without tagging an article twice.
+    async fn merge_tag(&self, tag: String, into: String) -> Result<()>;
+
+    /// Detaches a tag from every article.
+    async fn delete_tag(&self, tag: String) -> Result<()>;
+
+    async fn get_filters(&self) -> Result<Vec<Mute>>;
+
+    async fn add_filter(&self, m: Mute) -> Result<()>;
+
+    /// Replaces the rule stored under `id`. The rule keeps its id even when its pattern changes.
+    async fn update_filter(&self, id: String, m: Mute) -> Result<()>;
+
+    async fn delete_filter(&self, id: String) -> Result<()>;
+
+    async fn get_subscriptions(&self) -> Result<Vec<Subscription>>;
+
+    async fn add_subscription(&self, s: Subscription) -> Result<()>;
+
+    #[cfg(feature = "push")]
+    async fn get_push_subscriptions(&self) -> Result<Vec<PushSubscription>>;
+
+    /// Subscribing the same browser again replaces its keys.
+    async fn add_push_subscription(&self, s: PushSubscription) -> Result<()>;
+
+    async fn delete_push_subscription(&self, endpoint: String) -> Result<()>;
+
+    async fn get_views(&self) -> Result<Vec<View>>;
+
+    async fn get_view(&self, id: String) -> Result<View>;
+
+    async fn add_view(&self, v: View) -> Result<()>;
+
+    async fn delete_view(&self, id: String) -> Result<()>;
+
+    async fn get_scrape_rules(&self) -> Result<Vec<Rule>>;
+
+    async fn get_scrape_rule(&self, feed: String) -> Result<Option<Rule>>;
+
+    /// Saving a rule for a feed that has one replaces it.
+    async fn put_scrape_rule(&self, r: &Rule) -> Result<()>;
+
+    async fn delete_scrape_rule(&self, feed: String) -> Result<()>;
+
+    /// Records a blob written to the blob store. Storing the same bytes again keeps the first record.
+    async fn add_blob(&self, b: Blob) -> Result<()>;
+
+    /// Urls of image enclosures that have not been copied into the blob store, newest first.
+    async fn get_uncached_images(&self, limit: i64) -> Result<Vec<String>>;
+
+    /// Points every enclosure of `url` at its copy in the blob store.
+    async fn set_enclosure_blob(&self, url: String, hash: String) -> Result<()>;
+
+    async fn get_blob(&self, hash: String) -> Result<Option<Blob>>;
+
+    /// Blobs added before `before` that no table in `blob::REFERENCES` points to.
+    async fn get_orphaned_blobs(&self, before: String) -> Result<Vec<String>>;
+
+    /// The stored copy of an image the proxy fetched before, noting that it was asked for at `now`.
+    async fn get_image(&self, url: String, now: String) -> Result<Option<Blob>>;
+
+    async fn add_image(&self, url: String, hash: String, now: String) -> Result<()>;
+
+    /// Adds the jobs whose feed has no job queued or running yet, returning those added.
+    async fn add_jobs(&self, jobs: Vec<Job>) -> Result<Vec<Job>>;
+
+    /// Marks up to `limit` of the oldest queued jobs as running and returns them.
+    async fn claim_jobs(&self, limit: i64, now: String) -> Result<Vec<Job>>;
+
+    async fn finish_job(&self, id: String, state: State, error: String, now: String) -> Result<()>;
+
+    /// Queues jobs again that were running when the process stopped.
+    async fn requeue_running_jobs(&self) -> Result<u64>;
+
+    /// Marks the job canceled unless it finished already, returning it either way.
+    async fn cancel_job(&self, id: String, now: String) -> Result<Option<Job>>;
+
+    /// The most recently added jobs, newest first.
+    async fn get_jobs(&self, limit: i64) -> Result<Vec<Job>>;
+
+    async fn get_job(&self, id: String) -> Result<Option<Job>>;
+
+    /// Deletes finished jobs that finished before `before`.
+    async fn delete_jobs(&self, before: String) -> Result<u64>;
+
+    async fn add_fetch_attempt(&self, a: &Attempt) -> Result<()>;
+
+    /// The feed's most recent fetch attempts, newest first.
+    async fn get_fetch_attempts(&self, feed: String, limit: i64) -> Result<Vec<Attempt>>;
+
+    /// Replaces the last document kept for the feed.
+    async fn put_feed_payload(&self, p: &Payload) -> Result<()>;
+
+    /// The last document kept for the feed, none when payloads are not kept or it was never fetched.
+    async fn get_feed_payload(&self, feed: String) -> Result<Option<Payload>>;
+
+    async fn delete_fetch_attempts(&self, before: String) -> Result<u64>;
+
+    /// Forgets proxied images last asked for before `before`.
+    async fn delete_images(&self, before: String) -> Result<u64>;
+
+    async fn delete_blobs(&self, hashes: Vec<String>) -> Result<()>;
+
+    async fn get_rules(&self) -> Result<Rules> {
+        let filters = self.get_filters().await?;
+        let subscriptions = self.get_subscriptions().await?;
+        Ok(Rules {
+            filters: filters.iter().map(|m| m.into()).collect(),
+            subscriptions: subscriptions.iter().map(|s| s.into()).collect(),
+        })
+    }
+
+    /// Adds every imported rule in one transaction. Rules that already exist are left alone,
+    /// so importing the same file twice changes nothing.
+    async fn import_rules(&self, rules: Rules) -> Result<Imported>;
+
+    async fn delete_subscription(&self, id: String) -> Result<()>;
+
+    async fn get_report(&self, period: String) -> Result<Option<Report>>;
+
+    async fn has_digest(&self, period: String) -> Result<bool>;
+
+    async fn add_digest(&self, period: String, sent: String) -> Result<()>;
+
+    async fn save_report(&self, report: &Report) -> Result<()>;
+
+    /// Aggregates reading activity for articles read in `[start, end)`.
+    async fn compile_report(&self, period: String, start: String, end: String) -> Result<Report>;
+
+    async fn filter(
+        &self,
+        filter: Filter,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        match filter {
+            Filter::Unread => self.get_unread_articles(sort, pagination).await,
+            Filter::Favorite => self.get_favorited_articles(sort, pagination).await,
+            Filter::Read => self.get_read_articles(sort, pagination).await,
+            Filter::Hidden => self.get_hidden_articles(sort, pagination).await,
+        }
+    }
+}
+
+#[async_trait]
+impl Store for Storage {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        Storage::migrate(self).await
+    }
+
+    async fn applied_migrations(&self) -> Result<BTreeSet<i32>> {
+        let conn = self.conn().await?;
+        let migrated: bool = conn
+            .query_one("SELECT to_regclass('schema_migrations') IS NOT NULL", &[])
+            .await?
+            .get(0);
+        if !migrated {
+            return Ok(BTreeSet::new());
+        }
+        Ok(conn
+            .query("SELECT version FROM schema_migrations", &[])
+            .await?
+            .iter()
+            .map(|r| r.get(0))
+            .collect())
+    }
+
+    async fn check_schema(&self) -> Result<Vec<Discrepancy>> {
+        let conn = self.conn().await?;
+        let columns = conn
+            .query(
+                "SELECT table_name::TEXT, column_name::TEXT, udt_name::TEXT, is_nullable = 'YES' FROM information_schema.columns WHERE table_schema = current_schema()",
+                &[],
+            )
+            .await?;
+        let found: BTreeMap<(String, String), Found> = columns
+            .iter()
+            .map(|r| {
+                (
+                    (r.get(0), r.get(1)),
+                    Found {
+                        udt: r.get(2),
+                        nullable: r.get(3),
+                    },
+                )
+            })
+            .collect();
+
+        let indexes = conn
+            .query(
+                "SELECT t.relname::TEXT, a.attname::TEXT FROM pg_index i
+                JOIN pg_class t ON t.oid = i.indrelid
+                JOIN pg_namespace n ON n.oid = t.relnamespace
+                JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = i.indkey[0]
+                WHERE i.indisunique AND i.indnatts = 1 AND n.nspname = current_schema()",
+                &[],
+            )
+            .await?;
+        let unique: BTreeSet<(String, String)> =
+            indexes.iter().map(|r| (r.get(0), r.get(1))).collect();
+
+        let mut problems = schema::verify(&found, &unique);
+        if !problems.is_empty() {
+            return Ok(problems);
+        }
+        let unlinked = conn
+            .query(
+                "SELECT articles.feed, feeds.count, count(*) FROM articles
+                JOIN (SELECT name, count(*) FROM feeds GROUP BY name HAVING count(*) > 1) AS feeds ON feeds.name = articles.feed
+                WHERE articles.feed_id IS NULL GROUP BY articles.feed, feeds.count ORDER BY articles.feed",
+                &[],
+            )
+            .await?;
+        problems.extend(unlinked.iter().map(|r| Discrepancy::Unlinked {
+            name: r.get(0),
+            feeds: r.get(1),
+            articles: r.get(2),
+        }));
+        Ok(problems)
+    }
+
+    async fn add_feed(&self, f: AddFeed) -> Result<Feed> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO FEEDS (id, name, site_url, feed_url, date_added, last_updated) VALUES ($1, $2, $3, $4, $5, $6)";
+        let tx = conn.transaction().await?;
+        if let Some(limit) = self.quota.max_feeds {
+            lock_quota(&tx, "feeds").await?;
+            let count = self.statement(&tx, "SELECT COUNT(*) FROM feeds").await?;
+            let count: i64 = tx.query_one(&count, &[]).await?.get(0);
+            if count >= limit {
+                return Err(QuotaExceeded {
+                    resource: "feeds",
+                    limit,
+                }
+                .into());
+            }
+        }
+
+        let stmt = self.statement(&tx, query).await?;
+        let fta = Feed::new(f.feed_name, f.site_url, f.feed_url);
+        // adding a feed by hand takes back having unsubscribed from it
+        let forget = self
+            .statement(&tx, "DELETE FROM feed_tombstones WHERE feed_url = $1")
+            .await?;
+        tx.execute(&forget, &[&fta.feed_url]).await?;
+        tx.execute(
+            &stmt,
+            &[
+                &fta.id,
+                &fta.name,
+                &fta.site_url,
+                &fta.feed_url,
+                &timestamp(&fta.date_added),
+                &timestamp(&fta.last_updated),
+            ],
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(fta)
+    }
+
+    async fn add_feeds(&self, feeds: Vec<AddFeed>, resubscribe: bool) -> Result<Vec<Feed>> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        if resubscribe {
+            let urls: Vec<&str> = feeds.iter().map(|f| f.feed_url.as_str()).collect();
+            tx.execute(
+                "DELETE FROM feed_tombstones WHERE feed_url = ANY($1)",
+                &[&urls],
+            )
+            .await?;
+        }
+        // checked before anything is added, an import either fits or adds nothing
+        if let Some(limit) = self.quota.max_feeds {
+            lock_quota(&tx, "feeds").await?;
+            let urls: Vec<&str> = feeds.iter().map(|f| f.feed_url.as_str()).collect();
+            let count: i64 = tx
+                .query_one(
+                    "SELECT (SELECT COUNT(*) FROM feeds) + (SELECT COUNT(DISTINCT u.feed_url) FROM unnest($1::TEXT[]) AS u(feed_url) WHERE NOT EXISTS (SELECT 1 FROM feeds WHERE feed_url = u.feed_url) AND NOT EXISTS (SELECT 1 FROM feed_tombstones WHERE feed_url = u.feed_url))",
+                    &[&urls],
+                )
+                .await?
+                .get(0);
+            if count > limit {
+                return Err(QuotaExceeded {
+                    resource: "feeds",
+                    limit,
+                }
+                .into());
+            }
+        }
+        let stmt = self
+            .statement(&tx, "INSERT INTO feeds (id, name, site_url, feed_url, date_added, last_updated) SELECT $1, $2, $3, $4, $5, $6 WHERE NOT EXISTS (SELECT 1 FROM feed_tombstones WHERE feed_url = $4) ON CONFLICT (feed_url) DO NOTHING")
+            .await?;
+
+        let mut added = vec![];
+        for f in feeds {
+            let fta = Feed::new(f.feed_name, f.site_url, f.feed_url);
+            let inserted = tx
+                .execute(
+                    &stmt,
+                    &[
+                        &fta.id,
+                        &fta.name,
+                        &fta.site_url,
+                        &fta.feed_url,
+                        &timestamp(&fta.date_added),
+                        &timestamp(&fta.last_updated),
+                    ],
+                )
+                .await?;
+            if inserted == 1 {
+                added.push(fta);
+            }
+        }
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    async fn update_feed_search_language(
+        &self,
+        id: String,
+        language: Option<Language>,
+    ) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let language = language.map(|l| l.to_string()).unwrap_or_default();
+        let tx = conn.transaction().await?;
+        tx.execute(
+            "UPDATE feeds SET search_language = $1 WHERE id = $2",
+            &[&language, &id],
+        )
+        .await?;
+        // stored articles are stemmed again in the new language
+        tx.execute(
+            "UPDATE articles SET search_vector = article_search_vector(title, summary, feed_id) WHERE feed_id = $1",
+            &[&id],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn set_search_language(&self, language: Language) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let current: String = tx
+            .query_one("SELECT default_search_language()::TEXT", &[])
+            .await?
+            .get(0);
+        if current == language.to_string() {
+            return Ok(());
+        }
+
+        // the language is one of ours, which are all valid postgres configurations
+        tx.batch_execute(
+            format!(
+                "CREATE OR REPLACE FUNCTION default_search_language() RETURNS regconfig AS $$ SELECT '{}'::regconfig $$ LANGUAGE sql STABLE",
+                language
+            )
+            .as_str(),
+        )
+        .await?;
+        tx.execute(
+            "UPDATE articles SET search_vector = article_search_vector(title, summary, feed_id) WHERE feed_id IS NULL OR feed_id IN (SELECT id FROM feeds WHERE search_language = '')",
+            &[],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn toggle_feed_keep_content(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "UPDATE feeds SET keep_content = NOT keep_content WHERE id = $1",
+            &[&id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn update_feed_refresh_interval(&self, id: String, seconds: i32) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "UPDATE feeds SET refresh_interval = $1, next_poll = '' WHERE id = $2",
+            &[&seconds, &id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn toggle_feed_push(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute("UPDATE feeds SET push = NOT push WHERE id = $1", &[&id])
+            .await?;
+        Ok(())
+    }
+
+    async fn get_feed_by_id(&self, id: String) -> Result<Feed> {
+        let conn = &mut self.conn().await?;
+        let query = self
+            .statement(&**conn, "SELECT * FROM feeds WHERE id = $1")
+            .await?;
+        let result = conn.query_one(&query, &[&id]).await?;
+        Ok(Feed::from(&result))
+    }
+
+    async fn get_feeds(&self, pagination: String) -> Result<Page<Feed>> {
+        let conn = &mut self.reader().conn().await?;
+        let page = keyset_page(
+            conn,
+            "feeds",
+            "true",
+            &[],
+            "date_added",
+            Ordering::Descending,
+            self.page_size,
+            pagination,
+        )
+        .await?;
+        Ok(page.map(|r| Feed::from(r)))
+    }
+
+    async fn delete_feed(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let removed = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let tx = conn.transaction().await?;
+        tx.execute(
+            "INSERT INTO feed_tombstones (feed_url, date_removed) SELECT feed_url, $2 FROM feeds WHERE id = $1 ON CONFLICT (feed_url) DO UPDATE SET date_removed = $2",
+            &[&id, &removed],
+        )
+        .await?;
+        tx.execute("DELETE FROM feeds WHERE id = $1", &[&id])
+            .await?;
+        tx.execute("DELETE FROM scrape_rules WHERE feed = $1", &[&id])
+            .await?;
+        tx.execute("DELETE FROM fetch_log WHERE feed = $1", &[&id])
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_feed_retry_after(&self, id: String, until: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "UPDATE feeds SET retry_after = $1, next_poll = GREATEST(next_poll, $1) WHERE id = $2",
+            &[&until, &id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn apply_refresh(
+        &self,
+        id: String,
+        refreshed: Refreshed,
+    ) -> Result<(Vec<Article>, usize)> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        // before the inserts, which would otherwise store the articles again under their new ids
+        if !refreshed.rekeyed.is_empty() {
+            let (old, new): (Vec<&str>, Vec<&str>) = refreshed
+                .rekeyed
+                .iter()
+                .map(|(old, new)| (old.as_str(), new.as_str()))
+                .unzip();
+            for table in ["articles", schema::ARCHIVE] {
+                let query = format!("WITH moved AS (UPDATE {} SET id = u.new FROM unnest($1::TEXT[], $2::TEXT[]) AS u(old, new) WHERE {}.id = u.old AND NOT EXISTS (SELECT 1 FROM articles WHERE id = u.new) AND NOT EXISTS (SELECT 1 FROM {} WHERE id = u.new) RETURNING u.old, u.new)
+UPDATE enclosures SET article = moved.new FROM moved WHERE enclosures.article = moved.old", table, table, schema::ARCHIVE);
+                let rekey = self.statement(&tx, &query).await?;
+                tx.execute(&rekey, &[&old, &new]).await?;
+            }
+        }
+        let new = self
+            .insert_articles(&tx, refreshed.articles.into_iter())
+            .await?;
+        let history = self
+            .insert_articles(&tx, refreshed.history.into_iter())
+            .await?
+            .len();
+        if let Some((etag, last_modified, build_marker)) = refreshed.validators {
+            tx.execute(
+                "UPDATE feeds SET etag = $1, last_modified = $2, build_marker = $3 WHERE id = $4",
+                &[&etag, &last_modified, &build_marker, &id],
+            )
+            .await?;
+        }
+        if let Some(hints) = refreshed.hints {
+            tx.execute(
+                "UPDATE feeds SET hints = $1 WHERE id = $2",
+                &[&Json(hints), &id],
+            )
+            .await?;
+        }
+        tx.execute(
+            "UPDATE feeds SET fetch_error = '', failures = 0, last_updated = $1 WHERE id = $2",
+            &[&refreshed.updated, &id],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok((new, history))
+    }
+
+    async fn update_feed_fetch_error(&self, id: String, error: String) -> Result<()> {
+        let conn = self.conn().await?;
+        conn.execute(
+            "UPDATE feeds SET fetch_error = $1 WHERE id = $2",
+            &[&error, &id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn add_feed_failure(&self, id: String, max: i32) -> Result<bool> {
+        let conn = self.conn().await?;
+        let row = conn
+            .query_opt(
+                "UPDATE feeds SET failures = failures + 1, erroring = erroring OR failures + 1 >= $1 WHERE id = $2 RETURNING erroring, failures = $1",
+                &[&max, &id],
+            )
+            .await?;
+        Ok(row
+            .map(|r| r.get::<_, bool>(0) && r.get::<_, bool>(1))
+            .unwrap_or(false))
+    }
+
+    async fn enable_feed(&self, id: String) -> Result<()> {
+        let conn = self.conn().await?;
+        conn.execute(
+            "UPDATE feeds SET erroring = false, failures = 0, next_poll = '' WHERE id = $1",
+            &[&id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_unread_suggestions(
+        &self,
+        since: DateTime<Utc>,
+        min_articles: i64,
+        min_unread_ratio: f64,
+    ) -> Result<Vec<Suggestion>> {
+        let conn = &mut self.reader().conn().await?;
+        let query = "SELECT feeds.id, feeds.name, COUNT(*) AS total, COUNT(*) FILTER (WHERE NOT articles.read) AS unread FROM feeds JOIN articles ON articles.feed_id = feeds.id WHERE articles.date_added >= $1 AND NOT feeds.muted GROUP BY feeds.id, feeds.name HAVING COUNT(*) >= $2 AND COUNT(*) FILTER (WHERE NOT articles.read)::float8 / COUNT(*) >= $3 ORDER BY COUNT(*) FILTER (WHERE NOT articles.read)::float8 / COUNT(*) DESC, feeds.name";
+        let rows = conn
+            .query(query, &[&since, &min_articles, &min_unread_ratio])
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|r| Suggestion {
+                id: r.get(0),
+                name: r.get(1),
+                total: r.get(2),
+                unread: r.get(3),
+            })
+            .collect())
+    }
+
+    async fn get_feed_domains(&self) -> Result<Vec<Domain>> {
+        let conn = &mut self.reader().conn().await?;
+        let query = format!("SELECT domain, array_agg(name ORDER BY name), bool_and(muted) FROM (SELECT name, muted, {} AS domain FROM feeds) AS d GROUP BY domain ORDER BY domain", FEED_DOMAIN);
+        let rows = conn.query(query.as_str(), &[]).await?;
+        Ok(rows
+            .iter()
+            .map(|r| Domain {
+                name: r.get(0),
+                feeds: r.get(1),
+                muted: r.get(2),
+            })
+            .collect())
+    }
+
+    async fn get_feeds_by_domain(&self, domain: String) -> Result<Vec<Feed>> {
+        let conn = &mut self.reader().conn().await?;
+        let query = format!(
+            "SELECT * FROM feeds WHERE {} = $1 ORDER BY name",
+            FEED_DOMAIN
+        );
+        let rows = conn.query(query.as_str(), &[&domain]).await?;
+        Ok(rows.iter().map(Feed::from).collect())
+    }
+
+    async fn mute_domain(&self, domain: String, muted: bool) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let query = format!(
+            "UPDATE feeds SET muted = $1 WHERE {} = $2 RETURNING id",
+            FEED_DOMAIN
+        );
+        let feeds: Vec<String> = tx
+            .query(query.as_str(), &[&muted, &domain])
+            .await?
+            .iter()
+            .map(|r| r.get(0))
+            .collect();
+
+        if muted {
+            let query =
+                "UPDATE articles SET read = true, read_date = $1 WHERE NOT read AND feed_id = ANY($2)";
+            tx.execute(query, &[&Utc::now(), &feeds]).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_due_feeds(&self, now: String) -> Result<Vec<Feed>> {
+        let conn = &mut self.conn().await?;
+        let query = "SELECT * FROM feeds WHERE next_poll <= $1 AND NOT muted AND NOT erroring ORDER BY next_poll";
+        let rows = conn.query(query, &[&now]).await?;
+        Ok(rows.iter().map(Feed::from).collect())
+    }
+
+    async fn update_feed_schedule(
+        &self,
+        id: String,
+        poll_interval: i32,
+        next_poll: String,
+    ) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let query = "UPDATE feeds SET poll_interval = $1, next_poll = $2 WHERE id = $3";
+        tx.execute(query, &[&poll_interval, &next_poll, &id])
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_feed_publish_history(&self, feed: String, limit: i64) -> Result<Vec<String>> {
+        let conn = &mut self.conn().await?;
+        let query =
+            "SELECT published FROM articles WHERE feed_id = $1 ORDER BY published DESC LIMIT $2";
+        let rows = conn.query(query, &[&feed, &limit]).await?;
+        Ok(rows.iter().map(|r| text(r.get(0), "")).collect())
+    }
+
+    async fn get_counts(&self, today: String) -> Result<Counts> {
+        let conn = &mut self.reader().conn().await?;
+        // the unread total comes from the per feed counts, the others each have an index to go by
+        let query = "SELECT (SELECT COALESCE(SUM(unread), 0)::BIGINT FROM feed_counts), (SELECT COUNT(*) FROM articles WHERE read AND read_date >= $1 AND NOT hidden), (SELECT COUNT(*) FROM articles WHERE favorited AND NOT hidden)";
+        let row = conn.query_one(query, &[&timestamp(&today)]).await?;
+        Ok(Counts {
+            unread: row.get(0),
+            read_today: row.get(1),
+            favorites: row.get(2),
+        })
+    }
+
+    async fn get_unread_counts(&self) -> Result<BTreeMap<String, i64>> {
+        let conn = &mut self.reader().conn().await?;
+        let rows = conn
+            .query("SELECT feed, unread FROM feed_counts WHERE unread > 0", &[])
+            .await?;
+        Ok(rows.iter().map(|r| (r.get(0), r.get(1))).collect())
+    }
+
+    async fn recount_unread(&self) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        tx.execute("LOCK TABLE feed_counts IN SHARE ROW EXCLUSIVE MODE", &[])
+            .await?;
+        let mut corrected = tx
+            .execute(
+                "INSERT INTO feed_counts (feed, unread) SELECT COALESCE(feed_id, ''), COUNT(*) FROM articles WHERE NOT read AND NOT hidden GROUP BY 1 ON CONFLICT (feed) DO UPDATE SET unread = EXCLUDED.unread WHERE feed_counts.unread <> EXCLUDED.unread",
+                &[],
+            )
+            .await?;
+        // feeds left without unread articles, including deleted ones, are not kept at all
+        let emptied = tx
+            .query(
+                "DELETE FROM feed_counts WHERE NOT EXISTS (SELECT 1 FROM articles WHERE COALESCE(feed_id, '') = feed_counts.feed AND NOT read AND NOT hidden) RETURNING unread",
+                &[],
+            )
+            .await?;
+        corrected += emptied.iter().filter(|r| r.get::<_, i64>(0) != 0).count() as u64;
+        tx.commit().await?;
+        Ok(corrected)
+    }
+
+    async fn vacuum(&self, table: &str) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.batch_execute(&format!("VACUUM {}", table)).await?;
+        Ok(())
+    }
+
+    async fn analyze(&self) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.batch_execute("ANALYZE").await?;
+        Ok(())
+    }
+
+    async fn auto_expire_unread(&self, expiry: Expiry, now: DateTime<Utc>) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let mut expired = 0;
+
+        if let Some(days) = expiry.max_age_days {
+            let cutoff = now - Duration::days(days);
+            let query =
+                "UPDATE articles SET read = true, read_date = $1 WHERE NOT read AND NOT hidden AND NOT pinned AND NOT favorited AND published < $2";
+            expired += tx.execute(query, &[&now, &cutoff]).await?;
+        }
+
+        if let Some(limit) = expiry.max_unread_per_feed {
+            let query = "UPDATE articles SET read = true, read_date = $1 WHERE id IN (SELECT id FROM (SELECT id, row_number() OVER (PARTITION BY COALESCE(feed_id, feed) ORDER BY published DESC) AS n FROM articles WHERE NOT read AND NOT hidden AND NOT pinned AND NOT favorited) AS ranked WHERE n > $2)";
+            expired += tx.execute(query, &[&now, &limit]).await?;
+        }
+
+        tx.commit().await?;
+        Ok(expired)
+    }
+
+    async fn strip_read_content(&self, retention: Retention, now: DateTime<Utc>) -> Result<u64> {
+        let days = match retention.content_days {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let cutoff = now - Duration::days(days);
+        let stale = "SELECT id FROM articles WHERE read AND NOT favorited AND NOT pinned AND read_date < $1 AND (feed_id IS NULL OR feed_id NOT IN (SELECT id FROM feeds WHERE keep_content))";
+
+        tx.execute(
+            format!(
+                "DELETE FROM enclosures WHERE mime LIKE 'image/%' AND article IN ({})",
+                stale
+            )
+            .as_str(),
+            &[&cutoff],
+        )
+        .await?;
+        let stripped = tx
+            .execute(
+                format!(
+                    "UPDATE articles SET content = '' WHERE content <> '' AND id IN ({})",
+                    stale
+                )
+                .as_str(),
+                &[&cutoff],
+            )
+            .await?;
+
+        tx.commit().await?;
+        Ok(stripped)
+    }
+
+    async fn archive_read_articles(&self, retention: Retention, now: DateTime<Utc>) -> Result<u64> {
+        let days = match retention.archive_days {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+        let conn = &mut self.conn().await?;
+        let cutoff = now - Duration::days(days);
+        let columns = schema::columns("articles");
+        let query = format!(
+            "WITH archived AS (DELETE FROM articles WHERE id IN (SELECT id FROM articles WHERE read AND NOT favorited AND NOT pinned AND NOT hidden AND read_date < $1 LIMIT $2) RETURNING {}) INSERT INTO {} ({}) SELECT {} FROM archived",
+            columns,
+            schema::ARCHIVE,
+            columns,
+            columns
+        );
+        Ok(conn
+            .execute(query.as_str(), &[&cutoff, &ARCHIVE_BATCH])
+            .await?)
+    }
+
+    async fn get_recent_articles(&self, limit: i64) -> Result<Vec<Article>> {
+        let conn = &mut self.conn().await?;
+        let query = format!(
+            "SELECT * FROM {} ORDER BY date_added DESC LIMIT $1",
+            ARTICLES
+        );
+        let rows = conn.query(query.as_str(), &[&limit]).await?;
+        articles(conn, rows).await
+    }
+
+    async fn get_known_links(&self, links: Vec<String>) -> Result<Vec<String>> {
+        let conn = &mut self.conn().await?;
+        let rows = conn
+            .query(
+                format!(
+                    "SELECT link FROM articles WHERE link = ANY($1) UNION ALL SELECT link FROM {} WHERE link = ANY($1)",
+                    schema::ARCHIVE
+                )
+                .as_str(),
+                &[&links],
+            )
+            .await?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    async fn get_article_by_id(&self, id: String) -> Result<Article> {
+        let conn = &mut self.conn().await?;
+        let query = format!("SELECT * FROM {} WHERE id = $1", history());
+        let row = conn.query_one(query.as_str(), &[&id]).await?;
+        Ok(articles(conn, vec![row]).await?.remove(0))
+    }
+
+    async fn get_unread_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>> {
+        Storage::get_unread_articles(self, sort, pagination).await
+    }
+
+    async fn search_articles(&self, query: String, language: Language) -> Result<Page<Article>> {
+        Storage::search_articles(self, query, language).await
+    }
+
+    async fn fuzzy_search_titles(&self, query: String) -> Result<Page<Article>> {
+        let conn = &mut self.reader().conn().await?;
+        let trigrams: bool = conn
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_trgm')",
+                &[],
+            )
+            .await?
+            .get(0);
+        let items = match trigrams {
+            true => {
+                let search = format!(
+                    "SELECT articles.* FROM {} WHERE hidden = false AND $1 <% title ORDER BY word_similarity($1, title) DESC, published DESC LIMIT {}",
+                    ARTICLES, SEARCH_LIMIT
+                );
+                conn.query(search.as_str(), &[&query]).await?
+            }
+            false => {
+                let search = format!(
+                    "SELECT articles.* FROM {} WHERE hidden = false AND title ILIKE $1 ORDER BY published DESC LIMIT {}",
+                    ARTICLES, SEARCH_LIMIT
+                );
+                let pattern = format!(
+                    "%{}%",
+                    query
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                );
+                conn.query(search.as_str(), &[&pattern]).await?
+            }
+        };
+        Ok(Page::all(articles(conn, items).await?))
+    }
+
+    async fn sample_articles(&self, before: DateTime<Utc>) -> Result<Page<Article>> {
+        let conn = &mut self.reader().conn().await?;
+        // weighted sampling without replacement: order by random() ^ (1 / weight)
+        let sample = format!(
+            r#"SELECT articles.* FROM {}
+WHERE (read = false OR favorited = true) AND hidden = false AND published < $1
+ORDER BY random() ^ (1.0 / row_number() OVER (ORDER BY published DESC)) DESC
+LIMIT {}"#,
+            ARTICLES, SAMPLE_LIMIT
+        );
+        let items = conn.query(sample.as_str(), &[&before]).await?;
+        Ok(Page::all(articles(conn, items).await?))
+    }
+
+    async fn get_podcast_episodes(&self, pagination: String) -> Result<Page<Article>> {
+        let condition = "hidden = false AND EXISTS (SELECT 1 FROM enclosures WHERE enclosures.article = articles.id AND enclosures.mime LIKE 'audio/%')";
+        self.articles_page(ARTICLES, condition, &[], Sort::default(), pagination)
+            .await
+    }
+
+    async fn get_read_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>> {
+        self.articles_page(&history(), Filter::Read.condition(), &[], sort, pagination)
+            .await
+    }
+
+    async fn get_hidden_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>> {
+        self.articles_page(ARTICLES, Filter::Hidden.condition(), &[], sort, pagination)
+            .await
+    }
+
+    async fn get_favorited_articles(
+        &self,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        self.articles_page(
+            ARTICLES,
+            Filter::Favorite.condition(),
+            &[],
+            sort,
+            pagination,
+        )
+        .await
+    }
+
+    fn stream_articles(
+        &self,
+        columns: &[Column],
+        q: ArticleQuery,
+    ) -> BoxStream<'static, Result<Vec<Value>>> {
+        // read EXPORT_BATCH at a time, each batch a query of its own, so a large export neither
+        // holds the connection between batches nor runs into the statement timeout. Rows hold
+        // the columns followed by the published date and id batches are keyed on. The feed is
+        // exported under its current name.
+        let columns: Vec<&str> = columns
+            .iter()
+            .map(|c| match c {
+                Column::Feed => "feed_name AS feed",
+                c => c.name(),
+            })
+            .collect();
+        let select = format!(
+            "SELECT {}, published, id FROM {}",
+            columns.join(", "),
+            ARTICLES
+        );
+        let width = columns.len();
+
+        let state = (self.clone(), q, select, None, false);
+        stream::try_unfold(state, move |(store, q, select, after, done)| async move {
+            if done {
+                return Ok::<_, anyhow::Error>(None);
+            }
+            let rows = store.export_batch(&select, &q, &after).await?;
+            let last = rows.last().map(|r| (r.get(width), r.get(width + 1)));
+            let done = rows.len() < EXPORT_BATCH as usize;
+            let values = rows.iter().map(|r| Ok(export::values(r, width)));
+            let values: Vec<Result<Vec<Value>>> = values.collect();
+            Ok(Some((stream::iter(values), (store, q, select, last, done))))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    async fn get_view_articles(
+        &self,
+        q: &ArticleQuery,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let (mut conditions, params) = q.conditions(3);
+        conditions.push("true".to_string());
+        self.articles_page(
+            ARTICLES,
+            conditions.join(" AND ").as_str(),
+            &params,
+            sort,
+            pagination,
+        )
+        .await
+    }
+
+    async fn mark_article_read(&self, a: Article) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let timestamp = match a.read {
+            true => None,
+            false => Some(Utc::now()),
+        };
+
+        let query = "UPDATE articles SET read = NOT read, read_date = $1 WHERE id = $2";
+        let tx = conn.transaction().await?;
+        restore(&tx, &[a.id.as_str()]).await?;
+        tx.execute(query, &[&timestamp, &a.clone().id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_article_hidden(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "UPDATE articles SET hidden = NOT hidden WHERE id = $1";
+        let tx = conn.transaction().await?;
+        restore(&tx, &[id.as_str()]).await?;
+        tx.execute(query, &[&id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_article_pinned(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "UPDATE articles SET pinned = NOT pinned WHERE id = $1";
+        let tx = conn.transaction().await?;
+        restore(&tx, &[id.as_str()]).await?;
+        tx.execute(query, &[&id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn mark_feed_read(&self, feed: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query =
+            "UPDATE articles SET read = true, read_date = $1 WHERE NOT read AND feed_id = $2";
+        let tx = conn.transaction().await?;
+        tx.execute(query, &[&Utc::now(), &feed]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_article_note(&self, id: String, note: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "UPDATE articles SET note = $1 WHERE id = $2";
+        let tx = conn.transaction().await?;
+        restore(&tx, &[id.as_str()]).await?;
+        tx.execute(query, &[&note, &id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_article_progress(&self, id: String, progress: i32) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        restore(&**conn, &[id.as_str()]).await?;
+        conn.execute(
+            "UPDATE articles SET progress = $1 WHERE id = $2",
+            &[&progress, &id],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn mark_article_favorite(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "UPDATE articles SET favorited = NOT favorited WHERE id = $1";
+        let tx = conn.transaction().await?;
+        restore(&tx, &[id.as_str()]).await?;
+        tx.execute(query, &[&id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn bulk_update_articles(&self, ids: Vec<String>, action: BulkAction) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let archived: Vec<&str> = ids.iter().map(|id| id.as_str()).collect();
+        restore(&tx, &archived).await?;
+        let updated = match action {
+            BulkAction::Read => {
+                let query = "UPDATE articles SET read = true, read_date = $2 WHERE id = ANY($1) AND NOT read";
+                tx.execute(query, &[&ids, &Utc::now()]).await?
+            }
+            BulkAction::Unread => {
+                let query = "UPDATE articles SET read = false, read_date = NULL WHERE id = ANY($1) AND read";
+                tx.execute(query, &[&ids]).await?
+            }
+            BulkAction::Favorite => {
+                let query =
+                    "UPDATE articles SET favorited = true WHERE id = ANY($1) AND NOT favorited";
+                tx.execute(query, &[&ids]).await?
+            }
+            BulkAction::Unfavorite => {
+                let query =
+                    "UPDATE articles SET favorited = false WHERE id = ANY($1) AND favorited";
+                tx.execute(query, &[&ids]).await?
+            }
+            BulkAction::Hide => {
+                let query = "UPDATE articles SET hidden = true WHERE id = ANY($1) AND NOT hidden";
+                tx.execute(query, &[&ids]).await?
+            }
+            BulkAction::Unhide => {
+                let query = "UPDATE articles SET hidden = false WHERE id = ANY($1) AND hidden";
+                tx.execute(query, &[&ids]).await?
+            }
+        };
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    async fn get_last_refreshed(&self) -> Result<Option<DateTime<Utc>>> {
+        let conn = &mut self.reader().conn().await?;
+        let row = conn
+            .query_one("SELECT max(last_updated) FROM feeds", &[])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    async fn get_fever_feeds(&self) -> Result<Vec<fever::Feed>> {
+        let conn = &mut self.reader().conn().await?;
+        let rows = conn
+            .query(
+                "SELECT seq, name, feed_url, site_url, last_updated FROM feeds ORDER BY name",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(fever::Feed::from).collect())
+    }
+
+    async fn get_fever_items(&self, items: fever::Items, limit: i64) -> Result<Vec<fever::Item>> {
+        let conn = &mut self.reader().conn().await?;
+        let select = format!(
+            "SELECT articles.seq, feeds.seq, articles.title, articles.author, articles.content, articles.summary, articles.link, articles.favorited, articles.read, articles.published FROM {} LEFT JOIN feeds ON feeds.id = articles.feed_id",
+            history()
+        );
+        let rows = match items {
+            fever::Items::Since(seq) => {
+                let query = format!(
+                    "{} WHERE articles.seq > $1 AND NOT articles.hidden ORDER BY articles.seq LIMIT $2",
+                    select
+                );
+                conn.query(query.as_str(), &[&seq, &limit]).await?
+            }
+            fever::Items::Before(seq) => {
+                let query = format!(
+                    "{} WHERE articles.seq < $1 AND NOT articles.hidden ORDER BY articles.seq DESC LIMIT $2",
+                    select
+                );
+                conn.query(query.as_str(), &[&seq, &limit]).await?
+            }
+            fever::Items::With(seqs) => {
+                let query = format!(
+                    "{} WHERE articles.seq = ANY($1) ORDER BY articles.seq LIMIT $2",
+                    select
+                );
+                conn.query(query.as_str(), &[&seqs, &limit]).await?
+            }
+        };
+        Ok(rows.iter().map(fever::Item::from).collect())
+    }
+
+    async fn count_fever_items(&self) -> Result<i64> {
+        let conn = &mut self.reader().conn().await?;
+        let query = format!(
+            "SELECT (SELECT COUNT(*) FROM articles WHERE NOT hidden) + (SELECT COUNT(*) FROM {} WHERE NOT hidden)",
+            schema::ARCHIVE
+        );
+        Ok(conn.query_one(query.as_str(), &[]).await?.get(0))
+    }
+
+    async fn get_fever_item_ids(&self, saved: bool) -> Result<Vec<i64>> {
+        let conn = &mut self.reader().conn().await?;
+        let condition = match saved {
+            true => "favorited",
+            false => "NOT read",
+        };
+        let query = format!(
+            "SELECT seq FROM articles WHERE {} AND NOT hidden ORDER BY seq",
+            condition
+        );
+        let rows = conn.query(query.as_str(), &[]).await?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    async fn get_article_ids_by_seq(&self, seqs: Vec<i64>) -> Result<Vec<String>> {
+        let conn = &mut self.conn().await?;
+        let query = format!(
+            "SELECT id FROM articles WHERE seq = ANY($1) UNION ALL SELECT id FROM {} WHERE seq = ANY($1)",
+            schema::ARCHIVE
+        );
+        let rows = conn.query(query.as_str(), &[&seqs]).await?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    async fn mark_read_before(
+        &self,
+        feed: Option<i64>,
+        tag: Option<String>,
+        before: DateTime<Utc>,
+    ) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        let query = "UPDATE articles SET read = true, read_date = $1 WHERE NOT read AND date_added < $2 AND ($3::BIGINT IS NULL OR feed_id = (SELECT id FROM feeds WHERE seq = $3)) AND ($4::TEXT IS NULL OR $4 = ANY(tags))";
+        Ok(conn
+            .execute(query, &[&Utc::now(), &before, &feed, &tag])
+            .await?)
+    }
+
+    async fn get_greader_subscriptions(&self) -> Result<Vec<greader::Subscription>> {
+        let conn = &mut self.reader().conn().await?;
+        let rows = conn
+            .query(
+                "SELECT seq, name, feed_url, site_url FROM feeds ORDER BY name",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(greader::Subscription::from).collect())
+    }
+
+    async fn get_greader_unread_counts(&self) -> Result<Vec<greader::UnreadCount>> {
+        let conn = &mut self.reader().conn().await?;
+        let rows = conn
+            .query(
+                "SELECT feeds.seq, feed_counts.unread, feeds.last_updated FROM feed_counts JOIN feeds ON feeds.id = feed_counts.feed WHERE feed_counts.unread > 0",
+                &[],
+            )
+            .await?;
+        Ok(rows.iter().map(greader::UnreadCount::from).collect())
+    }
+
+    async fn get_greader_items(&self, s: &greader::Selection) -> Result<Vec<greader::Item>> {
+        let conn = &mut self.reader().conn().await?;
+        let (feed, tag, only_read, only_starred) = match &s.stream {
+            greader::Stream::All => (None, None, false, false),
+            greader::Stream::Read => (None, None, true, false),
+            greader::Stream::Starred => (None, None, false, true),
+            greader::Stream::Feed(seq) => (Some(*seq), None, false, false),
+            greader::Stream::Label(tag) => (None, Some(tag), false, false),
+        };
+        let (comparison, direction) = match s.oldest_first {
+            true => (">", "ASC"),
+            false => ("<", "DESC"),
+        };
+        let query = format!(
+            "{} WHERE NOT articles.hidden AND ($1::BIGINT IS NULL OR feeds.seq = $1) AND (NOT $2 OR articles.read) AND (NOT $3 OR articles.favorited) AND (NOT $4 OR NOT articles.read) AND ($5::TIMESTAMPTZ IS NULL OR articles.date_added >= $5) AND ($6::TIMESTAMPTZ IS NULL OR articles.date_added < $6) AND ($7::BIGINT IS NULL OR articles.seq {} $7) AND ($9::TEXT IS NULL OR $9 = ANY(articles.tags)) ORDER BY articles.seq {} LIMIT $8",
+            greader_items(),
+            comparison,
+            direction
+        );
+        let rows = conn
+            .query(
+                query.as_str(),
+                &[
+                    &feed,
+                    &only_read,
+                    &only_starred,
+                    &s.exclude_read,
+                    &s.since,
+                    &s.until,
+                    &s.continuation,
+                    &s.count,
+                    &tag,
+                ],
+            )
+            .await?;
+        Ok(rows.iter().map(greader::Item::from).collect())
+    }
+
+    async fn get_greader_items_by_seq(&self, seqs: Vec<i64>) -> Result<Vec<greader::Item>> {
+        let conn = &mut self.reader().conn().await?;
+        let query = format!(
+            "{} WHERE articles.seq = ANY($1) ORDER BY articles.seq",
+            greader_items()
+        );
+        let rows = conn.query(query.as_str(), &[&seqs]).await?;
+        Ok(rows.iter().map(greader::Item::from).collect())
+    }
+
+    async fn get_tags(&self) -> Result<Vec<Count>> {
+        let conn = &mut self.reader().conn().await?;
+        let query =
+            "SELECT tag, COUNT(*) FROM articles, unnest(tags) AS tag GROUP BY tag ORDER BY tag";
+        let rows = conn.query(query, &[]).await?;
+        Ok(rows
+            .iter()
+            .map(|r| Count {
+                name: r.get(0),
+                count: r.get(1),
+            })
+            .collect())
+    }
+
+    async fn rename_tag(&self, tag: String, name: String) -> Result<()> {
+        if name.is_empty() {
+            return Err(anyhow::Error::msg("tag name cannot be empty"));
+        }
+
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let existing = tx
+            .query_opt(
+                format!(
+                    "SELECT 1 FROM articles WHERE $1 = ANY(tags) UNION ALL SELECT 1 FROM {} WHERE $1 = ANY(tags) LIMIT 1",
+                    schema::ARCHIVE
+                )
+                .as_str(),
+                &[&name],
+            )
+            .await?;
+        if existing.is_some() {
+            return Err(anyhow::Error::msg(format!(
+                "tag {} already exists, merge into it instead",
+                name
+            )));
+        }
+
+        for table in ["articles", schema::ARCHIVE] {
+            let query = format!(
+                "UPDATE {} SET tags = array_replace(tags, $1, $2) WHERE $1 = ANY(tags)",
+                table
+            );
+            tx.execute(query.as_str(), &[&tag, &name]).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn merge_tag(&self, tag: String, into: String) -> Result<()> {
+        if into.is_empty() || into == tag {
+            return Err(anyhow::Error::msg(format!(
+                "cannot merge {} into {}",
+                tag, into
+            )));
+        }
+
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        for table in ["articles", schema::ARCHIVE] {
+            let query = format!("UPDATE {} SET tags = CASE WHEN $2 = ANY(tags) THEN array_remove(tags, $1) ELSE array_replace(tags, $1, $2) END WHERE $1 = ANY(tags)", table);
+            tx.execute(query.as_str(), &[&tag, &into]).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_tag(&self, tag: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        for table in ["articles", schema::ARCHIVE] {
+            let query = format!(
+                "UPDATE {} SET tags = array_remove(tags, $1) WHERE $1 = ANY(tags)",
+                table
+            );
+            tx.execute(query.as_str(), &[&tag]).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_filters(&self) -> Result<Vec<Mute>> {
+        let conn = &mut self.conn().await?;
+        let query = "SELECT * FROM filters ORDER BY date_added";
+        let rows = conn.query(query, &[]).await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn add_filter(&self, m: Mute) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO filters (id, pattern, regex, field, action, date_added) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING";
+        let tx = conn.transaction().await?;
+        tx.execute(
+            query,
+            &[
+                &m.id,
+                &m.pattern,
+                &m.regex,
+                &m.field.to_string(),
+                &m.action.to_string(),
+                &m.date_added,
+            ],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn update_filter(&self, id: String, m: Mute) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query =
+            "UPDATE filters SET pattern = $1, regex = $2, field = $3, action = $4 WHERE id = $5";
+        let tx = conn.transaction().await?;
+        let updated = tx
+            .execute(
+                query,
+                &[
+                    &m.pattern,
+                    &m.regex,
+                    &m.field.to_string(),
+                    &m.action.to_string(),
+                    &id,
+                ],
+            )
+            .await?;
+        if updated == 0 {
+            return Err(anyhow::Error::msg(format!("no such filter: {}", id)));
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_filter(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "DELETE FROM filters WHERE id = $1";
+        let tx = conn.transaction().await?;
+        tx.execute(query, &[&id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_subscriptions(&self) -> Result<Vec<Subscription>> {
+        let conn = &mut self.conn().await?;
+        let query = "SELECT * FROM subscriptions ORDER BY tag, date_added";
+        let rows = conn.query(query, &[]).await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn add_subscription(&self, s: Subscription) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO subscriptions (id, tag, webhook, date_added) VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO NOTHING";
+        let tx = conn.transaction().await?;
+        tx.execute(query, &[&s.id, &s.tag, &s.webhook, &s.date_added])
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "push")]
+    async fn get_push_subscriptions(&self) -> Result<Vec<PushSubscription>> {
+        let conn = &mut self.conn().await?;
+        let query = "SELECT * FROM push_subscriptions ORDER BY date_added";
+        let rows = conn.query(query, &[]).await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn add_push_subscription(&self, s: PushSubscription) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO push_subscriptions (endpoint, p256dh, auth, date_added) VALUES ($1, $2, $3, $4) ON CONFLICT (endpoint) DO UPDATE SET p256dh = $2, auth = $3";
+        conn.execute(query, &[&s.endpoint, &s.p256dh, &s.auth, &s.date_added])
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_push_subscription(&self, endpoint: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "DELETE FROM push_subscriptions WHERE endpoint = $1",
+            &[&endpoint],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_views(&self) -> Result<Vec<View>> {
+        let conn = &mut self.conn().await?;
+        let rows = conn.query("SELECT * FROM views ORDER BY name", &[]).await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn get_view(&self, id: String) -> Result<View> {
+        let conn = &mut self.conn().await?;
+        let row = conn
+            .query_one("SELECT * FROM views WHERE id = $1", &[&id])
+            .await?;
+        Ok((&row).into())
+    }
+
+    async fn add_view(&self, v: View) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO views (id, name, filter, feed, tag, keyword, date_added) VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (id) DO UPDATE SET filter = $3, feed = $4, tag = $5, keyword = $6";
+        let tx = conn.transaction().await?;
+        tx.execute(
+            query,
+            &[
+                &v.id,
+                &v.name,
+                &v.filter,
+                &v.feed,
+                &v.tag,
+                &v.keyword,
+                &v.date_added,
+            ],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_view(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        tx.execute("DELETE FROM views WHERE id = $1", &[&id])
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_scrape_rules(&self) -> Result<Vec<Rule>> {
+        let conn = &mut self.conn().await?;
+        let rows = conn
+            .query("SELECT * FROM scrape_rules ORDER BY date_added", &[])
+            .await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn get_scrape_rule(&self, feed: String) -> Result<Option<Rule>> {
+        let conn = &mut self.conn().await?;
+        let row = conn
+            .query_opt("SELECT * FROM scrape_rules WHERE feed = $1", &[&feed])
+            .await?;
+        Ok(row.as_ref().map(|r| r.into()))
+    }
+
+    async fn put_scrape_rule(&self, r: &Rule) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO scrape_rules (feed, item, title, link, date, date_added) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (feed) DO UPDATE SET item = $2, title = $3, link = $4, date = $5";
+        conn.execute(
+            query,
+            &[&r.feed, &r.item, &r.title, &r.link, &r.date, &r.date_added],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_scrape_rule(&self, feed: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute("DELETE FROM scrape_rules WHERE feed = $1", &[&feed])
+            .await?;
+        Ok(())
+    }
+
+    async fn add_blob(&self, b: Blob) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO blobs (hash, mime, size, date_added) VALUES ($1, $2, $3, $4) ON CONFLICT (hash) DO NOTHING";
+        conn.execute(query, &[&b.hash, &b.mime, &b.size, &b.date_added])
+            .await?;
+        Ok(())
+    }
+
+    async fn get_uncached_images(&self, limit: i64) -> Result<Vec<String>> {
+        let conn = &mut self.conn().await?;
+        let query = "SELECT enclosures.url FROM enclosures JOIN articles ON articles.id = enclosures.article WHERE enclosures.blob = '' AND enclosures.mime LIKE 'image/%' GROUP BY enclosures.url ORDER BY max(articles.date_added) DESC LIMIT $1";
+        let rows = conn.query(query, &[&limit]).await?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    async fn set_enclosure_blob(&self, url: String, hash: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "UPDATE enclosures SET blob = $1 WHERE url = $2",
+            &[&hash, &url],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_blob(&self, hash: String) -> Result<Option<Blob>> {
+        let conn = &mut self.conn().await?;
+        let row = conn
+            .query_opt("SELECT * FROM blobs WHERE hash = $1", &[&hash])
+            .await?;
+        Ok(row.as_ref().map(|r| r.into()))
+    }
+
+    async fn get_orphaned_blobs(&self, before: String) -> Result<Vec<String>> {
+        let conn = &mut self.conn().await?;
+        let mut query = "SELECT hash FROM blobs WHERE date_added < $1".to_string();
+        for (table, column) in blob::REFERENCES {
+            query = format!(
+                "{} AND NOT EXISTS (SELECT 1 FROM {} WHERE {}.{} = blobs.hash)",
+                query, table, table, column
+            );
+        }
+        let rows = conn.query(query.as_str(), &[&before]).await?;
+        Ok(rows.iter().map(|r| r.get(0)).collect())
+    }
+
+    async fn get_image(&self, url: String, now: String) -> Result<Option<Blob>> {
+        let conn = &mut self.conn().await?;
+        let query = "WITH used AS (UPDATE images SET last_used = $2 WHERE url = $1 RETURNING blob) SELECT blobs.* FROM blobs JOIN used ON used.blob = blobs.hash";
+        let row = conn.query_opt(query, &[&url, &now]).await?;
+        Ok(row.as_ref().map(|r| r.into()))
+    }
+
+    async fn add_image(&self, url: String, hash: String, now: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO images (url, blob, date_added, last_used) VALUES ($1, $2, $3, $3) ON CONFLICT (url) DO UPDATE SET blob = $2, last_used = $3";
+        conn.execute(query, &[&url, &hash, &now]).await?;
+        Ok(())
+    }
+
+    async fn add_jobs(&self, jobs: Vec<Job>) -> Result<Vec<Job>> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let stmt = tx
+            .prepare("INSERT INTO jobs (id, feed, state, date_added) SELECT $1, $2, $3, $4 WHERE NOT EXISTS (SELECT 1 FROM jobs WHERE feed = $2 AND state IN ($3, $5))")
+            .await?;
+        let (queued, running) = (State::Queued.to_string(), State::Running.to_string());
+
+        let mut added = vec![];
+        for j in jobs {
+            let inserted = tx
+                .execute(&stmt, &[&j.id, &j.feed, &queued, &j.date_added, &running])
+                .await?;
+            if inserted == 1 {
+                added.push(j);
+            }
+        }
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    async fn claim_jobs(&self, limit: i64, now: String) -> Result<Vec<Job>> {
+        let conn = &mut self.conn().await?;
+        let query = "UPDATE jobs SET state = $1, started = $2 WHERE id IN (SELECT id FROM jobs WHERE state = $3 ORDER BY date_added LIMIT $4 FOR UPDATE SKIP LOCKED) RETURNING *";
+        let rows = conn
+            .query(
+                query,
+                &[
+                    &State::Running.to_string(),
+                    &now,
+                    &State::Queued.to_string(),
+                    &limit,
+                ],
+            )
+            .await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn finish_job(&self, id: String, state: State, error: String, now: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        // a job canceled while it ran keeps its canceled state
+        conn.execute(
+            "UPDATE jobs SET state = $1, error = $2, finished = $3 WHERE id = $4 AND state = $5",
+            &[
+                &state.to_string(),
+                &error,
+                &now,
+                &id,
+                &State::Running.to_string(),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn requeue_running_jobs(&self) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        Ok(conn
+            .execute(
+                "UPDATE jobs SET state = $1, started = '' WHERE state = $2",
+                &[&State::Queued.to_string(), &State::Running.to_string()],
+            )
+            .await?)
+    }
+
+    async fn cancel_job(&self, id: String, now: String) -> Result<Option<Job>> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "UPDATE jobs SET state = $1, finished = $2 WHERE id = $3 AND state IN ($4, $5)",
+            &[
+                &State::Canceled.to_string(),
+                &now,
+                &id,
+                &State::Queued.to_string(),
+                &State::Running.to_string(),
+            ],
+        )
+        .await?;
+        let row = conn
+            .query_opt("SELECT * FROM jobs WHERE id = $1", &[&id])
+            .await?;
+        Ok(row.as_ref().map(|r| r.into()))
+    }
+
+    async fn get_jobs(&self, limit: i64) -> Result<Vec<Job>> {
+        let conn = &mut self.conn().await?;
+        let rows = conn
+            .query(
+                "SELECT * FROM jobs ORDER BY date_added DESC LIMIT $1",
+                &[&limit],
+            )
+            .await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn get_job(&self, id: String) -> Result<Option<Job>> {
+        let conn = &mut self.conn().await?;
+        let row = conn
+            .query_opt("SELECT * FROM jobs WHERE id = $1", &[&id])
+            .await?;
+        Ok(row.as_ref().map(|r| r.into()))
+    }
+
+    async fn delete_jobs(&self, before: String) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        Ok(conn
+            .execute(
+                "DELETE FROM jobs WHERE finished != '' AND finished < $1",
+                &[&before],
+            )
+            .await?)
+    }
+
+    async fn add_fetch_attempt(&self, a: &Attempt) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "INSERT INTO fetch_log (feed, date, status, bytes, duration_ms, added, error) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            &[&a.feed, &a.date, &a.status, &a.bytes, &a.duration_ms, &a.added, &a.error],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_fetch_attempts(&self, feed: String, limit: i64) -> Result<Vec<Attempt>> {
+        let conn = &mut self.conn().await?;
+        let rows = conn
+            .query(
+                "SELECT * FROM fetch_log WHERE feed = $1 ORDER BY date DESC LIMIT $2",
+                &[&feed, &limit],
+            )
+            .await?;
+        Ok(rows.iter().map(|r| r.into()).collect())
+    }
+
+    async fn put_feed_payload(&self, p: &Payload) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "INSERT INTO feed_payloads (feed, fetched, content_type, size, truncated, body) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (feed) DO UPDATE SET fetched = $2, content_type = $3, size = $4, truncated = $5, body = $6",
+            &[&p.feed, &p.fetched, &p.content_type, &p.size, &p.truncated, &p.body],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_feed_payload(&self, feed: String) -> Result<Option<Payload>> {
+        let conn = &mut self.conn().await?;
+        let row = conn
+            .query_opt("SELECT * FROM feed_payloads WHERE feed = $1", &[&feed])
+            .await?;
+        Ok(row.as_ref().map(|r| r.into()))
+    }
+
+    async fn delete_fetch_attempts(&self, before: String) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        Ok(conn
+            .execute("DELETE FROM fetch_log WHERE date < $1", &[&before])
+            .await?)
+    }
+
+    async fn delete_images(&self, before: String) -> Result<u64> {
+        let conn = &mut self.conn().await?;
+        Ok(conn
+            .execute("DELETE FROM images WHERE last_used < $1", &[&before])
+            .await?)
+    }
+
+    async fn delete_blobs(&self, hashes: Vec<String>) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute("DELETE FROM blobs WHERE hash = ANY($1)", &[&hashes])
+            .await?;
+        Ok(())
+    }
+
+    async fn import_rules(&self, rules: Rules) -> Result<Imported> {
+        let conn = &mut self.conn().await?;
+        let tx = conn.transaction().await?;
+        let filter_stmt = tx
+            .prepare("INSERT INTO filters (id, pattern, regex, field, action, date_added) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING")
+            .await?;
+        let subscription_stmt = tx
+            .prepare("INSERT INTO subscriptions (id, tag, webhook, date_added) VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO NOTHING")
+            .await?;
+
+        let mut imported = Imported::default();
+        for rule in rules.filters.iter() {
+            let m = Mute::new(rule.pattern.clone(), rule.regex, rule.field, rule.action)?;
+            imported.filters += tx
+                .execute(
+                    &filter_stmt,
+                    &[
+                        &m.id,
+                        &m.pattern,
+                        &m.regex,
+                        &m.field.to_string(),
+                        &m.action.to_string(),
+                        &m.date_added,
+                    ],
+                )
+                .await?;
+        }
+        for rule in rules.subscriptions.iter() {
+            let s: Subscription = rule.into();
+            imported.subscriptions += tx
+                .execute(
+                    &subscription_stmt,
+                    &[&s.id, &s.tag, &s.webhook, &s.date_added],
+                )
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(imported)
+    }
+
+    async fn delete_subscription(&self, id: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "DELETE FROM subscriptions WHERE id = $1";
+        let tx = conn.transaction().await?;
+        tx.execute(query, &[&id]).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_report(&self, period: String) -> Result<Option<Report>> {
+        let conn = &mut self.reader().conn().await?;
+        let query = "SELECT data FROM reports WHERE period = $1";
+        let row = conn.query_opt(query, &[&period]).await?;
+        Ok(row.map(|r| r.get::<_, Json<Report>>(0).0))
+    }
+
+    async fn has_digest(&self, period: String) -> Result<bool> {
+        let conn = &mut self.conn().await?;
+        let row = conn
+            .query_opt("SELECT 1 FROM digests WHERE period = $1", &[&period])
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn add_digest(&self, period: String, sent: String) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        conn.execute(
+            "INSERT INTO digests (period, sent) VALUES ($1, $2) ON CONFLICT (period) DO NOTHING",
+            &[&period, &sent],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn save_report(&self, report: &Report) -> Result<()> {
+        let conn = &mut self.conn().await?;
+        let query = "INSERT INTO reports (period, generated, data) VALUES ($1, $2, $3) ON CONFLICT (period) DO UPDATE SET generated = $2, data = $3";
+        let tx = conn.transaction().await?;
+        tx.execute(query, &[&report.period, &report.generated, &Json(report)])
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn compile_report(&self, period: String, start: String, end: String) -> Result<Report> {
+        let conn = &mut self.conn().await?;
+        let read_in_period = "read = true AND read_date >= $1 AND read_date < $2";
+        let (start, end) = (timestamp(&start), timestamp(&end));
+
+        let totals = conn
+            .query_one(
+                format!(
+                    "SELECT COUNT(*), COALESCE(SUM(reading_minutes), 0) FROM articles WHERE {}",
+                    read_in_period
+                )
+                .as_str(),
+                &[&start, &end],
+            )
+            .await?;
+
+        let top_feeds = conn
+            .query(
+                format!("SELECT feed_name, COUNT(*) AS count FROM {} WHERE {} GROUP BY feed_name ORDER BY count DESC, feed_name LIMIT 5", ARTICLES, read_in_period).as_str(),
+                &[&start, &end],
+            )
+            .await?;
+
+        let busiest_day = conn
+            .query_opt(
+                format!("SELECT to_char(read_date AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS day, COUNT(*) AS count FROM articles WHERE {} GROUP BY day ORDER BY count DESC, day LIMIT 1", read_in_period).as_str(),
+                &[&start, &end],
+            )
+            .await?;
+
+        let count = |r: &Row| Count {
+            name: r.get(0),
+            count: r.get(1),
+        };
+
+        Ok(Report {
+            period,
+            generated: Article::rfc3339_timestamp(),
+            articles_read: totals.get(0),
+            minutes_read: totals.get(1),
+            top_feeds: top_feeds.iter().map(count).collect(),
+            busiest_day: busiest_day.as_ref().map(count),
+        })
+    }
+}
+
+pub async fn connection(
+    username: &str,
+    password: &str,
+    host: &str,
+    port: u16,
+    dbname: &str,
+    quota: Quota,
+    breaker: Breaker,
+) -> Result<Storage> {
+    let mut config = Config::new();
+    config
+        .user(username)
+        .password(password)
+        .host(host)
+        .dbname(dbname)
+        .port(port)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .keepalives(true)
+        .keepalives_idle(KEEPALIVE_IDLE);
+    let (client, connection) = config.connect(NoTls).await?;
+
+    let storage = Storage {
+        client: Arc::new(Mutex::new(client)),
+        quota,
+        page_size: DEFAULT_PAGE_SIZE,
+        timeout: None,
+        statement_timeout: Arc::new(AtomicU64::new(0)),
+        config,
+        breaker,
+        circuit: Arc::new(Circuit::new(breaker.threshold)),
+        generation: Arc::new(AtomicU64::new(0)),
+        statements: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        replica: None,
+    };
+    storage.drive(connection, 0);
+    if let Some(interval) = breaker.health_interval {
+        storage.watch(interval);
+    }
+    Ok(storage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_round_trips() {
+        let key = Key {
+            at: timestamp("2024-05-01T10:00:00.123456Z").unwrap(),
+            id: "2dce0a4c50441bfccfa9caf4b58c3cba".to_string(),
+        };
+        let decoded = Key::decode(key.encode().as_str()).unwrap().unwrap();
+        assert_eq!(decoded.at, key.at);
+        assert_eq!(decoded.id, key.id);
+    }
+
+    #[test]
+    fn key_of_first_page_is_none() {
+        assert!(Key::decode(START).unwrap().is_none());
+    }
+
+    #[test]
+    fn key_rejects_bad_cursors() {
+        assert!(Key::decode("not base64!").is_err());
+        assert!(Key::decode(URL_SAFE_NO_PAD.encode("no separator").as_str()).is_err());
+        assert!(Key::decode(URL_SAFE_NO_PAD.encode("yesterday|id").as_str()).is_err());
+    }
+
+    fn links(page: usize, pages: usize) -> Vec<Option<usize>> {
+        PageNumbers {
+            page,
+            pages,
+            total: pages * DEFAULT_PAGE_SIZE,
+        }
+        .links()
+        .iter()
+        .map(|l| l.as_ref().map(|l| l.number))
+        .collect()
+    }
+
+    #[test]
+    fn page_links_list_few_pages_whole() {
+        assert_eq!(links(1, 1), vec![Some(1)]);
+        assert_eq!(links(2, 4), vec![Some(1), Some(2), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn page_links_leave_out_pages_far_from_this_one() {
+        assert_eq!(
+            links(1, 10),
+            vec![Some(1), Some(2), Some(3), None, Some(10)]
+        );
+        assert_eq!(
+            links(6, 10),
+            vec![
+                Some(1),
+                None,
+                Some(4),
+                Some(5),
+                Some(6),
+                Some(7),
+                Some(8),
+                None,
+                Some(10)
+            ]
+        );
+        assert_eq!(
+            links(10, 10),
+            vec![Some(1), None, Some(8), Some(9), Some(10)]
+        );
+    }
+
+    #[test]
+    fn page_links_mark_the_current_page() {
+        let numbers = PageNumbers {
+            page: 3,
+            pages: 5,
+            total: 20,
+        };
+        let current: Vec<usize> = numbers
+            .links()
+            .iter()
+            .flatten()
+            .filter(|l| l.current)
+            .map(|l| l.number)
+            .collect();
+        assert_eq!(current, vec![3]);
+    }
 }
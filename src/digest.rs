@@ -0,0 +1,207 @@
+use super::db::{self, Ordering, Sort, SortKey};
+use super::Article;
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Datelike, SecondsFormat, Timelike, Utc};
+#[cfg(feature = "email")]
+use lettre::message::{header::ContentType, Mailbox};
+#[cfg(feature = "email")]
+use lettre::transport::smtp::authentication::Credentials;
+#[cfg(feature = "email")]
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env;
+#[cfg(feature = "email")]
+use std::sync::Arc;
+
+const DEFAULT_HOUR: u32 = 7;
+
+#[derive(Clone, Copy)]
+pub enum Schedule {
+    Daily,
+    Weekly,
+}
+
+impl Schedule {
+    /// The day or ISO week `now` falls in, a digest is sent once for each.
+    fn period(&self, now: DateTime<Utc>) -> String {
+        match self {
+            Schedule::Daily => now.format("%Y-%m-%d").to_string(),
+            Schedule::Weekly => {
+                let week = now.iso_week();
+                format!("{:04}-W{:02}", week.year(), week.week())
+            }
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Schedule::Daily => "Your daily digest",
+            Schedule::Weekly => "Your weekly digest",
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "digest.html")]
+struct DigestTemplate {
+    title: &'static str,
+    articles: Vec<Article>,
+}
+
+#[cfg(feature = "email")]
+struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+/// Emails the first page of unread articles once a day or once a week, for catching up from the
+/// inbox. Sent with the `email` feature when `DIGEST_SMTP_HOST`, `DIGEST_EMAIL_FROM` and
+/// `DIGEST_EMAIL_TO` are set, `DIGEST_SMTP_USERNAME` and `DIGEST_SMTP_PASSWORD` log in.
+///
+/// - `DIGEST_SCHEDULE` is `daily` (the default) or `weekly`, weekly digests go out on Mondays.
+/// - `DIGEST_HOUR` (7) is the hour of the day in UTC from which the digest is sent.
+/// - `DIGEST_SORT` is `published` (the default) for the newest articles or `score` for the ones
+///   most likely worth reading.
+#[derive(Clone)]
+pub struct Digest {
+    #[cfg(feature = "email")]
+    mailer: Option<Arc<Mailer>>,
+    schedule: Schedule,
+    hour: u32,
+    sort: Sort,
+}
+
+impl Digest {
+    pub fn from_env() -> Result<Digest> {
+        let schedule = match env::var("DIGEST_SCHEDULE").as_deref() {
+            Ok("daily") | Err(_) => Schedule::Daily,
+            Ok("weekly") => Schedule::Weekly,
+            Ok(s) => {
+                return Err(anyhow::Error::msg(format!(
+                    "unknown digest schedule: {}",
+                    s
+                )))
+            }
+        };
+        let hour = env::var("DIGEST_HOUR")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|h| *h < 24)
+            .unwrap_or(DEFAULT_HOUR);
+        let key = match env::var("DIGEST_SORT").as_deref() {
+            Ok("published") | Err(_) => SortKey::Published,
+            Ok("score") => SortKey::Score,
+            Ok(s) => return Err(anyhow::Error::msg(format!("unknown digest sort: {}", s))),
+        };
+        let sort = Sort {
+            key,
+            ordering: Ordering::Descending,
+            page_size: None,
+            page: None,
+        };
+
+        #[cfg(feature = "email")]
+        let mailer = match (
+            env::var("DIGEST_SMTP_HOST"),
+            env::var("DIGEST_EMAIL_FROM"),
+            env::var("DIGEST_EMAIL_TO"),
+        ) {
+            (Ok(host), Ok(from), Ok(to)) => {
+                let mut transport = AsyncSmtpTransport::<Tokio1Executor>::relay(host.as_str())?;
+                if let (Ok(username), Ok(password)) = (
+                    env::var("DIGEST_SMTP_USERNAME"),
+                    env::var("DIGEST_SMTP_PASSWORD"),
+                ) {
+                    transport = transport.credentials(Credentials::new(username, password));
+                }
+                Some(Arc::new(Mailer {
+                    transport: transport.build(),
+                    from: from.parse()?,
+                    to: to.parse()?,
+                }))
+            }
+            _ => None,
+        };
+        #[cfg(not(feature = "email"))]
+        if env::var("DIGEST_SMTP_HOST").is_ok() {
+            return Err(anyhow::Error::msg(
+                "DIGEST_SMTP_HOST is set but this build has no email feature",
+            ));
+        }
+
+        Ok(Digest {
+            #[cfg(feature = "email")]
+            mailer,
+            schedule,
+            hour,
+            sort,
+        })
+    }
+
+    /// Sends this period's digest unless it went out already or its hour has not come yet.
+    pub async fn due(&self, store: &dyn db::Store) -> Result<()> {
+        let now = Utc::now();
+        if !self.configured() || now.hour() < self.hour {
+            return Ok(());
+        }
+        let period = self.schedule.period(now);
+        if store.has_digest(period.clone()).await? {
+            return Ok(());
+        }
+
+        let sent = self.send(store).await?;
+        store
+            .add_digest(
+                period.clone(),
+                now.to_rfc3339_opts(SecondsFormat::Millis, true),
+            )
+            .await?;
+        println!("sent the {} digest with {} articles", period, sent);
+        Ok(())
+    }
+
+    /// Renders and sends the digest right away, returning how many articles it listed.
+    pub async fn send(&self, store: &dyn db::Store) -> Result<usize> {
+        let page = store
+            .get_unread_articles(self.sort, self.sort.start().to_string())
+            .await?;
+        let articles: Vec<Article> = page.pinned.into_iter().chain(page.items).collect();
+        let count = articles.len();
+        let title = self.schedule.title();
+        let html = DigestTemplate { title, articles }.render()?;
+        self.deliver(title, html).await?;
+        Ok(count)
+    }
+
+    #[cfg(feature = "email")]
+    fn configured(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    #[cfg(not(feature = "email"))]
+    fn configured(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "email")]
+    async fn deliver(&self, subject: &str, html: String) -> Result<()> {
+        let mailer = self
+            .mailer
+            .as_ref()
+            .ok_or_else(|| anyhow::Error::msg("digest email is not configured"))?;
+        let message = Message::builder()
+            .from(mailer.from.clone())
+            .to(mailer.to.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_HTML)
+            .body(html)?;
+        mailer.transport.send(message).await?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "email"))]
+    async fn deliver(&self, _subject: &str, _html: String) -> Result<()> {
+        Err(anyhow::Error::msg("this build has no email feature"))
+    }
+}
@@ -0,0 +1,129 @@
+use super::{blob, proxy};
+use feed_rs::model::Entry;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A media file attached to an article, such as a podcast episode or a photo.
+#[derive(Deserialize, Serialize, Clone, Debug, Schema)]
+pub struct Enclosure {
+    pub url: String,
+    pub mime: String,
+    pub length: i64,
+    /// Play time in seconds, zero when the feed does not say.
+    pub duration: i32,
+    /// Hash of the copy in the blob store, empty when there is none.
+    pub blob: String,
+}
+
+impl Enclosure {
+    /// Collects media content and thumbnails along with atom `rel="enclosure"` links, keeping the first of any repeated url.
+    pub fn from_entry(entry: &Entry) -> Vec<Enclosure> {
+        let media = entry.media.iter().flat_map(|m| {
+            // itunes:duration is given for the whole media object rather than each file
+            let duration =
+                |d: Option<Duration>| d.or(m.duration).unwrap_or_default().as_secs() as i32;
+            let content = m.content.iter().filter_map(|c| {
+                Some(Enclosure {
+                    url: c.url.as_ref()?.to_string(),
+                    mime: c
+                        .content_type
+                        .as_ref()
+                        .map(|t| t.to_string())
+                        .unwrap_or_default(),
+                    length: c.size.unwrap_or_default() as i64,
+                    duration: duration(c.duration),
+                    blob: "".to_string(),
+                })
+            });
+            let mut content: Vec<Enclosure> = content.collect();
+            // thumbnails carry no type, only that they are an image of some kind, and are
+            // left out when the full size image is already there
+            if !content.iter().any(|c| c.is_image()) {
+                content.extend(m.thumbnails.iter().map(|t| Enclosure {
+                    url: t.image.uri.clone(),
+                    mime: "image/*".to_string(),
+                    length: 0,
+                    duration: 0,
+                    blob: "".to_string(),
+                }));
+            }
+            content
+        });
+
+        let links = entry
+            .links
+            .iter()
+            .filter(|l| l.rel.as_deref() == Some("enclosure"))
+            .map(|l| Enclosure {
+                url: l.href.clone(),
+                mime: l.media_type.clone().unwrap_or_default(),
+                length: l.length.unwrap_or_default() as i64,
+                duration: 0,
+                blob: "".to_string(),
+            });
+
+        let mut enclosures: Vec<Enclosure> = vec![];
+        for e in media.chain(links) {
+            if !e.url.is_empty() && !enclosures.iter().any(|x| x.url == e.url) {
+                enclosures.push(e);
+            }
+        }
+        enclosures
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.mime.starts_with("image/")
+    }
+
+    pub fn is_audio(&self) -> bool {
+        self.mime.starts_with("audio/")
+    }
+
+    pub fn is_video(&self) -> bool {
+        self.mime.starts_with("video/")
+    }
+
+    /// Where the browser should load the image from, the cached copy when there is one and the
+    /// image proxy otherwise.
+    pub fn src(&self) -> String {
+        match self.blob.as_str() {
+            "" | blob::UNCACHEABLE => proxy::url(&self.url),
+            hash => format!("/blobs/{}", hash),
+        }
+    }
+
+    /// Play time as h:mm:ss or m:ss, empty when unknown.
+    pub fn runtime(&self) -> String {
+        let (h, m, s) = (
+            self.duration / 3600,
+            self.duration / 60 % 60,
+            self.duration % 60,
+        );
+        match (h, m, s) {
+            (0, 0, 0) => "".to_string(),
+            (0, m, s) => format!("{}:{:02}", m, s),
+            (h, m, s) => format!("{}:{:02}:{:02}", h, m, s),
+        }
+    }
+
+    /// The file name at the end of the url, used as the link text for downloads.
+    pub fn name(&self) -> &str {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        path.rsplit('/')
+            .find(|s| !s.is_empty())
+            .unwrap_or(self.url.as_str())
+    }
+}
+
+impl From<&tokio_postgres::Row> for Enclosure {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Enclosure {
+            url: row.get(1),
+            mime: row.get(2),
+            length: row.get(3),
+            duration: row.get(4),
+            blob: row.get(5),
+        }
+    }
+}
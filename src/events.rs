@@ -0,0 +1,95 @@
+use super::Article;
+use futures::{Stream, StreamExt};
+use rweb::warp::sse;
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+/// Events kept for listeners that fall behind, older ones are dropped for them.
+const CAPACITY: usize = 256;
+
+/// What happened during a refresh, as sent to `/events` listeners. Job events name the feed by
+/// id, articles by the feed name they are listed under.
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Started {
+        job: String,
+        feed: String,
+    },
+    Finished {
+        job: String,
+        feed: String,
+        added: usize,
+    },
+    Failed {
+        job: String,
+        feed: String,
+        error: String,
+    },
+    Canceled {
+        job: String,
+        feed: String,
+    },
+    Article {
+        feed: String,
+        id: String,
+        title: String,
+        link: String,
+    },
+}
+
+impl Event {
+    pub fn article(a: &Article) -> Event {
+        Event::Article {
+            feed: a.feed.clone(),
+            id: a.id.clone(),
+            title: a.title.clone(),
+            link: a.link.clone(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Started { .. } => "started",
+            Event::Finished { .. } => "finished",
+            Event::Failed { .. } => "failed",
+            Event::Canceled { .. } => "canceled",
+            Event::Article { .. } => "article",
+        }
+    }
+}
+
+/// Broadcasts refresh progress and new articles to whoever is listening on `/events`. Sending
+/// with nobody listening does nothing.
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CAPACITY);
+        Events { tx }
+    }
+}
+
+impl Events {
+    pub fn send(&self, e: Event) {
+        let _ = self.tx.send(e);
+    }
+
+    /// Every event from now on as server-sent events named after their type. A listener that
+    /// fell behind misses what was dropped and carries on.
+    pub fn stream(&self) -> impl Stream<Item = Result<sse::Event, Infallible>> {
+        BroadcastStream::new(self.tx.subscribe()).filter_map(|e| async move {
+            let e = e.ok()?;
+            sse::Event::default()
+                .event(e.name())
+                .json_data(&e)
+                .ok()
+                .map(Ok)
+        })
+    }
+}
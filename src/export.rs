@@ -0,0 +1,229 @@
+use super::db;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+use tokio_postgres::types::Type;
+use tokio_postgres::Row;
+
+#[derive(Clone, Copy)]
+pub enum Column {
+    Id,
+    Feed,
+    Title,
+    Link,
+    Author,
+    Published,
+    Read,
+    Favorited,
+    ReadDate,
+    Summary,
+    Content,
+    WordCount,
+    ReadingMinutes,
+    Note,
+}
+
+pub const DEFAULT_COLUMNS: [Column; 8] = [
+    Column::Feed,
+    Column::Title,
+    Column::Link,
+    Column::Author,
+    Column::Published,
+    Column::Read,
+    Column::Favorited,
+    Column::ReadDate,
+];
+
+impl Column {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::Feed => "feed",
+            Column::Title => "title",
+            Column::Link => "link",
+            Column::Author => "author",
+            Column::Published => "published",
+            Column::Read => "read",
+            Column::Favorited => "favorited",
+            Column::ReadDate => "read_date",
+            Column::Summary => "summary",
+            Column::Content => "content",
+            Column::WordCount => "word_count",
+            Column::ReadingMinutes => "reading_minutes",
+            Column::Note => "note",
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Column> {
+        match s {
+            "id" => Ok(Column::Id),
+            "feed" => Ok(Column::Feed),
+            "title" => Ok(Column::Title),
+            "link" => Ok(Column::Link),
+            "author" => Ok(Column::Author),
+            "published" => Ok(Column::Published),
+            "read" => Ok(Column::Read),
+            "favorited" => Ok(Column::Favorited),
+            "read_date" => Ok(Column::ReadDate),
+            "summary" => Ok(Column::Summary),
+            "content" => Ok(Column::Content),
+            "word_count" => Ok(Column::WordCount),
+            "reading_minutes" => Ok(Column::ReadingMinutes),
+            "note" => Ok(Column::Note),
+            _ => Err(anyhow::Error::msg(format!("bad export column: {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Json => "application/json",
+        }
+    }
+
+    pub fn filename(&self) -> &'static str {
+        match self {
+            Format::Csv => "articles.csv",
+            Format::Json => "articles.json",
+        }
+    }
+
+    /// Written before the first row.
+    pub fn header(&self, columns: &[Column]) -> String {
+        match self {
+            Format::Csv => csv_header(columns),
+            Format::Json => "[".to_string(),
+        }
+    }
+
+    /// Written after the last row.
+    pub fn footer(&self) -> String {
+        match self {
+            Format::Csv => "".to_string(),
+            Format::Json => "\n]\n".to_string(),
+        }
+    }
+
+    /// Formats the `i`th row of the export from the values of its `columns`.
+    pub fn row(&self, i: usize, columns: &[Column], values: &[Value]) -> String {
+        match self {
+            Format::Csv => csv_row(values),
+            Format::Json if i == 0 => format!("\n{}", json_row(columns, values)),
+            Format::Json => format!(",\n{}", json_row(columns, values)),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Format> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow::Error::msg(format!("bad export format: {}", s))),
+        }
+    }
+}
+
+/// Parses a comma separated column list, falling back to [DEFAULT_COLUMNS] when none is given.
+pub fn columns(list: Option<String>) -> Result<Vec<Column>> {
+    match list {
+        Some(list) if !list.is_empty() => list.split(',').map(|c| c.trim().parse()).collect(),
+        _ => Ok(DEFAULT_COLUMNS.to_vec()),
+    }
+}
+
+pub fn csv_header(columns: &[Column]) -> String {
+    let names: Vec<String> = columns.iter().map(|c| csv_escape(c.name())).collect();
+    names.join(",") + "\n"
+}
+
+/// The first `width` columns of an exported row, dates in rfc3339 and null when there is none.
+pub fn values(row: &Row, width: usize) -> Vec<Value> {
+    row.columns()
+        .iter()
+        .take(width)
+        .enumerate()
+        .map(|(i, c)| match *c.type_() {
+            Type::BOOL => Value::from(row.get::<_, bool>(i)),
+            Type::INT4 => Value::from(row.get::<_, i32>(i)),
+            Type::TIMESTAMPTZ => match row.get::<_, Option<DateTime<Utc>>>(i) {
+                Some(dt) => Value::from(db::text(Some(dt), "")),
+                None => Value::Null,
+            },
+            _ => Value::from(row.get::<_, String>(i)),
+        })
+        .collect()
+}
+
+pub fn csv_row(values: &[Value]) -> String {
+    let fields: Vec<String> = values
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => csv_escape(s),
+            Value::Null => "".to_string(),
+            v => v.to_string(),
+        })
+        .collect();
+    fields.join(",") + "\n"
+}
+
+pub fn json_row(columns: &[Column], values: &[Value]) -> String {
+    let fields: Map<String, Value> = columns
+        .iter()
+        .zip(values)
+        .map(|(c, v)| (c.name().to_string(), v.clone()))
+        .collect();
+    Value::Object(fields).to_string()
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_escape_quotes_fields_that_need_it() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape("a, b"), "\"a, b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_escape("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_escape(""), "");
+    }
+
+    #[test]
+    fn csv_row_writes_nulls_empty_and_other_values_as_is() {
+        let row = csv_row(&[
+            Value::from("Hello, \"world\""),
+            Value::Null,
+            Value::from(true),
+            Value::from(42),
+        ]);
+        assert_eq!(row, "\"Hello, \"\"world\"\"\",,true,42\n");
+    }
+
+    #[test]
+    fn csv_header_names_the_columns() {
+        assert_eq!(csv_header(&[Column::Feed, Column::Title]), "feed,title\n");
+    }
+}
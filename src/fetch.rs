@@ -0,0 +1,277 @@
+use super::{client, db};
+use anyhow::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{
+    HeaderMap, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE,
+    RETRY_AFTER,
+};
+use reqwest::StatusCode;
+use rweb::hyper::body::Bytes;
+use rweb::Schema;
+use serde::Serialize;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// How much of a document is downloaded to look for its build marker.
+const PROBE_BYTES: usize = 1024;
+/// A Retry-After further out than this is cut short, so a bad header cannot stop a feed for good.
+const MAX_RETRY_AFTER_DAYS: i64 = 7;
+/// Fetch attempts are kept this long, to look back on when and why a feed went quiet.
+const LOG_KEEP_DAYS: i64 = 365;
+
+/// A server that answered 429 or 503 and asked with Retry-After not to be fetched before `until`.
+#[derive(Debug)]
+pub struct RetryAfter {
+    pub status: StatusCode,
+    pub until: DateTime<Utc>,
+}
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, asked not to be fetched before {}",
+            self.status,
+            self.until.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )
+    }
+}
+
+impl std::error::Error for RetryAfter {}
+
+pub struct Fetched {
+    pub status: StatusCode,
+    pub body: Bytes,
+    pub etag: String,
+    pub last_modified: String,
+    pub marker: String,
+    pub content_type: String,
+}
+
+/// The last document fetched for a feed, gzipped and cut at a size, kept to look at when it
+/// does not parse.
+#[derive(Clone)]
+pub struct Payload {
+    pub feed: String,
+    pub fetched: DateTime<Utc>,
+    pub content_type: String,
+    /// The size of the whole document, `body` holds at most the kept part of it.
+    pub size: i64,
+    pub truncated: bool,
+    pub body: Vec<u8>,
+}
+
+impl Payload {
+    /// Compresses up to `max` bytes of the document.
+    pub fn new(feed: String, fetched: &Fetched, max: usize) -> Result<Self> {
+        let kept = &fetched.body[..fetched.body.len().min(max)];
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(kept)?;
+        Ok(Payload {
+            feed,
+            fetched: Utc::now(),
+            content_type: fetched.content_type.clone(),
+            size: fetched.body.len() as i64,
+            truncated: kept.len() < fetched.body.len(),
+            body: encoder.finish()?,
+        })
+    }
+
+    /// The kept part of the document as it was sent.
+    pub fn document(&self) -> Result<Vec<u8>> {
+        let mut document = Vec::new();
+        GzDecoder::new(&self.body[..]).read_to_end(&mut document)?;
+        Ok(document)
+    }
+}
+
+impl From<&tokio_postgres::Row> for Payload {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Payload {
+            feed: row.get("feed"),
+            fetched: row.get("fetched"),
+            content_type: row.get("content_type"),
+            size: row.get("size"),
+            truncated: row.get("truncated"),
+            body: row.get("body"),
+        }
+    }
+}
+
+pub enum Fetch {
+    Unchanged,
+    Fetched(Fetched),
+}
+
+/// One refresh of a feed as recorded in the fetch log: the status it was answered with (304
+/// when unchanged, 0 when no answer came), the size of the document, how long fetching and
+/// parsing it took and how many new articles it brought.
+#[derive(Serialize, Clone, Schema)]
+pub struct Attempt {
+    pub feed: String,
+    pub date: String,
+    pub status: i32,
+    pub bytes: i64,
+    pub duration_ms: i64,
+    pub added: i32,
+    pub error: String,
+}
+
+impl Attempt {
+    pub fn new(feed: String, date: String) -> Self {
+        Attempt {
+            feed,
+            date,
+            status: 0,
+            bytes: 0,
+            duration_ms: 0,
+            added: 0,
+            error: "".to_string(),
+        }
+    }
+}
+
+impl From<&tokio_postgres::Row> for Attempt {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Attempt {
+            feed: row.get(0),
+            date: row.get(1),
+            status: row.get(2),
+            bytes: row.get(3),
+            duration_ms: row.get(4),
+            added: row.get(5),
+            error: row.get(6),
+        }
+    }
+}
+
+/// The status a failed fetch was answered with, 0 when it got no answer.
+pub fn status(e: &anyhow::Error) -> i32 {
+    if let Some(r) = e.downcast_ref::<RetryAfter>() {
+        return r.status.as_u16() as i32;
+    }
+    e.downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status())
+        .map(|s| s.as_u16() as i32)
+        .unwrap_or(0)
+}
+
+/// Deletes fetch attempts older than `LOG_KEEP_DAYS`, returning how many.
+pub async fn expire(store: &dyn db::Store) -> Result<u64> {
+    let before =
+        (Utc::now() - Duration::days(LOG_KEEP_DAYS)).to_rfc3339_opts(SecondsFormat::Millis, true);
+    store.delete_fetch_attempts(before).await
+}
+
+/// Fetches a feed document unless it has not changed since the `etag`, `last_modified` and
+/// `marker` recorded on the previous fetch. Servers that sent validators are asked with a
+/// conditional GET, for the rest the start of the document is probed for its build marker.
+pub async fn fetch(url: &str, etag: &str, last_modified: &str, marker: &str) -> Result<Fetch> {
+    let client = client::shared();
+
+    if !etag.is_empty() || !last_modified.is_empty() {
+        let mut req = client.get(url);
+        if !etag.is_empty() {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if !last_modified.is_empty() {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let resp = req.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetch::Unchanged);
+        }
+        return Ok(Fetch::Fetched(fetched(check(resp)?).await?));
+    }
+
+    if !marker.is_empty() {
+        let resp = client
+            .get(url)
+            .header(RANGE, format!("bytes=0-{}", PROBE_BYTES - 1))
+            .send()
+            .await?;
+        match resp.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                let probe = resp.bytes().await?;
+                if build_marker(&probe) == marker {
+                    return Ok(Fetch::Unchanged);
+                }
+            }
+            // the server ignored the range, so this already is the full document
+            s if s.is_success() => return Ok(Fetch::Fetched(fetched(resp).await?)),
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                check(resp)?;
+            }
+            _ => {}
+        }
+    }
+
+    let resp = check(client.get(url).send().await?)?;
+    Ok(Fetch::Fetched(fetched(resp).await?))
+}
+
+/// Fails on error statuses, with `RetryAfter` when the server said when to come back.
+fn check(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        if let Some(until) = retry_after(resp.headers(), Utc::now()) {
+            return Err(RetryAfter { status, until }.into());
+        }
+    }
+    Ok(resp.error_for_status()?)
+}
+
+/// When a Retry-After header, in seconds or as an http date, allows the next request.
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = header(headers, RETRY_AFTER);
+    let value = value.trim();
+    let max = Duration::days(MAX_RETRY_AFTER_DAYS);
+    let until = match value.parse::<i64>() {
+        Ok(seconds) => now + Duration::seconds(seconds.clamp(0, max.num_seconds())),
+        Err(_) => DateTime::parse_from_rfc2822(value)
+            .ok()?
+            .with_timezone(&Utc),
+    };
+    Some(until.min(now + max))
+}
+
+async fn fetched(resp: reqwest::Response) -> Result<Fetched> {
+    let status = resp.status();
+    let etag = header(resp.headers(), ETAG);
+    let last_modified = header(resp.headers(), LAST_MODIFIED);
+    let content_type = header(resp.headers(), CONTENT_TYPE);
+    let body = resp.bytes().await?;
+    Ok(Fetched {
+        status,
+        marker: build_marker(&body),
+        etag,
+        last_modified,
+        content_type,
+        body,
+    })
+}
+
+fn header(headers: &HeaderMap, name: reqwest::header::HeaderName) -> String {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Finds the channel's `lastBuildDate` (rss) or `updated` (atom) near the start of a document.
+fn build_marker(body: &[u8]) -> String {
+    let head = String::from_utf8_lossy(&body[..body.len().min(PROBE_BYTES)]);
+    ["lastBuildDate", "updated"]
+        .iter()
+        .find_map(|tag| {
+            let open = format!("<{}>", tag);
+            let start = head.find(open.as_str())? + open.len();
+            let end = head[start..].find(format!("</{}>", tag).as_str())?;
+            Some(head[start..start + end].trim().to_string())
+        })
+        .unwrap_or_default()
+}
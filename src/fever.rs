@@ -0,0 +1,328 @@
+use super::openapi::{self, Location};
+use super::{db, reject_anyhow, BulkAction};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use md5::{Digest, Md5};
+use rweb::filters::BoxedFilter;
+use rweb::*;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+const API_VERSION: u32 = 3;
+/// The most items one call returns, clients page through the rest with since_id and max_id.
+const ITEMS_LIMIT: i64 = 50;
+/// Feeds are not sorted into folders here, they are all listed in the one group.
+const GROUP_ID: i64 = 1;
+const GROUP_TITLE: &str = "All";
+
+/// A feed as the Fever api lists it.
+#[derive(Serialize)]
+pub struct Feed {
+    pub(crate) id: i64,
+    pub(crate) favicon_id: i64,
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) site_url: String,
+    pub(crate) is_spark: u8,
+    pub(crate) last_updated_on_time: i64,
+}
+
+impl From<&tokio_postgres::Row> for Feed {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Feed {
+            id: row.get(0),
+            favicon_id: 0,
+            title: row.get(1),
+            url: row.get(2),
+            site_url: row.get(3),
+            is_spark: 0,
+            last_updated_on_time: seconds(row.get(4)),
+        }
+    }
+}
+
+/// An article as the Fever api lists it, identified by its number.
+#[derive(Serialize)]
+pub struct Item {
+    pub(crate) id: i64,
+    pub(crate) feed_id: i64,
+    pub(crate) title: String,
+    pub(crate) author: String,
+    pub(crate) html: String,
+    pub(crate) url: String,
+    pub(crate) is_saved: u8,
+    pub(crate) is_read: u8,
+    pub(crate) created_on_time: i64,
+}
+
+impl From<&tokio_postgres::Row> for Item {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        let content: String = row.get(4);
+        Item {
+            id: row.get(0),
+            feed_id: row.get::<_, Option<i64>>(1).unwrap_or_default(),
+            title: row.get(2),
+            author: row.get(3),
+            html: match content.is_empty() {
+                true => row.get(5),
+                false => content,
+            },
+            url: row.get(6),
+            is_saved: row.get::<_, bool>(7).into(),
+            is_read: row.get::<_, bool>(8).into(),
+            created_on_time: seconds(row.get(9)),
+        }
+    }
+}
+
+/// Which articles a call for items asks for, by number.
+pub enum Items {
+    /// The ones after this number, oldest first.
+    Since(i64),
+    /// The ones before this number, newest first.
+    Before(i64),
+    /// These, whatever state they are in.
+    With(Vec<i64>),
+}
+
+/// Unix time as Fever counts it, zero for never.
+pub(crate) fn seconds(dt: Option<DateTime<Utc>>) -> i64 {
+    dt.map(|dt| dt.timestamp()).unwrap_or_default()
+}
+
+/// Numbers as a comma separated list, the way Fever sends the unread and saved items.
+fn joined(ids: &[i64]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Access to the Fever sync api at `/fever/`, for mobile clients such as Reeder and Unread.
+/// Clients log in with `FEVER_EMAIL` and `FEVER_PASSWORD`, the api is off unless both are set.
+#[derive(Clone, Default)]
+pub struct Fever {
+    /// md5 of `email:password` in hex, the api_key clients send with every call.
+    key: Option<Arc<String>>,
+}
+
+impl Fever {
+    pub fn from_env() -> Fever {
+        match (env::var("FEVER_EMAIL"), env::var("FEVER_PASSWORD")) {
+            (Ok(email), Ok(password)) => Fever {
+                key: Some(Arc::new(api_key(&email, &password))),
+            },
+            _ => Fever::default(),
+        }
+    }
+
+    fn authorized(&self, key: Option<&String>) -> bool {
+        match (&self.key, key) {
+            (Some(expected), Some(key)) => expected.eq_ignore_ascii_case(key),
+            _ => false,
+        }
+    }
+}
+
+fn api_key(email: &str, password: &str) -> String {
+    Md5::digest(format!("{}:{}", email, password).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Answers one api call. What to return is named in the query, as in `?api&items&since_id=10`,
+/// the key and changes usually come in the form body, so both are taken from either. An unknown
+/// key gets `auth: 0` and nothing else.
+async fn call(
+    fever: &Fever,
+    store: &dyn db::Store,
+    params: HashMap<String, String>,
+) -> Result<Map<String, Value>> {
+    let mut reply = Map::new();
+    reply.insert("api_version".to_string(), json!(API_VERSION));
+    if !fever.authorized(params.get("api_key")) {
+        reply.insert("auth".to_string(), json!(0));
+        return Ok(reply);
+    }
+    reply.insert("auth".to_string(), json!(1));
+    reply.insert(
+        "last_refreshed_on_time".to_string(),
+        json!(seconds(store.get_last_refreshed().await?)),
+    );
+
+    if let Some(mark) = params.get("mark") {
+        mark_as(store, mark, &params).await?;
+    }
+
+    let wants = |name: &str| params.contains_key(name);
+    if wants("groups") || wants("feeds") {
+        let feeds = store.get_fever_feeds().await?;
+        let ids: Vec<i64> = feeds.iter().map(|f| f.id).collect();
+        reply.insert(
+            "feeds_groups".to_string(),
+            json!([{ "group_id": GROUP_ID, "feed_ids": joined(&ids) }]),
+        );
+        if wants("groups") {
+            reply.insert(
+                "groups".to_string(),
+                json!([{ "id": GROUP_ID, "title": GROUP_TITLE }]),
+            );
+        }
+        if wants("feeds") {
+            reply.insert("feeds".to_string(), json!(feeds));
+        }
+    }
+    // there are no favicons or hot links to offer, clients still expect the lists
+    if wants("favicons") {
+        reply.insert("favicons".to_string(), json!([]));
+    }
+    if wants("links") {
+        reply.insert("links".to_string(), json!([]));
+    }
+    if wants("items") {
+        let number = |name: &str| params.get(name).and_then(|s| s.parse::<i64>().ok());
+        let items = match (number("max_id"), params.get("with_ids")) {
+            (_, Some(ids)) => Items::With(
+                ids.split(',')
+                    .filter_map(|id| id.trim().parse().ok())
+                    .take(ITEMS_LIMIT as usize)
+                    .collect(),
+            ),
+            (Some(max), None) => Items::Before(max),
+            (None, None) => Items::Since(number("since_id").unwrap_or_default()),
+        };
+        reply.insert(
+            "total_items".to_string(),
+            json!(store.count_fever_items().await?),
+        );
+        reply.insert(
+            "items".to_string(),
+            json!(store.get_fever_items(items, ITEMS_LIMIT).await?),
+        );
+    }
+    // a change is answered with the list it changed, for the client to check against
+    let marked = params.get("as").map(String::as_str);
+    if wants("unread_item_ids") || matches!(marked, Some("read" | "unread")) {
+        let ids = store.get_fever_item_ids(false).await?;
+        reply.insert("unread_item_ids".to_string(), json!(joined(&ids)));
+    }
+    if wants("saved_item_ids") || matches!(marked, Some("saved" | "unsaved")) {
+        let ids = store.get_fever_item_ids(true).await?;
+        reply.insert("saved_item_ids".to_string(), json!(joined(&ids)));
+    }
+    Ok(reply)
+}
+
+/// Applies `mark=item|feed|group`, `as` says what to, `id` which one. Feeds and groups can only
+/// be marked read, up to `before` so articles stored since the client last synced stay unread.
+async fn mark_as(
+    store: &dyn db::Store,
+    mark: &str,
+    params: &HashMap<String, String>,
+) -> Result<()> {
+    let id: i64 = params
+        .get("id")
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("mark needs a numeric id"))?;
+    let what = params.get("as").map(String::as_str).unwrap_or_default();
+    match mark {
+        "item" => {
+            let action = match what {
+                "read" => BulkAction::Read,
+                "unread" => BulkAction::Unread,
+                "saved" => BulkAction::Favorite,
+                "unsaved" => BulkAction::Unfavorite,
+                other => return Err(anyhow!("cannot mark an item as {}", other)),
+            };
+            let articles = store.get_article_ids_by_seq(vec![id]).await?;
+            store.bulk_update_articles(articles, action).await?;
+        }
+        "feed" | "group" if what == "read" => {
+            let before = params
+                .get("before")
+                .and_then(|s| s.parse().ok())
+                .and_then(|s| Utc.timestamp_opt(s, 0).single())
+                .unwrap_or_else(Utc::now);
+            // group 0 is Kindling, every feed, and the one group holds every feed as well
+            let feed = match mark {
+                "feed" => Some(id),
+                _ => None,
+            };
+            store.mark_read_before(feed, None, before).await?;
+        }
+        other => return Err(anyhow!("cannot mark {} as {}", other, what)),
+    }
+    Ok(())
+}
+
+/// `/fever/` takes GET and POST, with or without the trailing slash clients tend to add.
+/// What [call] reads, from the query or the form alike. The route takes any parameters, so
+/// the spec lists these by hand.
+const PARAMETERS: &[(&str, &str)] = &[
+    ("api", "Any call to the api."),
+    ("api_key", "The md5 of `<email>:<password>`."),
+    ("groups", "Return the one group."),
+    ("feeds", "Return the feeds."),
+    ("favicons", "Return the favicons, there are none."),
+    ("links", "Return the hot links, there are none."),
+    ("items", "Return a page of items."),
+    ("since_id", "Items after this one."),
+    ("max_id", "Items before this one."),
+    ("with_ids", "These items, comma separated."),
+    ("unread_item_ids", "Return the ids of the unread items."),
+    ("saved_item_ids", "Return the ids of the saved items."),
+    ("mark", "`item`, `feed` or `group`, what to mark."),
+    (
+        "as",
+        "`read`, `unread`, `saved` or `unsaved`, what to mark it as.",
+    ),
+    ("id", "The item, feed or group to mark."),
+    ("before", "Mark a feed or group read up to this unix time."),
+];
+
+pub fn routes(store: Arc<dyn db::Store>, fever: Fever) -> BoxedFilter<(Box<dyn Reply>,)> {
+    for method in [http::Method::GET, http::Method::POST] {
+        openapi::describe(
+            "/fever/",
+            method,
+            "The Fever api. What to return is named in the query as in `?api&items`, the key \
+             and changes usually come in the form.",
+            |c, op| {
+                for (name, description) in PARAMETERS {
+                    openapi::parameter(op, Location::Query, name, description);
+                }
+                c.add_response_to::<Json<Value>>(op);
+            },
+        );
+    }
+    let form = warp::body::form::<HashMap<String, String>>()
+        .or(warp::any().map(HashMap::new))
+        .unify();
+    warp::path("fever")
+        .and(warp::path::tail())
+        .and(warp::get().or(warp::post()).unify())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(form)
+        .and_then(
+            move |tail: warp::path::Tail,
+                  mut params: HashMap<String, String>,
+                  form: HashMap<String, String>| {
+                let (store, fever) = (store.clone(), fever.clone());
+                async move {
+                    if !tail.as_str().is_empty() {
+                        return Err(warp::reject::not_found());
+                    }
+                    params.extend(form);
+                    let reply = call(&fever, store.as_ref(), params)
+                        .await
+                        .map_err(reject_anyhow)?;
+                    Ok(Box::new(warp::reply::json(&reply)) as Box<dyn Reply>)
+                }
+            },
+        )
+        .boxed()
+}
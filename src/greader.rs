@@ -0,0 +1,732 @@
+use super::openapi::{self, Authorized, Documented, Fields, Location, Text};
+use super::{db, reject_anyhow, BulkAction};
+use chrono::{DateTime, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use rweb::filters::BoxedFilter;
+use rweb::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const READING_LIST: &str = "user/-/state/com.google/reading-list";
+const READ: &str = "user/-/state/com.google/read";
+const STARRED: &str = "user/-/state/com.google/starred";
+const LABEL: &str = "user/-/label/";
+pub(crate) const FEED: &str = "feed/";
+/// Long item ids are this followed by the article's number as 16 hex digits.
+const ITEM: &str = "tag:google.com,2005:reader/item/";
+/// Items in a stream page unless the client asks for another number.
+const DEFAULT_COUNT: i64 = 20;
+const MAX_COUNT: i64 = 1000;
+
+/// A stream of items as clients name them. Feeds are named by their number, as in `feed/12`,
+/// and labels by the tag, as in `user/-/label/news`.
+#[derive(Clone)]
+pub enum Stream {
+    All,
+    Read,
+    Starred,
+    Feed(i64),
+    Label(String),
+}
+
+impl FromStr for Stream {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = own(s);
+        match s.as_ref() {
+            READING_LIST => Ok(Stream::All),
+            READ => Ok(Stream::Read),
+            STARRED => Ok(Stream::Starred),
+            s => match (s.strip_prefix(LABEL), s.strip_prefix(FEED)) {
+                (Some(tag), _) if !tag.is_empty() => Ok(Stream::Label(tag.to_string())),
+                (_, Some(seq)) => seq
+                    .parse()
+                    .map(Stream::Feed)
+                    .map_err(|_| anyhow::anyhow!("unknown stream {}", s)),
+                _ => Err(anyhow::anyhow!("unknown stream {}", s)),
+            },
+        }
+    }
+}
+
+/// A state or label as the user `-`, which is how they are listed. Some clients name the user
+/// by number instead, as in `user/1001/state/com.google/read`.
+fn own(s: &str) -> Cow<'_, str> {
+    match s.strip_prefix("user/").and_then(|s| s.split_once('/')) {
+        Some((user, rest)) if user != "-" => Cow::Owned(format!("user/-/{}", rest)),
+        _ => Cow::Borrowed(s),
+    }
+}
+
+/// What a client asks of a stream: which items, in what order and from where to continue.
+pub struct Selection {
+    pub stream: Stream,
+    pub exclude_read: bool,
+    /// Items stored at or after this time, `ot`.
+    pub since: Option<DateTime<Utc>>,
+    /// Items stored before this time, `nt`.
+    pub until: Option<DateTime<Utc>>,
+    pub oldest_first: bool,
+    /// The number of the last item on the previous page.
+    pub continuation: Option<i64>,
+    pub count: i64,
+}
+
+/// An article as it is sent to clients.
+pub struct Item {
+    pub seq: i64,
+    pub(crate) feed: Option<i64>,
+    pub(crate) feed_name: String,
+    pub(crate) site_url: String,
+    pub(crate) title: String,
+    pub(crate) author: String,
+    pub(crate) html: String,
+    pub(crate) link: String,
+    pub(crate) read: bool,
+    pub(crate) favorited: bool,
+    pub(crate) tags: Vec<String>,
+    pub(crate) published: Option<DateTime<Utc>>,
+    pub(crate) date_added: DateTime<Utc>,
+}
+
+impl From<&tokio_postgres::Row> for Item {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        let content: String = row.get(6);
+        Item {
+            seq: row.get(0),
+            feed: row.get(1),
+            feed_name: row.get(2),
+            site_url: row.get::<_, Option<String>>(3).unwrap_or_default(),
+            title: row.get(4),
+            author: row.get(5),
+            html: match content.is_empty() {
+                true => row.get(7),
+                false => content,
+            },
+            link: row.get(8),
+            read: row.get(9),
+            favorited: row.get(10),
+            tags: row.get(11),
+            published: row.get(12),
+            date_added: row.get(13),
+        }
+    }
+}
+
+impl Item {
+    fn json(&self) -> Value {
+        let mut categories = vec![READING_LIST.to_string()];
+        if self.read {
+            categories.push(READ.to_string());
+        }
+        if self.favorited {
+            categories.push(STARRED.to_string());
+        }
+        categories.extend(self.tags.iter().map(|t| format!("{}{}", LABEL, t)));
+        let published = self.published.unwrap_or(self.date_added).timestamp();
+        json!({
+            "id": long_id(self.seq),
+            "crawlTimeMsec": self.date_added.timestamp_millis().to_string(),
+            "timestampUsec": self.date_added.timestamp_micros().to_string(),
+            "published": published,
+            "updated": published,
+            "title": self.title,
+            "author": self.author,
+            "canonical": [{ "href": self.link }],
+            "alternate": [{ "href": self.link, "type": "text/html" }],
+            "summary": { "direction": "ltr", "content": self.html },
+            "categories": categories,
+            "origin": {
+                "streamId": format!("{}{}", FEED, self.feed.unwrap_or_default()),
+                "title": self.feed_name,
+                "htmlUrl": self.site_url,
+            },
+        })
+    }
+}
+
+/// A feed in the subscription list.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) categories: Vec<Value>,
+    pub(crate) url: String,
+    pub(crate) html_url: String,
+    pub(crate) icon_url: String,
+}
+
+impl From<&tokio_postgres::Row> for Subscription {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Subscription {
+            id: format!("{}{}", FEED, row.get::<_, i64>(0)),
+            title: row.get(1),
+            categories: vec![],
+            url: row.get(2),
+            html_url: row.get(3),
+            icon_url: "".to_string(),
+        }
+    }
+}
+
+/// Unread articles of a feed.
+pub struct UnreadCount {
+    pub(crate) feed: i64,
+    pub(crate) count: i64,
+    pub(crate) last_updated: Option<DateTime<Utc>>,
+}
+
+impl From<&tokio_postgres::Row> for UnreadCount {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        UnreadCount {
+            feed: row.get(0),
+            count: row.get(1),
+            last_updated: row.get(2),
+        }
+    }
+}
+
+fn long_id(seq: i64) -> String {
+    format!("{}{:016x}", ITEM, seq)
+}
+
+/// The number of an item id in either form: long as in [long_id], or short as the same number
+/// in decimal.
+fn item_id(id: &str) -> Option<i64> {
+    match id.strip_prefix(ITEM) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok().map(|n| n as i64),
+        None => id.parse().ok(),
+    }
+}
+
+/// Access to the Google Reader api, for clients such as NetNewsWire and FeedMe. Clients log in
+/// at `/accounts/ClientLogin` with `GREADER_USERNAME` and `GREADER_PASSWORD`, the api is off
+/// unless both are set.
+#[derive(Clone, Default)]
+pub struct GReader {
+    login: Option<Arc<Login>>,
+}
+
+struct Login {
+    username: String,
+    password: String,
+    /// Handed out on login and sent back with every call, it stays the same until the password
+    /// changes.
+    token: String,
+}
+
+impl GReader {
+    pub fn from_env() -> GReader {
+        match (env::var("GREADER_USERNAME"), env::var("GREADER_PASSWORD")) {
+            (Ok(username), Ok(password)) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(password.as_bytes())
+                    .expect("hmac takes keys of any size");
+                mac.update(username.as_bytes());
+                let token = mac
+                    .finalize()
+                    .into_bytes()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                GReader {
+                    login: Some(Arc::new(Login {
+                        username,
+                        password,
+                        token,
+                    })),
+                }
+            }
+            _ => GReader::default(),
+        }
+    }
+
+    /// Whether the `Authorization` header carries the token, as `GoogleLogin auth=<token>`.
+    fn authorized(&self, header: &Option<String>) -> bool {
+        let token = header
+            .as_deref()
+            .and_then(|h| h.strip_prefix("GoogleLogin auth="));
+        match (&self.login, token) {
+            (Some(login), Some(token)) => login.token == token.trim(),
+            _ => false,
+        }
+    }
+}
+
+openapi::header!(
+    /// The `Authorization` header, a call without one is answered 401 like one with a wrong token.
+    Authorization("authorization"): Option<String>,
+    "`GoogleLogin auth=<token>`, with the token `/accounts/ClientLogin` answered with."
+);
+
+/// What a call answers with when authorized, documented as `T`.
+type Answer<T> = Documented<Authorized<T>>;
+
+fn text<T>(status: http::StatusCode, body: String) -> Answer<T> {
+    Documented::new(warp::reply::with_status(body, status))
+}
+
+fn json<T>(body: &Value) -> Answer<T> {
+    Documented::new(warp::reply::json(body))
+}
+
+fn unauthorized<T>() -> Answer<T> {
+    text(http::StatusCode::UNAUTHORIZED, "Unauthorized".to_string())
+}
+
+fn ok() -> Answer<Text> {
+    text(http::StatusCode::OK, "OK".to_string())
+}
+
+fn seconds(s: &str) -> Option<DateTime<Utc>> {
+    s.parse()
+        .ok()
+        .and_then(|s| Utc.timestamp_opt(s, 0).single())
+}
+
+/// The values of every `name` in a form, which may repeat as in `i=1&i=2`.
+fn all<'a>(form: &'a [(String, String)], name: &'a str) -> impl Iterator<Item = &'a str> {
+    form.iter()
+        .filter(move |(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// The items a form lists as `i`, `i=1&i=2`.
+#[derive(Schema)]
+struct Items {
+    /// An item, in either id form.
+    i: Vec<String>,
+}
+
+impl From<Vec<(String, String)>> for Items {
+    fn from(form: Vec<(String, String)>) -> Self {
+        Items {
+            i: all(&form, "i").map(str::to_string).collect(),
+        }
+    }
+}
+
+/// The items listed as `i` and the states added to (`a`) and removed from (`r`) them.
+#[derive(Schema)]
+struct EditTag {
+    /// An item, in either id form.
+    i: Vec<String>,
+    /// A state to add, `user/-/state/com.google/read` or `.../starred`.
+    a: Vec<String>,
+    /// A state to remove.
+    r: Vec<String>,
+}
+
+impl From<Vec<(String, String)>> for EditTag {
+    fn from(form: Vec<(String, String)>) -> Self {
+        let values = |name| all(&form, name).map(str::to_string).collect();
+        EditTag {
+            i: values("i"),
+            a: values("a"),
+            r: values("r"),
+        }
+    }
+}
+
+#[derive(Deserialize, Schema)]
+struct MarkAll {
+    /// The stream to mark, everything when left out.
+    s: Option<String>,
+    /// Microseconds since the epoch, articles that arrived later stay unread.
+    ts: Option<String>,
+}
+
+#[derive(Deserialize, Schema)]
+struct Credentials {
+    #[serde(rename = "Email")]
+    email: String,
+    #[serde(rename = "Passwd")]
+    passwd: String,
+}
+
+/// Google Reader login, answers with the token as `Auth=<token>`.
+#[post("/accounts/ClientLogin")]
+async fn client_login(
+    c: Form<Credentials>,
+    #[data] greader: GReader,
+) -> Result<Answer<Text>, Rejection> {
+    let c = c.into_inner();
+    match greader.login.as_deref() {
+        Some(login) if login.username == c.email && login.password == c.passwd => Ok(text(
+            http::StatusCode::OK,
+            format!(
+                "SID={}\nLSID={}\nAuth={}\n",
+                login.token, login.token, login.token
+            ),
+        )),
+        _ => Ok(text(
+            http::StatusCode::UNAUTHORIZED,
+            "Error=BadAuthentication\n".to_string(),
+        )),
+    }
+}
+
+/// The token asked for before edits, which is the login token again.
+#[get("/reader/api/0/token")]
+async fn token(auth: Authorization, #[data] greader: GReader) -> Result<Answer<Text>, Rejection> {
+    match (&greader.login, greader.authorized(&auth.0)) {
+        (Some(login), true) => Ok(text(http::StatusCode::OK, login.token.clone())),
+        _ => Ok(unauthorized()),
+    }
+}
+
+/// The user the login is for.
+#[get("/reader/api/0/user-info")]
+async fn user_info(
+    auth: Authorization,
+    #[data] greader: GReader,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    match (&greader.login, greader.authorized(&auth.0)) {
+        (Some(login), true) => Ok(json(&json!({
+            "userId": "1",
+            "userName": login.username,
+            "userProfileId": "1",
+            "userEmail": "",
+        }))),
+        _ => Ok(unauthorized()),
+    }
+}
+
+/// The feeds subscribed to.
+#[get("/reader/api/0/subscription/list")]
+async fn subscriptions(
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let subscriptions = store
+        .get_greader_subscriptions()
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(json(&json!({ "subscriptions": subscriptions })))
+}
+
+/// Starred and the article tags, which clients show as labels.
+#[get("/reader/api/0/tag/list")]
+async fn tags(
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let mut tags = vec![json!({ "id": STARRED })];
+    for t in store.get_tags().await.map_err(reject_anyhow)? {
+        tags.push(json!({ "id": format!("{}{}", LABEL, t.name), "type": "tag" }));
+    }
+    Ok(json(&json!({ "tags": tags })))
+}
+
+/// How many items each feed has unread, and the reading list all of them.
+#[get("/reader/api/0/unread-count")]
+async fn unread_count(
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let counts = store
+        .get_greader_unread_counts()
+        .await
+        .map_err(reject_anyhow)?;
+    let usec = |dt: Option<DateTime<Utc>>| dt.map(|dt| dt.timestamp_micros()).unwrap_or_default();
+    let mut unread: Vec<Value> = counts
+        .iter()
+        .map(|c| {
+            json!({
+                "id": format!("{}{}", FEED, c.feed),
+                "count": c.count,
+                "newestItemTimestampUsec": usec(c.last_updated).to_string(),
+            })
+        })
+        .collect();
+    unread.push(json!({
+        "id": READING_LIST,
+        "count": counts.iter().map(|c| c.count).sum::<i64>(),
+        "newestItemTimestampUsec": usec(counts.iter().filter_map(|c| c.last_updated).max()).to_string(),
+    }));
+    Ok(json(&json!({ "max": MAX_COUNT, "unreadcounts": unread })))
+}
+
+#[derive(Deserialize, Default, Schema)]
+struct StreamOptions {
+    /// The stream: `user/-/state/com.google/reading-list`, `.../read`, `.../starred`,
+    /// `user/-/label/<tag>` or `feed/<id>`.
+    s: Option<String>,
+    /// A stream to leave out, usually `user/-/state/com.google/read`.
+    xt: Option<String>,
+    /// How many items to return.
+    n: Option<i64>,
+    /// `o` for oldest first.
+    r: Option<String>,
+    /// The continuation the page before ended with.
+    c: Option<String>,
+    /// Only items newer than this unix time.
+    ot: Option<String>,
+    /// Only items older than this unix time.
+    nt: Option<String>,
+}
+
+impl StreamOptions {
+    /// The query for the stream these options name, or else `stream`.
+    fn selection(&self, stream: Option<&str>) -> anyhow::Result<Selection> {
+        let stream = stream
+            .or(self.s.as_deref())
+            .unwrap_or(READING_LIST)
+            .parse()?;
+        Ok(Selection {
+            stream,
+            exclude_read: self.xt.as_deref().map(own).as_deref() == Some(READ),
+            since: self.ot.as_deref().and_then(seconds),
+            until: self.nt.as_deref().and_then(seconds),
+            oldest_first: self.r.as_deref() == Some("o"),
+            continuation: self.c.as_deref().and_then(|c| c.parse().ok()),
+            count: self.n.unwrap_or(DEFAULT_COUNT).clamp(1, MAX_COUNT),
+        })
+    }
+}
+
+/// Where the page after `items` starts, when the page was full.
+fn continuation(q: &Selection, items: &[Item]) -> Option<String> {
+    match items.len() as i64 == q.count {
+        true => items.last().map(|i| i.seq.to_string()),
+        false => None,
+    }
+}
+
+/// The ids of a stream's items, clients ask for the contents of the ones they do not have.
+#[get("/reader/api/0/stream/items/ids")]
+async fn stream_ids(
+    options: Query<StreamOptions>,
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let q = options
+        .into_inner()
+        .selection(None)
+        .map_err(reject_anyhow)?;
+    let items = store.get_greader_items(&q).await.map_err(reject_anyhow)?;
+    let refs: Vec<Value> = items
+        .iter()
+        .map(|i| json!({ "id": i.seq.to_string() }))
+        .collect();
+    let mut body = json!({ "itemRefs": refs });
+    if let Some(c) = continuation(&q, &items) {
+        body["continuation"] = json!(c);
+    }
+    Ok(json(&body))
+}
+
+/// The items listed as `i`, in either id form.
+#[post("/reader/api/0/stream/items/contents")]
+async fn item_contents(
+    form: Fields<Items>,
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let seqs: Vec<i64> = form
+        .0
+        .i
+        .iter()
+        .map(String::as_str)
+        .filter_map(item_id)
+        .collect();
+    let items = store
+        .get_greader_items_by_seq(seqs)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(json(&json!({
+        "id": READING_LIST,
+        "updated": Utc::now().timestamp(),
+        "items": items.iter().map(Item::json).collect::<Vec<_>>(),
+    })))
+}
+
+/// A page of a stream with the items in full. The stream is named in the path, percent
+/// encoded or not, or else by `s`.
+async fn stream_contents(
+    tail: warp::path::Tail,
+    options: StreamOptions,
+    auth: Authorization,
+    greader: GReader,
+    store: Arc<dyn db::Store>,
+) -> Result<Answer<Json<Value>>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let stream = percent_decode_str(tail.as_str()).decode_utf8_lossy();
+    let stream = Some(stream.as_ref()).filter(|s| !s.is_empty());
+    let q = options.selection(stream).map_err(reject_anyhow)?;
+    let items = store.get_greader_items(&q).await.map_err(reject_anyhow)?;
+    let mut body = json!({
+        "id": stream.unwrap_or(READING_LIST),
+        "updated": Utc::now().timestamp(),
+        "items": items.iter().map(Item::json).collect::<Vec<_>>(),
+    });
+    if let Some(c) = continuation(&q, &items) {
+        body["continuation"] = json!(c);
+    }
+    Ok(json(&body))
+}
+
+/// Adds (`a`) and removes (`r`) the read and starred states of the items listed as `i`.
+#[post("/reader/api/0/edit-tag")]
+async fn edit_tag(
+    form: Fields<EditTag>,
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Text>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let form = form.0;
+    let seqs: Vec<i64> = form
+        .i
+        .iter()
+        .map(String::as_str)
+        .filter_map(item_id)
+        .collect();
+    let ids = store
+        .get_article_ids_by_seq(seqs)
+        .await
+        .map_err(reject_anyhow)?;
+    let actions = form
+        .a
+        .iter()
+        .filter_map(|tag| match own(tag).as_ref() {
+            READ => Some(BulkAction::Read),
+            STARRED => Some(BulkAction::Favorite),
+            _ => None,
+        })
+        .chain(form.r.iter().filter_map(|tag| match own(tag).as_ref() {
+            READ => Some(BulkAction::Unread),
+            STARRED => Some(BulkAction::Unfavorite),
+            _ => None,
+        }));
+    for action in actions {
+        store
+            .bulk_update_articles(ids.clone(), action)
+            .await
+            .map_err(reject_anyhow)?;
+    }
+    Ok(ok())
+}
+
+/// Marks a feed or everything read, up to `ts` in microseconds so what arrived after the
+/// client last synced stays unread.
+#[post("/reader/api/0/mark-all-as-read")]
+async fn mark_all_read(
+    form: Form<MarkAll>,
+    auth: Authorization,
+    #[data] greader: GReader,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Answer<Text>, Rejection> {
+    if !greader.authorized(&auth.0) {
+        return Ok(unauthorized());
+    }
+    let form = form.into_inner();
+    let stream: Stream = form
+        .s
+        .as_deref()
+        .unwrap_or(READING_LIST)
+        .parse()
+        .map_err(reject_anyhow)?;
+    let before = form
+        .ts
+        .and_then(|ts| ts.parse().ok())
+        .and_then(|us| Utc.timestamp_micros(us).single())
+        .unwrap_or_else(Utc::now);
+    let (feed, tag) = match stream {
+        Stream::Feed(seq) => (Some(seq), None),
+        Stream::Label(tag) => (None, Some(tag)),
+        Stream::All => (None, None),
+        // read articles are read already and starred ones are left to be read one by one
+        Stream::Read | Stream::Starred => return Ok(ok()),
+    };
+    store
+        .mark_read_before(feed, tag, before)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(ok())
+}
+
+pub fn routes(store: Arc<dyn db::Store>, greader: GReader) -> BoxedFilter<(Box<dyn Reply>,)> {
+    // the stream is the rest of the path, which the route macros cannot take
+    openapi::describe(
+        "/reader/api/0/stream/contents/{stream}",
+        http::Method::GET,
+        "A page of a stream with the items in full. The stream is named in the path, percent \
+         encoded or not, or else by `s`.",
+        |c, op| {
+            openapi::parameter(
+                op,
+                Location::Path,
+                "stream",
+                "The stream, as `s` names them.",
+            );
+            c.add_request_type_to::<Query<StreamOptions>>(op);
+            c.add_request_type_to::<Authorization>(op);
+            c.add_response_to::<Authorized<Json<Value>>>(op);
+        },
+    );
+    let contents = {
+        let (store, greader) = (store.clone(), greader.clone());
+        warp::get()
+            .and(warp::path!(
+                "reader" / "api" / "0" / "stream" / "contents" / ..
+            ))
+            .and(warp::path::tail())
+            .and(
+                warp::query::<StreamOptions>()
+                    .or(warp::any().map(StreamOptions::default))
+                    .unify(),
+            )
+            .and(Authorization::new())
+            .and_then(move |tail, options, auth| {
+                stream_contents(tail, options, auth, greader.clone(), store.clone())
+            })
+            .map(|r| Box::new(r) as Box<dyn Reply>)
+    };
+
+    client_login(greader.clone())
+        .or(token(greader.clone()))
+        .or(user_info(greader.clone()))
+        .or(subscriptions(greader.clone(), store.clone()))
+        .or(tags(greader.clone(), store.clone()))
+        .or(unread_count(greader.clone(), store.clone()))
+        .or(stream_ids(greader.clone(), store.clone()))
+        .or(item_contents(greader.clone(), store.clone()))
+        .or(edit_tag(greader.clone(), store.clone()))
+        .or(mark_all_read(greader, store))
+        .map(|r| Box::new(r) as Box<dyn Reply>)
+        .or(contents)
+        .unify()
+        .boxed()
+}
@@ -0,0 +1,82 @@
+use super::AddFeed;
+use anyhow::Result;
+#[cfg(feature = "email")]
+use mailparse::ParsedMail;
+use opml::{Outline, OPML};
+use rweb::Schema;
+use serde::Serialize;
+
+/// What an opml import added to the subscriptions. Added feeds are refreshed in the background
+/// after the response is sent. Skipped feeds were already subscribed to or were unsubscribed
+/// from before.
+#[derive(Serialize, Schema)]
+pub struct FeedImport {
+    pub added: Vec<String>,
+    pub skipped: usize,
+}
+
+/// Every outline with a feed url, including ones nested inside folders.
+pub fn feeds(document: &str) -> Result<Vec<AddFeed>> {
+    let opml = OPML::from_str(document)?;
+    let mut feeds = vec![];
+    collect(&opml.body.outlines, &mut feeds);
+    Ok(feeds)
+}
+
+fn collect(outlines: &[Outline], feeds: &mut Vec<AddFeed>) {
+    for o in outlines {
+        if let Some(feed_url) = o.xml_url.as_ref().filter(|u| !u.is_empty()) {
+            let name = o
+                .title
+                .clone()
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| o.text.clone());
+            feeds.push(AddFeed {
+                feed_name: name,
+                site_url: o.html_url.clone().unwrap_or_default(),
+                feed_url: feed_url.clone(),
+            });
+        }
+        collect(&o.outlines, feeds);
+    }
+}
+
+/// The feeds from every opml attachment of a raw email message, for mail piped in by an mta.
+#[cfg(feature = "email")]
+pub fn email(message: &[u8]) -> Result<Vec<AddFeed>> {
+    let mail = mailparse::parse_mail(message)?;
+    let mut attachments = vec![];
+    find_opml(&mail, &mut attachments);
+    if attachments.is_empty() {
+        return Err(anyhow::Error::msg("message has no opml attachment"));
+    }
+
+    let mut feeds = vec![];
+    for a in attachments {
+        feeds.extend(self::feeds(a.get_body()?.as_str())?);
+    }
+    Ok(feeds)
+}
+
+#[cfg(not(feature = "email"))]
+pub fn email(_message: &[u8]) -> Result<Vec<AddFeed>> {
+    Err(anyhow::Error::msg("this build has no email feature"))
+}
+
+#[cfg(feature = "email")]
+fn find_opml<'a>(part: &'a ParsedMail<'a>, found: &mut Vec<&'a ParsedMail<'a>>) {
+    let filename = part
+        .get_content_disposition()
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .map(|f| f.to_lowercase())
+        .unwrap_or_default();
+    let mime = part.ctype.mimetype.to_lowercase();
+    if filename.ends_with(".opml") || mime == "text/x-opml" || mime == "application/x-opml" {
+        found.push(part);
+    }
+    for p in part.subparts.iter() {
+        find_opml(p, found);
+    }
+}
@@ -0,0 +1,19 @@
+use whatlang::Lang;
+
+/// Guesses the language of an article from its title and summary, returning the ISO 639-3
+/// code or an empty string when the guess is not reliable.
+pub fn detect(title: &str, summary: &str) -> String {
+    let summary = ammonia::Builder::empty().clean(summary).to_string();
+    match whatlang::detect(format!("{}\n{}", title, summary).as_str()) {
+        Some(info) if info.is_reliable() => info.lang().code().to_string(),
+        _ => "".to_string(),
+    }
+}
+
+/// The english name of a language code, or the code itself when it is not known.
+pub fn name(code: &str) -> &str {
+    match Lang::from_code(code) {
+        Some(lang) => lang.eng_name(),
+        None => code,
+    }
+}
@@ -0,0 +1,3472 @@
+#![recursion_limit = "256"]
+
+mod alert;
+mod api;
+mod blob;
+mod canonical;
+mod chaos;
+mod circuit;
+mod client;
+pub mod db;
+mod digest;
+mod enclosure;
+mod events;
+mod export;
+mod fetch;
+mod fever;
+mod greader;
+mod import;
+mod language;
+mod maintenance;
+pub mod memory;
+mod migrate;
+mod mute;
+mod notify;
+mod openapi;
+mod politeness;
+mod polling;
+mod prefetch;
+mod proxy;
+mod push;
+mod queue;
+mod refresh;
+mod report;
+mod rules;
+mod sanitize;
+mod schema;
+mod score;
+mod scrape;
+pub mod search;
+mod share;
+mod view;
+#[cfg(feature = "push")]
+mod webpush;
+
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, SecondsFormat, Utc};
+use core::panic;
+use futures::stream::StreamExt;
+use futures::{future, stream, FutureExt};
+use rweb::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, env, str::FromStr, sync::Arc, vec};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time;
+use tokio_stream::wrappers::{IntervalStream, SignalStream};
+
+const DEFAULT_REFRESH_SECONDS: u64 = 3 * 60;
+const WORDS_PER_MINUTE: i32 = 200;
+const REPORT_INTERVAL_SECONDS: u64 = 24 * 60 * 60;
+const DEFAULT_MAINTENANCE_SECONDS: u64 = 24 * 60 * 60;
+const DEFAULT_REQUEST_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_JOB_TIMEOUT_SECONDS: u64 = 5 * 60;
+const IMAGES_CACHED_PER_REFRESH: i64 = 50;
+const SUGGESTION_DAYS: i64 = 90;
+const RANDOM_MIN_AGE_DAYS: i64 = 7;
+const SUGGESTION_MIN_ARTICLES: i64 = 10;
+const SUGGESTION_UNREAD_RATIO: f64 = 0.9;
+const DEFAULT_SIMULATE_LIMIT: i64 = 100;
+const MAX_SIMULATE_LIMIT: i64 = 1000;
+const JOBS_LIMIT: i64 = 100;
+const FETCHES_LIMIT: i64 = 100;
+/// How much of the sha256 feed and article ids are made of, migrations/0011_short_ids.sql cuts
+/// ids of older articles to the same length.
+const ID_BYTES: usize = 16;
+
+#[derive(Debug)]
+struct AppError(anyhow::Error);
+impl rweb::reject::Reject for AppError {}
+
+fn reject_anyhow(err: anyhow::Error) -> Rejection {
+    warp::reject::custom(AppError(err))
+}
+
+async fn recover(err: Rejection) -> Result<Box<dyn Reply>, Rejection> {
+    let AppError(e) = match err.find::<AppError>() {
+        Some(e) => e,
+        None => return Err(err),
+    };
+
+    let cancelled = e
+        .downcast_ref::<tokio_postgres::Error>()
+        .and_then(|e| e.code())
+        == Some(&tokio_postgres::error::SqlState::QUERY_CANCELED);
+
+    let status = if e.is::<db::QuotaExceeded>() || e.is::<proxy::Forbidden>() {
+        http::StatusCode::FORBIDDEN
+    } else if e.is::<db::DeadlineExceeded>() || e.is::<db::Unavailable>() || cancelled {
+        http::StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        http::StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    Ok(Box::new(warp::reply::with_status(e.to_string(), status)))
+}
+
+#[derive(Template)]
+#[template(path = "tags.html")]
+struct TagsTemplate {
+    tags: Vec<report::Count>,
+}
+
+#[derive(Template)]
+#[template(path = "tag_list.html")]
+struct TagListTemplate {
+    tags: Vec<report::Count>,
+}
+
+#[derive(Template)]
+#[template(path = "cleanup.html")]
+struct CleanupTemplate {
+    suggestions: Vec<db::Suggestion>,
+}
+
+#[derive(Template)]
+#[template(path = "suggestion_list.html")]
+struct SuggestionListTemplate {
+    suggestions: Vec<db::Suggestion>,
+}
+
+#[derive(Template)]
+#[template(path = "domains.html")]
+struct DomainsTemplate {
+    domains: Vec<db::Domain>,
+}
+
+#[derive(Template)]
+#[template(path = "domain_list.html")]
+struct DomainListTemplate {
+    domains: Vec<db::Domain>,
+}
+
+#[derive(Template)]
+#[template(path = "filters.html")]
+struct FiltersTemplate {
+    filters: Vec<mute::Mute>,
+}
+
+#[derive(Template)]
+#[template(path = "filter_list.html")]
+struct FilterListTemplate {
+    filters: Vec<mute::Mute>,
+}
+
+#[derive(Template)]
+#[template(path = "notifications.html")]
+struct NotificationsTemplate {
+    subscriptions: Vec<notify::Subscription>,
+    /// Empty when browser notifications are off.
+    push_key: String,
+}
+
+#[derive(Template)]
+#[template(path = "sw.js", escape = "none")]
+struct ServiceWorkerTemplate {}
+
+#[derive(Template)]
+#[template(path = "subscription_list.html")]
+struct SubscriptionListTemplate {
+    subscriptions: Vec<notify::Subscription>,
+}
+
+#[derive(Template)]
+#[template(path = "views.html")]
+struct ViewsTemplate {
+    views: Vec<view::View>,
+}
+
+#[derive(Template)]
+#[template(path = "view_list.html")]
+struct ViewListTemplate {
+    views: Vec<view::View>,
+}
+
+#[derive(Deserialize, Serialize, Schema)]
+struct Healthz {
+    up: bool,
+}
+
+#[derive(Serialize, Schema)]
+struct Version {
+    version: &'static str,
+    commit: &'static str,
+    built: &'static str,
+    features: Vec<&'static str>,
+    schema: u32,
+}
+
+#[derive(Template)]
+#[template(path = "feeds.html")]
+struct FeedsTemplate {
+    cursor: db::Cursor,
+    feeds: Vec<Feed>,
+    /// Unread articles by feed id, feeds without any are left out.
+    unread: BTreeMap<String, i64>,
+}
+
+#[derive(Template)]
+#[template(path = "feed_list.html")]
+struct FeedListTemplate {
+    cursor: db::Cursor,
+    feeds: Vec<Feed>,
+    /// Unread articles by feed id, feeds without any are left out.
+    unread: BTreeMap<String, i64>,
+}
+
+#[derive(Template)]
+#[template(path = "podcasts.html")]
+struct PodcastsTemplate {
+    cursor: db::Cursor,
+    articles: Vec<Article>,
+}
+
+#[derive(Template)]
+#[template(path = "podcast_list.html")]
+struct PodcastListTemplate {
+    cursor: db::Cursor,
+    articles: Vec<Article>,
+}
+
+#[derive(Template)]
+#[template(path = "add_feed.html")]
+struct AddFeedTemplate {}
+
+#[derive(Template)]
+#[template(path = "article_list.html")]
+struct ArticleListTemplate {
+    cursor: db::Cursor,
+    sort: db::Sort,
+    pinned: Vec<Article>,
+    articles: Vec<Article>,
+    /// Url of the prefetched next page, empty when the next page is asked for by cursor.
+    next_page: String,
+    numbers: Option<db::PageNumbers>,
+}
+
+#[derive(Template)]
+#[template(path = "article.html")]
+struct ArticleTemplate {
+    article: Article,
+    targets: Vec<&'static str>,
+}
+
+#[derive(Template)]
+#[template(path = "article_detail.html")]
+struct ArticleDetailTemplate {
+    article: Article,
+    targets: Vec<&'static str>,
+}
+
+#[derive(Template)]
+#[template(path = "report.html")]
+struct ReportTemplate {
+    title: String,
+    report: report::Report,
+}
+
+#[derive(Template, Default)]
+#[template(path = "articles.html")]
+struct ArticleBaseTemplate {
+    article_filter: String,
+    title: String,
+    cursor: db::Cursor,
+    sort: db::Sort,
+    counts: db::Counts,
+    pinned: Vec<Article>,
+    articles: Vec<Article>,
+    next_page: String,
+    numbers: Option<db::PageNumbers>,
+}
+
+openapi::reply_as!(
+    openapi::Html: TagsTemplate,
+    TagListTemplate,
+    CleanupTemplate,
+    SuggestionListTemplate,
+    DomainsTemplate,
+    DomainListTemplate,
+    FiltersTemplate,
+    FilterListTemplate,
+    NotificationsTemplate,
+    SubscriptionListTemplate,
+    ViewsTemplate,
+    ViewListTemplate,
+    FeedsTemplate,
+    FeedListTemplate,
+    PodcastsTemplate,
+    PodcastListTemplate,
+    AddFeedTemplate,
+    ArticleListTemplate,
+    ArticleDetailTemplate,
+    ReportTemplate,
+    ArticleBaseTemplate,
+);
+openapi::reply_as!(openapi::Script: ServiceWorkerTemplate);
+
+#[derive(Deserialize, Serialize, Clone, Debug, Schema)]
+pub struct Feed {
+    id: String,
+    name: String,
+    site_url: String,
+    feed_url: String,
+    date_added: String,
+    last_updated: String,
+    poll_interval: i32,
+    next_poll: String,
+    last_modified: String,
+    build_marker: String,
+    muted: bool,
+    search_language: String,
+    keep_content: bool,
+    push: bool,
+    /// Seconds between refreshes chosen by hand, zero to go by how often the feed publishes.
+    refresh_interval: i32,
+    etag: String,
+    /// Why the last refresh failed after retrying, empty when it succeeded.
+    fetch_error: String,
+    /// Scheduling hints from the feed document, used when the interval is not set by hand.
+    hints: polling::Hints,
+    /// When the publisher last asked, with Retry-After, not to be fetched before.
+    retry_after: String,
+    /// Refreshes that failed in a row since the last one that succeeded.
+    failures: i32,
+    /// Set once `failures` reaches `FEED_MAX_FAILURES`, the scheduler leaves the feed alone until
+    /// it is enabled again by hand.
+    erroring: bool,
+}
+
+impl Feed {
+    pub fn new(name: String, site_url: String, feed_url: String) -> Self {
+        Feed {
+            id: short_id(feed_url.as_str()),
+            name,
+            site_url,
+            feed_url,
+            date_added: Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .to_string(),
+            last_updated: "-1".to_string(),
+            poll_interval: 0,
+            next_poll: "".to_string(),
+            last_modified: "".to_string(),
+            build_marker: "".to_string(),
+            muted: false,
+            search_language: "".to_string(),
+            keep_content: false,
+            push: false,
+            refresh_interval: 0,
+            etag: "".to_string(),
+            fetch_error: "".to_string(),
+            hints: polling::Hints::default(),
+            retry_after: "".to_string(),
+            failures: 0,
+            erroring: false,
+        }
+    }
+
+    /// Whether the feed was never refreshed successfully since it was added.
+    pub fn never_refreshed(&self) -> bool {
+        self.last_updated == "-1"
+    }
+
+    pub fn poll_every(&self) -> String {
+        match self.poll_interval {
+            0 => "every refresh".to_string(),
+            s if s % (24 * 60 * 60) == 0 => format!("every {}d", s / (24 * 60 * 60)),
+            s if s % (60 * 60) == 0 => format!("every {}h", s / (60 * 60)),
+            s => format!("every {}m", s / 60),
+        }
+    }
+}
+
+impl From<&tokio_postgres::Row> for Feed {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Feed {
+            id: row.get(0),
+            name: row.get(1),
+            site_url: row.get(2),
+            feed_url: row.get(3),
+            date_added: db::text(row.get(4), ""),
+            last_updated: db::text(row.get(5), "-1"),
+            poll_interval: row.get(6),
+            next_poll: row.get(7),
+            last_modified: row.get(8),
+            build_marker: row.get(9),
+            muted: row.get(10),
+            search_language: row.get(11),
+            keep_content: row.get(12),
+            push: row.get(13),
+            refresh_interval: row.get(14),
+            etag: row.get(15),
+            fetch_error: row.get(16),
+            hints: row
+                .get::<_, tokio_postgres::types::Json<polling::Hints>>(17)
+                .0,
+            retry_after: row.get(18),
+            failures: row.get(19),
+            erroring: row.get(20),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+struct AddFeed {
+    feed_name: String,
+    site_url: String,
+    feed_url: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct SearchOptions {
+    /// What to search for.
+    q: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct SearchLanguage {
+    language: String,
+}
+
+openapi::header!(
+    /// The cursor of the page an html list is on, the pages send it with every request that
+    /// renders the list again.
+    Pagination("pagination"): String,
+    "The cursor of the page to render, as the page before it was given in its cursor. `start` \
+     is the first page. The pages send it back with every request that renders a list again, \
+     so the list stays where it was."
+);
+
+openapi::header!(
+    /// The list an article action renders again.
+    ArticleFilter("article_filter"): String,
+    "The list the article belongs to and that is rendered again: `unread`, `read`, \
+     `favorite`, `hidden` or `view:<id>`."
+);
+
+#[derive(Deserialize, Schema)]
+struct SortOptions {
+    /// The column to sort by.
+    sort: Option<String>,
+    /// `asc` or `desc`.
+    dir: Option<String>,
+    #[serde(alias = "per_page")]
+    /// How many articles a page holds, up to 100. The page links keep it.
+    page_size: Option<usize>,
+    /// Pages by number instead of by cursor.
+    page: Option<usize>,
+}
+
+impl SortOptions {
+    fn sort(&self, filter: &db::Filter) -> Result<db::Sort> {
+        let mut sort = filter.default_sort();
+        if let Some(key) = &self.sort {
+            sort.key = key.parse()?;
+        }
+        if let Some(dir) = &self.dir {
+            sort.ordering = dir.parse()?;
+        }
+        sort.page_size = self.page_size.map(|s| s.clamp(1, db::MAX_PAGE_SIZE));
+        sort.page = self.page.map(|p| p.max(1));
+        Ok(sort)
+    }
+}
+
+#[derive(Deserialize, Schema)]
+struct ExportOptions {
+    /// `json` (the default) or `csv`.
+    format: Option<String>,
+    /// The columns to export, comma separated.
+    columns: Option<String>,
+    /// `unread`, `read`, `favorite` or `hidden`, every article when left out.
+    filter: Option<String>,
+    /// Only the articles of this feed.
+    feed: Option<String>,
+    /// Only articles published from this date on.
+    since: Option<String>,
+    /// Only articles published before this date.
+    until: Option<String>,
+}
+
+#[derive(Deserialize, Schema)]
+#[serde(rename_all = "lowercase")]
+enum Toggle {
+    Read,
+    Favorite,
+    Pin,
+}
+
+#[derive(Deserialize, Schema)]
+struct ArticleToggle {
+    toggle: Toggle,
+}
+
+#[derive(Deserialize, Schema)]
+#[serde(rename_all = "lowercase")]
+enum BulkAction {
+    Read,
+    Unread,
+    Favorite,
+    Unfavorite,
+    Hide,
+    Unhide,
+}
+
+#[derive(Deserialize, Schema)]
+struct BulkArticles {
+    ids: Vec<String>,
+    action: BulkAction,
+}
+
+#[derive(Deserialize, Schema)]
+struct RenameTag {
+    tag: String,
+    name: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct MergeTag {
+    tag: String,
+    /// The tag the articles end up with.
+    into: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct DeleteTag {
+    tag: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct AddFilter {
+    pattern: String,
+    regex: Option<String>,
+    field: String,
+    action: String,
+}
+
+impl AddFilter {
+    fn mute(self) -> Result<mute::Mute> {
+        mute::Mute::new(
+            self.pattern,
+            self.regex.is_some(),
+            self.field.parse()?,
+            self.action.parse()?,
+        )
+    }
+}
+
+#[derive(Deserialize, Schema)]
+struct AddView {
+    name: String,
+    filter: String,
+    feed: String,
+    tag: String,
+    keyword: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct AddSubscription {
+    tag: String,
+    webhook: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct Simulate {
+    /// How many recent articles to try the rules on.
+    limit: Option<i64>,
+    /// The rules to try, the ones in use when left out.
+    rules: Option<rules::Rules>,
+}
+
+#[derive(Deserialize, Schema)]
+struct ArticleNote {
+    note: String,
+}
+
+#[derive(Deserialize, Schema)]
+struct ArticleProgress {
+    /// The paragraph scrolled to.
+    paragraph: i32,
+}
+
+#[derive(Deserialize, Schema)]
+struct ShareArticle {
+    /// The name of the share target.
+    target: String,
+}
+
+#[derive(Serialize, Schema)]
+struct DigestSent {
+    articles: usize,
+}
+
+#[derive(Serialize, Schema)]
+struct BulkResult {
+    updated: u64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Schema)]
+pub struct Article {
+    id: String,
+    /// The name of the feed, as it is shown.
+    feed: String,
+    feed_id: String,
+    title: String,
+    link: String,
+    author: String,
+    published: String,
+    read: bool,
+    favorited: bool,
+    read_date: String,
+    summary: String,
+    content: String,
+    word_count: i32,
+    reading_minutes: i32,
+    canonical_link: String,
+    fingerprint: String,
+    sources: Vec<String>,
+    date_added: String,
+    tags: Vec<String>,
+    hidden: bool,
+    note: String,
+    pinned: bool,
+    enclosures: Vec<enclosure::Enclosure>,
+    raw: sanitize::Raw,
+    language: String,
+    /// Index of the paragraph the reader got to on the article page.
+    progress: i32,
+    content_hash: String,
+    /// When the publisher last edited it, empty if they never did.
+    updated_at: String,
+}
+
+impl Article {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        title: String,
+        link: String,
+        author: String,
+        published: String,
+        read: bool,
+        favorited: bool,
+        summary: String,
+        content: String,
+    ) -> Self {
+        // prefer the full content for estimates, the summary is usually a teaser
+        let text = match content.is_empty() {
+            true => summary.as_str(),
+            false => content.as_str(),
+        };
+        let word_count = ammonia::Builder::empty()
+            .clean(text)
+            .to_string()
+            .split_whitespace()
+            .count() as i32;
+
+        Article {
+            id: short_id(link.as_str()),
+            feed: "".to_string(),
+            feed_id: "".to_string(),
+            canonical_link: canonical::strip_tracking(link.as_str()),
+            fingerprint: Article::fingerprint(title.as_str(), author.as_str()),
+            content_hash: Article::content_hash(title.as_str(), summary.as_str(), content.as_str()),
+            sources: vec![],
+            date_added: Article::rfc3339_timestamp(),
+            tags: vec![],
+            hidden: false,
+            note: "".to_string(),
+            pinned: false,
+            enclosures: vec![],
+            raw: sanitize::Raw::default(),
+            language: language::detect(title.as_str(), summary.as_str()),
+            progress: 0,
+            updated_at: "".to_string(),
+            title,
+            link,
+            author,
+            published: match DateTime::parse_from_rfc2822(published.as_str()) {
+                Ok(dt) => dt.to_rfc3339_opts(SecondsFormat::Secs, true).to_string(),
+                Err(_) => published,
+            },
+            read,
+            favorited,
+            read_date: "-1".to_string(),
+            summary: ammonia::clean(summary.as_str()),
+            content: ammonia::clean(content.as_str()),
+            word_count,
+            reading_minutes: (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE,
+        }
+    }
+
+    /// The id of the entry a feed calls `guid`. Guids are only unique within their feed so the
+    /// feed's id is hashed along, which unlike its url stays the same when the feed moves. Links
+    /// are left out since some feeds change them on every fetch.
+    pub fn guid_id(feed: &str, guid: &str) -> String {
+        short_id(format!("{}\n{}", feed, guid).as_str())
+    }
+
+    /// The id an entry with a `guid` was stored under before guid ids hashed the feed's id
+    /// rather than its url, as migration 0011 left it.
+    pub fn legacy_guid_id(feed_url: &str, guid: &str) -> String {
+        short_id(format!("{}\n{}", feed_url, guid).as_str())
+    }
+
+    /// Hashes the normalized title and author so reposts under different links can be matched.
+    /// Untitled entries have no fingerprint since they would all collide.
+    pub fn fingerprint(title: &str, author: &str) -> String {
+        let title = title.trim().to_lowercase();
+        if title.is_empty() {
+            return "".to_string();
+        }
+
+        let digest = Sha256::new()
+            .chain_update(title)
+            .chain_update("\n")
+            .chain_update(author.trim().to_lowercase())
+            .finalize();
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Hashes what a feed says about an entry, so a refresh can tell entries it has already
+    /// stored as they are from ones the publisher has since edited.
+    pub fn content_hash(title: &str, summary: &str, content: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(title)
+            .chain_update("\n")
+            .chain_update(summary)
+            .chain_update("\n")
+            .chain_update(content)
+            .finalize();
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// The first audio enclosure, which is what podcast mode plays.
+    pub fn audio(&self) -> Option<&enclosure::Enclosure> {
+        self.enclosures.iter().find(|e| e.is_audio())
+    }
+
+    pub fn language_name(&self) -> &str {
+        language::name(self.language.as_str())
+    }
+
+    pub fn preview(&self) -> String {
+        match self.summary.is_empty() {
+            true => proxy::images(&self.content),
+            false => proxy::images(&self.summary),
+        }
+    }
+
+    /// The full text shown on the article page, falling back to the summary.
+    pub fn body(&self) -> String {
+        match self.content.is_empty() {
+            true => proxy::images(&self.summary),
+            false => proxy::images(&self.content),
+        }
+    }
+
+    pub fn rfc3339_timestamp() -> String {
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .to_string()
+    }
+
+    pub fn rfc3339_timestamp_to_human(timestamp: String) -> String {
+        match DateTime::parse_from_rfc3339(timestamp.as_str()) {
+            Ok(dt) => dt.format("%m/%d/%Y").to_string(),
+            Err(_) => timestamp,
+        }
+    }
+}
+
+impl From<&tokio_postgres::Row> for Article {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Article {
+            id: row.get(0),
+            feed: row.get("feed_name"),
+            feed_id: row.get::<_, Option<String>>("feed_id").unwrap_or_default(),
+            title: row.get(2),
+            link: row.get(3),
+            author: row.get(4),
+            published: Article::rfc3339_timestamp_to_human(db::text(row.get(5), "")),
+            read: row.get(6),
+            favorited: row.get(7),
+            read_date: Article::rfc3339_timestamp_to_human(db::text(row.get(8), "-1")),
+            summary: row.get(9),
+            content: row.get(10),
+            word_count: row.get(11),
+            reading_minutes: row.get(12),
+            canonical_link: row.get(13),
+            fingerprint: row.get(14),
+            sources: row.get("source_names"),
+            date_added: db::text(row.get(16), ""),
+            tags: row.get(17),
+            hidden: row.get(18),
+            note: row.get(19),
+            pinned: row.get(20),
+            enclosures: vec![],
+            raw: row
+                .get::<_, tokio_postgres::types::Json<sanitize::Raw>>(21)
+                .0,
+            language: row.get(22),
+            progress: row.get(23),
+            content_hash: row.get(24),
+            updated_at: Article::rfc3339_timestamp_to_human(db::text(row.get("updated_at"), "")),
+        }
+    }
+}
+
+impl From<&feed_rs::model::Entry> for Article {
+    fn from(value: &feed_rs::model::Entry) -> Self {
+        let title = match value.title.clone() {
+            Some(text) => text.content.to_string(),
+            None => "".to_string(),
+        };
+
+        let link = value
+            .links
+            .iter()
+            .take(1)
+            .map(|l| l.href.to_string())
+            .next()
+            .unwrap_or_else(|| "".to_string());
+
+        let author = value
+            .authors
+            .iter()
+            .take(1)
+            .map(|p| p.name.to_string())
+            .next()
+            .unwrap_or_else(|| "".to_string());
+
+        let timestamp = if let Some(_published) = value.published {
+            value.published
+        } else if let Some(_updated) = value.updated {
+            value.updated
+        } else {
+            None
+        };
+
+        let published = match timestamp {
+            Some(ts) => ts.to_rfc3339_opts(SecondsFormat::Millis, true),
+            None => "".to_string(),
+        };
+
+        let summary = match value.summary.clone() {
+            Some(text) => text.content,
+            None => "".to_string(),
+        };
+
+        let content = value
+            .content
+            .as_ref()
+            .and_then(|c| c.body.clone())
+            .unwrap_or_default();
+
+        let mut tags: Vec<String> = value.categories.iter().map(|c| tag(&c.term)).collect();
+        tags.sort();
+        tags.dedup();
+        tags.retain(|t| !t.is_empty());
+
+        let mut article = Article::new(
+            title, link, author, published, false, false, summary, content,
+        );
+        article.tags = tags;
+        article.enclosures = enclosure::Enclosure::from_entry(value);
+        article
+    }
+}
+
+/// The upper bounds on what is stored configured in the environment.
+fn quota() -> db::Quota {
+    db::Quota {
+        max_feeds: env::var("MAX_FEEDS").ok().and_then(|s| s.parse().ok()),
+        max_articles: env::var("MAX_ARTICLES").ok().and_then(|s| s.parse().ok()),
+    }
+}
+
+/// Rows on a page unless a request asks for another size.
+fn page_size() -> usize {
+    env::var("PAGE_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(db::DEFAULT_PAGE_SIZE)
+}
+
+/// Connects to the database configured in the environment.
+async fn connect() -> db::Storage {
+    let db_username = env::var("POSTGRES_USERNAME").unwrap();
+    let db_password = env::var("POSTGRES_PASSWORD").unwrap();
+    let db_host = env::var("POSTGRES_HOST").unwrap_or("0.0.0.0".to_string());
+    let db_name = env::var("POSTGRES_DB").unwrap_or("feedreader".to_string());
+    let db_port = env::var("POSTGRES_PORT")
+        .unwrap_or("5432".to_string())
+        .parse()
+        .unwrap();
+
+    let quota = quota();
+    let mut breaker = db::Breaker::default();
+    if let Some(threshold) = env::var("DB_FAILURE_THRESHOLD")
+        .ok()
+        .and_then(|s| s.parse().ok())
+    {
+        breaker.threshold = threshold;
+    }
+    if let Some(seconds) = env::var("DB_PROBE_SECONDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+    {
+        breaker.probe_interval = time::Duration::from_secs(seconds);
+    }
+    if let Some(seconds) = env::var("DB_PROBE_MAX_SECONDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+    {
+        breaker.max_probe_interval = time::Duration::from_secs(seconds);
+    }
+    if let Some(seconds) = env::var("DB_HEALTH_SECONDS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+    {
+        breaker.health_interval = match seconds {
+            0 => None,
+            seconds => Some(time::Duration::from_secs(seconds)),
+        };
+    }
+
+    let store = db::connection(
+        db_username.as_str(),
+        db_password.as_str(),
+        db_host.as_str(),
+        db_port,
+        db_name.as_str(),
+        quota,
+        breaker,
+    )
+    .await
+    .unwrap()
+    .with_page_size(page_size());
+
+    // a read-only replica shares the primary's credentials and database name
+    match env::var("POSTGRES_REPLICA_HOST") {
+        Ok(replica_host) => {
+            let replica_port = env::var("POSTGRES_REPLICA_PORT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(db_port);
+            let replica = db::connection(
+                db_username.as_str(),
+                db_password.as_str(),
+                replica_host.as_str(),
+                replica_port,
+                db_name.as_str(),
+                quota,
+                breaker,
+            )
+            .await
+            .unwrap();
+            store.with_replica(replica)
+        }
+        Err(_) => store,
+    }
+}
+
+/// The storage the server keeps its data in, without a timeout, with the timeout of background
+/// jobs and with that of requests. `STORE=memory` keeps everything in memory instead of postgres,
+/// it is gone once the server stops.
+async fn stores(
+    job_timeout: Option<time::Duration>,
+    request_timeout: Option<time::Duration>,
+) -> (Arc<dyn db::Store>, Arc<dyn db::Store>, Arc<dyn db::Store>) {
+    if env::var("STORE").as_deref() == Ok("memory") {
+        let store: Arc<dyn db::Store> = Arc::new(memory::Memory::new(quota(), page_size()));
+        return (store.clone(), store.clone(), store);
+    }
+    let store = connect().await;
+    (
+        Arc::new(store.clone()),
+        Arc::new(store.with_timeout(job_timeout)),
+        Arc::new(store.with_timeout(request_timeout)),
+    )
+}
+
+/// Brings the database schema up to date and exits, to migrate ahead of rolling out a release.
+pub async fn migrate() {
+    let store = connect().await;
+    match db::Store::migrate(&store).await {
+        Ok(applied) if applied.is_empty() => println!("the schema is up to date"),
+        Ok(applied) => {
+            for m in applied {
+                println!("applied migration {}", m);
+            }
+        }
+        Err(e) => panic!("could not migrate db: {}", e),
+    }
+}
+
+/// The timeout set in `name` in seconds, `default` when it is not set and none when it is `0`.
+fn timeout(name: &str, default: u64) -> Option<time::Duration> {
+    let seconds = env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default);
+    match seconds {
+        0 => None,
+        seconds => Some(time::Duration::from_secs(seconds)),
+    }
+}
+
+/// Connects to the database, starts the background refresh and serves the web ui until shutdown.
+pub async fn run() {
+    let expiry = db::Expiry {
+        max_age_days: env::var("EXPIRE_UNREAD_DAYS")
+            .ok()
+            .and_then(|s| s.parse().ok()),
+        max_unread_per_feed: env::var("EXPIRE_UNREAD_PER_FEED")
+            .ok()
+            .and_then(|s| s.parse().ok()),
+    };
+
+    let retention = db::Retention {
+        content_days: env::var("STRIP_READ_CONTENT_DAYS")
+            .ok()
+            .and_then(|s| s.parse().ok()),
+        archive_days: env::var("ARCHIVE_READ_DAYS")
+            .ok()
+            .and_then(|s| s.parse().ok()),
+    };
+
+    let request_timeout = timeout("REQUEST_TIMEOUT_SECONDS", DEFAULT_REQUEST_TIMEOUT_SECONDS);
+    let job_timeout = timeout("JOB_TIMEOUT_SECONDS", DEFAULT_JOB_TIMEOUT_SECONDS);
+
+    // storage calls made for a request give up after the request timeout, the background jobs
+    // further down use `jobs` with a longer one. Migrations and maintenance, which vacuums whole
+    // tables, use `unlimited`, they are the only things that run without a timeout.
+    let (unlimited, jobs, store) = stores(job_timeout, request_timeout).await;
+
+    let migrate_on_start = env::var("MIGRATE_ON_START")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(true);
+    let applied = match unlimited.applied_migrations().await {
+        Ok(applied) => applied,
+        Err(e) => panic!("could not read applied migrations: {}", e),
+    };
+    for version in migrate::unknown(&applied) {
+        println!(
+            "schema: migration {:04} was applied by a newer release, this one may not work with it",
+            version
+        );
+    }
+    let pending = migrate::pending(&applied);
+    if !migrate_on_start && !pending.is_empty() {
+        panic!("{}", migrate::Outdated { pending });
+    }
+
+    // a failed migration is reported after the schema check, which usually explains it better
+    let migrated = match migrate_on_start {
+        true => unlimited.migrate().await,
+        false => Ok(vec![]),
+    };
+    match unlimited.check_schema().await {
+        Ok(problems) => {
+            for p in &problems {
+                println!("schema: {}", p);
+            }
+            let fatal = problems.iter().filter(|p| p.fatal()).count();
+            if fatal > 0 {
+                panic!("database schema has {} problem(s), see above", fatal);
+            }
+        }
+        Err(e) => panic!("could not check db schema: {}", e),
+    }
+    match migrated {
+        Ok(applied) => {
+            for m in applied {
+                println!("applied migration {}", m);
+            }
+        }
+        Err(e) => panic!("could not migrate db: {}", e),
+    }
+
+    let share = match share::Share::from_env() {
+        Ok(s) => s,
+        Err(e) => panic!("could not configure share targets: {}", e),
+    };
+
+    let push = match push::Push::from_env() {
+        Ok(p) => p,
+        Err(e) => panic!("could not configure push notifications: {}", e),
+    };
+    let pages = prefetch::Pages::from_env();
+    let fever = fever::Fever::from_env();
+    let greader = greader::GReader::from_env();
+    let digest = match digest::Digest::from_env() {
+        Ok(d) => d,
+        Err(e) => panic!("could not configure digest: {}", e),
+    };
+    let alerts = match alert::Alerts::from_env() {
+        Ok(a) => a,
+        Err(e) => panic!("could not configure alerts: {}", e),
+    };
+    let quiet = match polling::QuietHours::from_env() {
+        Ok(q) => q,
+        Err(e) => panic!("could not configure quiet hours: {}", e),
+    };
+    let events = events::Events::default();
+    let refresher = refresh::Refresh::new(
+        sanitize::Fields::from_env(),
+        push.clone(),
+        alerts,
+        events.clone(),
+        quiet,
+    );
+    let queue = queue::Queue::default();
+    let maintenance = maintenance::Maintenance::default();
+    let blobs = match blob::Store::from_env() {
+        Ok(b) => b,
+        Err(e) => panic!("could not configure blob store: {}", e),
+    };
+    let cache_images = env::var("BLOB_CACHE_IMAGES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(false);
+    let language = match search::Language::from_env() {
+        Ok(l) => l,
+        Err(e) => panic!("could not configure search: {}", e),
+    };
+    if let Err(e) = unlimited.set_search_language(language).await {
+        panic!("could not configure search: {}", e);
+    }
+
+    let cors = warp::cors()
+        .allow_any_origin()
+        .allow_headers(vec![
+            "Authorization",
+            "Content-Type",
+            "User-Agent",
+            "Sec-Fetch-Mode",
+            "Referer",
+            "Origin",
+            "Access-Control-Request-Method",
+            "Access-Control-Request-Headers",
+            "article_filter",
+            "pagination",
+        ])
+        .allow_methods(vec!["GET", "HEAD", "POST", "DELETE"]);
+
+    // the route groups are boxed, one long chain of `or` nests deep enough to overflow the stack.
+    // Building them in `openapi::build` documents every route on the way.
+    let routes = openapi::build(|| {
+        let article_routes = openapi::tagged("articles", || {
+            index(store.clone(), pages.clone())
+                .or(favorites(store.clone(), pages.clone()))
+                .or(history(store.clone(), pages.clone()))
+                .or(hidden(store.clone(), pages.clone()))
+                .or(search(store.clone(), language))
+                .or(random(store.clone()))
+                .or(get_view(store.clone(), pages.clone()))
+                .or(get_blob(store.clone(), blobs.clone()))
+                .or(proxy_image(store.clone(), blobs.clone()))
+                .or(get_articles(store.clone(), pages.clone()))
+                .or(get_articles_json(store.clone(), pages.clone()))
+                .or(article_page(store.clone(), pages.clone()))
+                .or(article_counts(store.clone()))
+                .boxed()
+        });
+
+        let article_action_routes = openapi::tagged("articles", || {
+            bulk_articles(store.clone())
+                .or(get_article(store.clone(), share.clone()))
+                .or(export_articles(store.clone()))
+                .or(export_articles_csv(store.clone()))
+                .or(toggle_article(store.clone(), share.clone()))
+                .or(share_article(store.clone(), share.clone()))
+                .or(note_article(store.clone(), share.clone()))
+                .or(article_progress(store.clone()))
+                .or(mark_article_read(store.clone(), pages.clone()))
+                .or(mark_article_favorite(store.clone(), pages.clone()))
+                .or(mark_article_pinned(store.clone(), pages.clone()))
+                .or(mark_article_hidden(store.clone(), pages.clone()))
+                .boxed()
+        });
+
+        let feed_routes = openapi::tagged("feeds", || {
+            create_feed(store.clone(), queue.clone())
+                .or(feeds(store.clone()))
+                .or(get_feeds_json(store.clone()))
+                .or(delete_feed(store.clone()))
+                .or(add_feed())
+                .or(import_feeds(store.clone(), queue.clone()))
+                .or(import_feeds_email(store.clone(), queue.clone()))
+                .or(refresh_feed(store.clone(), queue.clone()))
+                .or(update_feed_refresh_interval(store.clone()))
+                .or(update_feed_search_language(store.clone()))
+                .or(toggle_feed_keep_content(store.clone()))
+                .or(toggle_feed_push(store.clone()))
+                .or(enable_feed(store.clone()))
+                .or(podcasts(store.clone()))
+                .or(podcast_episodes(store.clone()))
+                .or(mark_episode_played(store.clone()))
+                .or(scrape_rules(store.clone()))
+                .or(put_scrape_rule(store.clone()))
+                .or(delete_scrape_rule(store.clone()))
+                .or(feed_fetches(store.clone()))
+                .or(feed_raw(store.clone()))
+                .boxed()
+        });
+
+        let admin_routes = openapi::tagged("admin", || {
+            get_report(store.clone())
+                .or(cleanup(store.clone()))
+                .or(cleanup_unsubscribe(store.clone()))
+                .or(cleanup_auto_read(store.clone()))
+                .or(domains(store.clone()))
+                .or(mute_domain(store.clone()))
+                .or(unmute_domain(store.clone()))
+                .or(refresh_domain(store.clone(), queue.clone()))
+                .or(tags(store.clone()))
+                .or(rename_tag(store.clone()))
+                .or(merge_tag(store.clone()))
+                .or(delete_tag(store.clone()))
+                .or(filters(store.clone()))
+                .or(create_filter(store.clone()))
+                .or(update_filter(store.clone()))
+                .or(delete_filter(store.clone()))
+                .or(views(store.clone()))
+                .or(create_view(store.clone()))
+                .or(delete_view(store.clone()))
+                .or(export_rules(store.clone()))
+                .or(import_rules(store.clone()))
+                .or(simulate_rules(store.clone()))
+                .or(send_digest(store.clone(), digest.clone()))
+                .boxed()
+        });
+
+        let job_routes = openapi::tagged("jobs", || {
+            get_jobs(store.clone())
+                .or(get_job(store.clone()))
+                .or(cancel_job(store.clone(), queue.clone()))
+                .or(get_maintenance(maintenance.clone()))
+                .boxed()
+        });
+
+        let notification_routes = openapi::tagged("notifications", || {
+            notifications(store.clone(), push.clone())
+                .or(create_subscription(store.clone()))
+                .or(delete_subscription(store.clone()))
+                .or(service_worker())
+                .or(create_push_subscription(store.clone()))
+                .or(delete_push_subscription(store.clone()))
+                .or(live_events(events))
+                .boxed()
+        });
+
+        let server_routes = openapi::tagged("server", || {
+            healthz()
+                .or(version())
+                .or(openapi::routes())
+                .or(chaos::routes())
+                .boxed()
+        });
+        let api_routes = openapi::tagged("api", || {
+            api::routes(store.clone(), pages.clone(), language)
+        });
+        let sync_routes = openapi::tagged("sync", || {
+            fever::routes(store.clone(), fever)
+                .or(greader::routes(store.clone(), greader))
+                .boxed()
+        });
+
+        server_routes
+            .or(api_routes)
+            .or(sync_routes)
+            .or(article_routes)
+            .or(article_action_routes)
+            .or(feed_routes)
+            .or(admin_routes)
+            .or(job_routes)
+            .or(notification_routes)
+            .boxed()
+    })
+    .recover(recover)
+    .with(cors);
+
+    let refresh_seconds = match env::var("FEED_REFRESH_SECONDS") {
+        Ok(s) => s.parse().unwrap_or(DEFAULT_REFRESH_SECONDS),
+        Err(_) => DEFAULT_REFRESH_SECONDS,
+    };
+    let maintenance_seconds = match env::var("MAINTENANCE_SECONDS") {
+        Ok(s) => s.parse().unwrap_or(DEFAULT_MAINTENANCE_SECONDS),
+        Err(_) => DEFAULT_MAINTENANCE_SECONDS,
+    };
+
+    let exit = stream::select_all(vec![
+        SignalStream::new(signal(SignalKind::interrupt()).unwrap()),
+        SignalStream::new(signal(SignalKind::terminate()).unwrap()),
+        SignalStream::new(signal(SignalKind::quit()).unwrap()),
+    ])
+    .into_future()
+    .map(|_| ())
+    .shared();
+
+    let refresh_stream =
+        IntervalStream::new(time::interval(time::Duration::from_secs(refresh_seconds)))
+            .take_until(exit.clone())
+            .for_each(|_| async {
+                // nothing is fetched or sent in the quiet hours, housekeeping in the database still runs
+                let quiet = quiet.contains(Utc::now());
+                if !quiet {
+                    if let Err(e) = queue.due(jobs.as_ref()).await {
+                        println!("could not queue due feeds: {}", e);
+                        return;
+                    }
+                }
+
+                if let Err(e) = jobs.auto_expire_unread(expiry, Utc::now()).await {
+                    println!("could not expire unread articles: {}", e);
+                }
+
+                if let Err(e) = jobs.strip_read_content(retention, Utc::now()).await {
+                    println!("could not strip read article content: {}", e);
+                }
+
+                if let Err(e) = jobs.archive_read_articles(retention, Utc::now()).await {
+                    println!("could not archive read articles: {}", e);
+                }
+
+                if !quiet {
+                    if let Err(e) = digest.due(jobs.as_ref()).await {
+                        println!("could not send digest: {}", e);
+                    }
+                }
+
+                if cache_images && !quiet {
+                    if let Err(e) =
+                        blob::cache_images(jobs.as_ref(), &blobs, IMAGES_CACHED_PER_REFRESH).await
+                    {
+                        println!("could not cache images: {}", e);
+                    }
+                }
+            });
+
+    let report_stream = IntervalStream::new(time::interval(time::Duration::from_secs(
+        REPORT_INTERVAL_SECONDS,
+    )))
+    .take_until(exit.clone())
+    .for_each(|_| async {
+        if let Err(e) = report::generate_previous(jobs.as_ref()).await {
+            println!("could not generate monthly report: {}", e);
+        }
+    });
+
+    let maintenance_stream = IntervalStream::new(time::interval(time::Duration::from_secs(
+        maintenance_seconds,
+    )))
+    .take_until(exit.clone())
+    .for_each(|_| async {
+        maintenance.run(unlimited.as_ref(), &blobs).await;
+    });
+
+    future::select(
+        Box::pin(serve(routes).run(([0, 0, 0, 0], 8080))),
+        Box::pin(future::join4(
+            refresh_stream,
+            report_stream,
+            maintenance_stream,
+            queue.work(jobs.as_ref(), &refresher, exit.clone()),
+        )),
+    )
+    .await;
+}
+
+/// Refresh progress and new articles as server-sent events, for pages to update themselves.
+#[get("/events")]
+fn live_events(#[data] events: events::Events) -> openapi::Documented<openapi::Events> {
+    openapi::Documented::new(warp::sse::reply(
+        warp::sse::keep_alive().stream(events.stream()),
+    ))
+}
+
+/// Whether the server is up.
+#[get("/healthz")]
+fn healthz() -> Json<Healthz> {
+    Healthz { up: true }.into()
+}
+
+/// The build, and the features it was built with.
+#[get("/version")]
+fn version() -> Json<Version> {
+    Version {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("GIT_COMMIT"),
+        built: env!("BUILD_TIMESTAMP"),
+        features: env!("BUILD_FEATURES")
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .collect(),
+        schema: schema::VERSION,
+    }
+    .into()
+}
+
+/// The unread articles.
+#[get("/")]
+async fn index(
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let sort = options
+        .into_inner()
+        .sort(&db::Filter::Unread)
+        .map_err(reject_anyhow)?;
+    let page = store
+        .get_unread_articles(sort, sort.start().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    api::articles(
+        json,
+        ArticleBaseTemplate {
+            title: db::Filter::Unread.to_string(),
+            article_filter: db::Filter::Unread.to_string(),
+            sort,
+            counts,
+            pinned: page.pinned,
+            articles: page.items,
+            next_page: prefetch_next(
+                &store,
+                &pages,
+                &db::Filter::Unread.to_string(),
+                sort,
+                &page.cursor,
+            ),
+            numbers: page.numbers,
+            cursor: page.cursor,
+        },
+    )
+}
+
+/// The favorite articles.
+#[get("/favorites.html")]
+async fn favorites(
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let sort = options
+        .into_inner()
+        .sort(&db::Filter::Favorite)
+        .map_err(reject_anyhow)?;
+    let page = store
+        .get_favorited_articles(sort, sort.start().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    api::articles(
+        json,
+        ArticleBaseTemplate {
+            sort,
+            counts,
+            title: "favorites".to_string(),
+            article_filter: db::Filter::Favorite.to_string(),
+            pinned: page.pinned,
+            articles: page.items,
+            next_page: prefetch_next(
+                &store,
+                &pages,
+                &db::Filter::Favorite.to_string(),
+                sort,
+                &page.cursor,
+            ),
+            numbers: page.numbers,
+            cursor: page.cursor,
+        },
+    )
+}
+
+/// The articles a saved view matches.
+#[get("/views/{id}")]
+async fn get_view(
+    id: String,
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<ArticleBaseTemplate, Rejection> {
+    let v = store.get_view(id).await.map_err(reject_anyhow)?;
+    let q = v.query().map_err(reject_anyhow)?;
+    let sort = options
+        .into_inner()
+        .sort(q.filter.as_ref().unwrap_or(&db::Filter::Unread))
+        .map_err(reject_anyhow)?;
+    let page = store
+        .get_view_articles(&q, sort, sort.start().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(ArticleBaseTemplate {
+        sort,
+        counts,
+        title: v.name.clone(),
+        article_filter: v.article_filter(),
+        pinned: page.pinned,
+        articles: page.items,
+        next_page: prefetch_next(&store, &pages, &v.article_filter(), sort, &page.cursor),
+        numbers: page.numbers,
+        cursor: page.cursor,
+    })
+}
+
+/// Stored media, by the sha256 of its contents.
+/// Blobs never change under their hash, so clients may cache them for good.
+#[get("/blobs/{hash}")]
+async fn get_blob(
+    hash: String,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] blobs: blob::Store,
+) -> Result<openapi::Documented<openapi::Stored>, Rejection> {
+    let b = store
+        .get_blob(hash.clone())
+        .await
+        .map_err(reject_anyhow)?
+        .ok_or_else(warp::reject::not_found)?;
+    let bytes = blobs
+        .get(&hash)
+        .await
+        .map_err(reject_anyhow)?
+        .ok_or_else(warp::reject::not_found)?;
+
+    blob_response(b, bytes, "public, max-age=31536000, immutable")
+}
+
+#[derive(Deserialize, Schema)]
+struct ProxyOptions {
+    /// The image to fetch.
+    url: String,
+    /// The signature the page was rendered with, only urls signed by this server are fetched.
+    sig: String,
+}
+
+/// A remote image, fetched and stored on first use.
+/// Serves a remote image from our own origin, fetching and caching it on first use, so reading
+/// an article tells publishers nothing and hotlink protection has no referrer to object to.
+#[get("/proxy/image")]
+async fn proxy_image(
+    options: Query<ProxyOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] blobs: blob::Store,
+) -> Result<openapi::Documented<openapi::Image>, Rejection> {
+    let options = options.into_inner();
+    let (b, bytes) = proxy::fetch(
+        store.as_ref(),
+        &blobs,
+        &options.url,
+        &options.sig,
+        Utc::now(),
+    )
+    .await
+    .map_err(reject_anyhow)?;
+
+    // the url may be republished with another image, so this is cached for a day only
+    blob_response(b, bytes, "public, max-age=86400")
+}
+
+/// A page of a json list, with RFC 8288 `Link` headers to the pages around it so clients can
+/// follow them without reading the cursor out of the body.
+fn paged_json<T: Serialize>(
+    body: &T,
+    path: &str,
+    params: &[(&str, &str)],
+    cursor: &db::Cursor,
+) -> Result<openapi::Documented<openapi::Paged<T>>, Rejection> {
+    let link = |pagination: &str, rel: &str| {
+        let mut u = reqwest::Url::parse("http://localhost").unwrap();
+        u.set_path(path);
+        u.query_pairs_mut()
+            .extend_pairs(params)
+            .append_pair("pagination", pagination);
+        format!(
+            "<{}?{}>; rel=\"{}\"",
+            u.path(),
+            u.query().unwrap_or_default(),
+            rel
+        )
+    };
+    let mut links = vec![];
+    if cursor.has_next {
+        links.push(link(&cursor.next, "next"));
+    }
+    if cursor.has_prev {
+        links.push(link(&cursor.prev, "prev"));
+    }
+
+    let json = serde_json::to_vec(body).map_err(|e| reject_anyhow(e.into()))?;
+    let mut response =
+        http::Response::builder().header(http::header::CONTENT_TYPE, "application/json");
+    if !links.is_empty() {
+        response = response.header(http::header::LINK, links.join(", "));
+    }
+    response
+        .body(hyper::Body::from(json))
+        .map(openapi::Documented::new)
+        .map_err(|e| reject_anyhow(e.into()))
+}
+
+/// Stored bytes are whatever a publisher sent, so they are sandboxed in case a browser opens
+/// an svg from our origin directly.
+fn blob_response<T>(
+    b: blob::Blob,
+    bytes: Vec<u8>,
+    cache: &str,
+) -> Result<openapi::Documented<T>, Rejection> {
+    http::Response::builder()
+        .header(http::header::CONTENT_TYPE, b.mime)
+        .header(http::header::CACHE_CONTROL, cache)
+        .header(http::header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(
+            http::header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; style-src 'unsafe-inline'; sandbox",
+        )
+        .body(hyper::Body::from(bytes))
+        .map(openapi::Documented::new)
+        .map_err(|e| reject_anyhow(e.into()))
+}
+
+/// The read articles, archived ones included.
+#[get("/history.html")]
+async fn history(
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let sort = options
+        .into_inner()
+        .sort(&db::Filter::Read)
+        .map_err(reject_anyhow)?;
+    let page = store
+        .get_read_articles(sort, sort.start().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    api::articles(
+        json,
+        ArticleBaseTemplate {
+            sort,
+            counts,
+            title: "history".to_string(),
+            article_filter: db::Filter::Read.to_string(),
+            pinned: page.pinned,
+            articles: page.items,
+            next_page: prefetch_next(
+                &store,
+                &pages,
+                &db::Filter::Read.to_string(),
+                sort,
+                &page.cursor,
+            ),
+            numbers: page.numbers,
+            cursor: page.cursor,
+        },
+    )
+}
+
+/// The hidden articles.
+#[get("/hidden.html")]
+async fn hidden(
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let sort = options
+        .into_inner()
+        .sort(&db::Filter::Hidden)
+        .map_err(reject_anyhow)?;
+    let page = store
+        .get_hidden_articles(sort, sort.start().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    api::articles(
+        json,
+        ArticleBaseTemplate {
+            sort,
+            counts,
+            title: db::Filter::Hidden.to_string(),
+            article_filter: db::Filter::Hidden.to_string(),
+            pinned: page.pinned,
+            articles: page.items,
+            next_page: prefetch_next(
+                &store,
+                &pages,
+                &db::Filter::Hidden.to_string(),
+                sort,
+                &page.cursor,
+            ),
+            numbers: page.numbers,
+            cursor: page.cursor,
+        },
+    )
+}
+
+/// The feeds.
+#[get("/feeds.html")]
+async fn feeds(
+    #[data] db: Arc<dyn db::Store>,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<FeedPage>>>, Rejection> {
+    if json {
+        return Ok(api::vary(
+            api::feed_page(db.as_ref(), None, "/api/v1/feeds").await?,
+        ));
+    }
+    let page = db
+        .get_feeds(db::START.to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(api::vary(FeedsTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: db.get_unread_counts().await.map_err(reject_anyhow)?,
+    }))
+}
+
+#[derive(Deserialize, Schema)]
+struct PageQuery {
+    /// The cursor of the page, as the `Link` header of the page before gave it, none for the first.
+    pagination: Option<String>,
+}
+
+#[derive(Serialize, Schema)]
+struct FeedPage {
+    feeds: Vec<Feed>,
+    cursor: db::Cursor,
+}
+
+/// A page of the feeds.
+#[get("/feeds.json")]
+async fn get_feeds_json(
+    options: Query<PageQuery>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<openapi::Documented<openapi::Paged<FeedPage>>, Rejection> {
+    api::feed_page(
+        store.as_ref(),
+        options.into_inner().pagination,
+        "/feeds.json",
+    )
+    .await
+}
+
+/// The podcast episodes.
+#[get("/podcasts")]
+async fn podcasts(#[data] store: Arc<dyn db::Store>) -> Result<PodcastsTemplate, Rejection> {
+    let page = store
+        .get_podcast_episodes(db::START.to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(PodcastsTemplate {
+        articles: page.items,
+        cursor: page.cursor,
+    })
+}
+
+/// A page of podcast episodes.
+#[get("/podcasts/episodes")]
+async fn podcast_episodes(
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<PodcastListTemplate, Rejection> {
+    let page = store
+        .get_podcast_episodes(pagination.0)
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(PodcastListTemplate {
+        articles: page.items,
+        cursor: page.cursor,
+    })
+}
+
+/// Marks an episode played and renders its page again.
+/// Played is the read flag, so episodes leave the unread list once listened to.
+#[post("/podcasts/{article_id}/played")]
+async fn mark_episode_played(
+    article_id: String,
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<PodcastListTemplate, Rejection> {
+    let article = store
+        .get_article_by_id(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+    store
+        .mark_article_read(article)
+        .await
+        .map_err(reject_anyhow)?;
+
+    let page = store
+        .get_podcast_episodes(pagination.0)
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(PodcastListTemplate {
+        articles: page.items,
+        cursor: page.cursor,
+    })
+}
+
+/// The form to add a feed.
+#[get("/add_feed.html")]
+async fn add_feed() -> Result<AddFeedTemplate, Rejection> {
+    Ok(AddFeedTemplate {})
+}
+
+/// Adds a feed.
+#[post("/feeds")]
+async fn create_feed(
+    feed: Form<AddFeed>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] queue: queue::Queue,
+) -> Result<FeedsTemplate, Rejection> {
+    let feed = feed.into_inner();
+    let added = store.add_feed(feed).await.map_err(reject_anyhow)?;
+    // fetched in the background so the new feed has its articles without a manual refresh
+    queue
+        .push(store.as_ref(), vec![added.id])
+        .await
+        .map_err(reject_anyhow)?;
+    let page = store
+        .get_feeds(db::START.to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(FeedsTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+/// Unsubscribes from a feed.
+#[delete("/feeds/{id}")]
+async fn delete_feed(
+    #[data] store: Arc<dyn db::Store>,
+    id: String,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    store.delete_feed(id).await.map_err(reject_anyhow)?;
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+#[derive(Deserialize, Schema)]
+struct ImportOptions {
+    /// Subscribe again to feeds that were unsubscribed from, which are skipped otherwise.
+    resubscribe: Option<bool>,
+}
+
+/// Subscribes to the feeds of an opml document.
+#[post("/feeds/import")]
+async fn import_feeds(
+    body: openapi::Raw<openapi::Opml>,
+    options: Query<ImportOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] queue: queue::Queue,
+) -> Result<Json<import::FeedImport>, Rejection> {
+    let document = String::from_utf8_lossy(&body.0);
+    let feeds = import::feeds(&document).map_err(reject_anyhow)?;
+    let imported = subscribe(store, queue, feeds, options.into_inner()).await?;
+    Ok(imported.into())
+}
+
+/// Takes a raw email, as piped in by an mta for the import address, and subscribes to the
+/// feeds in its opml attachments.
+#[post("/feeds/import/email")]
+async fn import_feeds_email(
+    body: openapi::Raw<openapi::Email>,
+    options: Query<ImportOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] queue: queue::Queue,
+) -> Result<Json<import::FeedImport>, Rejection> {
+    let feeds = import::email(&body.0).map_err(reject_anyhow)?;
+    let imported = subscribe(store, queue, feeds, options.into_inner()).await?;
+    Ok(imported.into())
+}
+
+async fn subscribe(
+    store: Arc<dyn db::Store>,
+    queue: queue::Queue,
+    feeds: Vec<AddFeed>,
+    options: ImportOptions,
+) -> Result<import::FeedImport, Rejection> {
+    let total = feeds.len();
+    let added = store
+        .add_feeds(feeds, options.resubscribe.unwrap_or_default())
+        .await
+        .map_err(reject_anyhow)?;
+    let imported = import::FeedImport {
+        added: added.iter().map(|f| f.name.clone()).collect(),
+        skipped: total - added.len(),
+    };
+
+    queue
+        .push(store.as_ref(), added.into_iter().map(|f| f.id).collect())
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(imported)
+}
+
+/// Queues a refresh of a feed.
+#[post("/feeds/{id}/refresh")]
+async fn refresh_feed(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] queue: queue::Queue,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    // the refresh runs in the background, a failure is recorded on the feed and its job
+    queue
+        .push(store.as_ref(), vec![id])
+        .await
+        .map_err(reject_anyhow)?;
+
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+/// The scrape rules of sites without feeds.
+#[get("/scrape")]
+async fn scrape_rules(
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<Vec<scrape::Rule>>, Rejection> {
+    let rules = store.get_scrape_rules().await.map_err(reject_anyhow)?;
+    Ok(rules.into())
+}
+
+/// Sets the scrape rule of a feed.
+/// Reads the feed's page with the rule from now on instead of parsing it as a feed.
+#[put("/feeds/{id}/scrape")]
+async fn put_scrape_rule(
+    id: String,
+    rule: Json<scrape::Rule>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<scrape::Rule>, Rejection> {
+    let rule = rule.into_inner();
+    rule.validate().map_err(reject_anyhow)?;
+    store
+        .get_feed_by_id(id.clone())
+        .await
+        .map_err(reject_anyhow)?;
+    let rule = scrape::Rule {
+        feed: id,
+        date_added: Article::rfc3339_timestamp(),
+        ..rule
+    };
+    store.put_scrape_rule(&rule).await.map_err(reject_anyhow)?;
+    Ok(rule.into())
+}
+
+/// Removes the scrape rule of a feed.
+#[delete("/feeds/{id}/scrape")]
+async fn delete_scrape_rule(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<String, Rejection> {
+    store.delete_scrape_rule(id).await.map_err(reject_anyhow)?;
+    Ok("".to_string())
+}
+
+/// The feed's most recent fetch attempts, newest first.
+#[get("/feeds/{id}/fetches")]
+async fn feed_fetches(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<Vec<fetch::Attempt>>, Rejection> {
+    store
+        .get_feed_by_id(id.clone())
+        .await
+        .map_err(reject_anyhow)?;
+    let attempts = store
+        .get_fetch_attempts(id, FETCHES_LIMIT)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(attempts.into())
+}
+
+/// The last document fetched for the feed as the publisher sent it, cut short when it was larger
+/// than FEED_PAYLOAD_MAX_BYTES. Sandboxed like blobs since it is served from our origin.
+#[get("/feeds/{id}/raw")]
+async fn feed_raw(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<openapi::Documented<openapi::Stored>, Rejection> {
+    let payload = store
+        .get_feed_payload(id)
+        .await
+        .map_err(reject_anyhow)?
+        .ok_or_else(warp::reject::not_found)?;
+    let document = payload.document().map_err(reject_anyhow)?;
+    let content_type = match payload.content_type.as_str() {
+        "" => "application/octet-stream",
+        t => t,
+    };
+    let mut response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, content_type)
+        .header(
+            http::header::LAST_MODIFIED,
+            payload
+                .fetched
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+        )
+        .header(http::header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(
+            http::header::CONTENT_SECURITY_POLICY,
+            "default-src 'none'; style-src 'unsafe-inline'; sandbox",
+        );
+    if payload.truncated {
+        response = response.header("x-original-length", payload.size);
+    }
+    response
+        .body(hyper::Body::from(document))
+        .map(openapi::Documented::new)
+        .map_err(|e| reject_anyhow(e.into()))
+}
+
+/// Sets the language a feed is searched in.
+#[post("/feeds/{id}/search_language")]
+async fn update_feed_search_language(
+    id: String,
+    form: Form<SearchLanguage>,
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    let form = form.into_inner();
+    let language = match form.language.as_str() {
+        "" => None,
+        l => Some(l.parse().map_err(reject_anyhow)?),
+    };
+    store
+        .update_feed_search_language(id, language)
+        .await
+        .map_err(reject_anyhow)?;
+
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+#[derive(Deserialize, Schema)]
+struct RefreshInterval {
+    /// Seconds between refreshes, zero to go by the feed's history.
+    seconds: i32,
+}
+
+/// Sets how often a feed is refreshed.
+/// Zero hands the schedule back to the publish history. The feed is due straight away so the
+/// new interval takes effect on the next refresh.
+#[post("/feeds/{id}/refresh_interval")]
+async fn update_feed_refresh_interval(
+    id: String,
+    form: Form<RefreshInterval>,
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    let form = form.into_inner();
+    store
+        .update_feed_refresh_interval(id, form.seconds.max(0))
+        .await
+        .map_err(reject_anyhow)?;
+
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+/// Toggles browser notifications for a feed.
+#[post("/feeds/{id}/push")]
+async fn toggle_feed_push(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    store.toggle_feed_push(id).await.map_err(reject_anyhow)?;
+
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+/// Schedules a feed that stopped being polled after failing too often again, starting right away.
+#[post("/feeds/{id}/enable")]
+async fn enable_feed(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    store.enable_feed(id).await.map_err(reject_anyhow)?;
+
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+/// Toggles keeping the content of a feed's read articles.
+/// Feeds that keep content hold on to the body of read articles regardless of `STRIP_READ_CONTENT_DAYS`.
+#[post("/feeds/{id}/keep_content")]
+async fn toggle_feed_keep_content(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+    pagination: Pagination,
+) -> Result<FeedListTemplate, Rejection> {
+    store
+        .toggle_feed_keep_content(id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    let page = store.get_feeds(pagination.0).await.map_err(reject_anyhow)?;
+
+    Ok(FeedListTemplate {
+        cursor: page.cursor,
+        feeds: page.items,
+        unread: store.get_unread_counts().await.map_err(reject_anyhow)?,
+    })
+}
+
+/// Articles matching `q`, best first.
+#[get("/search.html")]
+async fn search(
+    options: Query<SearchOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] language: search::Language,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let q = options.into_inner().q;
+    let page = search_page(store.as_ref(), q.clone(), language)
+        .await
+        .map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    api::articles(
+        json,
+        ArticleBaseTemplate {
+            title: format!("search: {}", q),
+            article_filter: db::Filter::Unread.to_string(),
+            counts,
+            articles: page.items,
+            numbers: page.numbers,
+            cursor: page.cursor,
+            ..Default::default()
+        },
+    )
+}
+
+/// Articles matching `q`, best first.
+async fn search_page(
+    store: &dyn db::Store,
+    q: String,
+    language: search::Language,
+) -> Result<db::Page<Article>> {
+    let page = store.search_articles(q.clone(), language).await?;
+    // a typo or half a word finds nothing by stem, titles close to it are better than nothing
+    if page.items.is_empty() {
+        return store.fuzzy_search_titles(q).await;
+    }
+    Ok(page)
+}
+
+/// Older articles still unread or favorited, picked at random so things buried in the backlog
+/// come up again. Recent articles are left out, they are near the top of the unread page anyway.
+#[get("/random.html")]
+async fn random(
+    #[data] store: Arc<dyn db::Store>,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let before = Utc::now() - chrono::Duration::days(RANDOM_MIN_AGE_DAYS);
+    let page = store.sample_articles(before).await.map_err(reject_anyhow)?;
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    api::articles(
+        json,
+        ArticleBaseTemplate {
+            title: "random".to_string(),
+            article_filter: db::Filter::Unread.to_string(),
+            counts,
+            articles: page.items,
+            numbers: page.numbers,
+            cursor: page.cursor,
+            ..Default::default()
+        },
+    )
+}
+
+/// The id of a feed or article identified by `key`, its url or guid: the first half of its
+/// sha256 in hex, so ids have the same length whatever they stand for and do not reveal it.
+fn short_id(key: &str) -> String {
+    let digest = Sha256::digest(key.as_bytes());
+    digest[..ID_BYTES]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Tags are compared case insensitively, so they are stored trimmed and lowercased.
+fn tag(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// The tags and how many articles have each.
+#[get("/tags.html")]
+async fn tags(#[data] store: Arc<dyn db::Store>) -> Result<TagsTemplate, Rejection> {
+    let tags = store.get_tags().await.map_err(reject_anyhow)?;
+    Ok(TagsTemplate { tags })
+}
+
+/// Renames a tag.
+#[post("/tags/rename")]
+async fn rename_tag(
+    form: Form<RenameTag>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<TagListTemplate, Rejection> {
+    let form = form.into_inner();
+    store
+        .rename_tag(form.tag, tag(&form.name))
+        .await
+        .map_err(reject_anyhow)?;
+    let tags = store.get_tags().await.map_err(reject_anyhow)?;
+    Ok(TagListTemplate { tags })
+}
+
+/// Merges a tag into another.
+#[post("/tags/merge")]
+async fn merge_tag(
+    form: Form<MergeTag>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<TagListTemplate, Rejection> {
+    let form = form.into_inner();
+    store
+        .merge_tag(form.tag, form.into)
+        .await
+        .map_err(reject_anyhow)?;
+    let tags = store.get_tags().await.map_err(reject_anyhow)?;
+    Ok(TagListTemplate { tags })
+}
+
+/// Removes a tag from every article.
+#[post("/tags/delete")]
+async fn delete_tag(
+    form: Form<DeleteTag>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<TagListTemplate, Rejection> {
+    let form = form.into_inner();
+    store.delete_tag(form.tag).await.map_err(reject_anyhow)?;
+    let tags = store.get_tags().await.map_err(reject_anyhow)?;
+    Ok(TagListTemplate { tags })
+}
+
+/// The mute filters.
+#[get("/filters.html")]
+async fn filters(#[data] store: Arc<dyn db::Store>) -> Result<FiltersTemplate, Rejection> {
+    let filters = store.get_filters().await.map_err(reject_anyhow)?;
+    Ok(FiltersTemplate { filters })
+}
+
+/// Adds a mute filter.
+#[post("/filters")]
+async fn create_filter(
+    form: Form<AddFilter>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<FilterListTemplate, Rejection> {
+    let form = form.into_inner();
+    let m = form.mute().map_err(reject_anyhow)?;
+    store.add_filter(m).await.map_err(reject_anyhow)?;
+    let filters = store.get_filters().await.map_err(reject_anyhow)?;
+    Ok(FilterListTemplate { filters })
+}
+
+/// Replaces a mute filter.
+#[post("/filters/{id}")]
+async fn update_filter(
+    id: String,
+    form: Form<AddFilter>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<FilterListTemplate, Rejection> {
+    let form = form.into_inner();
+    let m = form.mute().map_err(reject_anyhow)?;
+    store.update_filter(id, m).await.map_err(reject_anyhow)?;
+    let filters = store.get_filters().await.map_err(reject_anyhow)?;
+    Ok(FilterListTemplate { filters })
+}
+
+/// Removes a mute filter.
+#[delete("/filters/{id}")]
+async fn delete_filter(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<FilterListTemplate, Rejection> {
+    store.delete_filter(id).await.map_err(reject_anyhow)?;
+    let filters = store.get_filters().await.map_err(reject_anyhow)?;
+    Ok(FilterListTemplate { filters })
+}
+
+/// Browser notification and webhook settings.
+#[get("/notifications.html")]
+async fn notifications(
+    #[data] store: Arc<dyn db::Store>,
+    #[data] push: push::Push,
+) -> Result<NotificationsTemplate, Rejection> {
+    let subscriptions = store.get_subscriptions().await.map_err(reject_anyhow)?;
+    Ok(NotificationsTemplate {
+        subscriptions,
+        push_key: push.public_key().unwrap_or_default().to_string(),
+    })
+}
+
+/// The service worker that shows notifications.
+/// Served from the root so its scope covers every page.
+#[get("/sw.js")]
+fn service_worker() -> ServiceWorkerTemplate {
+    ServiceWorkerTemplate {}
+}
+
+#[derive(Deserialize, Schema)]
+struct PushKeys {
+    p256dh: String,
+    auth: String,
+}
+
+/// What `PushSubscription.toJSON()` gives in the browser.
+#[derive(Deserialize, Schema)]
+struct AddPushSubscription {
+    endpoint: String,
+    keys: PushKeys,
+}
+
+#[derive(Deserialize, Schema)]
+struct DeletePushSubscription {
+    endpoint: String,
+}
+
+/// Sends notifications to a browser.
+#[post("/push/subscriptions")]
+async fn create_push_subscription(
+    s: Json<AddPushSubscription>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<String, Rejection> {
+    let s = s.into_inner();
+    let s = push::PushSubscription::new(s.endpoint, s.keys.p256dh, s.keys.auth)
+        .map_err(reject_anyhow)?;
+    store
+        .add_push_subscription(s)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok("".to_string())
+}
+
+/// Stops sending notifications to a browser.
+#[delete("/push/subscriptions")]
+async fn delete_push_subscription(
+    s: Json<DeletePushSubscription>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<String, Rejection> {
+    let s = s.into_inner();
+    store
+        .delete_push_subscription(s.endpoint)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok("".to_string())
+}
+
+/// The saved views.
+#[get("/views.html")]
+async fn views(#[data] store: Arc<dyn db::Store>) -> Result<ViewsTemplate, Rejection> {
+    let views = store.get_views().await.map_err(reject_anyhow)?;
+    Ok(ViewsTemplate { views })
+}
+
+/// Saves a view.
+#[post("/views")]
+async fn create_view(
+    form: Form<AddView>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<ViewListTemplate, Rejection> {
+    let form = form.into_inner();
+    let v = view::View::new(
+        form.name.trim().to_string(),
+        form.filter,
+        form.feed.trim().to_string(),
+        tag(&form.tag),
+        form.keyword.trim().to_string(),
+    )
+    .map_err(reject_anyhow)?;
+    store.add_view(v).await.map_err(reject_anyhow)?;
+    let views = store.get_views().await.map_err(reject_anyhow)?;
+    Ok(ViewListTemplate { views })
+}
+
+/// Removes a saved view.
+#[delete("/views/{id}")]
+async fn delete_view(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<ViewListTemplate, Rejection> {
+    store.delete_view(id).await.map_err(reject_anyhow)?;
+    let views = store.get_views().await.map_err(reject_anyhow)?;
+    Ok(ViewListTemplate { views })
+}
+
+/// Calls a webhook for new articles with a tag.
+#[post("/subscriptions")]
+async fn create_subscription(
+    form: Form<AddSubscription>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<SubscriptionListTemplate, Rejection> {
+    let form = form.into_inner();
+    store
+        .add_subscription(notify::Subscription::new(tag(&form.tag), form.webhook))
+        .await
+        .map_err(reject_anyhow)?;
+    let subscriptions = store.get_subscriptions().await.map_err(reject_anyhow)?;
+    Ok(SubscriptionListTemplate { subscriptions })
+}
+
+/// Removes a webhook.
+#[delete("/subscriptions/{id}")]
+async fn delete_subscription(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<SubscriptionListTemplate, Rejection> {
+    store.delete_subscription(id).await.map_err(reject_anyhow)?;
+    let subscriptions = store.get_subscriptions().await.map_err(reject_anyhow)?;
+    Ok(SubscriptionListTemplate { subscriptions })
+}
+
+/// The filters and webhooks, to import elsewhere.
+#[get("/rules/export")]
+async fn export_rules(#[data] store: Arc<dyn db::Store>) -> Result<Json<rules::Rules>, Rejection> {
+    let rules = store.get_rules().await.map_err(reject_anyhow)?;
+    Ok(rules.into())
+}
+
+/// Adds exported filters and webhooks.
+#[post("/rules/import")]
+async fn import_rules(
+    rules: Json<rules::Rules>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<rules::Imported>, Rejection> {
+    let rules = rules.into_inner();
+    let imported = store.import_rules(rules).await.map_err(reject_anyhow)?;
+    Ok(imported.into())
+}
+
+/// What the filters would do to recent articles.
+#[post("/rules/simulate")]
+async fn simulate_rules(
+    simulate: Json<Simulate>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<Vec<rules::Outcome>>, Rejection> {
+    let simulate = simulate.into_inner();
+    let rules = match simulate.rules {
+        Some(r) => r,
+        None => store.get_rules().await.map_err(reject_anyhow)?,
+    };
+    let limit = simulate
+        .limit
+        .unwrap_or(DEFAULT_SIMULATE_LIMIT)
+        .clamp(1, MAX_SIMULATE_LIMIT);
+
+    let articles = store
+        .get_recent_articles(limit)
+        .await
+        .map_err(reject_anyhow)?;
+
+    let outcomes = rules::simulate(&rules, &articles).map_err(reject_anyhow)?;
+    Ok(outcomes.into())
+}
+
+async fn suggestions(store: &dyn db::Store) -> Result<Vec<db::Suggestion>> {
+    let since = Utc::now() - chrono::Duration::days(SUGGESTION_DAYS);
+    store
+        .get_unread_suggestions(since, SUGGESTION_MIN_ARTICLES, SUGGESTION_UNREAD_RATIO)
+        .await
+}
+
+/// Feeds worth unsubscribing from or reading automatically.
+#[get("/cleanup.html")]
+async fn cleanup(#[data] store: Arc<dyn db::Store>) -> Result<CleanupTemplate, Rejection> {
+    let suggestions = suggestions(store.as_ref()).await.map_err(reject_anyhow)?;
+    Ok(CleanupTemplate { suggestions })
+}
+
+/// Unsubscribes from a feed and renders the cleanup page again.
+#[post("/cleanup/{id}/unsubscribe")]
+async fn cleanup_unsubscribe(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<SuggestionListTemplate, Rejection> {
+    store.delete_feed(id).await.map_err(reject_anyhow)?;
+    let suggestions = suggestions(store.as_ref()).await.map_err(reject_anyhow)?;
+    Ok(SuggestionListTemplate { suggestions })
+}
+
+/// Reads a feed's articles automatically.
+/// Keeps the feed but adds a filter that marks everything it publishes from now on as read,
+/// and clears what it has left unread.
+#[post("/cleanup/{id}/auto_read")]
+async fn cleanup_auto_read(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<SuggestionListTemplate, Rejection> {
+    let f = store.get_feed_by_id(id).await.map_err(reject_anyhow)?;
+    let m = mute::Mute::new(
+        format!("^{}$", regex::escape(f.name.as_str())),
+        true,
+        mute::Field::Feed,
+        mute::Action::Read,
+    )
+    .map_err(reject_anyhow)?;
+    store.add_filter(m).await.map_err(reject_anyhow)?;
+    store.mark_feed_read(f.id).await.map_err(reject_anyhow)?;
+
+    let suggestions = suggestions(store.as_ref()).await.map_err(reject_anyhow)?;
+    Ok(SuggestionListTemplate { suggestions })
+}
+
+/// The domains feeds are fetched from.
+#[get("/domains.html")]
+async fn domains(#[data] store: Arc<dyn db::Store>) -> Result<DomainsTemplate, Rejection> {
+    let domains = store.get_feed_domains().await.map_err(reject_anyhow)?;
+    Ok(DomainsTemplate { domains })
+}
+
+/// Stops fetching from a domain.
+#[post("/domains/{domain}/mute")]
+async fn mute_domain(
+    domain: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<DomainListTemplate, Rejection> {
+    store
+        .mute_domain(domain, true)
+        .await
+        .map_err(reject_anyhow)?;
+    let domains = store.get_feed_domains().await.map_err(reject_anyhow)?;
+    Ok(DomainListTemplate { domains })
+}
+
+/// Fetches from a domain again.
+#[post("/domains/{domain}/unmute")]
+async fn unmute_domain(
+    domain: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<DomainListTemplate, Rejection> {
+    store
+        .mute_domain(domain, false)
+        .await
+        .map_err(reject_anyhow)?;
+    let domains = store.get_feed_domains().await.map_err(reject_anyhow)?;
+    Ok(DomainListTemplate { domains })
+}
+
+/// Queues a refresh of every feed on a domain.
+#[post("/domains/{domain}/refresh")]
+async fn refresh_domain(
+    domain: String,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] queue: queue::Queue,
+) -> Result<DomainListTemplate, Rejection> {
+    let feeds = store
+        .get_feeds_by_domain(domain)
+        .await
+        .map_err(reject_anyhow)?;
+    queue
+        .push(store.as_ref(), feeds.into_iter().map(|f| f.id).collect())
+        .await
+        .map_err(reject_anyhow)?;
+    let domains = store.get_feed_domains().await.map_err(reject_anyhow)?;
+    Ok(DomainListTemplate { domains })
+}
+
+/// Sends the digest now, outside its schedule.
+#[post("/digest")]
+async fn send_digest(
+    #[data] store: Arc<dyn db::Store>,
+    #[data] digest: digest::Digest,
+) -> Result<Json<DigestSent>, Rejection> {
+    let articles = digest.send(store.as_ref()).await.map_err(reject_anyhow)?;
+    Ok(DigestSent { articles }.into())
+}
+
+/// The most recent refresh jobs, newest first.
+#[get("/jobs")]
+async fn get_jobs(#[data] store: Arc<dyn db::Store>) -> Result<Json<Vec<queue::Job>>, Rejection> {
+    let jobs = store.get_jobs(JOBS_LIMIT).await.map_err(reject_anyhow)?;
+    Ok(jobs.into())
+}
+
+/// A job.
+#[get("/jobs/{id}")]
+async fn get_job(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<queue::Job>, Rejection> {
+    let job = store
+        .get_job(id)
+        .await
+        .map_err(reject_anyhow)?
+        .ok_or_else(warp::reject::not_found)?;
+    Ok(job.into())
+}
+
+/// How long each step of the last database maintenance run took, null until the first one,
+/// which starts with the server, finished.
+#[get("/maintenance")]
+fn get_maintenance(
+    #[data] maintenance: maintenance::Maintenance,
+) -> Json<Option<maintenance::Run>> {
+    maintenance.last().into()
+}
+
+/// Cancels the job if it has not finished, stopping its fetch when it is running.
+#[delete("/jobs/{id}")]
+async fn cancel_job(
+    id: String,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] queue: queue::Queue,
+) -> Result<Json<queue::Job>, Rejection> {
+    let job = queue
+        .cancel(store.as_ref(), id)
+        .await
+        .map_err(reject_anyhow)?
+        .ok_or_else(warp::reject::not_found)?;
+    Ok(job.into())
+}
+
+/// Toggles an article read and renders its list again.
+#[post("/articles/{article_id}/read")]
+async fn mark_article_read(
+    article_id: String,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+    pagination: Pagination,
+    article_filter: ArticleFilter,
+    options: Query<SortOptions>,
+) -> Result<ArticleListTemplate, Rejection> {
+    let article = store
+        .get_article_by_id(article_id.clone())
+        .await
+        .map_err(reject_anyhow)?;
+
+    store
+        .mark_article_read(article)
+        .await
+        .map_err(reject_anyhow)?;
+
+    article_list(
+        &store,
+        &pages,
+        &article_filter.0,
+        options.into_inner(),
+        pagination.0,
+    )
+    .await
+    .map_err(reject_anyhow)
+}
+
+/// Toggles an article favorite and renders its list again.
+#[post("/articles/{article_id}/favorite")]
+async fn mark_article_favorite(
+    article_id: String,
+    pagination: Pagination,
+    article_filter: ArticleFilter,
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<ArticleListTemplate, Rejection> {
+    store
+        .mark_article_favorite(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    article_list(
+        &store,
+        &pages,
+        &article_filter.0,
+        options.into_inner(),
+        pagination.0,
+    )
+    .await
+    .map_err(reject_anyhow)
+}
+
+/// Toggles an article pinned and renders its list again.
+#[post("/articles/{article_id}/pin")]
+async fn mark_article_pinned(
+    article_id: String,
+    pagination: Pagination,
+    article_filter: ArticleFilter,
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<ArticleListTemplate, Rejection> {
+    store
+        .mark_article_pinned(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    article_list(
+        &store,
+        &pages,
+        &article_filter.0,
+        options.into_inner(),
+        pagination.0,
+    )
+    .await
+    .map_err(reject_anyhow)
+}
+
+/// Toggles an article hidden and renders its list again.
+#[post("/articles/{article_id}/hide")]
+async fn mark_article_hidden(
+    article_id: String,
+    pagination: Pagination,
+    article_filter: ArticleFilter,
+    options: Query<SortOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<ArticleListTemplate, Rejection> {
+    store
+        .mark_article_hidden(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    article_list(
+        &store,
+        &pages,
+        &article_filter.0,
+        options.into_inner(),
+        pagination.0,
+    )
+    .await
+    .map_err(reject_anyhow)
+}
+
+/// The page of the article list being looked at. `article_filter` names one of the filters,
+/// or a saved view as `view:<id>`.
+async fn article_list(
+    store: &Arc<dyn db::Store>,
+    pages: &prefetch::Pages,
+    article_filter: &str,
+    options: SortOptions,
+    pagination: String,
+) -> Result<ArticleListTemplate> {
+    let sort = list_sort(store.as_ref(), article_filter, &options).await?;
+    let list = list_page(store.as_ref(), pages, article_filter, sort, pagination).await?;
+    prefetch_next(store, pages, article_filter, sort, &list.cursor);
+    Ok(list)
+}
+
+/// The sort asked for, or else the default one of the filter or view.
+async fn list_sort(
+    store: &dyn db::Store,
+    article_filter: &str,
+    options: &SortOptions,
+) -> Result<db::Sort> {
+    match article_filter.strip_prefix("view:") {
+        Some(id) => {
+            let q = store.get_view(id.to_string()).await?.query()?;
+            options.sort(q.filter.as_ref().unwrap_or(&db::Filter::Unread))
+        }
+        None => options.sort(&db::Filter::from_str(article_filter)?),
+    }
+}
+
+/// A page of the list an `article_filter` header names, either a filter or a view.
+async fn list_page(
+    store: &dyn db::Store,
+    pages: &prefetch::Pages,
+    article_filter: &str,
+    sort: db::Sort,
+    pagination: String,
+) -> Result<ArticleListTemplate> {
+    let page = match article_filter.strip_prefix("view:") {
+        Some(id) => {
+            let q = store.get_view(id.to_string()).await?.query()?;
+            store.get_view_articles(&q, sort, pagination).await?
+        }
+        None => {
+            let filter = db::Filter::from_str(article_filter)?;
+            store.filter(filter, sort, pagination).await?
+        }
+    };
+
+    Ok(ArticleListTemplate {
+        sort,
+        pinned: page.pinned,
+        articles: page.items,
+        next_page: pages.next(article_filter, &sort, &page.cursor),
+        numbers: page.numbers,
+        cursor: page.cursor,
+    })
+}
+
+/// Renders the page after `cursor` in the background and returns its url, which is empty when
+/// prefetching is off or there is no next page.
+fn prefetch_next(
+    store: &Arc<dyn db::Store>,
+    pages: &prefetch::Pages,
+    article_filter: &str,
+    sort: db::Sort,
+    cursor: &db::Cursor,
+) -> String {
+    let url = pages.next(article_filter, &sort, cursor);
+    if url.is_empty() || pages.contains(&url) {
+        return url;
+    }
+
+    let (store, pages, next) = (store.clone(), pages.clone(), url.clone());
+    let (article_filter, pagination) = (article_filter.to_string(), cursor.next.clone());
+    tokio::spawn(async move {
+        let rendered =
+            match list_page(store.as_ref(), &pages, &article_filter, sort, pagination).await {
+                Ok(l) => l.render().map(|html| (html, l)).map_err(|e| e.into()),
+                Err(e) => Err(e),
+            };
+        match rendered {
+            Ok((html, l)) => pages.put(next, html, l.sort, l.cursor, l.next_page),
+            Err(e) => println!("could not prefetch {}: {}", next, e),
+        }
+    });
+    url
+}
+
+#[derive(Deserialize, Schema)]
+struct PageOptions {
+    /// `unread`, `read`, `favorite`, `hidden` or `view:<id>`.
+    filter: String,
+    /// The cursor of the page.
+    pagination: String,
+    /// The column to sort by.
+    sort: Option<String>,
+    /// `asc` or `desc`.
+    dir: Option<String>,
+    /// How many articles a page holds, up to 100. The page links keep it.
+    page_size: Option<usize>,
+}
+
+/// A page of an article list addressed by url alone, so browsers can prefetch it. Pages
+/// rendered ahead of time are served from the prefetch cache.
+#[get("/articles/page")]
+async fn article_page(
+    options: Query<PageOptions>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<openapi::Documented<openapi::Html>, Rejection> {
+    let o = options.into_inner();
+    let cached = match (&o.sort, &o.dir) {
+        (Some(sort), Some(dir)) => {
+            let query = match o.page_size {
+                Some(size) => format!("sort={}&dir={}&page_size={}", sort, dir, size),
+                None => format!("sort={}&dir={}", sort, dir),
+            };
+            pages.take(&prefetch::url(&o.filter, &query, &o.pagination))
+        }
+        _ => None,
+    };
+
+    let (html, next_page) = match cached {
+        Some(p) => {
+            prefetch_next(&store, &pages, &o.filter, p.sort, &p.cursor);
+            (p.html, p.next_page)
+        }
+        None => {
+            let options = SortOptions {
+                sort: o.sort,
+                dir: o.dir,
+                page_size: o.page_size,
+                page: None,
+            };
+            let list = article_list(&store, &pages, &o.filter, options, o.pagination)
+                .await
+                .map_err(reject_anyhow)?;
+            let html = list.render().map_err(|e| reject_anyhow(e.into()))?;
+            (html, list.next_page)
+        }
+    };
+
+    let max_age = pages.ttl().unwrap_or_default().as_secs();
+    let mut response = http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(
+            http::header::CACHE_CONTROL,
+            format!("private, max-age={}", max_age),
+        );
+    if !next_page.is_empty() {
+        response = response.header(http::header::LINK, format!("<{}>; rel=prefetch", next_page));
+    }
+    response
+        .body(hyper::Body::from(html))
+        .map(openapi::Documented::new)
+        .map_err(|e| reject_anyhow(e.into()))
+}
+
+/// A page of the list named by `article_filter`, as rendered for scrolling.
+#[get("/articles")]
+async fn get_articles(
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+    pagination: Pagination,
+    article_filter: ArticleFilter,
+    options: Query<SortOptions>,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<openapi::Paged<ArticlePage>>>, Rejection> {
+    let list = article_list(
+        &store,
+        &pages,
+        &article_filter.0,
+        options.into_inner(),
+        pagination.0,
+    )
+    .await
+    .map_err(reject_anyhow)?;
+    api::list(json, &article_filter.0, list)
+}
+
+#[derive(Deserialize, Schema)]
+struct ArticlesQuery {
+    /// `unread` (the default), `read`, `favorite`, `hidden` or `view:<id>`.
+    filter: Option<String>,
+    /// The cursor of the page, as the `Link` header of the page before gave it, none for the first.
+    pagination: Option<String>,
+    /// The column to sort by.
+    sort: Option<String>,
+    /// `asc` or `desc`.
+    dir: Option<String>,
+    /// How many articles a page holds, up to 100. The page links keep it.
+    page_size: Option<usize>,
+}
+
+#[derive(Serialize, Schema)]
+struct ArticlePage {
+    pinned: Vec<Article>,
+    articles: Vec<Article>,
+    cursor: db::Cursor,
+}
+
+/// The article lists for scripts, `filter` takes the same names as the `article_filter` header
+/// and defaults to unread.
+#[get("/articles.json")]
+async fn get_articles_json(
+    options: Query<ArticlesQuery>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] pages: prefetch::Pages,
+) -> Result<openapi::Documented<openapi::Paged<ArticlePage>>, Rejection> {
+    api::article_page(
+        store.as_ref(),
+        &pages,
+        options.into_inner(),
+        "/articles.json",
+    )
+    .await
+}
+
+/// How many articles each list holds.
+#[get("/articles/counts")]
+async fn article_counts(#[data] store: Arc<dyn db::Store>) -> Result<Json<db::Counts>, Rejection> {
+    let counts = store
+        .get_counts(Utc::now().date_naive().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(counts.into())
+}
+
+/// Marks many articles at once.
+#[post("/articles/bulk")]
+async fn bulk_articles(
+    bulk: Json<BulkArticles>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<Json<BulkResult>, Rejection> {
+    let bulk = bulk.into_inner();
+    let updated = store
+        .bulk_update_articles(bulk.ids, bulk.action)
+        .await
+        .map_err(reject_anyhow)?;
+    Ok(BulkResult { updated }.into())
+}
+
+/// An article.
+#[get("/articles/{article_id}")]
+async fn get_article(
+    article_id: String,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] share: share::Share,
+    #[filter = "api::accepts_json"] json: bool,
+) -> Result<openapi::Documented<openapi::Negotiated<Json<Article>>>, Rejection> {
+    let article = store
+        .get_article_by_id(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    if json {
+        return Ok(api::vary(warp::reply::json(&article)));
+    }
+    Ok(api::vary(ArticleTemplate {
+        article,
+        targets: share.names(),
+    }))
+}
+
+/// Toggles an article read, favorite or pinned.
+#[post("/articles/{article_id}")]
+async fn toggle_article(
+    article_id: String,
+    form: Form<ArticleToggle>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] share: share::Share,
+) -> Result<ArticleDetailTemplate, Rejection> {
+    let form = form.into_inner();
+    let article = store
+        .get_article_by_id(article_id.clone())
+        .await
+        .map_err(reject_anyhow)?;
+
+    match form.toggle {
+        Toggle::Read => store.mark_article_read(article).await,
+        Toggle::Favorite => store.mark_article_favorite(article_id.clone()).await,
+        Toggle::Pin => store.mark_article_pinned(article_id.clone()).await,
+    }
+    .map_err(reject_anyhow)?;
+
+    let article = store
+        .get_article_by_id(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(ArticleDetailTemplate {
+        article,
+        targets: share.names(),
+    })
+}
+
+/// Replaces an article's note.
+#[post("/articles/{article_id}/note")]
+async fn note_article(
+    article_id: String,
+    form: Form<ArticleNote>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] share: share::Share,
+) -> Result<ArticleDetailTemplate, Rejection> {
+    let form = form.into_inner();
+    store
+        .update_article_note(article_id.clone(), form.note.trim().to_string())
+        .await
+        .map_err(reject_anyhow)?;
+
+    let article = store
+        .get_article_by_id(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(ArticleDetailTemplate {
+        article,
+        targets: share.names(),
+    })
+}
+
+/// Remembers how far an article was read.
+/// Called while the article page is scrolled, so a long read can be picked up where it was left.
+#[post("/articles/{article_id}/progress")]
+async fn article_progress(
+    article_id: String,
+    form: Form<ArticleProgress>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<String, Rejection> {
+    let form = form.into_inner();
+    store
+        .update_article_progress(article_id, form.paragraph.max(0))
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok("".to_string())
+}
+
+/// Shares an article to a target.
+#[post("/articles/{article_id}/share")]
+async fn share_article(
+    article_id: String,
+    form: Form<ShareArticle>,
+    #[data] store: Arc<dyn db::Store>,
+    #[data] share: share::Share,
+) -> Result<String, Rejection> {
+    let form = form.into_inner();
+    let article = store
+        .get_article_by_id(article_id)
+        .await
+        .map_err(reject_anyhow)?;
+
+    share
+        .send(form.target.as_str(), &article)
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(format!("sent to {}", form.target))
+}
+
+/// Every article matching the options as csv, without paginating.
+#[get("/export/articles.csv")]
+async fn export_articles_csv(
+    options: Query<ExportOptions>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<openapi::Documented<openapi::Export>, Rejection> {
+    export(store, options.into_inner(), export::Format::Csv).await
+}
+
+/// Streams every article matching the options, without paginating, as json or csv.
+#[get("/articles/export")]
+async fn export_articles(
+    options: Query<ExportOptions>,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<openapi::Documented<openapi::Export>, Rejection> {
+    let options = options.into_inner();
+    let format = match &options.format {
+        Some(f) => export::Format::from_str(f.as_str()).map_err(reject_anyhow)?,
+        None => export::Format::Json,
+    };
+    export(store, options, format).await
+}
+
+/// Streams the articles for either export route in `format`.
+async fn export(
+    store: Arc<dyn db::Store>,
+    options: ExportOptions,
+    format: export::Format,
+) -> Result<openapi::Documented<openapi::Export>, Rejection> {
+    let columns = export::columns(options.columns).map_err(reject_anyhow)?;
+    let filter = match options.filter {
+        Some(f) => Some(db::Filter::from_str(f.as_str()).map_err(reject_anyhow)?),
+        None => None,
+    };
+
+    let date = |d: Option<String>| match d {
+        Some(d) => db::timestamp(d.as_str())
+            .map(Some)
+            .ok_or_else(|| anyhow::Error::msg(format!("bad date: {}", d))),
+        None => Ok(None),
+    };
+    let since = date(options.since).map_err(reject_anyhow)?;
+    let until = date(options.until).map_err(reject_anyhow)?;
+
+    let rows = store.stream_articles(
+        columns.as_slice(),
+        db::ArticleQuery {
+            filter,
+            feed: options.feed,
+            since,
+            until,
+            ..Default::default()
+        },
+    );
+
+    let body = stream::once(future::ok(format.header(&columns)))
+        .chain(
+            rows.enumerate()
+                .map(move |(i, row)| row.map(|values| format.row(i, &columns, &values))),
+        )
+        .chain(stream::once(future::ok(format.footer())));
+
+    http::Response::builder()
+        .header(http::header::CONTENT_TYPE, format.content_type())
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", format.filename()),
+        )
+        .body(hyper::Body::wrap_stream(body))
+        .map(openapi::Documented::new)
+        .map_err(|e| reject_anyhow(e.into()))
+}
+
+/// What was read in a month, the period is `YYYY-MM`.
+#[get("/reports/{period}")]
+async fn get_report(
+    period: String,
+    #[data] store: Arc<dyn db::Store>,
+) -> Result<ReportTemplate, Rejection> {
+    let period = report::Period::from_str(period.as_str()).map_err(reject_anyhow)?;
+    let report = report::monthly(store.as_ref(), period)
+        .await
+        .map_err(reject_anyhow)?;
+
+    Ok(ReportTemplate {
+        title: format!("Reading report for {}", period.title()),
+        report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn short_ids_are_stable() {
+        assert_eq!(
+            short_id("https://example.com/a"),
+            "2dce0a4c50441bfccfa9caf4b58c3cba"
+        );
+        assert_eq!(short_id("https://example.com/a").len(), ID_BYTES * 2);
+    }
+
+    #[test]
+    fn guid_ids_hash_the_feed_id() {
+        assert_eq!(
+            Article::guid_id("feedid", "guid-1"),
+            "7353c22d7b26473b249de7ad7242fe09"
+        );
+        assert_eq!(
+            Article::legacy_guid_id("https://example.com/feed.xml", "guid-1"),
+            "d7df71d26a42cb815d5199fe2b48d841"
+        );
+        assert_ne!(
+            Article::guid_id("feedid", "guid-1"),
+            Article::guid_id("other", "guid-1")
+        );
+    }
+
+    /// A memory store holding feed `a` with an article for each title, the first published last.
+    async fn store(titles: &[&str]) -> Arc<dyn db::Store> {
+        let store: Arc<dyn db::Store> = Arc::new(memory::Memory::new(
+            db::Quota::default(),
+            db::DEFAULT_PAGE_SIZE,
+        ));
+        let feed = store
+            .add_feed(AddFeed {
+                feed_name: "a".to_string(),
+                site_url: "https://example.com/".to_string(),
+                feed_url: "https://example.com/feed.xml".to_string(),
+            })
+            .await
+            .unwrap();
+        let articles = titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| {
+                let mut a = Article::new(
+                    title.to_string(),
+                    format!("https://example.com/{}", i),
+                    "author".to_string(),
+                    format!("2024-05-{:02}T10:00:00Z", 28 - i),
+                    false,
+                    false,
+                    "".to_string(),
+                    "".to_string(),
+                );
+                a.feed = feed.name.clone();
+                a.feed_id = feed.id.clone();
+                a
+            })
+            .collect();
+        let refreshed = db::Refreshed {
+            articles,
+            history: vec![],
+            rekeyed: vec![],
+            validators: None,
+            hints: None,
+            updated: Utc::now(),
+        };
+        store.apply_refresh(feed.id, refreshed).await.unwrap();
+        store
+    }
+
+    fn json(body: &[u8]) -> Value {
+        serde_json::from_slice(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn articles_json_pages_through_the_unread_articles() {
+        let store = store(&["one", "two", "three"]).await;
+        let route = get_articles_json(store, prefetch::Pages::from_env());
+
+        let first = warp::test::request()
+            .path("/articles.json?page_size=2")
+            .reply(&route)
+            .await;
+        assert_eq!(first.status(), 200);
+        let page = json(first.body());
+        let titles: Vec<&str> = page["articles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["one", "two"]);
+        assert_eq!(page["cursor"]["has_next"], true);
+
+        let next = page["cursor"]["next"].as_str().unwrap();
+        let second = warp::test::request()
+            .path(format!("/articles.json?page_size=2&pagination={}", next).as_str())
+            .reply(&route)
+            .await;
+        let page = json(second.body());
+        assert_eq!(page["articles"].as_array().unwrap().len(), 1);
+        assert_eq!(page["articles"][0]["title"], "three");
+        assert_eq!(page["cursor"]["has_next"], false);
+    }
+
+    #[tokio::test]
+    async fn marking_an_article_read_takes_it_off_the_unread_list() {
+        let store = store(&["one", "two"]).await;
+        let pages = prefetch::Pages::from_env();
+        let id = short_id("https://example.com/0");
+
+        let marked = warp::test::request()
+            .method("POST")
+            .path(format!("/articles/{}/read", id).as_str())
+            .header("pagination", db::START)
+            .header("article_filter", "unread")
+            .reply(&mark_article_read(store.clone(), pages.clone()))
+            .await;
+        assert_eq!(marked.status(), 200);
+        assert!(store.get_article_by_id(id).await.unwrap().read);
+
+        let unread = warp::test::request()
+            .path("/articles.json")
+            .reply(&get_articles_json(store.clone(), pages.clone()))
+            .await;
+        let page = json(unread.body());
+        assert_eq!(page["articles"].as_array().unwrap().len(), 1);
+        assert_eq!(page["articles"][0]["title"], "two");
+
+        let read = warp::test::request()
+            .path("/articles.json?filter=read")
+            .reply(&get_articles_json(store, pages))
+            .await;
+        assert_eq!(json(read.body())["articles"][0]["title"], "one");
+    }
+
+    #[tokio::test]
+    async fn csv_export_escapes_fields() {
+        let store = store(&["Hello, \"world\""]).await;
+
+        for path in [
+            "/export/articles.csv?columns=feed,title",
+            "/articles/export?format=csv&columns=feed,title",
+        ] {
+            let export = warp::test::request()
+                .path(path)
+                .reply(&export_articles_csv(store.clone()).or(export_articles(store.clone())))
+                .await;
+            assert_eq!(export.status(), 200);
+            assert_eq!(
+                export.headers()[http::header::CONTENT_TYPE],
+                "text/csv; charset=utf-8"
+            );
+            assert_eq!(
+                std::str::from_utf8(export.body()).unwrap(),
+                "feed,title\na,\"Hello, \"\"world\"\"\"\n"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn expiry_leaves_hidden_pinned_and_favorited_articles_unread() {
+        let store = store(&["plain", "pinned", "favorited", "hidden"]).await;
+        let id = |i: usize| short_id(format!("https://example.com/{}", i).as_str());
+        store.mark_article_pinned(id(1)).await.unwrap();
+        store
+            .bulk_update_articles(vec![id(2)], BulkAction::Favorite)
+            .await
+            .unwrap();
+        store
+            .bulk_update_articles(vec![id(3)], BulkAction::Hide)
+            .await
+            .unwrap();
+
+        let expiry = db::Expiry {
+            max_age_days: Some(1),
+            max_unread_per_feed: None,
+        };
+        let expired = store.auto_expire_unread(expiry, Utc::now()).await.unwrap();
+        assert_eq!(expired, 1);
+        assert!(store.get_article_by_id(id(0)).await.unwrap().read);
+        assert!(!store.get_article_by_id(id(1)).await.unwrap().read);
+        assert!(!store.get_article_by_id(id(2)).await.unwrap().read);
+        assert!(!store.get_article_by_id(id(3)).await.unwrap().read);
+    }
+
+    #[tokio::test]
+    async fn search_finds_articles_by_title_words() {
+        let store = store(&["Rust release notes", "Gardening tips"]).await;
+
+        let found = warp::test::request()
+            .path("/search.html?q=release")
+            .header("accept", "application/json")
+            .reply(&search(store, search::Language::English))
+            .await;
+        assert_eq!(found.status(), 200);
+        let page = json(found.body());
+        let titles: Vec<&str> = page["articles"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, vec!["Rust release notes"]);
+    }
+}
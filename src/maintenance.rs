@@ -0,0 +1,107 @@
+use super::{blob, db, fetch, proxy, queue};
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use rweb::Schema;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// How one step of a maintenance run went.
+#[derive(Serialize, Clone, Debug, Schema)]
+pub struct Step {
+    pub name: &'static str,
+    pub duration_ms: i64,
+    /// How many rows or blobs the step removed or corrected, 0 for steps that count none.
+    pub count: u64,
+    /// Why the step failed, empty when it did not. The steps after it still run.
+    pub error: String,
+}
+
+#[derive(Serialize, Clone, Debug, Schema)]
+pub struct Run {
+    pub started: String,
+    pub duration_ms: i64,
+    pub steps: Vec<Step>,
+}
+
+/// Keeps the database of a long running instance in shape: expired rows are deleted, the unread
+/// counts kept by triggers are checked against the articles, the fetch log's deleted rows are
+/// vacuumed and the planner's statistics updated. The last run is kept to be looked at.
+#[derive(Clone, Default)]
+pub struct Maintenance {
+    last: Arc<Mutex<Option<Run>>>,
+}
+
+impl Maintenance {
+    /// The last run, none before the first one finished.
+    pub fn last(&self) -> Option<Run> {
+        self.last.lock().unwrap().clone()
+    }
+
+    pub async fn run(&self, store: &dyn db::Store, blobs: &blob::Store) -> Run {
+        let started = Utc::now();
+        let clock = Instant::now();
+        let steps = vec![
+            step("proxied images", proxy::expire(store, Utc::now())).await,
+            step("blobs", async {
+                Ok(blob::collect(store, blobs, Utc::now()).await? as u64)
+            })
+            .await,
+            step("jobs", queue::expire(store)).await,
+            step("fetch log", fetch::expire(store)).await,
+            step("vacuum fetch log", async {
+                store.vacuum("fetch_log").await?;
+                Ok(0)
+            })
+            .await,
+            step("unread counts", store.recount_unread()).await,
+            step("analyze", async {
+                store.analyze().await?;
+                Ok(0)
+            })
+            .await,
+        ];
+        let run = Run {
+            started: started.to_rfc3339_opts(SecondsFormat::Millis, true),
+            duration_ms: clock.elapsed().as_millis() as i64,
+            steps,
+        };
+        *self.last.lock().unwrap() = Some(run.clone());
+        run
+    }
+}
+
+async fn step<F>(name: &'static str, work: F) -> Step
+where
+    F: Future<Output = Result<u64>>,
+{
+    let clock = Instant::now();
+    let result = work.await;
+    let duration_ms = clock.elapsed().as_millis() as i64;
+    match result {
+        Ok(count) => {
+            if count > 0 {
+                println!(
+                    "maintenance: {} handled {} in {}ms",
+                    name, count, duration_ms
+                );
+            }
+            Step {
+                name,
+                duration_ms,
+                count,
+                error: "".to_string(),
+            }
+        }
+        Err(e) => {
+            println!("maintenance: {} failed: {}", name, e);
+            Step {
+                name,
+                duration_ms,
+                count: 0,
+                error: e.to_string(),
+            }
+        }
+    }
+}
@@ -0,0 +1,2120 @@
+use super::blob::Blob;
+use super::db::{
+    self, ArticleQuery, Counts, Domain, Expiry, Filter, Key, Ordering, Page, Quota, QuotaExceeded,
+    Refreshed, Retention, Sort, SortKey, Store, Suggestion,
+};
+use super::export::Column;
+use super::fetch::{Attempt, Payload};
+use super::fever;
+use super::greader;
+use super::migrate::{self, Migration};
+use super::mute::{Mute, Mutes};
+use super::notify::Subscription;
+use super::push::PushSubscription;
+use super::queue::{Job, State as JobState};
+use super::report::{Count, Report};
+use super::rules::{Imported, Rules};
+use super::schema::Discrepancy;
+use super::score::Signals;
+use super::scrape::Rule;
+use super::search::Language;
+use super::view::View;
+use super::{AddFeed, Article, BulkAction, Feed};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use rand_core::{OsRng, RngCore};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+/// Keeps everything in memory instead of postgres, for trying the reader out and for testing
+/// handlers without a database. Nothing survives a restart. Search finds the words of a query in
+/// titles and summaries as written, without stemming, and lists the newest matches first.
+/// Everything else behaves like [db::Storage].
+pub struct Memory {
+    state: Mutex<State>,
+    quota: Quota,
+    page_size: usize,
+}
+
+/// A feed along with the number clients of the sync apis know it by.
+struct Subscribed {
+    seq: i64,
+    feed: Feed,
+}
+
+/// An article as it is kept, with the dates postgres has columns of their own for as
+/// timestamps. The dates in `article` are the ones it was added with and never read back.
+struct Stored {
+    article: Article,
+    seq: i64,
+    published: DateTime<Utc>,
+    date_added: DateTime<Utc>,
+    read_date: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    /// Left out of the lists like articles moved to the archive table, still found by id and in
+    /// the history.
+    archived: bool,
+}
+
+#[derive(Default)]
+struct State {
+    feeds: Vec<Subscribed>,
+    /// Urls of feeds that were unsubscribed from.
+    tombstones: HashSet<String>,
+    articles: Vec<Stored>,
+    /// The last numbers handed out to a feed and to an article.
+    feed_seq: i64,
+    article_seq: i64,
+    filters: Vec<Mute>,
+    subscriptions: Vec<Subscription>,
+    push_subscriptions: Vec<PushSubscription>,
+    views: Vec<View>,
+    scrape_rules: Vec<Rule>,
+    blobs: Vec<Blob>,
+    /// The blob cached for an image url and when it was last used.
+    images: HashMap<String, (String, String)>,
+    jobs: Vec<Job>,
+    fetch_log: Vec<Attempt>,
+    payloads: HashMap<String, Payload>,
+    reports: HashMap<String, Report>,
+    digests: HashSet<String>,
+}
+
+/// A timestamp column as [Article] shows it.
+fn human(dt: Option<DateTime<Utc>>, missing: &str) -> String {
+    Article::rfc3339_timestamp_to_human(db::text(dt, missing))
+}
+
+/// Whether `article` belongs to the list of `filter`.
+fn selected(filter: &Filter, article: &Article) -> bool {
+    match filter {
+        Filter::Unread => !article.read && !article.hidden,
+        Filter::Favorite => article.favorited && !article.hidden,
+        Filter::Read => article.read && !article.hidden,
+        Filter::Hidden => article.hidden,
+    }
+}
+
+/// Whether an article of the feed named `feed` meets the restrictions of `q`.
+fn queried(q: &ArticleQuery, s: &Stored, feed: &str) -> bool {
+    q.feed.as_ref().is_none_or(|f| f == feed)
+        && q.since.is_none_or(|since| s.published >= since)
+        && q.until.is_none_or(|until| s.published < until)
+        && q.tag.as_ref().is_none_or(|t| s.article.tags.contains(t))
+        && q.title.as_ref().is_none_or(|t| {
+            s.article
+                .title
+                .to_lowercase()
+                .contains(t.to_lowercase().as_str())
+        })
+        && q.filter.as_ref().is_none_or(|f| selected(f, &s.article))
+}
+
+/// Whether `text` matches `query` read the way websearch_to_tsquery reads it: every word,
+/// quoted phrases as a whole, none prefixed with `-`, and `or` between alternatives.
+fn found(query: &str, text: &str) -> bool {
+    let (query, text) = (query.to_lowercase(), text.to_lowercase());
+    let mut terms = vec![];
+    let mut rest = query.trim_start();
+    while !rest.is_empty() {
+        let (negated, r) = match rest.strip_prefix('-') {
+            Some(r) => (true, r),
+            None => (false, rest),
+        };
+        let (term, r) = match r.strip_prefix('"') {
+            Some(r) => r.split_once('"').unwrap_or((r, "")),
+            None => r.split_once(char::is_whitespace).unwrap_or((r, "")),
+        };
+        terms.push((negated, term));
+        rest = r.trim_start();
+    }
+    terms
+        .split(|(negated, term)| !negated && *term == "or")
+        .filter(|alternative| !alternative.is_empty())
+        .any(|alternative| {
+            alternative
+                .iter()
+                .all(|(negated, term)| text.contains(term) != *negated)
+        })
+}
+
+/// The registrable domain of a feed's site, the same one `FEED_DOMAIN` picks in sql.
+fn domain(site_url: &str) -> String {
+    let host = site_url
+        .split_once("://")
+        .filter(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphabetic()))
+        .and_then(|(_, rest)| rest.split(['/', ':', '?', '#']).next())
+        .unwrap_or("")
+        .to_lowercase();
+    if host.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return host;
+    }
+    let labels: Vec<&str> = host.split('.').collect();
+    let n = labels.len();
+    let country = n >= 3
+        && ["co", "com", "org", "net", "ac", "gov", "edu"].contains(&labels[n - 2])
+        && labels[n - 1].len() == 2
+        && labels[n - 1].chars().all(|c| c.is_ascii_lowercase());
+    match (country, n) {
+        (true, _) => labels[n - 3..].join("."),
+        (false, n) if n >= 2 => labels[n - 2..].join("."),
+        _ => host,
+    }
+}
+
+/// A number between zero and one, for weighted sampling.
+fn random() -> f64 {
+    (OsRng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Sorts `items` by their keys in `ordering`, rows sharing a timestamp by id.
+fn sort<T>(items: &mut [(Key, T)], ordering: Ordering) {
+    items.sort_by(|(a, _), (b, _)| {
+        let order = (a.at, &a.id).cmp(&(b.at, &b.id));
+        match ordering {
+            Ordering::Ascending => order,
+            Ordering::Descending => order.reverse(),
+        }
+    });
+}
+
+/// The page of `items` following the `pagination` cursor in `ordering`, the way
+/// [db::Page::new] pages rows queried from postgres.
+fn keyset<T: Clone>(
+    mut items: Vec<(Key, T)>,
+    ordering: Ordering,
+    limit: usize,
+    pagination: String,
+) -> Result<Page<T>> {
+    sort(&mut items, ordering);
+    let (prev, next) = match Key::decode(pagination.as_str())? {
+        Some(key) => {
+            // the item the cursor points at is the last one before the page
+            let split = items
+                .iter()
+                .position(|(k, _)| {
+                    let order = (k.at, &k.id).cmp(&(key.at, &key.id));
+                    match ordering {
+                        Ordering::Ascending => order.is_gt(),
+                        Ordering::Descending => order.is_lt(),
+                    }
+                })
+                .unwrap_or(items.len());
+            let next = items.split_off(split);
+            (items, next)
+        }
+        None => (vec![], items),
+    };
+    let prev: Vec<(Key, T)> = prev.into_iter().rev().take(limit + 1).collect();
+    let next: Vec<(Key, T)> = next.into_iter().take(limit + 1).collect();
+    let page = Page::new(next, prev, pagination, limit, |(k, _)| Key {
+        at: k.at,
+        id: k.id.clone(),
+    });
+    Ok(page.map(|(_, item)| item.clone()))
+}
+
+/// Page `number` of `items` sorted by their keys in `ordering`.
+fn numbered<T>(
+    mut items: Vec<(Key, T)>,
+    ordering: Ordering,
+    number: usize,
+    limit: usize,
+    pagination: String,
+) -> Page<T> {
+    sort(&mut items, ordering);
+    let total = items.len();
+    let items = items
+        .into_iter()
+        .skip((number - 1) * limit)
+        .take(limit)
+        .map(|(_, item)| item)
+        .collect();
+    Page::numbered(items, pagination, number, limit, total)
+}
+
+impl State {
+    fn feed(&mut self, id: &str) -> Option<&mut Feed> {
+        self.feeds
+            .iter_mut()
+            .find(|f| f.feed.id == id)
+            .map(|f| &mut f.feed)
+    }
+
+    fn feed_seq(&self, id: &str) -> Option<i64> {
+        self.feeds.iter().find(|f| f.feed.id == id).map(|f| f.seq)
+    }
+
+    /// The current name of an article's feed, the one it was stored with once the feed is gone.
+    fn name(&self, s: &Stored) -> String {
+        self.feeds
+            .iter()
+            .find(|f| f.feed.id == s.article.feed_id)
+            .map(|f| f.feed.name.clone())
+            .unwrap_or_else(|| s.article.feed.clone())
+    }
+
+    /// Articles that are not archived.
+    fn listed(&self) -> impl Iterator<Item = &Stored> {
+        self.articles.iter().filter(|s| !s.archived)
+    }
+
+    /// An article to change, moved back out of the archive first like postgres does.
+    fn restore(&mut self, id: &str) -> Option<&mut Stored> {
+        let stored = self.articles.iter_mut().find(|s| s.article.id == id)?;
+        stored.archived = false;
+        Some(stored)
+    }
+
+    /// An article the way it is read back from postgres.
+    fn shown(&self, s: &Stored) -> Article {
+        // sources holds feed ids, shown by the current name of the feeds still around
+        let sources = s
+            .article
+            .sources
+            .iter()
+            .filter_map(|id| self.feeds.iter().find(|f| &f.feed.id == id))
+            .map(|f| f.feed.name.clone())
+            .collect();
+        Article {
+            feed: self.name(s),
+            sources,
+            published: human(Some(s.published), ""),
+            read_date: human(s.read_date, "-1"),
+            date_added: db::text(Some(s.date_added), ""),
+            updated_at: human(s.updated_at, ""),
+            ..s.article.clone()
+        }
+    }
+
+    fn value(&self, s: &Stored, column: Column) -> Value {
+        let a = &s.article;
+        match column {
+            Column::Id => Value::from(a.id.as_str()),
+            Column::Feed => Value::from(self.name(s)),
+            Column::Title => Value::from(a.title.as_str()),
+            Column::Link => Value::from(a.link.as_str()),
+            Column::Author => Value::from(a.author.as_str()),
+            Column::Published => Value::from(db::text(Some(s.published), "")),
+            Column::Read => Value::from(a.read),
+            Column::Favorited => Value::from(a.favorited),
+            Column::ReadDate => match s.read_date {
+                Some(dt) => Value::from(db::text(Some(dt), "")),
+                None => Value::Null,
+            },
+            Column::Summary => Value::from(a.summary.as_str()),
+            Column::Content => Value::from(a.content.as_str()),
+            Column::WordCount => Value::from(a.word_count),
+            Column::ReadingMinutes => Value::from(a.reading_minutes),
+            Column::Note => Value::from(a.note.as_str()),
+        }
+    }
+
+    fn fever_item(&self, s: &Stored) -> fever::Item {
+        let a = &s.article;
+        fever::Item {
+            id: s.seq,
+            feed_id: self.feed_seq(&a.feed_id).unwrap_or_default(),
+            title: a.title.clone(),
+            author: a.author.clone(),
+            html: match a.content.is_empty() {
+                true => a.summary.clone(),
+                false => a.content.clone(),
+            },
+            url: a.link.clone(),
+            is_saved: a.favorited.into(),
+            is_read: a.read.into(),
+            created_on_time: s.published.timestamp(),
+        }
+    }
+
+    fn greader_item(&self, s: &Stored) -> greader::Item {
+        let a = &s.article;
+        let feed = self.feeds.iter().find(|f| f.feed.id == a.feed_id);
+        greader::Item {
+            seq: s.seq,
+            feed: feed.map(|f| f.seq),
+            feed_name: self.name(s),
+            site_url: feed.map(|f| f.feed.site_url.clone()).unwrap_or_default(),
+            title: a.title.clone(),
+            author: a.author.clone(),
+            html: match a.content.is_empty() {
+                true => a.summary.clone(),
+                false => a.content.clone(),
+            },
+            link: a.link.clone(),
+            read: a.read,
+            favorited: a.favorited,
+            tags: a.tags.clone(),
+            published: Some(s.published),
+            date_added: s.date_added,
+        }
+    }
+
+    /// Articles in the history or only those not archived that match `matching`, paged in the
+    /// order of `sort`.
+    fn articles_page(
+        &self,
+        history: bool,
+        matching: impl Fn(&Stored) -> bool,
+        sort: Sort,
+        limit: usize,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let column: fn(&Stored) -> Option<DateTime<Utc>> = match sort.key {
+            SortKey::Published => |s| Some(s.published),
+            SortKey::Added => |s| Some(s.date_added),
+            SortKey::ReadDate => |s| s.read_date,
+            SortKey::Score => {
+                return Err(anyhow::Error::msg(
+                    "only unread articles can be sorted by score",
+                ))
+            }
+        };
+        let keyed: Vec<(Key, &Stored)> = self
+            .articles
+            .iter()
+            .filter(|s| (history || !s.archived) && matching(s))
+            .filter_map(|s| {
+                column(s).map(|at| {
+                    let id = s.article.id.clone();
+                    (Key { at, id }, s)
+                })
+            })
+            .collect();
+        let page = match sort.page {
+            Some(number) => numbered(keyed, sort.ordering, number, limit, pagination),
+            None => keyset(keyed, sort.ordering, limit, pagination)?,
+        };
+        Ok(page.map(|s| self.shown(s)))
+    }
+
+    /// Articles matching `matching` ranked by score, `pagination` is an offset.
+    fn scored_page(
+        &self,
+        matching: impl Fn(&Stored) -> bool,
+        sort: Sort,
+        limit: usize,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let mut signals = Signals::default();
+        let mut read: HashMap<String, (i64, i64)> = HashMap::new();
+        for s in self.listed() {
+            let (count, total) = read.entry(self.name(s)).or_default();
+            *count += s.article.read as i64;
+            *total += 1;
+            if s.article.favorited {
+                signals.favorite(&s.article.title, &s.article.tags);
+            }
+        }
+        signals.read_ratio = read
+            .into_iter()
+            .map(|(feed, (count, total))| (feed, count as f64 / total as f64))
+            .collect();
+
+        let mut matching: Vec<&Stored> = self.listed().filter(|s| matching(s)).collect();
+        matching.sort_by_key(|s| Reverse(s.published));
+        let now = Utc::now();
+        let scored = matching
+            .into_iter()
+            .map(|s| {
+                let a = &s.article;
+                let score = signals.score(&self.name(s), &a.title, &a.tags, s.published, now);
+                (score, s)
+            })
+            .collect();
+        Ok(db::ranked(scored, sort, limit, pagination)?.map(|s| self.shown(s)))
+    }
+
+    /// Ranked results, which are not paged: the first `limit` of `matching` newest first.
+    fn newest(&self, matching: impl Fn(&Stored) -> bool, limit: usize) -> Page<Article> {
+        let mut matching: Vec<&Stored> = self.listed().filter(|s| matching(s)).collect();
+        matching.sort_by_key(|s| Reverse(s.published));
+        Page::all(
+            matching
+                .into_iter()
+                .take(limit)
+                .map(|s| self.shown(s))
+                .collect(),
+        )
+    }
+
+    /// Stores new articles like [db::Storage::add_articles].
+    fn insert(&mut self, articles: Vec<Article>, quota: Quota) -> Result<Vec<Article>> {
+        let mutes = Mutes::new(&self.filters)?;
+        let now = Utc::now();
+        // a link or guid listed twice is taken as it first appears
+        let mut links = HashSet::new();
+        let mut ids = HashSet::new();
+        let articles: Vec<Article> = articles
+            .into_iter()
+            .filter(|a| links.insert(a.link.clone()) && ids.insert(a.id.clone()))
+            .collect();
+        // archived articles are not stored again, nor updated since they were read long ago
+        let archived: HashSet<String> = self
+            .articles
+            .iter()
+            .filter(|s| s.archived)
+            .flat_map(|s| [s.article.link.clone(), s.article.id.clone()])
+            .collect();
+
+        let mut new = vec![];
+        for article in articles {
+            if archived.contains(&article.link) || archived.contains(&article.id) {
+                continue;
+            }
+            // an article is already stored under its link or, when its feed changes links
+            // between fetches, under the id taken from its guid
+            let stored = self
+                .listed_position(|s| s.article.link == article.link)
+                .or_else(|| self.listed_position(|s| s.article.id == article.id));
+            let stored = match stored {
+                Some(i) => &mut self.articles[i],
+                None => {
+                    new.push(article);
+                    continue;
+                }
+            };
+            let a = &mut stored.article;
+            match a.content_hash.as_str() {
+                h if h == article.content_hash && a.author == article.author => {}
+                // stored before hashes were, its content may have been stripped since so only
+                // the hash is filled in
+                "" if a.author == article.author => a.content_hash = article.content_hash,
+                _ => {
+                    a.title = article.title;
+                    a.author = article.author;
+                    a.summary = article.summary;
+                    a.content = article.content;
+                    a.word_count = article.word_count;
+                    a.reading_minutes = article.reading_minutes;
+                    a.fingerprint = article.fingerprint;
+                    a.raw = article.raw;
+                    a.language = article.language;
+                    a.content_hash = article.content_hash;
+                    stored.updated_at = Some(now);
+                }
+            }
+        }
+
+        // the same story syndicated by another feed around the same time is attributed to the
+        // existing article instead of stored twice, duplicates among the new articles to the
+        // first of them
+        let mut attributed = vec![];
+        let mut earlier: Vec<Article> = vec![];
+        let mut pending = vec![];
+        for mut article in new {
+            let duplicate = self
+                .listed()
+                .map(|s| &s.article)
+                .chain(earlier.iter())
+                .find(|a| a.link == article.link || db::syndicated(a, &article))
+                .map(|a| a.id.clone());
+            if let Some(id) = duplicate {
+                attributed.push((id, article.feed_id));
+                continue;
+            }
+            earlier.push(article.clone());
+
+            mutes.apply(&mut article);
+            pending.push(article);
+        }
+
+        // over the quota a refresh still updates what is stored, it only stops adding to it
+        if let Some(limit) = quota.max_articles {
+            let count = self.listed().count() as i64;
+            let allowed = (limit - count).max(0) as usize;
+            if pending.len() > allowed {
+                println!(
+                    "articles quota of {} reached, skipping {} new articles",
+                    limit,
+                    pending.len() - allowed
+                );
+                pending.truncate(allowed);
+            }
+        }
+
+        for article in &pending {
+            // articles without a usable publish date are treated as published when added
+            let date_added = db::timestamp(&article.date_added).unwrap_or(now);
+            let mut kept = article.clone();
+            let mut urls = HashSet::new();
+            kept.enclosures.retain(|e| urls.insert(e.url.clone()));
+            self.article_seq += 1;
+            self.articles.push(Stored {
+                seq: self.article_seq,
+                published: db::timestamp(&article.published).unwrap_or(date_added),
+                date_added,
+                read_date: db::timestamp(&article.read_date),
+                updated_at: None,
+                archived: false,
+                article: kept,
+            });
+        }
+
+        // after the inserts, so duplicates of articles stored just now are attributed as well
+        for (id, feed) in attributed {
+            if let Some(s) = self
+                .articles
+                .iter_mut()
+                .find(|s| !s.archived && s.article.id == id)
+            {
+                if s.article.feed_id != feed && !s.article.sources.contains(&feed) {
+                    s.article.sources.push(feed);
+                }
+            }
+        }
+
+        Ok(pending)
+    }
+
+    fn listed_position(&self, matching: impl Fn(&Stored) -> bool) -> Option<usize> {
+        self.articles
+            .iter()
+            .position(|s| !s.archived && matching(s))
+    }
+
+    /// Marks the unread articles matching `matching` read, returning how many were.
+    fn mark_read(&mut self, matching: impl Fn(&Stored) -> bool) -> u64 {
+        let now = Utc::now();
+        let mut marked = 0;
+        for s in self.articles.iter_mut() {
+            if !s.archived && !s.article.read && matching(s) {
+                s.article.read = true;
+                s.read_date = Some(now);
+                marked += 1;
+            }
+        }
+        marked
+    }
+
+    fn add_filter(&mut self, m: Mute) -> u64 {
+        if self.filters.iter().any(|f| f.id == m.id) {
+            return 0;
+        }
+        self.filters.push(m);
+        1
+    }
+
+    fn add_subscription(&mut self, s: Subscription) -> u64 {
+        if self.subscriptions.iter().any(|x| x.id == s.id) {
+            return 0;
+        }
+        self.subscriptions.push(s);
+        1
+    }
+}
+
+impl Memory {
+    pub fn new(quota: Quota, page_size: usize) -> Memory {
+        Memory {
+            state: Mutex::new(State::default()),
+            quota,
+            page_size: page_size.clamp(1, db::MAX_PAGE_SIZE),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Stores new articles like [db::Storage::add_articles] and returns the ones that were.
+    pub async fn add_articles<T>(&self, articles: T) -> Result<Vec<Article>>
+    where
+        T: Iterator<Item = Article>,
+    {
+        self.state().insert(articles.collect(), self.quota)
+    }
+
+    /// Pinned articles are kept out of the pages and returned separately like [db::Storage] does.
+    pub async fn get_unread_articles(
+        &self,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let state = self.state();
+        let unread = |s: &Stored| selected(&Filter::Unread, &s.article);
+        let limit = self.page_size(sort);
+        let mut page = match sort.key {
+            SortKey::Score => {
+                state.scored_page(|s| unread(s) && !s.article.pinned, sort, limit, pagination)?
+            }
+            _ => state.articles_page(
+                false,
+                |s| unread(s) && !s.article.pinned,
+                sort,
+                limit,
+                pagination,
+            )?,
+        };
+        let mut pinned: Vec<&Stored> = state
+            .listed()
+            .filter(|s| unread(s) && s.article.pinned)
+            .collect();
+        pinned.sort_by_key(|s| Reverse(s.published));
+        page.pinned = pinned.into_iter().map(|s| state.shown(s)).collect();
+        Ok(page)
+    }
+
+    /// Visible articles with every word of `query` in their title or summary, newest first.
+    pub async fn search_articles(
+        &self,
+        query: String,
+        _language: Language,
+    ) -> Result<Page<Article>> {
+        let state = self.state();
+        Ok(state.newest(
+            |s| {
+                let text = format!("{}\n{}", s.article.title, s.article.summary);
+                !s.article.hidden && found(&query, &text)
+            },
+            db::SEARCH_LIMIT,
+        ))
+    }
+
+    /// Rows on a page sorted by `sort`.
+    fn page_size(&self, sort: Sort) -> usize {
+        sort.page_size.unwrap_or(self.page_size)
+    }
+
+    /// Adds `feeds` unless they are already subscribed to, or were unsubscribed from, failing
+    /// without adding any when they would go over the quota.
+    fn subscribe(&self, feeds: Vec<Feed>, tombstoned: bool) -> Result<Vec<Feed>> {
+        let mut state = self.state();
+        let mut urls: HashSet<String> = state
+            .feeds
+            .iter()
+            .map(|f| f.feed.feed_url.clone())
+            .collect();
+        let feeds: Vec<Feed> = feeds
+            .into_iter()
+            .filter(|f| !(tombstoned && state.tombstones.contains(&f.feed_url)))
+            .filter(|f| urls.insert(f.feed_url.clone()))
+            .collect();
+        if let Some(limit) = self.quota.max_feeds {
+            if (state.feeds.len() + feeds.len()) as i64 > limit {
+                return Err(QuotaExceeded {
+                    resource: "feeds",
+                    limit,
+                }
+                .into());
+            }
+        }
+        for f in &feeds {
+            state.tombstones.remove(&f.feed_url);
+            state.feed_seq += 1;
+            let seq = state.feed_seq;
+            state.feeds.push(Subscribed {
+                seq,
+                feed: f.clone(),
+            });
+        }
+        Ok(feeds)
+    }
+}
+
+#[async_trait]
+impl Store for Memory {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        Ok(vec![])
+    }
+
+    /// There is no schema to migrate, so every migration counts as applied.
+    async fn applied_migrations(&self) -> Result<BTreeSet<i32>> {
+        Ok(migrate::MIGRATIONS.iter().map(|m| m.version).collect())
+    }
+
+    async fn check_schema(&self) -> Result<Vec<Discrepancy>> {
+        Ok(vec![])
+    }
+
+    async fn add_feed(&self, f: AddFeed) -> Result<Feed> {
+        let feed = Feed::new(f.feed_name, f.site_url, f.feed_url);
+        match self.subscribe(vec![feed.clone()], false)?.is_empty() {
+            true => Err(anyhow::Error::msg(format!(
+                "already subscribed to {}",
+                feed.feed_url
+            ))),
+            false => Ok(feed),
+        }
+    }
+
+    async fn add_feeds(&self, feeds: Vec<AddFeed>, resubscribe: bool) -> Result<Vec<Feed>> {
+        let feeds = feeds
+            .into_iter()
+            .map(|f| Feed::new(f.feed_name, f.site_url, f.feed_url))
+            .collect();
+        self.subscribe(feeds, !resubscribe)
+    }
+
+    async fn update_feed_search_language(
+        &self,
+        id: String,
+        language: Option<Language>,
+    ) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.search_language = language.map(|l| l.to_string()).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    /// Search does not stem words, so the language changes nothing.
+    async fn set_search_language(&self, _language: Language) -> Result<()> {
+        Ok(())
+    }
+
+    async fn toggle_feed_keep_content(&self, id: String) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.keep_content = !f.keep_content;
+        }
+        Ok(())
+    }
+
+    async fn update_feed_refresh_interval(&self, id: String, seconds: i32) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.refresh_interval = seconds;
+            f.next_poll = "".to_string();
+        }
+        Ok(())
+    }
+
+    async fn toggle_feed_push(&self, id: String) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.push = !f.push;
+        }
+        Ok(())
+    }
+
+    async fn get_feed_by_id(&self, id: String) -> Result<Feed> {
+        self.state()
+            .feed(&id)
+            .map(|f| f.clone())
+            .ok_or_else(|| anyhow::Error::msg(format!("no such feed: {}", id)))
+    }
+
+    async fn get_feeds(&self, pagination: String) -> Result<Page<Feed>> {
+        let state = self.state();
+        let keyed = state
+            .feeds
+            .iter()
+            .map(|f| {
+                let at = db::timestamp(&f.feed.date_added).unwrap_or_default();
+                let id = f.feed.id.clone();
+                (Key { at, id }, &f.feed)
+            })
+            .collect();
+        let page = keyset(keyed, Ordering::Descending, self.page_size, pagination)?;
+        Ok(page.map(|f| (*f).clone()))
+    }
+
+    async fn delete_feed(&self, id: String) -> Result<()> {
+        let mut state = self.state();
+        let url = match state.feeds.iter().find(|f| f.feed.id == id) {
+            Some(f) => f.feed.feed_url.clone(),
+            None => return Ok(()),
+        };
+        state.tombstones.insert(url);
+        state.feeds.retain(|f| f.feed.id != id);
+        state.scrape_rules.retain(|r| r.feed != id);
+        state.fetch_log.retain(|a| a.feed != id);
+        state.payloads.remove(&id);
+        for s in state.articles.iter_mut() {
+            if s.article.feed_id == id {
+                s.article.feed_id = "".to_string();
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_feed_retry_after(&self, id: String, until: String) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.next_poll = f.next_poll.clone().max(until.clone());
+            f.retry_after = until;
+        }
+        Ok(())
+    }
+
+    async fn apply_refresh(
+        &self,
+        id: String,
+        refreshed: Refreshed,
+    ) -> Result<(Vec<Article>, usize)> {
+        let mut state = self.state();
+        for (old, new) in refreshed.rekeyed {
+            if state.articles.iter().any(|s| s.article.id == new) {
+                continue;
+            }
+            if let Some(s) = state.articles.iter_mut().find(|s| s.article.id == old) {
+                s.article.id = new;
+            }
+        }
+        let new = state.insert(refreshed.articles, self.quota)?;
+        let history = state.insert(refreshed.history, self.quota)?.len();
+        if let Some(f) = state.feed(&id) {
+            if let Some((etag, last_modified, build_marker)) = refreshed.validators {
+                f.etag = etag;
+                f.last_modified = last_modified;
+                f.build_marker = build_marker;
+            }
+            if let Some(hints) = refreshed.hints {
+                f.hints = hints;
+            }
+            f.fetch_error = "".to_string();
+            f.failures = 0;
+            f.last_updated = db::text(Some(refreshed.updated), "-1");
+        }
+        Ok((new, history))
+    }
+
+    async fn update_feed_fetch_error(&self, id: String, error: String) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.fetch_error = error;
+        }
+        Ok(())
+    }
+
+    async fn add_feed_failure(&self, id: String, max: i32) -> Result<bool> {
+        Ok(match self.state().feed(&id) {
+            Some(f) => {
+                f.failures += 1;
+                f.erroring = f.erroring || f.failures >= max;
+                f.erroring && f.failures == max
+            }
+            None => false,
+        })
+    }
+
+    async fn enable_feed(&self, id: String) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.erroring = false;
+            f.failures = 0;
+            f.next_poll = "".to_string();
+        }
+        Ok(())
+    }
+
+    async fn get_unread_suggestions(
+        &self,
+        since: DateTime<Utc>,
+        min_articles: i64,
+        min_unread_ratio: f64,
+    ) -> Result<Vec<Suggestion>> {
+        let state = self.state();
+        let mut suggestions: Vec<Suggestion> = state
+            .feeds
+            .iter()
+            .filter(|f| !f.feed.muted)
+            .map(|f| {
+                let recent: Vec<&Stored> = state
+                    .listed()
+                    .filter(|s| s.article.feed_id == f.feed.id && s.date_added >= since)
+                    .collect();
+                Suggestion {
+                    id: f.feed.id.clone(),
+                    name: f.feed.name.clone(),
+                    total: recent.len() as i64,
+                    unread: recent.iter().filter(|s| !s.article.read).count() as i64,
+                }
+            })
+            .filter(|s| s.total > 0 && s.total >= min_articles)
+            .filter(|s| s.unread as f64 / s.total as f64 >= min_unread_ratio)
+            .collect();
+        let ratio = |s: &Suggestion| s.unread as f64 / s.total as f64;
+        suggestions.sort_by(|a, b| ratio(b).total_cmp(&ratio(a)).then(a.name.cmp(&b.name)));
+        Ok(suggestions)
+    }
+
+    async fn get_feed_domains(&self) -> Result<Vec<Domain>> {
+        let state = self.state();
+        let mut domains: BTreeMap<String, Domain> = BTreeMap::new();
+        for f in &state.feeds {
+            let name = domain(&f.feed.site_url);
+            let d = domains.entry(name.clone()).or_insert(Domain {
+                name,
+                feeds: vec![],
+                muted: true,
+            });
+            d.feeds.push(f.feed.name.clone());
+            d.muted = d.muted && f.feed.muted;
+        }
+        Ok(domains
+            .into_values()
+            .map(|mut d| {
+                d.feeds.sort();
+                d
+            })
+            .collect())
+    }
+
+    async fn get_feeds_by_domain(&self, domain: String) -> Result<Vec<Feed>> {
+        let state = self.state();
+        let mut feeds: Vec<Feed> = state
+            .feeds
+            .iter()
+            .filter(|f| self::domain(&f.feed.site_url) == domain)
+            .map(|f| f.feed.clone())
+            .collect();
+        feeds.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(feeds)
+    }
+
+    async fn mute_domain(&self, domain: String, muted: bool) -> Result<()> {
+        let mut state = self.state();
+        let mut feeds = HashSet::new();
+        for f in state.feeds.iter_mut() {
+            if self::domain(&f.feed.site_url) == domain {
+                f.feed.muted = muted;
+                feeds.insert(f.feed.id.clone());
+            }
+        }
+        if muted {
+            state.mark_read(|s| feeds.contains(&s.article.feed_id));
+        }
+        Ok(())
+    }
+
+    async fn get_due_feeds(&self, now: String) -> Result<Vec<Feed>> {
+        let state = self.state();
+        let mut feeds: Vec<Feed> = state
+            .feeds
+            .iter()
+            .map(|f| &f.feed)
+            .filter(|f| f.next_poll <= now && !f.muted && !f.erroring)
+            .cloned()
+            .collect();
+        feeds.sort_by(|a, b| a.next_poll.cmp(&b.next_poll));
+        Ok(feeds)
+    }
+
+    async fn update_feed_schedule(
+        &self,
+        id: String,
+        poll_interval: i32,
+        next_poll: String,
+    ) -> Result<()> {
+        if let Some(f) = self.state().feed(&id) {
+            f.poll_interval = poll_interval;
+            f.next_poll = next_poll;
+        }
+        Ok(())
+    }
+
+    async fn get_feed_publish_history(&self, feed: String, limit: i64) -> Result<Vec<String>> {
+        let state = self.state();
+        let mut published: Vec<DateTime<Utc>> = state
+            .listed()
+            .filter(|s| s.article.feed_id == feed)
+            .map(|s| s.published)
+            .collect();
+        published.sort_by(|a, b| b.cmp(a));
+        Ok(published
+            .into_iter()
+            .take(limit as usize)
+            .map(|dt| db::text(Some(dt), ""))
+            .collect())
+    }
+
+    async fn get_counts(&self, today: String) -> Result<Counts> {
+        let state = self.state();
+        let today = db::timestamp(&today);
+        let count = |matching: &dyn Fn(&Stored) -> bool| {
+            state.listed().filter(|s| matching(s)).count() as i64
+        };
+        Ok(Counts {
+            unread: count(&|s| selected(&Filter::Unread, &s.article)),
+            read_today: count(&|s| {
+                selected(&Filter::Read, &s.article)
+                    && today.is_some_and(|today| s.read_date.is_some_and(|dt| dt >= today))
+            }),
+            favorites: count(&|s| selected(&Filter::Favorite, &s.article)),
+        })
+    }
+
+    async fn get_unread_counts(&self) -> Result<BTreeMap<String, i64>> {
+        let state = self.state();
+        let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+        for s in state.listed() {
+            if selected(&Filter::Unread, &s.article) {
+                *counts.entry(s.article.feed_id.clone()).or_default() += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Unread articles are counted when they are asked for, there is nothing to correct.
+    async fn recount_unread(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    async fn vacuum(&self, _table: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn analyze(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn auto_expire_unread(&self, expiry: Expiry, now: DateTime<Utc>) -> Result<u64> {
+        let mut state = self.state();
+        let mut expired = 0;
+
+        if let Some(days) = expiry.max_age_days {
+            let cutoff = now - Duration::days(days);
+            expired += state.mark_read(|s| {
+                !s.article.hidden
+                    && !s.article.pinned
+                    && !s.article.favorited
+                    && s.published < cutoff
+            });
+        }
+
+        if let Some(limit) = expiry.max_unread_per_feed {
+            let mut unread: Vec<&Stored> = state
+                .listed()
+                .filter(|s| selected(&Filter::Unread, &s.article))
+                .filter(|s| !s.article.pinned && !s.article.favorited)
+                .collect();
+            unread.sort_by_key(|s| Reverse(s.published));
+            let mut seen: HashMap<&str, i64> = HashMap::new();
+            let mut over = HashSet::new();
+            for s in unread {
+                let feed = match s.article.feed_id.is_empty() {
+                    true => s.article.feed.as_str(),
+                    false => s.article.feed_id.as_str(),
+                };
+                let n = seen.entry(feed).or_default();
+                *n += 1;
+                if *n > limit {
+                    over.insert(s.article.id.clone());
+                }
+            }
+            expired += state.mark_read(|s| over.contains(&s.article.id));
+        }
+
+        Ok(expired)
+    }
+
+    async fn strip_read_content(&self, retention: Retention, now: DateTime<Utc>) -> Result<u64> {
+        let days = match retention.content_days {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+        let mut state = self.state();
+        let cutoff = now - Duration::days(days);
+        let kept: HashSet<String> = state
+            .feeds
+            .iter()
+            .filter(|f| f.feed.keep_content)
+            .map(|f| f.feed.id.clone())
+            .collect();
+
+        let mut stripped = 0;
+        for s in state.articles.iter_mut().filter(|s| !s.archived) {
+            let a = &mut s.article;
+            let stale = a.read
+                && !a.favorited
+                && !a.pinned
+                && s.read_date.is_some_and(|dt| dt < cutoff)
+                && !kept.contains(&a.feed_id);
+            if !stale {
+                continue;
+            }
+            a.enclosures.retain(|e| !e.is_image());
+            if !a.content.is_empty() {
+                a.content = "".to_string();
+                stripped += 1;
+            }
+        }
+        Ok(stripped)
+    }
+
+    async fn archive_read_articles(&self, retention: Retention, now: DateTime<Utc>) -> Result<u64> {
+        let days = match retention.archive_days {
+            Some(d) => d,
+            None => return Ok(0),
+        };
+        let mut state = self.state();
+        let cutoff = now - Duration::days(days);
+        let mut archived = 0;
+        for s in state.articles.iter_mut().filter(|s| !s.archived) {
+            let a = &s.article;
+            if archived < db::ARCHIVE_BATCH
+                && a.read
+                && !a.favorited
+                && !a.pinned
+                && !a.hidden
+                && s.read_date.is_some_and(|dt| dt < cutoff)
+            {
+                s.archived = true;
+                archived += 1;
+            }
+        }
+        Ok(archived as u64)
+    }
+
+    async fn get_recent_articles(&self, limit: i64) -> Result<Vec<Article>> {
+        let state = self.state();
+        let mut recent: Vec<&Stored> = state.listed().collect();
+        recent.sort_by_key(|s| Reverse(s.date_added));
+        Ok(recent
+            .into_iter()
+            .take(limit as usize)
+            .map(|s| state.shown(s))
+            .collect())
+    }
+
+    async fn get_known_links(&self, links: Vec<String>) -> Result<Vec<String>> {
+        let state = self.state();
+        Ok(state
+            .articles
+            .iter()
+            .filter(|s| links.contains(&s.article.link))
+            .map(|s| s.article.link.clone())
+            .collect())
+    }
+
+    async fn get_article_by_id(&self, id: String) -> Result<Article> {
+        let state = self.state();
+        state
+            .articles
+            .iter()
+            .find(|s| s.article.id == id)
+            .map(|s| state.shown(s))
+            .ok_or_else(|| anyhow::Error::msg(format!("no such article: {}", id)))
+    }
+
+    async fn get_unread_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>> {
+        Memory::get_unread_articles(self, sort, pagination).await
+    }
+
+    async fn search_articles(&self, query: String, language: Language) -> Result<Page<Article>> {
+        Memory::search_articles(self, query, language).await
+    }
+
+    async fn fuzzy_search_titles(&self, query: String) -> Result<Page<Article>> {
+        let state = self.state();
+        let query = query.to_lowercase();
+        Ok(state.newest(
+            |s| !s.article.hidden && s.article.title.to_lowercase().contains(&query),
+            db::SEARCH_LIMIT,
+        ))
+    }
+
+    async fn sample_articles(&self, before: DateTime<Utc>) -> Result<Page<Article>> {
+        let state = self.state();
+        let mut matching: Vec<&Stored> = state
+            .listed()
+            .filter(|s| {
+                let a = &s.article;
+                (!a.read || a.favorited) && !a.hidden && s.published < before
+            })
+            .collect();
+        matching.sort_by_key(|s| Reverse(s.published));
+        // weighted sampling without replacement: order by random ^ (1 / weight)
+        let mut weighted: Vec<(f64, &Stored)> = matching
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| (random().powf(1.0 / (i + 1) as f64), s))
+            .collect();
+        weighted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(Page::all(
+            weighted
+                .into_iter()
+                .take(db::SAMPLE_LIMIT)
+                .map(|(_, s)| state.shown(s))
+                .collect(),
+        ))
+    }
+
+    async fn get_podcast_episodes(&self, pagination: String) -> Result<Page<Article>> {
+        let sort = Sort::default();
+        self.state().articles_page(
+            false,
+            |s| !s.article.hidden && s.article.enclosures.iter().any(|e| e.is_audio()),
+            sort,
+            self.page_size(sort),
+            pagination,
+        )
+    }
+
+    async fn get_read_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>> {
+        self.state().articles_page(
+            true,
+            |s| selected(&Filter::Read, &s.article),
+            sort,
+            self.page_size(sort),
+            pagination,
+        )
+    }
+
+    async fn get_hidden_articles(&self, sort: Sort, pagination: String) -> Result<Page<Article>> {
+        self.state().articles_page(
+            false,
+            |s| selected(&Filter::Hidden, &s.article),
+            sort,
+            self.page_size(sort),
+            pagination,
+        )
+    }
+
+    async fn get_favorited_articles(
+        &self,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        self.state().articles_page(
+            false,
+            |s| selected(&Filter::Favorite, &s.article),
+            sort,
+            self.page_size(sort),
+            pagination,
+        )
+    }
+
+    fn stream_articles(
+        &self,
+        columns: &[Column],
+        q: ArticleQuery,
+    ) -> BoxStream<'static, Result<Vec<Value>>> {
+        let state = self.state();
+        let mut matching: Vec<&Stored> = state
+            .listed()
+            .filter(|s| queried(&q, s, &state.name(s)))
+            .collect();
+        matching.sort_by(|a, b| (b.published, &b.article.id).cmp(&(a.published, &a.article.id)));
+        let rows: Vec<Result<Vec<Value>>> = matching
+            .into_iter()
+            .map(|s| Ok(columns.iter().map(|c| state.value(s, *c)).collect()))
+            .collect();
+        stream::iter(rows).boxed()
+    }
+
+    async fn get_view_articles(
+        &self,
+        q: &ArticleQuery,
+        sort: Sort,
+        pagination: String,
+    ) -> Result<Page<Article>> {
+        let state = self.state();
+        state.articles_page(
+            false,
+            |s| queried(q, s, &state.name(s)),
+            sort,
+            self.page_size(sort),
+            pagination,
+        )
+    }
+
+    async fn mark_article_read(&self, a: Article) -> Result<()> {
+        if let Some(s) = self.state().restore(&a.id) {
+            s.article.read = !s.article.read;
+            s.read_date = match a.read {
+                true => None,
+                false => Some(Utc::now()),
+            };
+        }
+        Ok(())
+    }
+
+    async fn mark_article_hidden(&self, id: String) -> Result<()> {
+        if let Some(s) = self.state().restore(&id) {
+            s.article.hidden = !s.article.hidden;
+        }
+        Ok(())
+    }
+
+    async fn mark_article_pinned(&self, id: String) -> Result<()> {
+        if let Some(s) = self.state().restore(&id) {
+            s.article.pinned = !s.article.pinned;
+        }
+        Ok(())
+    }
+
+    async fn mark_feed_read(&self, feed: String) -> Result<()> {
+        self.state().mark_read(|s| s.article.feed_id == feed);
+        Ok(())
+    }
+
+    async fn update_article_note(&self, id: String, note: String) -> Result<()> {
+        if let Some(s) = self.state().restore(&id) {
+            s.article.note = note;
+        }
+        Ok(())
+    }
+
+    async fn update_article_progress(&self, id: String, progress: i32) -> Result<()> {
+        if let Some(s) = self.state().restore(&id) {
+            s.article.progress = progress;
+        }
+        Ok(())
+    }
+
+    async fn mark_article_favorite(&self, id: String) -> Result<()> {
+        if let Some(s) = self.state().restore(&id) {
+            s.article.favorited = !s.article.favorited;
+        }
+        Ok(())
+    }
+
+    async fn bulk_update_articles(&self, ids: Vec<String>, action: BulkAction) -> Result<u64> {
+        let mut state = self.state();
+        let now = Utc::now();
+        let mut updated = 0;
+        for id in &ids {
+            let s = match state.restore(id) {
+                Some(s) => s,
+                None => continue,
+            };
+            let a = &mut s.article;
+            let changed = match action {
+                BulkAction::Read if !a.read => {
+                    a.read = true;
+                    s.read_date = Some(now);
+                    true
+                }
+                BulkAction::Unread if a.read => {
+                    a.read = false;
+                    s.read_date = None;
+                    true
+                }
+                BulkAction::Favorite | BulkAction::Unfavorite => {
+                    let favorite = matches!(action, BulkAction::Favorite);
+                    std::mem::replace(&mut a.favorited, favorite) != favorite
+                }
+                BulkAction::Hide | BulkAction::Unhide => {
+                    let hide = matches!(action, BulkAction::Hide);
+                    std::mem::replace(&mut a.hidden, hide) != hide
+                }
+                _ => false,
+            };
+            updated += changed as u64;
+        }
+        Ok(updated)
+    }
+
+    async fn get_last_refreshed(&self) -> Result<Option<DateTime<Utc>>> {
+        let state = self.state();
+        Ok(state
+            .feeds
+            .iter()
+            .filter_map(|f| db::timestamp(&f.feed.last_updated))
+            .max())
+    }
+
+    async fn get_fever_feeds(&self) -> Result<Vec<fever::Feed>> {
+        let state = self.state();
+        let mut feeds: Vec<&Subscribed> = state.feeds.iter().collect();
+        feeds.sort_by(|a, b| a.feed.name.cmp(&b.feed.name));
+        Ok(feeds
+            .into_iter()
+            .map(|f| fever::Feed {
+                id: f.seq,
+                favicon_id: 0,
+                title: f.feed.name.clone(),
+                url: f.feed.feed_url.clone(),
+                site_url: f.feed.site_url.clone(),
+                is_spark: 0,
+                last_updated_on_time: fever::seconds(db::timestamp(&f.feed.last_updated)),
+            })
+            .collect())
+    }
+
+    async fn get_fever_items(&self, items: fever::Items, limit: i64) -> Result<Vec<fever::Item>> {
+        let state = self.state();
+        let mut matching: Vec<&Stored> = state
+            .articles
+            .iter()
+            .filter(|s| match &items {
+                fever::Items::Since(seq) => s.seq > *seq && !s.article.hidden,
+                fever::Items::Before(seq) => s.seq < *seq && !s.article.hidden,
+                fever::Items::With(seqs) => seqs.contains(&s.seq),
+            })
+            .collect();
+        matching.sort_by_key(|s| s.seq);
+        if let fever::Items::Before(_) = items {
+            matching.reverse();
+        }
+        Ok(matching
+            .into_iter()
+            .take(limit as usize)
+            .map(|s| state.fever_item(s))
+            .collect())
+    }
+
+    async fn count_fever_items(&self) -> Result<i64> {
+        let state = self.state();
+        Ok(state.articles.iter().filter(|s| !s.article.hidden).count() as i64)
+    }
+
+    async fn get_fever_item_ids(&self, saved: bool) -> Result<Vec<i64>> {
+        let state = self.state();
+        let mut seqs: Vec<i64> = state
+            .listed()
+            .filter(|s| {
+                let a = &s.article;
+                let wanted = match saved {
+                    true => a.favorited,
+                    false => !a.read,
+                };
+                wanted && !a.hidden
+            })
+            .map(|s| s.seq)
+            .collect();
+        seqs.sort();
+        Ok(seqs)
+    }
+
+    async fn get_article_ids_by_seq(&self, seqs: Vec<i64>) -> Result<Vec<String>> {
+        let state = self.state();
+        Ok(state
+            .articles
+            .iter()
+            .filter(|s| seqs.contains(&s.seq))
+            .map(|s| s.article.id.clone())
+            .collect())
+    }
+
+    async fn mark_read_before(
+        &self,
+        feed: Option<i64>,
+        tag: Option<String>,
+        before: DateTime<Utc>,
+    ) -> Result<u64> {
+        let mut state = self.state();
+        let feed = feed.map(|seq| {
+            state
+                .feeds
+                .iter()
+                .find(|f| f.seq == seq)
+                .map(|f| f.feed.id.clone())
+        });
+        Ok(state.mark_read(|s| {
+            s.date_added < before
+                && feed
+                    .as_ref()
+                    .is_none_or(|id| id.as_ref() == Some(&s.article.feed_id))
+                && tag.as_ref().is_none_or(|t| s.article.tags.contains(t))
+        }))
+    }
+
+    async fn get_greader_subscriptions(&self) -> Result<Vec<greader::Subscription>> {
+        let state = self.state();
+        let mut feeds: Vec<&Subscribed> = state.feeds.iter().collect();
+        feeds.sort_by(|a, b| a.feed.name.cmp(&b.feed.name));
+        Ok(feeds
+            .into_iter()
+            .map(|f| greader::Subscription {
+                id: format!("{}{}", greader::FEED, f.seq),
+                title: f.feed.name.clone(),
+                categories: vec![],
+                url: f.feed.feed_url.clone(),
+                html_url: f.feed.site_url.clone(),
+                icon_url: "".to_string(),
+            })
+            .collect())
+    }
+
+    async fn get_greader_unread_counts(&self) -> Result<Vec<greader::UnreadCount>> {
+        let state = self.state();
+        Ok(state
+            .feeds
+            .iter()
+            .map(|f| greader::UnreadCount {
+                feed: f.seq,
+                count: state
+                    .listed()
+                    .filter(|s| {
+                        s.article.feed_id == f.feed.id && selected(&Filter::Unread, &s.article)
+                    })
+                    .count() as i64,
+                last_updated: db::timestamp(&f.feed.last_updated),
+            })
+            .filter(|c| c.count > 0)
+            .collect())
+    }
+
+    async fn get_greader_items(&self, s: &greader::Selection) -> Result<Vec<greader::Item>> {
+        let state = self.state();
+        let (feed, tag, only_read, only_starred) = match &s.stream {
+            greader::Stream::All => (None, None, false, false),
+            greader::Stream::Read => (None, None, true, false),
+            greader::Stream::Starred => (None, None, false, true),
+            greader::Stream::Feed(seq) => (Some(*seq), None, false, false),
+            greader::Stream::Label(tag) => (None, Some(tag), false, false),
+        };
+        let mut matching: Vec<&Stored> = state
+            .articles
+            .iter()
+            .filter(|a| {
+                let article = &a.article;
+                !article.hidden
+                    && feed.is_none_or(|seq| state.feed_seq(&article.feed_id) == Some(seq))
+                    && (!only_read || article.read)
+                    && (!only_starred || article.favorited)
+                    && (!s.exclude_read || !article.read)
+                    && s.since.is_none_or(|since| a.date_added >= since)
+                    && s.until.is_none_or(|until| a.date_added < until)
+                    && s.continuation.is_none_or(|seq| match s.oldest_first {
+                        true => a.seq > seq,
+                        false => a.seq < seq,
+                    })
+                    && tag.is_none_or(|t| article.tags.contains(t))
+            })
+            .collect();
+        matching.sort_by_key(|a| a.seq);
+        if !s.oldest_first {
+            matching.reverse();
+        }
+        Ok(matching
+            .into_iter()
+            .take(s.count as usize)
+            .map(|a| state.greader_item(a))
+            .collect())
+    }
+
+    async fn get_greader_items_by_seq(&self, seqs: Vec<i64>) -> Result<Vec<greader::Item>> {
+        let state = self.state();
+        let mut matching: Vec<&Stored> = state
+            .articles
+            .iter()
+            .filter(|s| seqs.contains(&s.seq))
+            .collect();
+        matching.sort_by_key(|s| s.seq);
+        Ok(matching
+            .into_iter()
+            .map(|s| state.greader_item(s))
+            .collect())
+    }
+
+    async fn get_tags(&self) -> Result<Vec<Count>> {
+        let state = self.state();
+        let mut tags: BTreeMap<String, i64> = BTreeMap::new();
+        for tag in state.listed().flat_map(|s| s.article.tags.iter()) {
+            *tags.entry(tag.clone()).or_default() += 1;
+        }
+        Ok(tags
+            .into_iter()
+            .map(|(name, count)| Count { name, count })
+            .collect())
+    }
+
+    async fn rename_tag(&self, tag: String, name: String) -> Result<()> {
+        if name.is_empty() {
+            return Err(anyhow::Error::msg("tag name cannot be empty"));
+        }
+
+        let mut state = self.state();
+        if state
+            .articles
+            .iter()
+            .any(|s| s.article.tags.contains(&name))
+        {
+            return Err(anyhow::Error::msg(format!(
+                "tag {} already exists, merge into it instead",
+                name
+            )));
+        }
+        for t in state
+            .articles
+            .iter_mut()
+            .flat_map(|s| s.article.tags.iter_mut())
+        {
+            if *t == tag {
+                *t = name.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn merge_tag(&self, tag: String, into: String) -> Result<()> {
+        if into.is_empty() || into == tag {
+            return Err(anyhow::Error::msg(format!(
+                "cannot merge {} into {}",
+                tag, into
+            )));
+        }
+
+        let mut state = self.state();
+        for s in state.articles.iter_mut() {
+            let tags = &mut s.article.tags;
+            if !tags.contains(&tag) {
+                continue;
+            }
+            match tags.contains(&into) {
+                true => tags.retain(|t| *t != tag),
+                false => {
+                    for t in tags.iter_mut().filter(|t| **t == tag) {
+                        *t = into.clone();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_tag(&self, tag: String) -> Result<()> {
+        for s in self.state().articles.iter_mut() {
+            s.article.tags.retain(|t| *t != tag);
+        }
+        Ok(())
+    }
+
+    async fn get_filters(&self) -> Result<Vec<Mute>> {
+        let mut filters = self.state().filters.clone();
+        filters.sort_by(|a, b| a.date_added.cmp(&b.date_added));
+        Ok(filters)
+    }
+
+    async fn add_filter(&self, m: Mute) -> Result<()> {
+        self.state().add_filter(m);
+        Ok(())
+    }
+
+    async fn update_filter(&self, id: String, m: Mute) -> Result<()> {
+        let mut state = self.state();
+        let filter = state
+            .filters
+            .iter_mut()
+            .find(|f| f.id == id)
+            .ok_or_else(|| anyhow::Error::msg(format!("no such filter: {}", id)))?;
+        filter.pattern = m.pattern;
+        filter.regex = m.regex;
+        filter.field = m.field;
+        filter.action = m.action;
+        Ok(())
+    }
+
+    async fn delete_filter(&self, id: String) -> Result<()> {
+        self.state().filters.retain(|f| f.id != id);
+        Ok(())
+    }
+
+    async fn get_subscriptions(&self) -> Result<Vec<Subscription>> {
+        let mut subscriptions = self.state().subscriptions.clone();
+        subscriptions.sort_by(|a, b| (&a.tag, &a.date_added).cmp(&(&b.tag, &b.date_added)));
+        Ok(subscriptions)
+    }
+
+    async fn add_subscription(&self, s: Subscription) -> Result<()> {
+        self.state().add_subscription(s);
+        Ok(())
+    }
+
+    #[cfg(feature = "push")]
+    async fn get_push_subscriptions(&self) -> Result<Vec<PushSubscription>> {
+        let mut subscriptions = self.state().push_subscriptions.clone();
+        subscriptions.sort_by(|a, b| a.date_added.cmp(&b.date_added));
+        Ok(subscriptions)
+    }
+
+    async fn add_push_subscription(&self, s: PushSubscription) -> Result<()> {
+        let mut state = self.state();
+        match state
+            .push_subscriptions
+            .iter_mut()
+            .find(|x| x.endpoint == s.endpoint)
+        {
+            Some(existing) => {
+                existing.p256dh = s.p256dh;
+                existing.auth = s.auth;
+            }
+            None => state.push_subscriptions.push(s),
+        }
+        Ok(())
+    }
+
+    async fn delete_push_subscription(&self, endpoint: String) -> Result<()> {
+        self.state()
+            .push_subscriptions
+            .retain(|s| s.endpoint != endpoint);
+        Ok(())
+    }
+
+    async fn get_views(&self) -> Result<Vec<View>> {
+        let mut views = self.state().views.clone();
+        views.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(views)
+    }
+
+    async fn get_view(&self, id: String) -> Result<View> {
+        self.state()
+            .views
+            .iter()
+            .find(|v| v.id == id)
+            .cloned()
+            .ok_or_else(|| anyhow::Error::msg(format!("no such view: {}", id)))
+    }
+
+    async fn add_view(&self, v: View) -> Result<()> {
+        let mut state = self.state();
+        match state.views.iter_mut().find(|x| x.id == v.id) {
+            Some(existing) => {
+                existing.filter = v.filter;
+                existing.feed = v.feed;
+                existing.tag = v.tag;
+                existing.keyword = v.keyword;
+            }
+            None => state.views.push(v),
+        }
+        Ok(())
+    }
+
+    async fn delete_view(&self, id: String) -> Result<()> {
+        self.state().views.retain(|v| v.id != id);
+        Ok(())
+    }
+
+    async fn get_scrape_rules(&self) -> Result<Vec<Rule>> {
+        let mut rules = self.state().scrape_rules.clone();
+        rules.sort_by(|a, b| a.date_added.cmp(&b.date_added));
+        Ok(rules)
+    }
+
+    async fn get_scrape_rule(&self, feed: String) -> Result<Option<Rule>> {
+        Ok(self
+            .state()
+            .scrape_rules
+            .iter()
+            .find(|r| r.feed == feed)
+            .cloned())
+    }
+
+    async fn put_scrape_rule(&self, r: &Rule) -> Result<()> {
+        let mut state = self.state();
+        match state.scrape_rules.iter_mut().find(|x| x.feed == r.feed) {
+            Some(existing) => {
+                existing.item = r.item.clone();
+                existing.title = r.title.clone();
+                existing.link = r.link.clone();
+                existing.date = r.date.clone();
+            }
+            None => state.scrape_rules.push(r.clone()),
+        }
+        Ok(())
+    }
+
+    async fn delete_scrape_rule(&self, feed: String) -> Result<()> {
+        self.state().scrape_rules.retain(|r| r.feed != feed);
+        Ok(())
+    }
+
+    async fn add_blob(&self, b: Blob) -> Result<()> {
+        let mut state = self.state();
+        if !state.blobs.iter().any(|x| x.hash == b.hash) {
+            state.blobs.push(b);
+        }
+        Ok(())
+    }
+
+    async fn get_uncached_images(&self, limit: i64) -> Result<Vec<String>> {
+        let state = self.state();
+        let mut latest: HashMap<&str, DateTime<Utc>> = HashMap::new();
+        for s in state.listed() {
+            for e in &s.article.enclosures {
+                if e.blob.is_empty() && e.is_image() {
+                    let added = latest.entry(e.url.as_str()).or_insert(s.date_added);
+                    *added = (*added).max(s.date_added);
+                }
+            }
+        }
+        let mut urls: Vec<(&str, DateTime<Utc>)> = latest.into_iter().collect();
+        urls.sort_by_key(|(_, added)| Reverse(*added));
+        Ok(urls
+            .into_iter()
+            .take(limit as usize)
+            .map(|(url, _)| url.to_string())
+            .collect())
+    }
+
+    async fn set_enclosure_blob(&self, url: String, hash: String) -> Result<()> {
+        for s in self.state().articles.iter_mut() {
+            for e in s.article.enclosures.iter_mut().filter(|e| e.url == url) {
+                e.blob = hash.clone();
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_blob(&self, hash: String) -> Result<Option<Blob>> {
+        Ok(self.state().blobs.iter().find(|b| b.hash == hash).cloned())
+    }
+
+    async fn get_orphaned_blobs(&self, before: String) -> Result<Vec<String>> {
+        let state = self.state();
+        // blobs are referenced by enclosures and cached images, like blob::REFERENCES lists
+        let referenced: HashSet<&str> = state
+            .articles
+            .iter()
+            .flat_map(|s| s.article.enclosures.iter().map(|e| e.blob.as_str()))
+            .chain(state.images.values().map(|(hash, _)| hash.as_str()))
+            .collect();
+        Ok(state
+            .blobs
+            .iter()
+            .filter(|b| b.date_added < before && !referenced.contains(b.hash.as_str()))
+            .map(|b| b.hash.clone())
+            .collect())
+    }
+
+    async fn get_image(&self, url: String, now: String) -> Result<Option<Blob>> {
+        let mut state = self.state();
+        let hash = match state.images.get_mut(&url) {
+            Some((hash, last_used)) => {
+                *last_used = now;
+                hash.clone()
+            }
+            None => return Ok(None),
+        };
+        Ok(state.blobs.iter().find(|b| b.hash == hash).cloned())
+    }
+
+    async fn add_image(&self, url: String, hash: String, now: String) -> Result<()> {
+        self.state().images.insert(url, (hash, now));
+        Ok(())
+    }
+
+    async fn add_jobs(&self, jobs: Vec<Job>) -> Result<Vec<Job>> {
+        let mut state = self.state();
+        let (queued, running) = (JobState::Queued.to_string(), JobState::Running.to_string());
+        let mut added = vec![];
+        for j in jobs {
+            let pending = state
+                .jobs
+                .iter()
+                .any(|x| x.feed == j.feed && (x.state == queued || x.state == running));
+            if pending {
+                continue;
+            }
+            state.jobs.push(Job {
+                state: queued.clone(),
+                ..j.clone()
+            });
+            added.push(j);
+        }
+        Ok(added)
+    }
+
+    async fn claim_jobs(&self, limit: i64, now: String) -> Result<Vec<Job>> {
+        let mut state = self.state();
+        let queued = JobState::Queued.to_string();
+        let mut waiting: Vec<&mut Job> = state
+            .jobs
+            .iter_mut()
+            .filter(|j| j.state == queued)
+            .collect();
+        waiting.sort_by(|a, b| a.date_added.cmp(&b.date_added));
+        Ok(waiting
+            .into_iter()
+            .take(limit as usize)
+            .map(|j| {
+                j.state = JobState::Running.to_string();
+                j.started = now.clone();
+                j.clone()
+            })
+            .collect())
+    }
+
+    async fn finish_job(
+        &self,
+        id: String,
+        state: JobState,
+        error: String,
+        now: String,
+    ) -> Result<()> {
+        let running = JobState::Running.to_string();
+        // a job canceled while it ran keeps its canceled state
+        if let Some(j) = self
+            .state()
+            .jobs
+            .iter_mut()
+            .find(|j| j.id == id && j.state == running)
+        {
+            j.state = state.to_string();
+            j.error = error;
+            j.finished = now;
+        }
+        Ok(())
+    }
+
+    async fn requeue_running_jobs(&self) -> Result<u64> {
+        let running = JobState::Running.to_string();
+        let mut requeued = 0;
+        for j in self.state().jobs.iter_mut().filter(|j| j.state == running) {
+            j.state = JobState::Queued.to_string();
+            j.started = "".to_string();
+            requeued += 1;
+        }
+        Ok(requeued)
+    }
+
+    async fn cancel_job(&self, id: String, now: String) -> Result<Option<Job>> {
+        let mut state = self.state();
+        let job = match state.jobs.iter_mut().find(|j| j.id == id) {
+            Some(j) => j,
+            None => return Ok(None),
+        };
+        if [JobState::Queued, JobState::Running]
+            .iter()
+            .any(|s| s.to_string() == job.state)
+        {
+            job.state = JobState::Canceled.to_string();
+            job.finished = now;
+        }
+        Ok(Some(job.clone()))
+    }
+
+    async fn get_jobs(&self, limit: i64) -> Result<Vec<Job>> {
+        let mut jobs = self.state().jobs.clone();
+        jobs.sort_by(|a, b| b.date_added.cmp(&a.date_added));
+        jobs.truncate(limit as usize);
+        Ok(jobs)
+    }
+
+    async fn get_job(&self, id: String) -> Result<Option<Job>> {
+        Ok(self.state().jobs.iter().find(|j| j.id == id).cloned())
+    }
+
+    async fn delete_jobs(&self, before: String) -> Result<u64> {
+        let mut state = self.state();
+        let count = state.jobs.len();
+        state
+            .jobs
+            .retain(|j| j.finished.is_empty() || j.finished >= before);
+        Ok((count - state.jobs.len()) as u64)
+    }
+
+    async fn add_fetch_attempt(&self, a: &Attempt) -> Result<()> {
+        self.state().fetch_log.push(a.clone());
+        Ok(())
+    }
+
+    async fn get_fetch_attempts(&self, feed: String, limit: i64) -> Result<Vec<Attempt>> {
+        let state = self.state();
+        let mut attempts: Vec<Attempt> = state
+            .fetch_log
+            .iter()
+            .filter(|a| a.feed == feed)
+            .cloned()
+            .collect();
+        attempts.sort_by(|a, b| b.date.cmp(&a.date));
+        attempts.truncate(limit as usize);
+        Ok(attempts)
+    }
+
+    async fn put_feed_payload(&self, p: &Payload) -> Result<()> {
+        self.state().payloads.insert(p.feed.clone(), p.clone());
+        Ok(())
+    }
+
+    async fn get_feed_payload(&self, feed: String) -> Result<Option<Payload>> {
+        Ok(self.state().payloads.get(&feed).cloned())
+    }
+
+    async fn delete_fetch_attempts(&self, before: String) -> Result<u64> {
+        let mut state = self.state();
+        let count = state.fetch_log.len();
+        state.fetch_log.retain(|a| a.date >= before);
+        Ok((count - state.fetch_log.len()) as u64)
+    }
+
+    async fn delete_images(&self, before: String) -> Result<u64> {
+        let mut state = self.state();
+        let count = state.images.len();
+        state
+            .images
+            .retain(|_, (_, last_used)| *last_used >= before);
+        Ok((count - state.images.len()) as u64)
+    }
+
+    async fn delete_blobs(&self, hashes: Vec<String>) -> Result<()> {
+        self.state().blobs.retain(|b| !hashes.contains(&b.hash));
+        Ok(())
+    }
+
+    async fn import_rules(&self, rules: Rules) -> Result<Imported> {
+        let filters = rules
+            .filters
+            .iter()
+            .map(|rule| Mute::new(rule.pattern.clone(), rule.regex, rule.field, rule.action))
+            .collect::<Result<Vec<Mute>>>()?;
+
+        let mut state = self.state();
+        let mut imported = Imported::default();
+        for m in filters {
+            imported.filters += state.add_filter(m);
+        }
+        for rule in rules.subscriptions.iter() {
+            imported.subscriptions += state.add_subscription(rule.into());
+        }
+        Ok(imported)
+    }
+
+    async fn delete_subscription(&self, id: String) -> Result<()> {
+        self.state().subscriptions.retain(|s| s.id != id);
+        Ok(())
+    }
+
+    async fn get_report(&self, period: String) -> Result<Option<Report>> {
+        Ok(self.state().reports.get(&period).cloned())
+    }
+
+    async fn has_digest(&self, period: String) -> Result<bool> {
+        Ok(self.state().digests.contains(&period))
+    }
+
+    async fn add_digest(&self, period: String, _sent: String) -> Result<()> {
+        self.state().digests.insert(period);
+        Ok(())
+    }
+
+    async fn save_report(&self, report: &Report) -> Result<()> {
+        self.state()
+            .reports
+            .insert(report.period.clone(), report.clone());
+        Ok(())
+    }
+
+    async fn compile_report(&self, period: String, start: String, end: String) -> Result<Report> {
+        let state = self.state();
+        let (start, end) = (db::timestamp(&start), db::timestamp(&end));
+        let read: Vec<(&Stored, DateTime<Utc>)> = state
+            .listed()
+            .filter(|s| s.article.read)
+            .filter_map(|s| s.read_date.map(|dt| (s, dt)))
+            .filter(|(_, dt)| start.is_some_and(|start| *dt >= start))
+            .filter(|(_, dt)| end.is_some_and(|end| *dt < end))
+            .collect();
+
+        // the most counted first, ties by name
+        let ranked = |counts: BTreeMap<String, i64>| -> Vec<Count> {
+            let mut counts: Vec<Count> = counts
+                .into_iter()
+                .map(|(name, count)| Count { name, count })
+                .collect();
+            counts.sort_by_key(|c| Reverse(c.count));
+            counts
+        };
+        let mut feeds: BTreeMap<String, i64> = BTreeMap::new();
+        let mut days: BTreeMap<String, i64> = BTreeMap::new();
+        for (s, dt) in &read {
+            *feeds.entry(state.name(s)).or_default() += 1;
+            *days.entry(dt.format("%Y-%m-%d").to_string()).or_default() += 1;
+        }
+
+        Ok(Report {
+            period,
+            generated: Article::rfc3339_timestamp(),
+            articles_read: read.len() as i64,
+            minutes_read: read
+                .iter()
+                .map(|(s, _)| s.article.reading_minutes as i64)
+                .sum(),
+            top_feeds: ranked(feeds).into_iter().take(5).collect(),
+            busiest_day: ranked(days).into_iter().next(),
+        })
+    }
+}
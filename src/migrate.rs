@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Key of the advisory lock held while migrating.
+pub const LOCK: i64 = 0x6665_6564_7265_6164;
+
+/// A change to the schema, kept as a sql file under `migrations/` and compiled in.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl fmt::Display for Migration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}_{}", self.version, self.name)
+    }
+}
+
+/// Every migration in the order they are applied. A schema change is a new file added at the
+/// end, a migration that shipped is never edited since databases that had it will not see the
+/// change. Keep `schema::COLUMNS` and `schema::UNIQUE` in step, and add a column added to
+/// articles to articles_archive as well.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: include_str!("../migrations/0001_baseline.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "timestamps",
+        sql: include_str!("../migrations/0002_timestamps.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "keys",
+        sql: include_str!("../migrations/0003_keys.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "article_feed",
+        sql: include_str!("../migrations/0004_article_feed.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "search_vector",
+        sql: include_str!("../migrations/0005_search_vector.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "duplicate_lookup",
+        sql: include_str!("../migrations/0006_duplicate_lookup.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "updated_at",
+        sql: include_str!("../migrations/0007_updated_at.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "articles_archive",
+        sql: include_str!("../migrations/0008_articles_archive.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "feed_counts",
+        sql: include_str!("../migrations/0009_feed_counts.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "feed_payloads",
+        sql: include_str!("../migrations/0010_feed_payloads.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "short_ids",
+        sql: include_str!("../migrations/0011_short_ids.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "title_trigrams",
+        sql: include_str!("../migrations/0012_title_trigrams.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "sequence_numbers",
+        sql: include_str!("../migrations/0013_sequence_numbers.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "source_ids",
+        sql: include_str!("../migrations/0014_source_ids.sql"),
+    },
+];
+
+/// The migrations in `MIGRATIONS` that are not among the `applied` versions, in order.
+pub fn pending(applied: &BTreeSet<i32>) -> Vec<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect()
+}
+
+/// The `applied` versions this release has no migration for, applied by a newer release.
+pub fn unknown(applied: &BTreeSet<i32>) -> Vec<i32> {
+    applied
+        .iter()
+        .filter(|v| !MIGRATIONS.iter().any(|m| m.version == **v))
+        .copied()
+        .collect()
+}
+
+/// A database behind the schema this release expects.
+#[derive(Debug)]
+pub struct Outdated {
+    pub pending: Vec<&'static Migration>,
+}
+
+impl fmt::Display for Outdated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pending: Vec<String> = self.pending.iter().map(|m| m.to_string()).collect();
+        write!(
+            f,
+            "database schema is behind this release, missing migration(s) {}, run `feedreader migrate` first",
+            pending.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for Outdated {}
@@ -0,0 +1,256 @@
+use super::Article;
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{SecondsFormat, Utc};
+use regex::Regex;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The article field a mute rule is matched against.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Schema)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Title,
+    Author,
+    Feed,
+    /// The detected ISO 639-3 code, such as eng or deu.
+    Language,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::Title => write!(f, "title"),
+            Field::Author => write!(f, "author"),
+            Field::Feed => write!(f, "feed"),
+            Field::Language => write!(f, "language"),
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Field> {
+        match s {
+            "title" => Ok(Field::Title),
+            "author" => Ok(Field::Author),
+            "feed" => Ok(Field::Feed),
+            "language" => Ok(Field::Language),
+            _ => Err(anyhow::Error::msg(format!("bad filter field: {}", s))),
+        }
+    }
+}
+
+/// What happens to an article a mute rule matches.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Schema)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Read,
+    Hide,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Read => write!(f, "read"),
+            Action::Hide => write!(f, "hide"),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Action> {
+        match s {
+            "read" => Ok(Action::Read),
+            "hide" => Ok(Action::Hide),
+            _ => Err(anyhow::Error::msg(format!("bad filter action: {}", s))),
+        }
+    }
+}
+
+/// A rule stored in the `filters` table. Substring patterns match case insensitively,
+/// regex patterns are used as written.
+#[derive(Clone)]
+pub struct Mute {
+    pub id: String,
+    pub pattern: String,
+    pub regex: bool,
+    pub field: Field,
+    pub action: Action,
+    pub date_added: String,
+}
+
+impl Mute {
+    pub fn new(pattern: String, regex: bool, field: Field, action: Action) -> Result<Self> {
+        if pattern.is_empty() {
+            return Err(anyhow::Error::msg("filter pattern cannot be empty"));
+        }
+        if regex {
+            Regex::new(pattern.as_str())?;
+        }
+
+        Ok(Mute {
+            id: general_purpose::URL_SAFE.encode(format!("{}\n{}\n{}", field, regex, pattern)),
+            pattern,
+            regex,
+            field,
+            action,
+            date_added: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        })
+    }
+}
+
+impl From<&tokio_postgres::Row> for Mute {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Mute {
+            id: row.get(0),
+            pattern: row.get(1),
+            regex: row.get(2),
+            field: Field::from_str(row.get(3)).unwrap_or(Field::Title),
+            action: Action::from_str(row.get(4)).unwrap_or(Action::Read),
+            date_added: row.get(5),
+        }
+    }
+}
+
+enum Matcher {
+    Substring(String),
+    Regex(Regex),
+}
+
+/// Mute rules compiled once so they can be applied to every article of a refresh.
+pub struct Mutes {
+    rules: Vec<(Field, Action, Matcher)>,
+}
+
+impl Mutes {
+    pub fn new(mutes: &[Mute]) -> Result<Self> {
+        let rules = mutes
+            .iter()
+            .map(|m| {
+                let matcher = match m.regex {
+                    true => Matcher::Regex(Regex::new(m.pattern.as_str())?),
+                    false => Matcher::Substring(m.pattern.to_lowercase()),
+                };
+                Ok((m.field, m.action, matcher))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Mutes { rules })
+    }
+
+    /// The action the rules take on an article, if any rule matches it. Hiding wins over
+    /// reading when several rules match.
+    pub fn action(&self, article: &Article) -> Option<Action> {
+        let mut action = None;
+        for (field, a, matcher) in self.rules.iter() {
+            let value = match field {
+                Field::Title => article.title.as_str(),
+                Field::Author => article.author.as_str(),
+                Field::Feed => article.feed.as_str(),
+                Field::Language => article.language.as_str(),
+            };
+            let matched = match matcher {
+                Matcher::Substring(s) => value.to_lowercase().contains(s.as_str()),
+                Matcher::Regex(r) => r.is_match(value),
+            };
+            if matched && action != Some(Action::Hide) {
+                action = Some(*a);
+            }
+        }
+        action
+    }
+
+    /// Marks the article read or hidden when a rule matches it.
+    pub fn apply(&self, article: &mut Article) {
+        match self.action(article) {
+            Some(Action::Hide) => article.hidden = true,
+            Some(Action::Read) => {
+                article.read = true;
+                article.read_date = Article::rfc3339_timestamp();
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, author: &str) -> Article {
+        Article::new(
+            title.to_string(),
+            "https://example.com/a".to_string(),
+            author.to_string(),
+            "".to_string(),
+            false,
+            false,
+            "".to_string(),
+            "".to_string(),
+        )
+    }
+
+    fn mutes(rules: &[(&str, bool, Field, Action)]) -> Mutes {
+        let rules: Vec<Mute> = rules
+            .iter()
+            .map(|(p, regex, field, action)| {
+                Mute::new(p.to_string(), *regex, *field, *action).unwrap()
+            })
+            .collect();
+        Mutes::new(&rules).unwrap()
+    }
+
+    #[test]
+    fn substrings_match_case_insensitively() {
+        let m = mutes(&[("Sponsored", false, Field::Title, Action::Read)]);
+        assert!(m.action(&article("a SPONSORED post", "")) == Some(Action::Read));
+        assert!(m.action(&article("a post", "")).is_none());
+    }
+
+    #[test]
+    fn regexes_match_as_written() {
+        let m = mutes(&[("^Deal:", true, Field::Title, Action::Hide)]);
+        assert!(m.action(&article("Deal: half off", "")) == Some(Action::Hide));
+        assert!(m.action(&article("deal: half off", "")).is_none());
+        assert!(m.action(&article("No Deal: here", "")).is_none());
+    }
+
+    #[test]
+    fn rules_match_their_own_field() {
+        let m = mutes(&[("bot", false, Field::Author, Action::Read)]);
+        assert!(m.action(&article("a bot wrote this", "jane")).is_none());
+        assert!(m.action(&article("a post", "Newsbot")) == Some(Action::Read));
+    }
+
+    #[test]
+    fn hiding_wins_over_reading() {
+        let m = mutes(&[
+            ("post", false, Field::Title, Action::Hide),
+            ("a", false, Field::Title, Action::Read),
+        ]);
+        assert!(m.action(&article("a post", "")) == Some(Action::Hide));
+    }
+
+    #[test]
+    fn apply_marks_read_or_hidden() {
+        let m = mutes(&[
+            ("read me", false, Field::Title, Action::Read),
+            ("hide me", false, Field::Title, Action::Hide),
+        ]);
+        let mut read = article("read me", "");
+        m.apply(&mut read);
+        assert!(read.read && !read.hidden);
+        let mut hidden = article("hide me", "");
+        m.apply(&mut hidden);
+        assert!(hidden.hidden && !hidden.read);
+    }
+
+    #[test]
+    fn bad_rules_are_refused() {
+        assert!(Mute::new("".to_string(), false, Field::Title, Action::Read).is_err());
+        assert!(Mute::new("(".to_string(), true, Field::Title, Action::Read).is_err());
+    }
+}
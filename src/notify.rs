@@ -0,0 +1,84 @@
+use super::{client, db, Article};
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{SecondsFormat, Utc};
+use reqwest::header::CONTENT_TYPE;
+use serde::Serialize;
+
+/// A webhook that is sent every new article carrying `tag`. Tags come from the categories
+/// a feed assigns to its entries.
+#[derive(Clone)]
+pub struct Subscription {
+    pub id: String,
+    pub tag: String,
+    pub webhook: String,
+    pub date_added: String,
+}
+
+impl Subscription {
+    pub fn new(tag: String, webhook: String) -> Self {
+        Subscription {
+            id: general_purpose::URL_SAFE.encode(format!("{}\n{}", tag, webhook)),
+            tag,
+            webhook,
+            date_added: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+impl From<&tokio_postgres::Row> for Subscription {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Subscription {
+            id: row.get(0),
+            tag: row.get(1),
+            webhook: row.get(2),
+            date_added: row.get(3),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    tag: &'a str,
+    feed: &'a str,
+    title: &'a str,
+    link: &'a str,
+}
+
+/// Posts each newly stored article to the webhooks subscribed to its tags. A webhook that
+/// fails is logged and skipped so the others still hear about the article.
+pub async fn dispatch(store: &dyn db::Store, articles: &[Article]) -> Result<()> {
+    if articles.is_empty() {
+        return Ok(());
+    }
+
+    let subscriptions = store.get_subscriptions().await?;
+    let client = client::shared();
+    for s in subscriptions.iter() {
+        // articles muted during ingest are not worth a notification
+        for a in articles
+            .iter()
+            .filter(|a| !a.read && !a.hidden && a.tags.contains(&s.tag))
+        {
+            let body = serde_json::to_string(&Notification {
+                tag: s.tag.as_str(),
+                feed: a.feed.as_str(),
+                title: a.title.as_str(),
+                link: a.canonical_link.as_str(),
+            })?;
+
+            let sent = client
+                .post(s.webhook.as_str())
+                .header(CONTENT_TYPE, "application/json")
+                .body(body)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = sent {
+                println!("could not notify {}: {}", s.webhook, e);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,620 @@
+use rweb::filters::BoxedFilter;
+use rweb::hyper::body::Bytes;
+pub use rweb::openapi::Location;
+use rweb::openapi::{
+    Collector, ComponentDescriptor, ComponentOrInlineSchema, Entity, Header, Info, MediaType,
+    ObjectOrReference, Operation, Parameter, ParameterRepresentation, ResponseEntity, Responses,
+    Schema, Type,
+};
+use rweb::*;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::marker::PhantomData;
+use std::sync::OnceLock;
+
+/// rweb documents query types only, so [header] types describe themselves as a query of one
+/// parameter named this and the header, which [finish] moves to the headers.
+const HEADER: &str = "header:";
+const FORM: &str = "application/x-www-form-urlencoded";
+
+const DESCRIPTION: &str = "Lists are paged by opaque cursors. The json lists take a `pagination` \
+                           query parameter and link the pages around them in a `Link` header. \
+                           The html pages take the cursor in a `pagination` header instead, and \
+                           article actions also name the list in an `article_filter` header, so \
+                           the page they render again is the one being read.";
+
+const LINK: &str =
+    "The pages around this one as RFC 8288 links, `rel=\"next\"` and `rel=\"prev\"` \
+                    with their `pagination` cursor. A page without one is the last or first. Only \
+                    sent along with json.";
+
+const NEGOTIATED: &str = "html, or json when the `Accept` header prefers `application/json`. \
+                          Answers vary by `Accept`.";
+
+/// The document of the routes built by [build].
+static SPEC: OnceLock<Value> = OnceLock::new();
+
+/// Builds the routes with `build`, every route built on the way is documented in the spec
+/// `/openapi.json` serves.
+pub fn build<R>(build: impl FnOnce() -> R) -> R {
+    let (spec, routes) = rweb::openapi::spec()
+        .info(Info {
+            title: Cow::Borrowed("feedreader"),
+            version: Cow::Borrowed(env!("CARGO_PKG_VERSION")),
+            description: Cow::Borrowed(DESCRIPTION),
+            ..Default::default()
+        })
+        .build(build);
+    let spec = serde_json::to_value(spec).expect("the spec is json");
+    let _ = SPEC.set(finish(spec));
+    routes
+}
+
+/// Builds routes with `build`, listing them under `tag`.
+pub fn tagged<R>(tag: &'static str, build: impl FnOnce() -> R) -> R {
+    rweb::openapi::with(|c| match c {
+        Some(c) => c.with_appended_prefix("", vec![Cow::Borrowed(tag)], build),
+        None => build(),
+    })
+}
+
+/// Documents a route built with warp directly, which the route macros never see. `describe`
+/// adds what it takes and answers with to the operation.
+pub fn describe(
+    path: &str,
+    method: http::Method,
+    description: &'static str,
+    describe: impl FnOnce(&mut Collector, &mut Operation),
+) {
+    rweb::openapi::with(|c| {
+        if let Some(c) = c {
+            let mut op = Operation {
+                description: Cow::Borrowed(description),
+                ..Default::default()
+            };
+            describe(c, &mut op);
+            c.add(path, method, op);
+        }
+    })
+}
+
+/// Adds the parameter `name` to a route documented with [describe], only path parameters are
+/// required.
+pub fn parameter(
+    op: &mut Operation,
+    location: Location,
+    name: &'static str,
+    description: &'static str,
+) {
+    op.parameters.push(ObjectOrReference::Object(Parameter {
+        name: Cow::Borrowed(name),
+        required: Some(location == Location::Path),
+        location,
+        description: Cow::Borrowed(description),
+        representation: Some(ParameterRepresentation::Simple {
+            schema: string("", ""),
+        }),
+        ..Default::default()
+    }));
+}
+
+/// What rweb leaves to be done: header parameters, the form media type, summaries and the
+/// errors any route can answer with.
+fn finish(mut spec: Value) -> Value {
+    tidy(&mut spec);
+    spec["components"]["responses"] = json!({
+        "Error": {
+            "description": "What went wrong, as text. 403 is over a quota, 503 a database that \
+                            is unavailable or took too long.",
+            "content": { "text/plain": { "schema": { "type": "string" } } },
+        }
+    });
+    let paths = spec.get_mut("paths").and_then(Value::as_object_mut);
+    for item in paths.into_iter().flat_map(|p| p.values_mut()) {
+        let ops = item
+            .as_object_mut()
+            .into_iter()
+            .flat_map(|i| i.values_mut());
+        for op in ops.filter_map(Value::as_object_mut) {
+            operation(op);
+        }
+    }
+    spec
+}
+
+/// Doc comments come with the spaces after `///` and lines joined by another, descriptions
+/// read better with single spaces.
+fn tidy(value: &mut Value) {
+    match value {
+        Value::Object(o) => {
+            for (k, v) in o.iter_mut() {
+                match (k.as_str(), v.as_str()) {
+                    ("description", Some(d)) => {
+                        *v = json!(d.split_whitespace().collect::<Vec<_>>().join(" "))
+                    }
+                    _ => tidy(v),
+                }
+            }
+        }
+        Value::Array(a) => a.iter_mut().for_each(tidy),
+        _ => {}
+    }
+}
+
+fn operation(op: &mut serde_json::Map<String, Value>) {
+    let params = op.get_mut("parameters").and_then(Value::as_array_mut);
+    for p in params.into_iter().flatten() {
+        if let Some(name) = p["name"].as_str().and_then(|n| n.strip_prefix(HEADER)) {
+            p["name"] = json!(name);
+            p["in"] = json!("header");
+        }
+        // field docs end up on the schema, tools show them on the parameter
+        let description = p
+            .get_mut("schema")
+            .and_then(Value::as_object_mut)
+            .and_then(|s| s.remove("description"));
+        if let Some(description) = description {
+            p["description"] = description;
+        }
+    }
+
+    let content = op
+        .get_mut("requestBody")
+        .and_then(|b| b.get_mut("content"))
+        .and_then(Value::as_object_mut);
+    if let Some(content) = content {
+        if let Some(form) = content.remove("x-www-form-urlencoded") {
+            content.insert(FORM.to_string(), form);
+        }
+    }
+
+    // the first sentence of the handler's doc comment sums it up
+    if !op.contains_key("summary") {
+        let description = op
+            .remove("description")
+            .and_then(|d| d.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let (summary, rest) = description
+            .split_once(". ")
+            .unwrap_or((description.trim_end_matches('.'), ""));
+        op.insert("summary".to_string(), json!(summary));
+        if !rest.is_empty() {
+            op.insert("description".to_string(), json!(rest));
+        }
+    }
+
+    if let Some(responses) = op.get_mut("responses").and_then(Value::as_object_mut) {
+        for r in responses.values_mut() {
+            if r["description"].as_str().unwrap_or_default().is_empty() {
+                r["description"] = json!("OK");
+            }
+        }
+        for code in ["403", "500", "503"] {
+            responses.insert(
+                code.to_string(),
+                json!({ "$ref": "#/components/responses/Error" }),
+            );
+        }
+    }
+}
+
+fn string(format: &'static str, description: &'static str) -> ComponentOrInlineSchema {
+    ComponentOrInlineSchema::Inline(Schema {
+        schema_type: Some(Type::String),
+        format: Cow::Borrowed(format),
+        description: Cow::Borrowed(description),
+        ..Default::default()
+    })
+}
+
+/// A 200 answer of `schema` in each of `types`.
+fn ok(types: &[&'static str], schema: ComponentOrInlineSchema) -> Responses {
+    let content = types
+        .iter()
+        .map(|t| {
+            let media = MediaType {
+                schema: Some(schema.clone()),
+                ..Default::default()
+            };
+            (Cow::Borrowed(*t), media)
+        })
+        .collect();
+    let ok = rweb::openapi::Response {
+        content,
+        ..Default::default()
+    };
+    [(Cow::Borrowed("200"), ok)].into_iter().collect()
+}
+
+#[doc(hidden)]
+pub fn header_schema(
+    header: &str,
+    description: &'static str,
+    required: bool,
+) -> ComponentOrInlineSchema {
+    let name: Cow<'static, str> = Cow::Owned(format!("{}{}", HEADER, header));
+    ComponentOrInlineSchema::Inline(Schema {
+        schema_type: Some(Type::Object),
+        required: match required {
+            true => vec![name.clone()],
+            false => vec![],
+        },
+        properties: [(name, string("", description))].into_iter().collect(),
+        ..Default::default()
+    })
+}
+
+/// A request header as a handler argument, so the spec lists it with `description`. The
+/// header is required unless it is taken as an `Option<String>`.
+macro_rules! header {
+    ($(#[$doc:meta])* $name:ident($header:literal): String, $description:literal) => {
+        $(#[$doc])*
+        pub struct $name(pub String);
+
+        impl rweb::FromRequest for $name {
+            type Filter = rweb::filters::BoxedFilter<($name,)>;
+
+            fn is_query() -> bool {
+                true
+            }
+
+            fn new() -> Self::Filter {
+                use rweb::Filter;
+                rweb::filters::header::header::<String>($header)
+                    .map($name)
+                    .boxed()
+            }
+        }
+
+        $crate::openapi::header!(@entity $name, $header, $description, true);
+    };
+    ($(#[$doc:meta])* $name:ident($header:literal): Option<String>, $description:literal) => {
+        $(#[$doc])*
+        pub struct $name(pub Option<String>);
+
+        impl rweb::FromRequest for $name {
+            type Filter = rweb::filters::BoxedFilter<($name,)>;
+
+            fn is_query() -> bool {
+                true
+            }
+
+            fn new() -> Self::Filter {
+                use rweb::Filter;
+                rweb::filters::header::optional::<String>($header)
+                    .map($name)
+                    .boxed()
+            }
+        }
+
+        $crate::openapi::header!(@entity $name, $header, $description, false);
+    };
+    (@entity $name:ident, $header:literal, $description:literal, $required:literal) => {
+        impl rweb::openapi::Entity for $name {
+            fn type_name() -> std::borrow::Cow<'static, str> {
+                std::borrow::Cow::Borrowed(stringify!($name))
+            }
+
+            fn describe(
+                _: &mut rweb::openapi::ComponentDescriptor,
+            ) -> rweb::openapi::ComponentOrInlineSchema {
+                $crate::openapi::header_schema($header, $description, $required)
+            }
+        }
+    };
+}
+pub(crate) use header;
+
+/// Media types of bodies that are not json, sent or taken as they are.
+pub trait Media: Entity + 'static {
+    const TYPES: &'static [&'static str];
+}
+
+/// Declares [Media] markers: a name, the format of the string it is and its media types.
+macro_rules! media {
+    ($($(#[$doc:meta])* $name:ident $format:literal [$($media:literal),+];)+) => {$(
+        $(#[$doc])*
+        pub enum $name {}
+
+        impl Media for $name {
+            const TYPES: &'static [&'static str] = &[$($media),+];
+        }
+
+        impl Entity for $name {
+            fn type_name() -> Cow<'static, str> {
+                Cow::Borrowed(stringify!($name))
+            }
+
+            fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+                string($format, "")
+            }
+        }
+
+        impl ResponseEntity for $name {
+            fn describe_responses(d: &mut ComponentDescriptor) -> Responses {
+                ok(Self::TYPES, Self::describe(d))
+            }
+        }
+    )+};
+}
+
+media! {
+    /// A rendered page, or a part of one to swap in.
+    Html "" ["text/html"];
+    Text "" ["text/plain"];
+    Script "" ["application/javascript"];
+    Css "" ["text/css"];
+    /// Server-sent events, for as long as the connection is open.
+    Events "" ["text/event-stream"];
+    /// Bytes as they were stored or fetched, sent with the type they came with.
+    Stored "binary" ["*/*"];
+    Image "binary" ["image/*"];
+    /// An export, in the format asked for.
+    Export "binary" ["text/csv", "application/json"];
+    Opml "" ["text/x-opml"];
+    Email "" ["message/rfc822"];
+}
+
+/// Documents types that reply on their own, askama templates mostly, as the [Media] `M`.
+macro_rules! reply_as {
+    ($media:ty: $($t:ty),+ $(,)?) => {$(
+        impl rweb::openapi::Entity for $t {
+            fn type_name() -> std::borrow::Cow<'static, str> {
+                <$media as rweb::openapi::Entity>::type_name()
+            }
+
+            fn describe(
+                d: &mut rweb::openapi::ComponentDescriptor,
+            ) -> rweb::openapi::ComponentOrInlineSchema {
+                <$media as rweb::openapi::Entity>::describe(d)
+            }
+        }
+
+        impl rweb::openapi::ResponseEntity for $t {
+            fn describe_responses(
+                d: &mut rweb::openapi::ComponentDescriptor,
+            ) -> rweb::openapi::Responses {
+                <$media as rweb::openapi::ResponseEntity>::describe_responses(d)
+            }
+        }
+    )+};
+}
+pub(crate) use reply_as;
+
+/// A response built by hand, with headers of its own or a body that is not json, documented
+/// as `T`.
+pub struct Documented<T>(reply::Response, PhantomData<fn() -> T>);
+
+impl<T> Documented<T> {
+    pub fn new(reply: impl Reply) -> Self {
+        Documented(reply.into_response(), PhantomData)
+    }
+}
+
+impl<T> Reply for Documented<T> {
+    fn into_response(self) -> reply::Response {
+        self.0
+    }
+}
+
+impl<T: Entity> Entity for Documented<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(d)
+    }
+}
+
+impl<T: ResponseEntity> ResponseEntity for Documented<T> {
+    fn describe_responses(d: &mut ComponentDescriptor) -> Responses {
+        T::describe_responses(d)
+    }
+}
+
+/// A page of a json list, with the pages around it in a `Link` header.
+pub struct Paged<T>(PhantomData<T>);
+
+impl<T: Entity> Entity for Paged<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(d)
+    }
+}
+
+impl<T: Entity> ResponseEntity for Paged<T> {
+    fn describe_responses(d: &mut ComponentDescriptor) -> Responses {
+        let mut responses = Json::<T>::describe_responses(d);
+        for r in responses.values_mut() {
+            let link = Header {
+                description: Cow::Borrowed(LINK),
+                schema: Some(string("", "")),
+                ..Default::default()
+            };
+            r.headers
+                .insert(Cow::Borrowed("Link"), ObjectOrReference::Object(link));
+        }
+        responses
+    }
+}
+
+/// A page that is sent as json instead when the `Accept` header prefers it, as `T` describes.
+pub struct Negotiated<T>(PhantomData<T>);
+
+impl<T: Entity> Entity for Negotiated<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(d)
+    }
+}
+
+impl<T: ResponseEntity> ResponseEntity for Negotiated<T> {
+    fn describe_responses(d: &mut ComponentDescriptor) -> Responses {
+        let mut responses = T::describe_responses(d);
+        let ok = responses.entry(Cow::Borrowed("200")).or_default();
+        ok.description = Cow::Borrowed(NEGOTIATED);
+        let json = std::mem::take(&mut ok.content);
+        let html = MediaType {
+            schema: Some(Html::describe(d)),
+            ..Default::default()
+        };
+        ok.content.insert(Cow::Borrowed("text/html"), html);
+        ok.content.extend(json);
+        responses
+    }
+}
+
+/// An answer of the Google Reader api, which turns calls without its login token away with 401.
+pub struct Authorized<T>(PhantomData<T>);
+
+impl<T: Entity> Entity for Authorized<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(d)
+    }
+}
+
+impl<T: ResponseEntity> ResponseEntity for Authorized<T> {
+    fn describe_responses(d: &mut ComponentDescriptor) -> Responses {
+        let mut responses = T::describe_responses(d);
+        let mut unauthorized = Text::describe_responses(d)
+            .remove("200")
+            .unwrap_or_default();
+        unauthorized.description = Cow::Borrowed("The login token is missing or wrong.");
+        responses.insert(Cow::Borrowed("401"), unauthorized);
+        responses
+    }
+}
+
+/// A request body taken as the bytes it is, in one of the media types of `M`.
+pub struct Raw<M>(pub Bytes, PhantomData<fn() -> M>);
+
+impl<M: Media> FromRequest for Raw<M> {
+    type Filter = BoxedFilter<(Raw<M>,)>;
+
+    fn is_body() -> bool {
+        true
+    }
+
+    fn content_type() -> &'static str {
+        M::TYPES[0]
+    }
+
+    fn new() -> Self::Filter {
+        warp::body::bytes().map(|b| Raw(b, PhantomData)).boxed()
+    }
+}
+
+impl<M: Media> Entity for Raw<M> {
+    fn type_name() -> Cow<'static, str> {
+        M::type_name()
+    }
+
+    fn describe(d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        M::describe(d)
+    }
+}
+
+/// An urlencoded form whose fields may repeat, as in `i=1&i=2`, read into `T`.
+pub struct Fields<T>(pub T);
+
+impl<T> FromRequest for Fields<T>
+where
+    T: From<Vec<(String, String)>> + Send + 'static,
+{
+    type Filter = BoxedFilter<(Fields<T>,)>;
+
+    fn is_body() -> bool {
+        true
+    }
+
+    fn content_type() -> &'static str {
+        FORM
+    }
+
+    fn new() -> Self::Filter {
+        warp::body::form::<Vec<(String, String)>>()
+            .map(|form| Fields(T::from(form)))
+            .boxed()
+    }
+}
+
+impl<T: Entity> Entity for Fields<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(d)
+    }
+}
+
+/// Swagger UI 5.17.14 is served from `static/swagger-ui` rather than loaded from a CDN.
+const DOCS: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>feedreader api</title>
+  <link rel="stylesheet" href="/docs/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="/docs/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+const SWAGGER_UI_SCRIPT: &str = include_str!("../static/swagger-ui/swagger-ui-bundle.js");
+const SWAGGER_UI_STYLE: &str = include_str!("../static/swagger-ui/swagger-ui.css");
+
+/// This document.
+#[get("/openapi.json")]
+fn openapi() -> Json<Value> {
+    SPEC.get().cloned().unwrap_or_default().into()
+}
+
+/// This document, browsable.
+#[get("/docs")]
+fn docs() -> Documented<Html> {
+    Documented::new(warp::reply::html(DOCS))
+}
+
+/// The Swagger UI script of the docs page.
+#[get("/docs/swagger-ui-bundle.js")]
+fn docs_script() -> Documented<Script> {
+    Documented::new(warp::reply::with_header(
+        SWAGGER_UI_SCRIPT,
+        http::header::CONTENT_TYPE,
+        Script::TYPES[0],
+    ))
+}
+
+/// The Swagger UI style of the docs page.
+#[get("/docs/swagger-ui.css")]
+fn docs_style() -> Documented<Css> {
+    Documented::new(warp::reply::with_header(
+        SWAGGER_UI_STYLE,
+        http::header::CONTENT_TYPE,
+        Css::TYPES[0],
+    ))
+}
+
+pub fn routes() -> BoxedFilter<(Box<dyn Reply>,)> {
+    openapi()
+        .or(docs())
+        .or(docs_script())
+        .or(docs_style())
+        .map(|r| Box::new(r) as Box<dyn Reply>)
+        .boxed()
+}
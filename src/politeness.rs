@@ -0,0 +1,82 @@
+use reqwest::Url;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Duration, Instant};
+
+const DEFAULT_PER_HOST: usize = 2;
+const DEFAULT_DELAY_MS: u64 = 1000;
+
+struct Host {
+    permits: Arc<Semaphore>,
+    /// When the next request to the host may start.
+    next: Mutex<Instant>,
+}
+
+/// Keeps feed fetches polite to publishers that host several of the subscribed feeds, so a bulk
+/// refresh does not look like abuse.
+///
+/// - `FEED_HOST_CONCURRENCY` (2) is how many fetches to one host may be in flight at once.
+/// - `FEED_HOST_DELAY_MS` (1000) is the least time between starting two fetches to one host.
+#[derive(Clone)]
+pub struct Hosts {
+    per_host: usize,
+    delay: Duration,
+    hosts: Arc<Mutex<HashMap<String, Arc<Host>>>>,
+}
+
+impl Hosts {
+    pub fn from_env() -> Self {
+        Hosts {
+            per_host: env::var("FEED_HOST_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(DEFAULT_PER_HOST),
+            delay: Duration::from_millis(
+                env::var("FEED_HOST_DELAY_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(DEFAULT_DELAY_MS),
+            ),
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Waits until a request to the host of `url` is allowed. The request may go ahead while the
+    /// returned permit is held.
+    pub async fn wait(&self, url: &str) -> OwnedSemaphorePermit {
+        let name = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_lowercase()))
+            .unwrap_or_default();
+        let host = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(name)
+            .or_insert_with(|| {
+                Arc::new(Host {
+                    permits: Arc::new(Semaphore::new(self.per_host)),
+                    next: Mutex::new(Instant::now()),
+                })
+            })
+            .clone();
+
+        let permit = host
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("host semaphore is never closed");
+        let start = {
+            let mut next = host.next.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + self.delay;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+        permit
+    }
+}
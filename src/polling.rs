@@ -0,0 +1,229 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, DurationRound, NaiveTime, Timelike, Utc};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// How many recent articles are considered when estimating how often a feed posts.
+pub const HISTORY: i64 = 20;
+
+/// How many hours ahead a skipped poll is pushed at most, a feed skipping every hour of the week
+/// is polled anyway.
+const MAX_SKIPPED_HOURS: u32 = 7 * 24;
+
+/// When an RSS feed asks to be polled: `ttl` is the least minutes between polls, `skip_hours`
+/// (0 to 23) and `skip_days` (0 for Monday to 6 for Sunday) are GMT hours and days not to poll
+/// at all.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq, Schema)]
+pub struct Hints {
+    pub ttl: u32,
+    pub skip_hours: Vec<u32>,
+    pub skip_days: Vec<u32>,
+}
+
+impl Hints {
+    /// The hints in an RSS document, none for other formats or values that do not parse.
+    pub fn from_document(body: &[u8]) -> Hints {
+        let channel = match rss::Channel::read_from(body) {
+            Ok(c) => c,
+            Err(_) => return Hints::default(),
+        };
+        let days = [
+            "monday",
+            "tuesday",
+            "wednesday",
+            "thursday",
+            "friday",
+            "saturday",
+            "sunday",
+        ];
+        Hints {
+            ttl: channel
+                .ttl()
+                .and_then(|t| t.trim().parse().ok())
+                .unwrap_or(0),
+            skip_hours: channel
+                .skip_hours()
+                .iter()
+                .filter_map(|h| h.trim().parse().ok())
+                // some feeds count 1 to 24
+                .map(|h: u32| h % 24)
+                .collect(),
+            skip_days: channel
+                .skip_days()
+                .iter()
+                .filter_map(|d| days.iter().position(|n| d.trim().eq_ignore_ascii_case(n)))
+                .map(|d| d as u32)
+                .collect(),
+        }
+    }
+
+    /// The interval, raised to the ttl when that is longer.
+    pub fn interval(&self, interval: Duration) -> Duration {
+        interval.max(Duration::minutes(self.ttl as i64))
+    }
+
+    /// The first time from `at` on that is not in a skipped hour or day.
+    pub fn next(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let skipped = |t: DateTime<Utc>| {
+            self.skip_hours.contains(&t.hour())
+                || self.skip_days.contains(&t.weekday().num_days_from_monday())
+        };
+        let mut next = at;
+        for _ in 0..MAX_SKIPPED_HOURS {
+            if !skipped(next) {
+                return next;
+            }
+            next = next.duration_trunc(Duration::hours(1)).unwrap_or(next) + Duration::hours(1);
+        }
+        at
+    }
+}
+
+/// Feeds are polled this many times in the time they usually take to post.
+const POLLS_PER_POST: i32 = 2;
+
+/// Picks how often to poll a feed from how often it posts, for feeds whose interval is not set
+/// by hand: a news site posting hourly is polled every half hour, a blog posting weekly twice a
+/// week. A feed that has gone quiet for longer than it usually does is polled less and less.
+///
+/// - `FEED_MIN_INTERVAL_SECONDS` (900) is the most often any feed is polled.
+/// - `FEED_MAX_INTERVAL_SECONDS` (604800) is the least often, for feeds that stopped posting.
+/// - `FEED_DEFAULT_INTERVAL_SECONDS` (3600) is used until a feed has posted twice.
+#[derive(Clone)]
+pub struct Cadence {
+    min: Duration,
+    max: Duration,
+    default: Duration,
+}
+
+impl Cadence {
+    pub fn from_env() -> Cadence {
+        let seconds = |name: &str, default: i64| {
+            Duration::seconds(
+                env::var(name)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| *s > 0)
+                    .unwrap_or(default),
+            )
+        };
+        let min = seconds("FEED_MIN_INTERVAL_SECONDS", 15 * 60);
+        let max = seconds("FEED_MAX_INTERVAL_SECONDS", 7 * 24 * 60 * 60).max(min);
+        Cadence {
+            min,
+            max,
+            default: seconds("FEED_DEFAULT_INTERVAL_SECONDS", 60 * 60).clamp(min, max),
+        }
+    }
+
+    /// The interval for a feed given the publish dates of its most recent articles.
+    pub fn interval(&self, published: &[String], now: DateTime<Utc>) -> Duration {
+        let mut dates: Vec<DateTime<Utc>> = published
+            .iter()
+            .filter_map(|p| DateTime::parse_from_rfc3339(p).ok())
+            .map(|d| d.with_timezone(&Utc))
+            .collect();
+        dates.sort_unstable_by(|a, b| b.cmp(a));
+
+        let (latest, oldest) = match (dates.first(), dates.last()) {
+            (Some(l), Some(o)) if dates.len() >= 2 => (*l, *o),
+            _ => return self.default,
+        };
+
+        let gap = (latest - oldest) / (dates.len() as i32 - 1);
+        let quiet = now - latest;
+        (gap.max(quiet) / POLLS_PER_POST).clamp(self.min, self.max)
+    }
+}
+
+/// Hours of the day during which the scheduler polls nothing and nothing is sent, so the
+/// background fetches, the digest and notifications wait until morning. Set with `QUIET_HOURS`
+/// as a UTC range such as `23:00-07:00`, which may wrap around midnight. Feeds falling due
+/// meanwhile are polled when the quiet hours end, refreshes asked for by hand still run but
+/// notify nobody.
+#[derive(Clone, Copy, Default)]
+pub struct QuietHours {
+    span: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl QuietHours {
+    pub fn from_env() -> Result<QuietHours> {
+        let s = match env::var("QUIET_HOURS") {
+            Ok(s) if !s.trim().is_empty() => s,
+            _ => return Ok(QuietHours::default()),
+        };
+        let invalid = || anyhow::Error::msg(format!("quiet hours are not HH:MM-HH:MM: {}", s));
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            return Err(anyhow::Error::msg(format!(
+                "quiet hours start and end at the same time: {}",
+                s
+            )));
+        }
+        Ok(QuietHours {
+            span: Some((start, end)),
+        })
+    }
+
+    /// Whether `now` falls in the quiet hours, from their start up to but not including their end.
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let (start, end) = match self.span {
+            Some(span) => span,
+            None => return false,
+        };
+        let time = now.time();
+        match start < end {
+            true => start <= time && time < end,
+            false => start <= time || time < end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet(start: &str, end: &str) -> QuietHours {
+        let time = |t| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+        QuietHours {
+            span: Some((time(start), time(end))),
+        }
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(format!("2024-05-01T{}:00Z", time).as_str())
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let q = quiet("01:00", "05:00");
+        assert!(!q.contains(at("00:59")));
+        assert!(q.contains(at("01:00")));
+        assert!(q.contains(at("04:59")));
+        assert!(!q.contains(at("05:00")));
+        assert!(!q.contains(at("23:00")));
+    }
+
+    #[test]
+    fn quiet_hours_wrapping_around_midnight() {
+        let q = quiet("23:00", "07:00");
+        assert!(!q.contains(at("22:59")));
+        assert!(q.contains(at("23:00")));
+        assert!(q.contains(at("00:00")));
+        assert!(q.contains(at("06:59")));
+        assert!(!q.contains(at("07:00")));
+        assert!(!q.contains(at("12:00")));
+    }
+
+    #[test]
+    fn no_quiet_hours_by_default() {
+        let q = QuietHours::default();
+        assert!(!q.contains(at("00:00")));
+        assert!(!q.contains(at("12:00")));
+    }
+}
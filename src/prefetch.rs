@@ -0,0 +1,116 @@
+use super::db;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Prefetched pages past this many are not cached, the oldest are dropped first.
+const MAX_PAGES: usize = 64;
+
+/// A rendered page of an article list waiting to be asked for, together with what is needed to
+/// prefetch the page after it once it is.
+pub struct Prefetched {
+    pub html: String,
+    pub sort: db::Sort,
+    pub cursor: db::Cursor,
+    pub next_page: String,
+    added: Instant,
+}
+
+/// `PREFETCH_SECONDS`, how long a page rendered ahead of time is kept for. While it is set, each
+/// article list renders the page after it in the background and points the browser at it, so
+/// going to the next page does not wait on the database. Unset or zero turns prefetching off.
+#[derive(Clone)]
+pub struct Pages {
+    ttl: Option<Duration>,
+    cache: Arc<Mutex<HashMap<String, Prefetched>>>,
+}
+
+impl Pages {
+    pub fn from_env() -> Pages {
+        let ttl = env::var("PREFETCH_SECONDS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|s| *s > 0)
+            .map(Duration::from_secs);
+        Pages {
+            ttl,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Where the page after `cursor` can be loaded from by url alone, empty when there is none
+    /// or prefetching is off.
+    pub fn next(&self, article_filter: &str, sort: &db::Sort, cursor: &db::Cursor) -> String {
+        match self.ttl.is_some() && cursor.has_next {
+            true => url(article_filter, &sort.query(), &cursor.next),
+            false => "".to_string(),
+        }
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.cache.lock().unwrap().contains_key(url)
+    }
+
+    /// Removes and returns the page, each prefetched page is only served once since reading
+    /// articles changes what the list holds.
+    pub fn take(&self, url: &str) -> Option<Prefetched> {
+        let ttl = self.ttl?;
+        let page = self.cache.lock().unwrap().remove(url)?;
+        match page.added.elapsed() < ttl {
+            true => Some(page),
+            false => None,
+        }
+    }
+
+    pub fn put(
+        &self,
+        url: String,
+        html: String,
+        sort: db::Sort,
+        cursor: db::Cursor,
+        next_page: String,
+    ) {
+        let ttl = match self.ttl {
+            Some(t) => t,
+            None => return,
+        };
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_, p| p.added.elapsed() < ttl);
+        while cache.len() >= MAX_PAGES {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, p)| p.added)
+                .map(|(u, _)| u.clone());
+            match oldest {
+                Some(u) => cache.remove(&u),
+                None => break,
+            };
+        }
+        cache.insert(
+            url,
+            Prefetched {
+                html,
+                sort,
+                cursor,
+                next_page,
+                added: Instant::now(),
+            },
+        );
+    }
+}
+
+/// The url of a page of the list named by an `article_filter` header value. `sort` is the query
+/// string of the sort it is shown in.
+pub fn url(article_filter: &str, sort: &str, pagination: &str) -> String {
+    let mut u = Url::parse("http://localhost/articles/page").unwrap();
+    u.query_pairs_mut()
+        .append_pair("filter", article_filter)
+        .append_pair("pagination", pagination);
+    format!("{}?{}&{}", u.path(), u.query().unwrap_or_default(), sort)
+}
@@ -0,0 +1,286 @@
+use super::blob::{self, Blob};
+use super::{client, db};
+use anyhow::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Url};
+use rweb::hyper::client::connect::dns::Name;
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+
+/// Proxied images nobody asked for in this many days are forgotten, which leaves their blobs to
+/// be collected.
+const EXPIRE_DAYS: i64 = 30;
+
+static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+static CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Asked for an image with a signature `url` did not make, or one that lives on a private
+/// address.
+#[derive(Debug)]
+pub struct Forbidden(String);
+
+impl fmt::Display for Forbidden {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot proxy {}", self.0)
+    }
+}
+
+impl std::error::Error for Forbidden {}
+
+/// The path an image is loaded from through the proxy. Anything but http and https urls, data
+/// urls for example, is left alone. The url is signed so the proxy only fetches what we linked
+/// to, not whatever a visitor asks for.
+pub fn url(image: &str) -> String {
+    let parsed = match Url::parse(image) {
+        Ok(u) if u.scheme() == "http" || u.scheme() == "https" => u,
+        _ => return image.to_string(),
+    };
+    let mut proxied = Url::parse("http://localhost/proxy/image").unwrap();
+    proxied
+        .query_pairs_mut()
+        .append_pair("url", parsed.as_str())
+        .append_pair("sig", &sign(parsed.as_str()));
+    format!("{}?{}", proxied.path(), proxied.query().unwrap_or_default())
+}
+
+/// The hex hmac of `url` under `PROXY_KEY`. Without one a key is made up at startup, which is
+/// fine for a single instance but leaves pages rendered before a restart with broken images.
+fn sign(url: &str) -> String {
+    mac(url)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn verify(url: &str, sig: &str) -> bool {
+    let bytes = (0..sig.len())
+        .step_by(2)
+        .map(|i| {
+            sig.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>();
+    match bytes {
+        Some(bytes) => mac(url).verify_slice(&bytes).is_ok(),
+        None => false,
+    }
+}
+
+fn mac(url: &str) -> Hmac<Sha256> {
+    let key = KEY.get_or_init(|| match env::var("PROXY_KEY") {
+        Ok(k) if !k.is_empty() => k.into_bytes(),
+        _ => {
+            let mut k = vec![0; 32];
+            OsRng.fill_bytes(&mut k);
+            k
+        }
+    });
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any size");
+    mac.update(url.as_bytes());
+    mac
+}
+
+/// The shared client, except that it will not connect to private, loopback or link-local
+/// addresses, whether a name resolves to one or a redirect points straight at one.
+fn client() -> Client {
+    CLIENT
+        .get_or_init(|| {
+            let redirects = client::max_redirects();
+            client::builder()
+                .dns_resolver(Arc::new(PublicOnly))
+                .redirect(Policy::custom(move |attempt| {
+                    if attempt.previous().len() > redirects {
+                        attempt.error("too many redirects")
+                    } else if !public_host(attempt.url()) {
+                        let url = attempt.url().to_string();
+                        attempt.error(Forbidden(url))
+                    } else {
+                        attempt.follow()
+                    }
+                }))
+                .build()
+                .unwrap_or_else(|e| panic!("could not build http client: {}", e))
+        })
+        .clone()
+}
+
+/// Resolves names like the system does, dropping the addresses we should not be proxying from.
+struct PublicOnly;
+
+impl Resolve for PublicOnly {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|a| public(a.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(Box::new(Forbidden(name.as_str().to_string())) as _);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether the host of `url` may be connected to. Names are checked once resolved, only literal
+/// addresses are checked here since they never go through the resolver.
+fn public_host(url: &Url) -> bool {
+    match url.host_str() {
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .map(public)
+            .unwrap_or(true),
+        None => false,
+    }
+}
+
+fn public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+            || ip.is_loopback()
+            || ip.is_link_local()
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+            || ip.is_documentation()
+            // carrier-grade nat, 100.64.0.0/10
+            || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => public(IpAddr::V4(v4)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    // unique local fc00::/7 and link-local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Points the images in article html at the proxy, so the browser never contacts the publisher
+/// while reading. Content is stored as published and only rewritten when shown.
+pub fn images(html: &str) -> String {
+    ammonia::Builder::default()
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("img", "src") => Some(Cow::Owned(url(value))),
+            _ => Some(Cow::Borrowed(value)),
+        })
+        .clean(html)
+        .to_string()
+}
+
+/// The image at `url`, fetched and put in the blob store the first time it is asked for. `sig`
+/// is the signature `url` gave it, anything else is refused.
+pub async fn fetch(
+    store: &dyn db::Store,
+    blobs: &blob::Store,
+    url: &str,
+    sig: &str,
+    now: DateTime<Utc>,
+) -> Result<(Blob, Vec<u8>)> {
+    if !verify(url, sig) {
+        return Err(Forbidden(url.to_string()).into());
+    }
+    let now = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+    if let Some(b) = store.get_image(url.to_string(), now.clone()).await? {
+        if let Some(bytes) = blobs.get(&b.hash).await? {
+            return Ok((b, bytes));
+        }
+    }
+
+    let (bytes, mime) = fetch_public(url).await?;
+    let hash = blobs.put(&bytes).await?;
+    store
+        .add_blob(Blob::new(hash.clone(), mime.clone(), bytes.len()))
+        .await?;
+    store.add_image(url.to_string(), hash.clone(), now).await?;
+    Ok((Blob::new(hash, mime, bytes.len()), bytes))
+}
+
+/// Fetches the image at `url` unless it is not http or https or lives on a private address,
+/// for anything fetching image urls a feed or article gave us.
+pub(crate) async fn fetch_public(url: &str) -> Result<(Vec<u8>, String)> {
+    match Url::parse(url) {
+        Ok(u) if (u.scheme() == "http" || u.scheme() == "https") && public_host(&u) => {}
+        _ => return Err(Forbidden(url.to_string()).into()),
+    }
+    blob::fetch_image(&client(), url).await
+}
+
+/// Forgets images not asked for in `EXPIRE_DAYS`, returning how many.
+pub async fn expire(store: &dyn db::Store, now: DateTime<Utc>) -> Result<u64> {
+    let before = (now - Duration::days(EXPIRE_DAYS)).to_rfc3339_opts(SecondsFormat::Millis, true);
+    store.delete_images(before).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn public_rejects_private_addresses() {
+        for private in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "255.255.255.255",
+            "100.64.0.1",
+            "100.127.255.254",
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!public(ip(private)), "{} is not public", private);
+        }
+    }
+
+    #[test]
+    fn public_accepts_public_addresses() {
+        for address in [
+            "93.184.216.34",
+            "1.1.1.1",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "::ffff:93.184.216.34",
+        ] {
+            assert!(public(ip(address)), "{} is public", address);
+        }
+    }
+
+    #[test]
+    fn public_host_checks_literal_addresses_only() {
+        let host = |s: &str| public_host(&Url::parse(s).unwrap());
+        assert!(!host("http://127.0.0.1/a.png"));
+        assert!(!host("http://[::1]:8080/a.png"));
+        assert!(!host("http://[::ffff:192.168.0.1]/a.png"));
+        assert!(host("https://example.com/a.png"));
+        assert!(host("https://93.184.216.34/a.png"));
+        assert!(!host("data:image/png;base64,AAAA"));
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{SecondsFormat, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "push"))]
+use super::{db, Article};
+#[cfg(not(feature = "push"))]
+use std::env;
+
+/// Web Push itself, built with the `push` feature.
+#[cfg(feature = "push")]
+pub use super::webpush::Push;
+
+/// A browser that asked to be notified, as handed out by its push service. Notifications are
+/// encrypted for the browser with `p256dh` and `auth`, so the push service cannot read them.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct PushSubscription {
+    pub endpoint: String,
+    pub p256dh: String,
+    pub auth: String,
+    pub date_added: String,
+}
+
+impl PushSubscription {
+    pub fn new(endpoint: String, p256dh: String, auth: String) -> Result<Self> {
+        match Url::parse(endpoint.as_str()) {
+            Ok(u) if u.scheme() == "https" => {}
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "bad push endpoint: {}",
+                    endpoint
+                )))
+            }
+        }
+        decode(&p256dh)?;
+        decode(&auth)?;
+
+        Ok(PushSubscription {
+            endpoint,
+            p256dh,
+            auth,
+            date_added: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        })
+    }
+}
+
+impl From<&tokio_postgres::Row> for PushSubscription {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        PushSubscription {
+            endpoint: row.get(0),
+            p256dh: row.get(1),
+            auth: row.get(2),
+            date_added: row.get(3),
+        }
+    }
+}
+
+/// Stands in for Web Push in builds without the `push` feature, where browser notifications
+/// are always off.
+#[cfg(not(feature = "push"))]
+#[derive(Clone)]
+pub struct Push;
+
+#[cfg(not(feature = "push"))]
+impl Push {
+    pub fn from_env() -> Result<Push> {
+        match env::var("VAPID_PRIVATE_KEY") {
+            Ok(_) => Err(anyhow::Error::msg(
+                "VAPID_PRIVATE_KEY is set but this build has no push feature",
+            )),
+            Err(_) => Ok(Push),
+        }
+    }
+
+    pub fn public_key(&self) -> Option<&str> {
+        None
+    }
+
+    pub async fn dispatch(&self, _store: &dyn db::Store, _articles: &[Article]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Browsers hand out keys in base64url, some with padding.
+pub(crate) fn decode(s: &str) -> Result<Vec<u8>> {
+    Ok(URL_SAFE_NO_PAD.decode(s.trim_end_matches('='))?)
+}
@@ -0,0 +1,235 @@
+use super::events::Event;
+use super::{db, refresh, Article};
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, SecondsFormat, Utc};
+use futures::stream::{self, StreamExt};
+use rand_core::{OsRng, RngCore};
+use rweb::Schema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+/// Finished jobs are kept this long to be looked at, then deleted.
+const KEEP_DAYS: i64 = 7;
+
+/// How often the worker looks for jobs when nobody wakes it, in case a wakeup went missing.
+const POLL_SECONDS: u64 = 60;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Canceled,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            State::Queued => "queued",
+            State::Running => "running",
+            State::Succeeded => "succeeded",
+            State::Failed => "failed",
+            State::Canceled => "canceled",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A refresh of one feed, from being asked for until it finished. Timestamps of steps not
+/// reached yet are empty.
+#[derive(Serialize, Clone, Schema)]
+pub struct Job {
+    pub id: String,
+    pub feed: String,
+    pub state: String,
+    pub error: String,
+    pub date_added: String,
+    pub started: String,
+    pub finished: String,
+}
+
+impl Job {
+    pub fn new(feed: String) -> Self {
+        let mut id = [0u8; 12];
+        OsRng.fill_bytes(&mut id);
+        Job {
+            id: URL_SAFE_NO_PAD.encode(id),
+            feed,
+            state: State::Queued.to_string(),
+            error: "".to_string(),
+            date_added: Article::rfc3339_timestamp(),
+            started: "".to_string(),
+            finished: "".to_string(),
+        }
+    }
+}
+
+impl From<&tokio_postgres::Row> for Job {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Job {
+            id: row.get(0),
+            feed: row.get(1),
+            state: row.get(2),
+            error: row.get(3),
+            date_added: row.get(4),
+            started: row.get(5),
+            finished: row.get(6),
+        }
+    }
+}
+
+/// Feed refreshes waiting in the `jobs` table, run in the background by `work`. Routes and the
+/// scheduler only queue them, so nothing waits on a slow publisher.
+#[derive(Clone, Default)]
+pub struct Queue {
+    wake: Arc<Notify>,
+    /// Tokens of the jobs running in this process, by job id, to stop them when canceled.
+    running: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl Queue {
+    /// Queues a refresh of each feed that does not have one queued or running already,
+    /// returning the jobs added.
+    pub async fn push(&self, store: &dyn db::Store, feeds: Vec<String>) -> Result<Vec<Job>> {
+        let jobs = store
+            .add_jobs(feeds.into_iter().map(Job::new).collect())
+            .await?;
+        if !jobs.is_empty() {
+            self.wake.notify_one();
+        }
+        Ok(jobs)
+    }
+
+    /// Queues a refresh of every feed whose next poll has come.
+    pub async fn due(&self, store: &dyn db::Store) -> Result<Vec<Job>> {
+        let feeds = store.get_due_feeds(Article::rfc3339_timestamp()).await?;
+        self.push(store, feeds.into_iter().map(|f| f.id).collect())
+            .await
+    }
+
+    /// Cancels a job that has not finished yet. A queued job is never started, a running one
+    /// has its fetch dropped wherever it got to. Returns the job, none when there is no such
+    /// job, finished ones are returned as they are.
+    pub async fn cancel(&self, store: &dyn db::Store, id: String) -> Result<Option<Job>> {
+        let job = store
+            .cancel_job(id.clone(), Article::rfc3339_timestamp())
+            .await?;
+        if let Some(token) = self.running.lock().unwrap().get(&id) {
+            token.cancel();
+        }
+        Ok(job)
+    }
+
+    /// Runs queued jobs until `exit` resolves, up to the refresher's concurrency at a time.
+    /// Jobs left running by a previous process are queued again first.
+    pub async fn work(
+        &self,
+        store: &dyn db::Store,
+        refresher: &refresh::Refresh,
+        exit: impl std::future::Future<Output = ()>,
+    ) {
+        if let Err(e) = store.requeue_running_jobs().await {
+            println!("could not requeue interrupted jobs: {}", e);
+        }
+
+        let work = async {
+            loop {
+                let jobs = match store
+                    .claim_jobs(refresher.concurrency as i64, Article::rfc3339_timestamp())
+                    .await
+                {
+                    Ok(jobs) => jobs,
+                    Err(e) => {
+                        println!("could not claim jobs: {}", e);
+                        vec![]
+                    }
+                };
+                if jobs.is_empty() {
+                    let poll = tokio::time::sleep(std::time::Duration::from_secs(POLL_SECONDS));
+                    tokio::select! {
+                        _ = self.wake.notified() => {}
+                        _ = poll => {}
+                    }
+                    continue;
+                }
+
+                stream::iter(jobs)
+                    .for_each_concurrent(refresher.concurrency, |j| self.run(store, refresher, j))
+                    .await;
+            }
+        };
+
+        tokio::select! {
+            _ = work => {}
+            _ = exit => {}
+        }
+    }
+
+    async fn run(&self, store: &dyn db::Store, refresher: &refresh::Refresh, job: Job) {
+        let token = CancellationToken::new();
+        self.running
+            .lock()
+            .unwrap()
+            .insert(job.id.clone(), token.clone());
+        refresher.events.send(Event::Started {
+            job: job.id.clone(),
+            feed: job.feed.clone(),
+        });
+        let refresh = async {
+            let f = store.get_feed_by_id(job.feed.clone()).await?;
+            refresher.feed(store, f).await
+        };
+        let result = tokio::select! {
+            result = refresh => Some(result),
+            _ = token.cancelled() => None,
+        };
+        self.running.lock().unwrap().remove(&job.id);
+
+        let (state, error) = match result {
+            Some(Ok(added)) => {
+                refresher.events.send(Event::Finished {
+                    job: job.id.clone(),
+                    feed: job.feed.clone(),
+                    added,
+                });
+                (State::Succeeded, "".to_string())
+            }
+            Some(Err(e)) => {
+                println!("refresh job {} for feed {} failed: {}", job.id, job.feed, e);
+                refresher.events.send(Event::Failed {
+                    job: job.id.clone(),
+                    feed: job.feed.clone(),
+                    error: e.to_string(),
+                });
+                (State::Failed, e.to_string())
+            }
+            None => {
+                println!("refresh job {} for feed {} was canceled", job.id, job.feed);
+                refresher.events.send(Event::Canceled {
+                    job: job.id.clone(),
+                    feed: job.feed.clone(),
+                });
+                return;
+            }
+        };
+        if let Err(e) = store
+            .finish_job(job.id.clone(), state, error, Article::rfc3339_timestamp())
+            .await
+        {
+            println!("could not record the end of job {}: {}", job.id, e);
+        }
+    }
+}
+
+/// Deletes finished jobs older than `KEEP_DAYS`, returning how many.
+pub async fn expire(store: &dyn db::Store) -> Result<u64> {
+    let before =
+        (Utc::now() - Duration::days(KEEP_DAYS)).to_rfc3339_opts(SecondsFormat::Millis, true);
+    store.delete_jobs(before).await
+}
@@ -0,0 +1,499 @@
+use super::{
+    alert, canonical, chaos, db, events, fetch, notify, politeness, polling, push, sanitize,
+    scrape, Article, Feed,
+};
+use anyhow::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use feed_rs::parser;
+use futures::stream::{self, StreamExt};
+use rand_core::{OsRng, RngCore};
+use reqwest::{StatusCode, Url};
+use std::collections::HashSet;
+use std::env;
+
+const DEFAULT_JITTER_PERCENT: u32 = 10;
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_FETCH_ATTEMPTS: u32 = 3;
+const DEFAULT_FETCH_BACKOFF_MS: i64 = 1000;
+/// The longest wait between two fetch attempts, however many there are.
+const MAX_FETCH_BACKOFF_MS: i64 = 5 * 60 * 1000;
+const DEFAULT_MAX_FAILURES: i32 = 10;
+/// How many new links of a refresh are followed to their canonical url at once, the host limiter
+/// still keeps it to a few per publisher.
+const RESOLVE_CONCURRENCY: usize = 8;
+/// Link relations pointing from a feed document to older entries, RFC 5005 archived feeds first
+/// and paged feeds after.
+const ARCHIVE_RELS: &[&str] = &["prev-archive", "next"];
+
+/// Intervals a feed can be refreshed at instead of the one picked from its publish history.
+pub const INTERVALS: &[(i32, &str)] = &[
+    (15 * 60, "15m"),
+    (60 * 60, "1h"),
+    (6 * 60 * 60, "6h"),
+    (24 * 60 * 60, "1d"),
+    (7 * 24 * 60 * 60, "1w"),
+];
+
+/// Fetches feeds and stores their new articles, for the scheduler and for the routes that
+/// refresh feeds on demand.
+#[derive(Clone)]
+pub struct Refresh {
+    pub fields: sanitize::Fields,
+    pub push: push::Push,
+    pub alerts: alert::Alerts,
+    pub events: events::Events,
+    /// `FEED_REFRESH_JITTER_PERCENT`, up to how much of its interval is added at random to when
+    /// a feed is polled next, so feeds added together do not keep being fetched together.
+    pub jitter: u32,
+    pub cadence: polling::Cadence,
+    /// `FEED_REFRESH_CONCURRENCY`, how many feeds are fetched at once when refreshing several.
+    pub concurrency: usize,
+    /// `FEED_FETCH_ATTEMPTS`, how many times a fetch failing on the network or with a server
+    /// error is tried before the feed is marked as failed.
+    pub attempts: u32,
+    /// `FEED_FETCH_BACKOFF_MS`, the wait before the first retry, doubled for every one after it
+    /// up to five minutes and jittered like the polling interval.
+    pub backoff: Duration,
+    /// `FEED_MAX_FAILURES`, how many refreshes of a feed may fail in a row before the scheduler
+    /// stops polling it.
+    pub max_failures: i32,
+    /// `FEED_BACKFILL_PAGES`, how many archive pages of a new feed are read for its history on
+    /// the first refresh, none by default.
+    pub backfill_pages: usize,
+    /// `FEED_PAYLOAD_MAX_BYTES`, how much of the last document fetched for each feed is kept to
+    /// debug it with, none by default.
+    pub payload_bytes: usize,
+    pub hosts: politeness::Hosts,
+    /// Articles a refresh finds in these hours, one asked for by hand too, are stored without
+    /// calling webhooks, pushing or alerting.
+    pub quiet: polling::QuietHours,
+}
+
+impl Refresh {
+    pub fn new(
+        fields: sanitize::Fields,
+        push: push::Push,
+        alerts: alert::Alerts,
+        events: events::Events,
+        quiet: polling::QuietHours,
+    ) -> Self {
+        Refresh {
+            fields,
+            push,
+            alerts,
+            events,
+            quiet,
+            jitter: env::var("FEED_REFRESH_JITTER_PERCENT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_JITTER_PERCENT),
+            cadence: polling::Cadence::from_env(),
+            concurrency: env::var("FEED_REFRESH_CONCURRENCY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|c| *c > 0)
+                .unwrap_or(DEFAULT_CONCURRENCY),
+            attempts: env::var("FEED_FETCH_ATTEMPTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|a| *a > 0)
+                .unwrap_or(DEFAULT_FETCH_ATTEMPTS),
+            backoff: Duration::milliseconds(
+                env::var("FEED_FETCH_BACKOFF_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(DEFAULT_FETCH_BACKOFF_MS),
+            ),
+            max_failures: env::var("FEED_MAX_FAILURES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|m| *m > 0)
+                .unwrap_or(DEFAULT_MAX_FAILURES),
+            backfill_pages: env::var("FEED_BACKFILL_PAGES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            payload_bytes: env::var("FEED_PAYLOAD_MAX_BYTES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            hosts: politeness::Hosts::from_env(),
+        }
+    }
+
+    /// Fetches a feed and stores its new articles, returning how many. When the feed cannot be fetched or parsed the
+    /// error is recorded on the feed before it is returned.
+    pub async fn feed(&self, store: &dyn db::Store, f: Feed) -> Result<usize> {
+        if let Ok(until) = DateTime::parse_from_rfc3339(&f.retry_after) {
+            if until > Utc::now() {
+                return Err(anyhow::Error::msg(format!(
+                    "{} asked not to be fetched before {}",
+                    f.feed_url, f.retry_after
+                )));
+            }
+        }
+
+        let started = Utc::now();
+        let mut attempt = fetch::Attempt::new(
+            f.id.clone(),
+            started.to_rfc3339_opts(SecondsFormat::Millis, true),
+        );
+        let downloaded = self.download(store, &f, &mut attempt).await;
+        attempt.duration_ms = (Utc::now() - started).num_milliseconds();
+        let downloaded = match downloaded {
+            Ok(d) => d,
+            Err(e) => {
+                attempt.error = e.to_string();
+                log(store, &attempt).await;
+                if let Some(r) = e.downcast_ref::<fetch::RetryAfter>() {
+                    store
+                        .update_feed_retry_after(
+                            f.id.clone(),
+                            r.until.to_rfc3339_opts(SecondsFormat::Millis, true),
+                        )
+                        .await?;
+                }
+                store
+                    .update_feed_fetch_error(f.id.clone(), e.to_string())
+                    .await?;
+                // a publisher asking for a break is still there, only other failures count
+                if !e.is::<fetch::RetryAfter>()
+                    && store
+                        .add_feed_failure(f.id.clone(), self.max_failures)
+                        .await?
+                {
+                    println!(
+                        "stopped polling {} after {} failed refreshes in a row",
+                        f.feed_url, self.max_failures
+                    );
+                }
+                return Err(e);
+            }
+        };
+        let mut hints = f.hints.clone();
+        let mut refreshed = db::Refreshed {
+            articles: vec![],
+            history: vec![],
+            rekeyed: vec![],
+            validators: None,
+            hints: None,
+            updated: Utc::now(),
+        };
+        if let Some((fetched, parsed)) = downloaded {
+            let mut articles = parsed.articles;
+            for a in articles.iter_mut() {
+                a.feed = f.name.clone();
+                a.feed_id = f.id.clone();
+                self.fields.apply(a);
+            }
+
+            // only new links are resolved, stored articles already have their canonical link
+            let known = store
+                .get_known_links(articles.iter().map(|a| a.link.clone()).collect())
+                .await?;
+            stream::iter(articles.iter_mut().filter(|a| !known.contains(&a.link)))
+                .for_each_concurrent(RESOLVE_CONCURRENCY, |a| async move {
+                    a.canonical_link = canonical::resolve(&self.hosts, a.link.as_str()).await;
+                })
+                .await;
+            refreshed.articles = articles;
+            refreshed.rekeyed = parsed.rekeyed;
+
+            // history is stored quietly, nobody wants to be notified of last year's posts
+            if f.never_refreshed() && parsed.archive.is_some() {
+                let mut history = self.backfill(&f, parsed.archive).await;
+                for a in history.iter_mut() {
+                    a.feed = f.name.clone();
+                    a.feed_id = f.id.clone();
+                    self.fields.apply(a);
+                }
+                refreshed.history = history;
+            }
+
+            refreshed.validators = Some((fetched.etag, fetched.last_modified, fetched.marker));
+            hints = parsed.hints;
+            if hints != f.hints {
+                refreshed.hints = Some(hints.clone());
+            }
+        }
+
+        // everything is fetched before anything is stored, a refresh is applied whole or not at all
+        let (new, history) = store.apply_refresh(f.id.clone(), refreshed).await?;
+        for a in new.iter() {
+            self.events.send(events::Event::article(a));
+        }
+        if !self.quiet.contains(Utc::now()) {
+            if let Err(e) = notify::dispatch(store, &new).await {
+                println!("could not send notifications for {}: {}", f.feed_url, e);
+            }
+            if f.push {
+                if let Err(e) = self.push.dispatch(store, &new).await {
+                    println!("could not push notifications for {}: {}", f.feed_url, e);
+                }
+            }
+            self.alerts.dispatch(&new).await;
+        }
+        let added = new.len() + history;
+
+        attempt.added = added as i32;
+        log(store, &attempt).await;
+
+        // the feed's own hints only refine an interval picked from its history, one set by hand
+        // is kept as it is
+        let now = Utc::now();
+        let (interval, next) = match f.refresh_interval {
+            0 => {
+                let history = store
+                    .get_feed_publish_history(f.id.clone(), polling::HISTORY)
+                    .await?;
+                let interval = hints.interval(self.cadence.interval(history.as_slice(), now));
+                (interval, hints.next(now + interval + self.jitter(interval)))
+            }
+            seconds => {
+                let interval = Duration::seconds(seconds as i64);
+                (interval, now + interval + self.jitter(interval))
+            }
+        };
+        store
+            .update_feed_schedule(
+                f.id.clone(),
+                interval.num_seconds() as i32,
+                next.to_rfc3339_opts(SecondsFormat::Millis, true),
+            )
+            .await?;
+
+        Ok(added)
+    }
+
+    /// The feed document with the articles and scheduling hints in it, none when it has not
+    /// changed. Pages with a scrape rule are read with the rule instead of as a feed.
+    async fn download(
+        &self,
+        store: &dyn db::Store,
+        f: &Feed,
+        attempt: &mut fetch::Attempt,
+    ) -> Result<Option<(fetch::Fetched, Parsed)>> {
+        let fetched = match self.fetch(f).await {
+            Ok(fetch::Fetch::Fetched(fetched)) => fetched,
+            Ok(fetch::Fetch::Unchanged) => {
+                attempt.status = StatusCode::NOT_MODIFIED.as_u16() as i32;
+                return Ok(None);
+            }
+            Err(e) => {
+                attempt.status = fetch::status(&e);
+                return Err(e);
+            }
+        };
+        attempt.status = fetched.status.as_u16() as i32;
+        attempt.bytes = fetched.body.len() as i64;
+        // kept before parsing, a document that does not parse is the one worth looking at
+        if self.payload_bytes > 0 {
+            let kept = match fetch::Payload::new(f.id.clone(), &fetched, self.payload_bytes) {
+                Ok(payload) => store.put_feed_payload(&payload).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = kept {
+                println!("could not keep the document of {}: {}", f.feed_url, e);
+            }
+        }
+        let rule = store.get_scrape_rule(f.id.clone()).await?;
+        let (feed, url, body) = (f.clone(), f.feed_url.clone(), fetched.body.clone());
+        let parsed =
+            tokio::task::spawn_blocking(move || parse(&feed, &url, &body[..], rule)).await??;
+        Ok(Some((fetched, parsed)))
+    }
+
+    /// The articles on up to `backfill_pages` archive pages, following each page's link to the
+    /// one before it from `next`. A page that cannot be read ends the walk with what was found.
+    async fn backfill(&self, f: &Feed, mut next: Option<String>) -> Vec<Article> {
+        let mut articles = vec![];
+        let mut seen = HashSet::new();
+        for _ in 0..self.backfill_pages {
+            let url = match next.take() {
+                Some(url) if seen.insert(url.clone()) => url,
+                _ => break,
+            };
+            let fetched = {
+                let _permit = self.hosts.wait(url.as_str()).await;
+                fetch::fetch(url.as_str(), "", "", "").await
+            };
+            let body = match fetched {
+                Ok(fetch::Fetch::Fetched(fetched)) => fetched.body,
+                Ok(fetch::Fetch::Unchanged) => break,
+                Err(e) => {
+                    println!("could not backfill {} from {}: {}", f.feed_url, url, e);
+                    break;
+                }
+            };
+            let feed = f.clone();
+            let parsed = tokio::task::spawn_blocking(move || parse(&feed, &url, &body[..], None))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|p| p);
+            match parsed {
+                Ok(parsed) => {
+                    articles.extend(parsed.articles);
+                    next = parsed.archive;
+                }
+                Err(e) => {
+                    println!("could not backfill {}: {}", f.feed_url, e);
+                    break;
+                }
+            }
+        }
+        articles
+    }
+
+    /// Fetches a feed, retrying with exponential backoff while the failures look transient.
+    async fn fetch(&self, f: &Feed) -> Result<fetch::Fetch> {
+        let mut attempt = 1;
+        loop {
+            let result = match chaos::fetch().await {
+                Ok(()) => {
+                    let _permit = self.hosts.wait(f.feed_url.as_str()).await;
+                    fetch::fetch(
+                        f.feed_url.as_str(),
+                        f.etag.as_str(),
+                        f.last_modified.as_str(),
+                        f.build_marker.as_str(),
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(e) if attempt < self.attempts && transient(&e) => {
+                    let delay = backoff(self.backoff, attempt);
+                    println!(
+                        "retrying {} after attempt {} failed: {}",
+                        f.feed_url, attempt, e
+                    );
+                    let wait = (delay + self.jitter(delay)).to_std().unwrap_or_default();
+                    tokio::time::sleep(wait).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn jitter(&self, interval: Duration) -> Duration {
+        let max = interval.num_milliseconds().max(0) as u64 * self.jitter as u64 / 100;
+        Duration::milliseconds((OsRng.next_u64() % (max + 1)) as i64)
+    }
+}
+
+/// What was read out of a fetched document, with the link to its archive page if it has one.
+struct Parsed {
+    articles: Vec<Article>,
+    /// The legacy ids of the entries with a guid, each with the id it has now.
+    rekeyed: Vec<(String, String)>,
+    hints: polling::Hints,
+    archive: Option<String>,
+}
+
+/// Reads the articles and scheduling hints out of a fetched document. Parsing a large one takes
+/// a while, so this runs on a blocking thread to keep it from holding up other requests. `url`
+/// is where the document was fetched from, an archive page of `f` or the feed itself.
+fn parse(f: &Feed, url: &str, body: &[u8], rule: Option<scrape::Rule>) -> Result<Parsed> {
+    let hints = polling::Hints::from_document(body);
+    if let Some(rule) = rule {
+        return Ok(Parsed {
+            articles: rule.articles(url, body)?,
+            rekeyed: vec![],
+            hints,
+            archive: None,
+        });
+    }
+
+    // entries without a guid keep an empty id instead of one made up from their link, so they
+    // fall back to being identified by the link itself
+    let feed = parser::Builder::new()
+        .id_generator(|_, _, _| String::new())
+        .build()
+        .parse(body)?;
+    let archive = ARCHIVE_RELS.iter().find_map(|rel| {
+        let link = feed.links.iter().find(|l| l.rel.as_deref() == Some(*rel))?;
+        Url::parse(url).ok()?.join(link.href.as_str()).ok()
+    });
+    let mut rekeyed = vec![];
+    Ok(Parsed {
+        articles: feed
+            .entries
+            .iter()
+            .map(|e| {
+                let mut article: Article = e.into();
+                if !e.id.is_empty() {
+                    article.id = Article::guid_id(&f.id, e.id.as_str());
+                    let legacy = Article::legacy_guid_id(&f.feed_url, e.id.as_str());
+                    rekeyed.push((legacy, article.id.clone()));
+                }
+                article
+            })
+            .collect(),
+        rekeyed,
+        hints,
+        archive: archive.map(|u| u.to_string()),
+    })
+}
+
+/// Adds the attempt to the fetch log. Failing to is not worth failing the refresh over.
+async fn log(store: &dyn db::Store, attempt: &fetch::Attempt) {
+    if let Err(e) = store.add_fetch_attempt(attempt).await {
+        println!("could not log the fetch of {}: {}", attempt.feed, e);
+    }
+}
+
+/// The wait after failed attempt `attempt`, counted from 1, when the first retry waits `first`.
+fn backoff(first: Duration, attempt: u32) -> Duration {
+    let factor = 2i64.saturating_pow(attempt.saturating_sub(1));
+    Duration::milliseconds(
+        first
+            .num_milliseconds()
+            .saturating_mul(factor)
+            .clamp(0, MAX_FETCH_BACKOFF_MS),
+    )
+}
+
+/// Whether trying again might help: the request never got an answer, or the server said it is
+/// overloaded or broken. A missing feed or one that does not parse fails the same way twice.
+fn transient(e: &anyhow::Error) -> bool {
+    if e.is::<chaos::Injected>() {
+        return true;
+    }
+    match e.downcast_ref::<reqwest::Error>() {
+        Some(e) => match e.status() {
+            Some(s) => s.is_server_error() || s == StatusCode::TOO_MANY_REQUESTS,
+            None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_after_every_attempt() {
+        let first = Duration::milliseconds(500);
+        assert_eq!(backoff(first, 1), Duration::milliseconds(500));
+        assert_eq!(backoff(first, 2), Duration::seconds(1));
+        assert_eq!(backoff(first, 3), Duration::seconds(2));
+        assert_eq!(backoff(first, 5), Duration::seconds(8));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let first = Duration::seconds(1);
+        assert_eq!(
+            backoff(first, 10),
+            Duration::milliseconds(MAX_FETCH_BACKOFF_MS)
+        );
+        assert_eq!(
+            backoff(first, 200),
+            Duration::milliseconds(MAX_FETCH_BACKOFF_MS)
+        );
+        assert_eq!(backoff(Duration::zero(), 3), Duration::zero());
+    }
+}
@@ -0,0 +1,124 @@
+use super::db;
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A calendar month in UTC, written as `YYYY-MM`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Period {
+    year: i32,
+    month: u32,
+}
+
+impl Period {
+    pub fn current() -> Self {
+        let now = Utc::now();
+        Period {
+            year: now.year(),
+            month: now.month(),
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self.month {
+            1 => Period {
+                year: self.year - 1,
+                month: 12,
+            },
+            m => Period {
+                year: self.year,
+                month: m - 1,
+            },
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self.month {
+            12 => Period {
+                year: self.year + 1,
+                month: 1,
+            },
+            m => Period {
+                year: self.year,
+                month: m + 1,
+            },
+        }
+    }
+
+    /// the first day of the month, comparable against stored rfc3339 timestamps
+    pub fn start(&self) -> String {
+        format!("{:04}-{:02}-01", self.year, self.month)
+    }
+
+    pub fn title(&self) -> String {
+        match NaiveDate::from_ymd_opt(self.year, self.month, 1) {
+            Some(d) => d.format("%B %Y").to_string(),
+            None => self.to_string(),
+        }
+    }
+
+    /// a month is complete once it has ended, only complete months are stored
+    pub fn is_complete(&self) -> bool {
+        *self < Period::current()
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl FromStr for Period {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Period> {
+        let err = || anyhow::Error::msg(format!("bad report period: {}", s));
+        let (year, month) = s.split_once('-').ok_or_else(err)?;
+        let year = year.parse().map_err(|_| err())?;
+        let month = month.parse().map_err(|_| err())?;
+        match month {
+            1..=12 => Ok(Period { year, month }),
+            _ => Err(err()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Count {
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Report {
+    pub period: String,
+    pub generated: String,
+    pub articles_read: i64,
+    pub minutes_read: i64,
+    pub top_feeds: Vec<Count>,
+    pub busiest_day: Option<Count>,
+}
+
+/// Returns the stored report for a period, compiling it when it has not been stored yet.
+pub async fn monthly(store: &dyn db::Store, period: Period) -> Result<Report> {
+    if let Some(report) = store.get_report(period.to_string()).await? {
+        return Ok(report);
+    }
+
+    let report = store
+        .compile_report(period.to_string(), period.start(), period.next().start())
+        .await?;
+    if period.is_complete() {
+        store.save_report(&report).await?;
+    }
+
+    Ok(report)
+}
+
+/// Makes sure last month's report has been compiled and stored.
+pub async fn generate_previous(store: &dyn db::Store) -> Result<()> {
+    monthly(store, Period::current().previous()).await?;
+    Ok(())
+}
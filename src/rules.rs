@@ -0,0 +1,110 @@
+use super::mute::{Action, Field, Mute, Mutes};
+use super::notify::Subscription;
+use super::Article;
+use anyhow::Result;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+
+/// The filtering setup that can be exported from one instance and imported into another.
+/// Every section defaults to empty so older exports stay importable.
+#[derive(Serialize, Deserialize, Default, Schema)]
+pub struct Rules {
+    #[serde(default)]
+    pub filters: Vec<FilterRule>,
+    #[serde(default)]
+    pub subscriptions: Vec<SubscriptionRule>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct FilterRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub regex: bool,
+    pub field: Field,
+    pub action: Action,
+}
+
+impl From<&Mute> for FilterRule {
+    fn from(m: &Mute) -> Self {
+        FilterRule {
+            pattern: m.pattern.clone(),
+            regex: m.regex,
+            field: m.field,
+            action: m.action,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+pub struct SubscriptionRule {
+    pub tag: String,
+    pub webhook: String,
+}
+
+impl From<&Subscription> for SubscriptionRule {
+    fn from(s: &Subscription) -> Self {
+        SubscriptionRule {
+            tag: s.tag.clone(),
+            webhook: s.webhook.clone(),
+        }
+    }
+}
+
+impl From<&SubscriptionRule> for Subscription {
+    fn from(r: &SubscriptionRule) -> Self {
+        Subscription::new(super::tag(&r.tag), r.webhook.clone())
+    }
+}
+
+#[derive(Serialize, Default, Schema)]
+pub struct Imported {
+    pub filters: u64,
+    pub subscriptions: u64,
+}
+
+/// What a rule set would have done to one article.
+#[derive(Serialize, Schema)]
+pub struct Outcome {
+    pub id: String,
+    pub feed: String,
+    pub title: String,
+    pub action: Option<Action>,
+    pub notify: Vec<String>,
+}
+
+/// Runs articles through a rule set without storing anything, returning the articles at
+/// least one rule would have acted on.
+pub fn simulate(rules: &Rules, articles: &[Article]) -> Result<Vec<Outcome>> {
+    let mutes = rules
+        .filters
+        .iter()
+        .map(|r| Mute::new(r.pattern.clone(), r.regex, r.field, r.action))
+        .collect::<Result<Vec<Mute>>>()?;
+    let mutes = Mutes::new(&mutes)?;
+
+    Ok(articles
+        .iter()
+        .filter_map(|a| {
+            let action = mutes.action(a);
+            let notify: Vec<String> = match action {
+                Some(_) => vec![],
+                None => rules
+                    .subscriptions
+                    .iter()
+                    .filter(|s| a.tags.contains(&super::tag(&s.tag)))
+                    .map(|s| s.webhook.clone())
+                    .collect(),
+            };
+            if action.is_none() && notify.is_empty() {
+                return None;
+            }
+            Some(Outcome {
+                id: a.id.clone(),
+                feed: a.feed.clone(),
+                title: a.title.clone(),
+                action,
+                notify,
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,95 @@
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use std::env;
+use unicode_normalization::UnicodeNormalization;
+
+use super::Article;
+
+/// How the text fields of incoming articles are cleaned up before they are stored.
+#[derive(Clone, Copy)]
+pub struct Fields {
+    /// Longest title kept, in characters. Zero keeps titles whole.
+    pub max_title: usize,
+    /// Longest author kept, in characters. Zero keeps authors whole.
+    pub max_author: usize,
+    pub strip_control: bool,
+    pub normalize: bool,
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields {
+            max_title: 300,
+            max_author: 120,
+            strip_control: true,
+            normalize: true,
+        }
+    }
+}
+
+/// The original value of any field that was changed on the way in.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, Schema)]
+pub struct Raw {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+impl Fields {
+    /// Reads TITLE_MAX_CHARS, AUTHOR_MAX_CHARS, STRIP_CONTROL_CHARS and NORMALIZE_UNICODE,
+    /// keeping the default for any that are unset or do not parse.
+    pub fn from_env() -> Fields {
+        let default = Fields::default();
+        Fields {
+            max_title: parse("TITLE_MAX_CHARS").unwrap_or(default.max_title),
+            max_author: parse("AUTHOR_MAX_CHARS").unwrap_or(default.max_author),
+            strip_control: parse("STRIP_CONTROL_CHARS").unwrap_or(default.strip_control),
+            normalize: parse("NORMALIZE_UNICODE").unwrap_or(default.normalize),
+        }
+    }
+
+    /// Cleans the title and author, recording the originals of whichever changed.
+    pub fn apply(&self, article: &mut Article) {
+        let title = self.clean(article.title.as_str(), self.max_title);
+        if title != article.title {
+            article.raw.title = Some(std::mem::replace(&mut article.title, title));
+        }
+
+        let author = self.clean(article.author.as_str(), self.max_author);
+        if author != article.author {
+            article.raw.author = Some(std::mem::replace(&mut article.author, author));
+        }
+    }
+
+    fn clean(&self, value: &str, max: usize) -> String {
+        let mut value = match self.strip_control {
+            // line breaks and tabs become spaces so words on either side stay apart
+            true => value
+                .chars()
+                .filter_map(|c| match c {
+                    '\n' | '\r' | '\t' => Some(' '),
+                    c if c.is_control() => None,
+                    c => Some(c),
+                })
+                .collect::<String>()
+                .trim()
+                .to_string(),
+            false => value.to_string(),
+        };
+
+        if self.normalize {
+            value = value.nfc().collect();
+        }
+
+        if max > 0 && value.chars().count() > max {
+            let cut: String = value.chars().take(max - 1).collect();
+            value = format!("{}…", cut.trim_end());
+        }
+        value
+    }
+}
+
+fn parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|s| s.parse().ok())
+}
@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Bumped whenever `COLUMNS` or `UNIQUE` change, so deployments can tell which schema a build expects.
+pub const VERSION: u32 = 33;
+
+/// A column the application reads or writes, as created by the migrations.
+pub struct Column {
+    pub table: &'static str,
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub constraint: &'static str,
+}
+
+const fn column(
+    table: &'static str,
+    name: &'static str,
+    kind: &'static str,
+    constraint: &'static str,
+) -> Column {
+    Column {
+        table,
+        name,
+        kind,
+        constraint,
+    }
+}
+
+/// Where read articles are moved once they are old enough, with every column `articles` has.
+pub const ARCHIVE: &str = "articles_archive";
+
+/// Every column the queries depend on. Keep in step with the migrations when the schema changes.
+pub const COLUMNS: &[Column] = &[
+    column("feeds", "id", "TEXT", "NOT NULL"),
+    column("feeds", "name", "TEXT", "NOT NULL"),
+    column("feeds", "site_url", "TEXT", "NOT NULL"),
+    column("feeds", "feed_url", "TEXT", "NOT NULL"),
+    column("feeds", "date_added", "TIMESTAMPTZ", "NOT NULL"),
+    column("feeds", "last_updated", "TIMESTAMPTZ", ""),
+    column("feeds", "poll_interval", "INTEGER", "NOT NULL DEFAULT 0"),
+    column("feeds", "next_poll", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feeds", "last_modified", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feeds", "build_marker", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feeds", "muted", "BOOLEAN", "NOT NULL DEFAULT false"),
+    column("feeds", "search_language", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feeds", "keep_content", "BOOLEAN", "NOT NULL DEFAULT false"),
+    column("feeds", "push", "BOOLEAN", "NOT NULL DEFAULT false"),
+    column("feeds", "refresh_interval", "INTEGER", "NOT NULL DEFAULT 0"),
+    column("feeds", "etag", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feeds", "fetch_error", "TEXT", "NOT NULL DEFAULT ''"),
+    column(
+        "feeds",
+        "hints",
+        "JSONB",
+        r#"NOT NULL DEFAULT '{"ttl": 0, "skip_hours": [], "skip_days": []}'"#,
+    ),
+    column("feeds", "retry_after", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feeds", "failures", "INTEGER", "NOT NULL DEFAULT 0"),
+    column("feeds", "erroring", "BOOLEAN", "NOT NULL DEFAULT false"),
+    column("feeds", "seq", "BIGSERIAL", "NOT NULL"),
+    column("articles", "id", "TEXT", "NOT NULL"),
+    column("articles", "feed", "TEXT", "NOT NULL"),
+    column("articles", "title", "TEXT", "NOT NULL"),
+    column("articles", "link", "TEXT", "NOT NULL"),
+    column("articles", "author", "TEXT", "NOT NULL"),
+    column("articles", "published", "TIMESTAMPTZ", "NOT NULL"),
+    column("articles", "read", "BOOLEAN", "NOT NULL"),
+    column("articles", "favorited", "BOOLEAN", "NOT NULL"),
+    column("articles", "read_date", "TIMESTAMPTZ", ""),
+    column("articles", "summary", "TEXT", "NOT NULL DEFAULT ''"),
+    column("articles", "content", "TEXT", "NOT NULL DEFAULT ''"),
+    column("articles", "word_count", "INTEGER", "NOT NULL DEFAULT 0"),
+    column(
+        "articles",
+        "reading_minutes",
+        "INTEGER",
+        "NOT NULL DEFAULT 0",
+    ),
+    column("articles", "canonical_link", "TEXT", "NOT NULL DEFAULT ''"),
+    column("articles", "fingerprint", "TEXT", "NOT NULL DEFAULT ''"),
+    column("articles", "sources", "TEXT[]", "NOT NULL DEFAULT '{}'"),
+    column(
+        "articles",
+        "date_added",
+        "TIMESTAMPTZ",
+        "NOT NULL DEFAULT now()",
+    ),
+    column("articles", "tags", "TEXT[]", "NOT NULL DEFAULT '{}'"),
+    column("articles", "hidden", "BOOLEAN", "NOT NULL DEFAULT false"),
+    column("articles", "note", "TEXT", "NOT NULL DEFAULT ''"),
+    column("articles", "pinned", "BOOLEAN", "NOT NULL DEFAULT false"),
+    column("articles", "raw", "JSONB", "NOT NULL DEFAULT '{}'"),
+    column("articles", "language", "TEXT", "NOT NULL DEFAULT ''"),
+    column("articles", "progress", "INTEGER", "NOT NULL DEFAULT 0"),
+    column("articles", "content_hash", "TEXT", "NOT NULL DEFAULT ''"),
+    column(
+        "articles",
+        "feed_id",
+        "TEXT",
+        "REFERENCES feeds (id) ON DELETE SET NULL",
+    ),
+    column("articles", "search_vector", "TSVECTOR", ""),
+    column("articles", "updated_at", "TIMESTAMPTZ", ""),
+    column("articles", "seq", "BIGSERIAL", "NOT NULL"),
+    column("filters", "id", "TEXT", "NOT NULL"),
+    column("filters", "pattern", "TEXT", "NOT NULL"),
+    column("filters", "regex", "BOOLEAN", "NOT NULL"),
+    column("filters", "field", "TEXT", "NOT NULL"),
+    column("filters", "action", "TEXT", "NOT NULL"),
+    column("filters", "date_added", "TEXT", "NOT NULL"),
+    column("subscriptions", "id", "TEXT", "NOT NULL"),
+    column("subscriptions", "tag", "TEXT", "NOT NULL"),
+    column("subscriptions", "webhook", "TEXT", "NOT NULL"),
+    column("subscriptions", "date_added", "TEXT", "NOT NULL"),
+    column("reports", "period", "TEXT", "NOT NULL"),
+    column("reports", "generated", "TEXT", "NOT NULL"),
+    column("reports", "data", "JSONB", "NOT NULL"),
+    column("digests", "period", "TEXT", "NOT NULL"),
+    column("digests", "sent", "TEXT", "NOT NULL"),
+    column("enclosures", "article", "TEXT", "NOT NULL"),
+    column("enclosures", "url", "TEXT", "NOT NULL"),
+    column("enclosures", "mime", "TEXT", "NOT NULL DEFAULT ''"),
+    column("enclosures", "length", "BIGINT", "NOT NULL DEFAULT 0"),
+    column("enclosures", "duration", "INTEGER", "NOT NULL DEFAULT 0"),
+    column("enclosures", "blob", "TEXT", "NOT NULL DEFAULT ''"),
+    column("blobs", "hash", "TEXT", "NOT NULL"),
+    column("blobs", "mime", "TEXT", "NOT NULL DEFAULT ''"),
+    column("blobs", "size", "BIGINT", "NOT NULL DEFAULT 0"),
+    column("blobs", "date_added", "TEXT", "NOT NULL"),
+    column("views", "id", "TEXT", "NOT NULL"),
+    column("views", "name", "TEXT", "NOT NULL"),
+    column("views", "filter", "TEXT", "NOT NULL"),
+    column("views", "feed", "TEXT", "NOT NULL DEFAULT ''"),
+    column("views", "tag", "TEXT", "NOT NULL DEFAULT ''"),
+    column("views", "keyword", "TEXT", "NOT NULL DEFAULT ''"),
+    column("views", "date_added", "TEXT", "NOT NULL"),
+    column("images", "url", "TEXT", "NOT NULL"),
+    column("images", "blob", "TEXT", "NOT NULL"),
+    column("images", "date_added", "TEXT", "NOT NULL"),
+    column("images", "last_used", "TEXT", "NOT NULL"),
+    column("push_subscriptions", "endpoint", "TEXT", "NOT NULL"),
+    column("push_subscriptions", "p256dh", "TEXT", "NOT NULL"),
+    column("push_subscriptions", "auth", "TEXT", "NOT NULL"),
+    column("push_subscriptions", "date_added", "TEXT", "NOT NULL"),
+    column("feed_tombstones", "feed_url", "TEXT", "NOT NULL"),
+    column("feed_tombstones", "date_removed", "TEXT", "NOT NULL"),
+    column("scrape_rules", "feed", "TEXT", "NOT NULL"),
+    column("scrape_rules", "item", "TEXT", "NOT NULL"),
+    column("scrape_rules", "title", "TEXT", "NOT NULL"),
+    column("scrape_rules", "link", "TEXT", "NOT NULL"),
+    column("scrape_rules", "date", "TEXT", "NOT NULL DEFAULT ''"),
+    column("scrape_rules", "date_added", "TEXT", "NOT NULL"),
+    column("jobs", "id", "TEXT", "NOT NULL"),
+    column("jobs", "feed", "TEXT", "NOT NULL"),
+    column("jobs", "state", "TEXT", "NOT NULL"),
+    column("jobs", "error", "TEXT", "NOT NULL DEFAULT ''"),
+    column("jobs", "date_added", "TEXT", "NOT NULL"),
+    column("jobs", "started", "TEXT", "NOT NULL DEFAULT ''"),
+    column("jobs", "finished", "TEXT", "NOT NULL DEFAULT ''"),
+    column("fetch_log", "feed", "TEXT", "NOT NULL"),
+    column("fetch_log", "date", "TEXT", "NOT NULL"),
+    column("fetch_log", "status", "INTEGER", "NOT NULL"),
+    column("fetch_log", "bytes", "BIGINT", "NOT NULL DEFAULT 0"),
+    column("fetch_log", "duration_ms", "BIGINT", "NOT NULL DEFAULT 0"),
+    column("fetch_log", "added", "INTEGER", "NOT NULL DEFAULT 0"),
+    column("fetch_log", "error", "TEXT", "NOT NULL DEFAULT ''"),
+    column("feed_counts", "feed", "TEXT", "NOT NULL"),
+    column("feed_counts", "unread", "BIGINT", "NOT NULL DEFAULT 0"),
+    column("feed_payloads", "feed", "TEXT", "NOT NULL"),
+    column("feed_payloads", "fetched", "TIMESTAMPTZ", "NOT NULL"),
+    column(
+        "feed_payloads",
+        "content_type",
+        "TEXT",
+        "NOT NULL DEFAULT ''",
+    ),
+    column("feed_payloads", "size", "BIGINT", "NOT NULL"),
+    column(
+        "feed_payloads",
+        "truncated",
+        "BOOLEAN",
+        "NOT NULL DEFAULT false",
+    ),
+    column("feed_payloads", "body", "BYTEA", "NOT NULL"),
+];
+
+/// Columns that need a unique index because an upsert names them in `ON CONFLICT`.
+pub const UNIQUE: &[(&str, &str)] = &[
+    ("feeds", "id"),
+    ("feeds", "feed_url"),
+    ("articles", "id"),
+    ("articles", "link"),
+    ("filters", "id"),
+    ("subscriptions", "id"),
+    ("reports", "period"),
+    ("digests", "period"),
+    ("blobs", "hash"),
+    ("views", "id"),
+    ("images", "url"),
+    ("push_subscriptions", "endpoint"),
+    ("feed_tombstones", "feed_url"),
+    ("scrape_rules", "feed"),
+    ("feed_counts", "feed"),
+    ("feed_payloads", "feed"),
+];
+
+/// The columns of `table` listed in `COLUMNS`, comma separated, for copying rows by column name.
+pub fn columns(table: &str) -> String {
+    COLUMNS
+        .iter()
+        .filter(|c| c.table == table)
+        .map(|c| c.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A column as postgres reports it in information_schema.
+pub struct Found {
+    pub udt: String,
+    pub nullable: bool,
+}
+
+/// A difference between the database and what the queries expect, along with the statement that repairs it.
+pub enum Discrepancy {
+    MissingTable(&'static str),
+    MissingColumn(&'static Column),
+    WrongType(&'static Column, String),
+    Nullable(&'static Column),
+    MissingUnique(&'static str, &'static str),
+    /// A column of `articles` that the archive lacks.
+    MissingArchived(&'static Column),
+    /// Articles left without a feed_id because several feeds share the name they were stored
+    /// under. Reported without stopping startup.
+    Unlinked {
+        name: String,
+        feeds: i64,
+        articles: i64,
+    },
+}
+
+impl Discrepancy {
+    /// Whether the queries fail on this difference, rather than only missing a feed for some rows.
+    pub fn fatal(&self) -> bool {
+        !matches!(self, Discrepancy::Unlinked { .. })
+    }
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Discrepancy::MissingTable(table) if *table == ARCHIVE => write!(
+                f,
+                "table {} is missing, fix with: CREATE TABLE {} (LIKE articles INCLUDING DEFAULTS INCLUDING CONSTRAINTS);",
+                table, table
+            ),
+            Discrepancy::MissingTable(table) => {
+                let columns: Vec<String> = COLUMNS
+                    .iter()
+                    .filter(|c| c.table == *table)
+                    .map(|c| format!("{} {} {}", c.name, c.kind, c.constraint))
+                    .chain(
+                        UNIQUE
+                            .iter()
+                            .filter(|(t, _)| t == table)
+                            .map(|(_, c)| format!("UNIQUE ({})", c)),
+                    )
+                    .collect();
+                write!(
+                    f,
+                    "table {} is missing, fix with: CREATE TABLE {} ({});",
+                    table,
+                    table,
+                    columns.join(", ")
+                )
+            }
+            Discrepancy::MissingColumn(c) => write!(
+                f,
+                "column {}.{} is missing, fix with: ALTER TABLE {} ADD COLUMN {} {} {};",
+                c.table, c.name, c.table, c.name, c.kind, c.constraint
+            ),
+            Discrepancy::WrongType(c, found) => write!(
+                f,
+                "column {}.{} is {} instead of {}, fix with: ALTER TABLE {} ALTER COLUMN {} TYPE {} USING {}::{};",
+                c.table, c.name, found, c.kind, c.table, c.name, c.kind, c.name, c.kind
+            ),
+            Discrepancy::Nullable(c) if c.constraint.contains("DEFAULT") => write!(
+                f,
+                "column {}.{} allows nulls, fix with: UPDATE {} SET {} = DEFAULT WHERE {} IS NULL; ALTER TABLE {} ALTER COLUMN {} SET NOT NULL;",
+                c.table, c.name, c.table, c.name, c.name, c.table, c.name
+            ),
+            Discrepancy::Nullable(c) => write!(
+                f,
+                "column {}.{} allows nulls, fix by filling in rows where it is null and then: ALTER TABLE {} ALTER COLUMN {} SET NOT NULL;",
+                c.table, c.name, c.table, c.name
+            ),
+            Discrepancy::MissingUnique(table, column) => write!(
+                f,
+                "{}.{} has no unique index, fix with: CREATE UNIQUE INDEX ON {} ({});",
+                table, column, table, column
+            ),
+            Discrepancy::MissingArchived(c) => write!(
+                f,
+                "column {}.{} is missing, fix with: ALTER TABLE {} ADD COLUMN {} {} {};",
+                ARCHIVE, c.name, ARCHIVE, c.name, c.kind, c.constraint
+            ),
+            Discrepancy::Unlinked {
+                name,
+                feeds,
+                articles,
+            } => write!(
+                f,
+                "{} article(s) stored under feed name {:?} have no feed_id since {} feeds share the name, fix by picking one with: UPDATE articles SET feed_id = '<feed id>' WHERE feed = '{}' AND feed_id IS NULL;",
+                articles,
+                name,
+                feeds,
+                name.replace('\'', "''")
+            ),
+        }
+    }
+}
+
+/// The name postgres uses in information_schema.columns.udt_name for a column type.
+fn udt(kind: &str) -> &str {
+    match kind {
+        "TEXT" => "text",
+        "INTEGER" => "int4",
+        "BIGINT" | "BIGSERIAL" => "int8",
+        "BOOLEAN" => "bool",
+        "TEXT[]" => "_text",
+        "JSONB" => "jsonb",
+        "TIMESTAMPTZ" => "timestamptz",
+        "TSVECTOR" => "tsvector",
+        "BYTEA" => "bytea",
+        other => other,
+    }
+}
+
+/// Compares the columns and unique indexes found in the database against the ones the queries expect.
+pub fn verify(
+    found: &BTreeMap<(String, String), Found>,
+    unique: &BTreeSet<(String, String)>,
+) -> Vec<Discrepancy> {
+    let tables: BTreeSet<&str> = found.keys().map(|(t, _)| t.as_str()).collect();
+    let mut missing_tables = BTreeSet::new();
+    let mut problems = Vec::new();
+
+    for c in COLUMNS {
+        if !tables.contains(c.table) {
+            if missing_tables.insert(c.table) {
+                problems.push(Discrepancy::MissingTable(c.table));
+            }
+            continue;
+        }
+        match found.get(&(c.table.to_string(), c.name.to_string())) {
+            None => problems.push(Discrepancy::MissingColumn(c)),
+            Some(f) if f.udt != udt(c.kind) => {
+                problems.push(Discrepancy::WrongType(c, f.udt.clone()))
+            }
+            Some(f) if f.nullable && c.constraint.starts_with("NOT NULL") => {
+                problems.push(Discrepancy::Nullable(c))
+            }
+            Some(_) => {}
+        }
+    }
+
+    // the archive is checked against the articles columns rather than listed on its own
+    if !tables.contains(ARCHIVE) {
+        problems.push(Discrepancy::MissingTable(ARCHIVE));
+    } else {
+        for c in COLUMNS.iter().filter(|c| c.table == "articles") {
+            if !found.contains_key(&(ARCHIVE.to_string(), c.name.to_string())) {
+                problems.push(Discrepancy::MissingArchived(c));
+            }
+        }
+    }
+
+    for (table, column) in UNIQUE {
+        if missing_tables.contains(table) {
+            continue;
+        }
+        if !unique.contains(&(table.to_string(), column.to_string())) {
+            problems.push(Discrepancy::MissingUnique(table, column));
+        }
+    }
+
+    problems
+}
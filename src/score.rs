@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// How much each signal counts towards a score, together adding up to one.
+const READ_RATIO_WEIGHT: f64 = 0.4;
+const KEYWORD_WEIGHT: f64 = 0.4;
+const RECENCY_WEIGHT: f64 = 0.2;
+/// Keyword hits past this many add nothing more.
+const MAX_KEYWORD_HITS: f64 = 5.0;
+/// Hours until the recency signal of an article is halved.
+const RECENCY_HALF_LIFE_HOURS: f64 = 48.0;
+/// Read ratio assumed for feeds with nothing read or unread yet.
+const UNKNOWN_READ_RATIO: f64 = 0.5;
+const MIN_KEYWORD_LEN: usize = 4;
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "also", "been", "before", "being", "could", "does", "from", "have",
+    "here", "into", "just", "more", "most", "much", "only", "other", "over", "said", "should",
+    "some", "such", "than", "that", "their", "them", "then", "there", "these", "they", "this",
+    "those", "through", "very", "were", "what", "when", "where", "which", "while", "will", "with",
+    "would", "your",
+];
+
+/// What is known about past reading, gathered once per page of scored articles.
+#[derive(Default)]
+pub struct Signals {
+    /// Share of each feed's articles that were read, keyed by feed name.
+    pub read_ratio: HashMap<String, f64>,
+    /// Words from the titles and tags of favorited articles.
+    pub keywords: HashSet<String>,
+}
+
+impl Signals {
+    /// Adds the title and tags of a favorited article to the keywords.
+    pub fn favorite(&mut self, title: &str, tags: &[String]) {
+        self.keywords.extend(words(title));
+        self.keywords.extend(tags.iter().map(|t| t.to_lowercase()));
+    }
+
+    /// Ranks an article of `feed` between zero and one, higher is more likely to be worth reading.
+    pub fn score(
+        &self,
+        feed: &str,
+        title: &str,
+        tags: &[String],
+        published: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> f64 {
+        let read_ratio = self
+            .read_ratio
+            .get(feed)
+            .copied()
+            .unwrap_or(UNKNOWN_READ_RATIO);
+
+        let hits = words(title)
+            .chain(tags.iter().map(|t| t.to_lowercase()))
+            .collect::<HashSet<String>>()
+            .intersection(&self.keywords)
+            .count() as f64;
+
+        let hours = (now - published).num_minutes().max(0) as f64 / 60.0;
+        let recency = 0.5_f64.powf(hours / RECENCY_HALF_LIFE_HOURS);
+
+        READ_RATIO_WEIGHT * read_ratio
+            + KEYWORD_WEIGHT * hits.min(MAX_KEYWORD_HITS) / MAX_KEYWORD_HITS
+            + RECENCY_WEIGHT * recency
+    }
+}
+
+/// Lowercased words of a title worth matching on, leaving out short and common ones.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= MIN_KEYWORD_LEN)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+}
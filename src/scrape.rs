@@ -0,0 +1,136 @@
+use super::Article;
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use reqwest::Url;
+use rweb::Schema;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+
+/// Formats tried on dates that are neither RFC 3339 nor RFC 2822, as blogs print them.
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%B %d, %Y", "%b %d, %Y", "%d %B %Y", "%d %b %Y"];
+
+/// How to read articles off the page of a site that publishes no feed. The page is subscribed
+/// to like a feed, `feed` is its id. Each element matching `item` becomes an article, with its
+/// title, link and date taken from the first match of the other selectors inside it. An empty
+/// `date` selector dates articles when they are first seen.
+#[derive(Deserialize, Serialize, Clone, Schema)]
+pub struct Rule {
+    #[serde(default)]
+    pub feed: String,
+    pub item: String,
+    pub title: String,
+    pub link: String,
+    #[serde(default)]
+    pub date: String,
+    #[serde(default)]
+    pub date_added: String,
+}
+
+impl Rule {
+    /// Checks every selector parses, so a typo fails when the rule is saved instead of on each
+    /// refresh.
+    pub fn validate(&self) -> Result<()> {
+        for s in [&self.item, &self.title, &self.link] {
+            selector(s)?;
+        }
+        if !self.date.is_empty() {
+            selector(&self.date)?;
+        }
+        Ok(())
+    }
+
+    /// The articles on `page`, the document fetched from `url`. Items without a title or a
+    /// link are skipped.
+    pub fn articles(&self, url: &str, page: &[u8]) -> Result<Vec<Article>> {
+        let base = Url::parse(url)?;
+        let item = selector(&self.item)?;
+        let title = selector(&self.title)?;
+        let link = selector(&self.link)?;
+        let date = match self.date.is_empty() {
+            true => None,
+            false => Some(selector(&self.date)?),
+        };
+
+        let document = Html::parse_document(&String::from_utf8_lossy(page));
+        let articles = document
+            .select(&item)
+            .filter_map(|i| {
+                let t = text(i.select(&title).next()?);
+                let href = i.select(&link).next().and_then(href)?;
+                let href = base.join(href).ok()?.to_string();
+                if t.is_empty() {
+                    return None;
+                }
+                let published = date
+                    .as_ref()
+                    .and_then(|d| i.select(d).next())
+                    .and_then(published)
+                    .unwrap_or_else(Article::rfc3339_timestamp);
+                Some(Article::new(
+                    t,
+                    href,
+                    "".to_string(),
+                    published,
+                    false,
+                    false,
+                    "".to_string(),
+                    "".to_string(),
+                ))
+            })
+            .collect();
+        Ok(articles)
+    }
+}
+
+impl From<&tokio_postgres::Row> for Rule {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        Rule {
+            feed: row.get(0),
+            item: row.get(1),
+            title: row.get(2),
+            link: row.get(3),
+            date: row.get(4),
+            date_added: row.get(5),
+        }
+    }
+}
+
+fn selector(s: &str) -> Result<Selector> {
+    Selector::parse(s).map_err(|e| anyhow::Error::msg(format!("invalid selector {}: {:?}", s, e)))
+}
+
+fn text(e: ElementRef) -> String {
+    e.text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The link of the element, or of the first link inside it when it is not one itself.
+fn href(e: ElementRef<'_>) -> Option<&str> {
+    e.value().attr("href").or_else(|| {
+        e.select(&Selector::parse("a[href]").unwrap())
+            .next()
+            .and_then(|a| a.value().attr("href"))
+    })
+}
+
+/// The date in the element's `datetime` attribute, as `<time>` elements carry it, or its text,
+/// in RFC 3339. None when neither reads as a date.
+fn published(e: ElementRef) -> Option<String> {
+    let text = text(e);
+    let s = e.value().attr("datetime").unwrap_or(text.as_str()).trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s).or_else(|_| DateTime::parse_from_rfc2822(s)) {
+        return Some(
+            dt.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+    }
+    DATE_FORMATS.iter().find_map(|f| {
+        NaiveDate::parse_from_str(s, f)
+            .ok()
+            .map(|d| format!("{}T00:00:00Z", d.format("%Y-%m-%d")))
+    })
+}
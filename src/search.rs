@@ -0,0 +1,49 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+/// The postgres text search configuration used to stem articles and queries. Feeds use the
+/// configured default unless they set their own.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    English,
+    German,
+    /// Lowercases words without stemming or dropping stopwords, for languages without a dictionary.
+    Simple,
+}
+
+pub const LANGUAGES: &[Language] = &[Language::English, Language::German, Language::Simple];
+
+impl Language {
+    /// `SEARCH_LANGUAGE`, english when unset.
+    pub fn from_env() -> Result<Language> {
+        match env::var("SEARCH_LANGUAGE") {
+            Ok(l) => l.parse(),
+            Err(_) => Ok(Language::English),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Language::English => write!(f, "english"),
+            Language::German => write!(f, "german"),
+            Language::Simple => write!(f, "simple"),
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Language> {
+        match s {
+            "english" => Ok(Language::English),
+            "german" => Ok(Language::German),
+            "simple" => Ok(Language::Simple),
+            _ => Err(anyhow::Error::msg(format!("bad search language: {}", s))),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use super::{client, Article};
+use anyhow::Result;
+#[cfg(feature = "email")]
+use lettre::message::Mailbox;
+#[cfg(feature = "email")]
+use lettre::transport::smtp::authentication::Credentials;
+#[cfg(feature = "email")]
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+use std::env;
+use std::sync::Arc;
+
+/// Somewhere an article can be sent. Each target is enabled by setting its environment variables.
+pub enum Target {
+    /// SHARE_SMTP_HOST, SHARE_SMTP_USERNAME, SHARE_SMTP_PASSWORD, SHARE_EMAIL_FROM, SHARE_EMAIL_TO
+    #[cfg(feature = "email")]
+    Email {
+        mailer: Box<AsyncSmtpTransport<Tokio1Executor>>,
+        from: Mailbox,
+        to: Mailbox,
+    },
+    /// SHARE_POCKET_CONSUMER_KEY, SHARE_POCKET_ACCESS_TOKEN
+    Pocket {
+        consumer_key: String,
+        access_token: String,
+    },
+    /// SHARE_WALLABAG_URL, SHARE_WALLABAG_TOKEN
+    Wallabag { url: String, token: String },
+    /// SHARE_WEBHOOK_URL
+    Webhook { url: String },
+}
+
+impl Target {
+    pub fn name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "email")]
+            Target::Email { .. } => "email",
+            Target::Pocket { .. } => "pocket",
+            Target::Wallabag { .. } => "wallabag",
+            Target::Webhook { .. } => "webhook",
+        }
+    }
+
+    async fn send(&self, client: &Client, article: &Article) -> Result<()> {
+        let link = article.canonical_link.as_str();
+        match self {
+            #[cfg(feature = "email")]
+            Target::Email { mailer, from, to } => {
+                let message = Message::builder()
+                    .from(from.clone())
+                    .to(to.clone())
+                    .subject(article.title.as_str())
+                    .body(format!("{}\n\n{}\n", article.title, link))?;
+                mailer.send(message).await?;
+            }
+            Target::Pocket {
+                consumer_key,
+                access_token,
+            } => {
+                let body = serde_json::to_string(&PocketAdd {
+                    url: link,
+                    title: article.title.as_str(),
+                    consumer_key: consumer_key.as_str(),
+                    access_token: access_token.as_str(),
+                })?;
+                client
+                    .post("https://getpocket.com/v3/add")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Target::Wallabag { url, token } => {
+                client
+                    .post(format!("{}/api/entries.json", url.trim_end_matches('/')))
+                    .bearer_auth(token)
+                    .form(&[("url", link), ("title", article.title.as_str())])
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Target::Webhook { url } => {
+                let body = serde_json::to_string(&Shared {
+                    feed: article.feed.as_str(),
+                    title: article.title.as_str(),
+                    link,
+                })?;
+                client
+                    .post(url.as_str())
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct PocketAdd<'a> {
+    url: &'a str,
+    title: &'a str,
+    consumer_key: &'a str,
+    access_token: &'a str,
+}
+
+#[derive(Serialize)]
+struct Shared<'a> {
+    feed: &'a str,
+    title: &'a str,
+    link: &'a str,
+}
+
+/// The share targets configured for this instance.
+#[derive(Clone, Default)]
+pub struct Share {
+    targets: Arc<Vec<Target>>,
+}
+
+impl Share {
+    pub fn from_env() -> Result<Self> {
+        let mut targets = vec![];
+
+        #[cfg(feature = "email")]
+        if let (Ok(host), Ok(from), Ok(to)) = (
+            env::var("SHARE_SMTP_HOST"),
+            env::var("SHARE_EMAIL_FROM"),
+            env::var("SHARE_EMAIL_TO"),
+        ) {
+            let mut mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(host.as_str())?;
+            if let (Ok(username), Ok(password)) = (
+                env::var("SHARE_SMTP_USERNAME"),
+                env::var("SHARE_SMTP_PASSWORD"),
+            ) {
+                mailer = mailer.credentials(Credentials::new(username, password));
+            }
+            targets.push(Target::Email {
+                mailer: Box::new(mailer.build()),
+                from: from.parse()?,
+                to: to.parse()?,
+            });
+        }
+        #[cfg(not(feature = "email"))]
+        if env::var("SHARE_SMTP_HOST").is_ok() {
+            return Err(anyhow::Error::msg(
+                "SHARE_SMTP_HOST is set but this build has no email feature",
+            ));
+        }
+
+        if let (Ok(consumer_key), Ok(access_token)) = (
+            env::var("SHARE_POCKET_CONSUMER_KEY"),
+            env::var("SHARE_POCKET_ACCESS_TOKEN"),
+        ) {
+            targets.push(Target::Pocket {
+                consumer_key,
+                access_token,
+            });
+        }
+
+        if let (Ok(url), Ok(token)) = (
+            env::var("SHARE_WALLABAG_URL"),
+            env::var("SHARE_WALLABAG_TOKEN"),
+        ) {
+            targets.push(Target::Wallabag { url, token });
+        }
+
+        if let Ok(url) = env::var("SHARE_WEBHOOK_URL") {
+            targets.push(Target::Webhook { url });
+        }
+
+        Ok(Share {
+            targets: Arc::new(targets),
+        })
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.targets.iter().map(|t| t.name()).collect()
+    }
+
+    /// Sends an article to the named target.
+    pub async fn send(&self, target: &str, article: &Article) -> Result<()> {
+        let target = self
+            .targets
+            .iter()
+            .find(|t| t.name() == target)
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!("share target not configured: {}", target))
+            })?;
+        target.send(&client::shared(), article).await
+    }
+}
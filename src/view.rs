@@ -0,0 +1,73 @@
+use super::db::{ArticleQuery, Filter};
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{SecondsFormat, Utc};
+
+/// A named article query shown as its own page, e.g. unread articles tagged rust with async
+/// in the title. Empty restrictions match everything.
+#[derive(Clone)]
+pub struct View {
+    pub id: String,
+    pub name: String,
+    pub filter: String,
+    pub feed: String,
+    pub tag: String,
+    pub keyword: String,
+    pub date_added: String,
+}
+
+impl View {
+    /// Saving a view under an existing name replaces it.
+    pub fn new(
+        name: String,
+        filter: String,
+        feed: String,
+        tag: String,
+        keyword: String,
+    ) -> Result<Self> {
+        if name.is_empty() {
+            return Err(anyhow::Error::msg("view name cannot be empty"));
+        }
+        filter.parse::<Filter>()?;
+
+        Ok(View {
+            id: general_purpose::URL_SAFE.encode(name.as_str()),
+            name,
+            filter,
+            feed,
+            tag,
+            keyword,
+            date_added: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        })
+    }
+
+    pub fn query(&self) -> Result<ArticleQuery> {
+        let some = |s: &String| Some(s.clone()).filter(|s| !s.is_empty());
+        Ok(ArticleQuery {
+            filter: Some(self.filter.parse()?),
+            feed: some(&self.feed),
+            tag: some(&self.tag),
+            title: some(&self.keyword),
+            ..Default::default()
+        })
+    }
+
+    /// The value of the article_filter header for lists showing this view.
+    pub fn article_filter(&self) -> String {
+        format!("view:{}", self.id)
+    }
+}
+
+impl From<&tokio_postgres::Row> for View {
+    fn from(row: &tokio_postgres::Row) -> Self {
+        View {
+            id: row.get(0),
+            name: row.get(1),
+            filter: row.get(2),
+            feed: row.get(3),
+            tag: row.get(4),
+            keyword: row.get(5),
+            date_added: row.get(6),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use super::push::{decode, PushSubscription};
+use super::Article;
+use super::{client, db};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, Utc};
+use hkdf::Hkdf;
+use p256::ecdh::EphemeralSecret;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::PublicKey;
+use rand_core::{OsRng, RngCore};
+use reqwest::{Client, StatusCode, Url};
+use serde::Serialize;
+use sha2::Sha256;
+use std::env;
+use std::sync::Arc;
+
+/// How long a push service holds on to a notification for a browser that is offline.
+const TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// Size of the single encrypted record a notification is sent as, which bounds the payload.
+const RECORD_SIZE: u32 = 4096;
+
+/// The key pair identifying this server to push services, see RFC 8292.
+struct Vapid {
+    key: SigningKey,
+    /// The uncompressed public key, base64url encoded, as browsers expect it when subscribing.
+    public: String,
+    subject: String,
+}
+
+/// VAPID_PRIVATE_KEY, the base64url encoded P-256 private key, and VAPID_SUBJECT, a mailto: or
+/// https: contact for push services. Browser notifications are off without a key.
+#[derive(Clone)]
+pub struct Push {
+    vapid: Option<Arc<Vapid>>,
+    client: Client,
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    title: &'a str,
+    body: &'a str,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    aud: String,
+    exp: i64,
+    sub: &'a str,
+}
+
+impl Push {
+    pub fn from_env() -> Result<Push> {
+        let client = client::shared();
+        let private = match env::var("VAPID_PRIVATE_KEY") {
+            Ok(k) => k,
+            Err(_) => {
+                return Ok(Push {
+                    vapid: None,
+                    client,
+                })
+            }
+        };
+        let subject = env::var("VAPID_SUBJECT")
+            .map_err(|_| anyhow::Error::msg("VAPID_SUBJECT must be set"))?;
+        if !subject.starts_with("mailto:") && !subject.starts_with("https:") {
+            return Err(anyhow::Error::msg(
+                "VAPID_SUBJECT must be a mailto: or https: url",
+            ));
+        }
+
+        let key = SigningKey::from_slice(&decode(&private)?)
+            .map_err(|_| anyhow::Error::msg("VAPID_PRIVATE_KEY is not a P-256 private key"))?;
+        let public = URL_SAFE_NO_PAD.encode(key.verifying_key().to_encoded_point(false));
+        Ok(Push {
+            vapid: Some(Arc::new(Vapid {
+                key,
+                public,
+                subject,
+            })),
+            client,
+        })
+    }
+
+    /// The key browsers pass to `pushManager.subscribe`, none when push is off.
+    pub fn public_key(&self) -> Option<&str> {
+        self.vapid.as_ref().map(|v| v.public.as_str())
+    }
+
+    /// Sends each new article to every subscribed browser. Subscriptions the push service says
+    /// are gone are deleted, any other failure is logged and the rest are still sent.
+    pub async fn dispatch(&self, store: &dyn db::Store, articles: &[Article]) -> Result<()> {
+        let vapid = match &self.vapid {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        // articles muted during ingest are not worth a notification
+        let articles: Vec<&Article> = articles.iter().filter(|a| !a.read && !a.hidden).collect();
+        if articles.is_empty() {
+            return Ok(());
+        }
+
+        for s in store.get_push_subscriptions().await? {
+            for a in articles.iter() {
+                let payload = serde_json::to_vec(&Notification {
+                    title: a.title.as_str(),
+                    body: a.feed.as_str(),
+                    url: format!("/articles/{}", a.id),
+                })?;
+                match self.send(vapid, &s, &payload).await {
+                    Ok(StatusCode::NOT_FOUND) | Ok(StatusCode::GONE) => {
+                        store.delete_push_subscription(s.endpoint.clone()).await?;
+                        break;
+                    }
+                    Ok(status) if !status.is_success() => {
+                        println!("could not push to {}: {}", s.endpoint, status)
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("could not push to {}: {}", s.endpoint, e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        vapid: &Vapid,
+        s: &PushSubscription,
+        payload: &[u8],
+    ) -> Result<StatusCode> {
+        let body = encrypt(&decode(&s.p256dh)?, &decode(&s.auth)?, payload)?;
+        let endpoint = Url::parse(s.endpoint.as_str())?;
+        let token = vapid.token(endpoint.origin().ascii_serialization())?;
+
+        let response = self
+            .client
+            .post(endpoint)
+            .header("TTL", TTL_SECONDS)
+            .header("Urgency", "high")
+            .header(reqwest::header::CONTENT_ENCODING, "aes128gcm")
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("vapid t={}, k={}", token, vapid.public),
+            )
+            .body(body)
+            .send()
+            .await?;
+        Ok(response.status())
+    }
+}
+
+impl Vapid {
+    /// A signed JWT telling the push service at `audience` who is sending, valid for 12 hours.
+    fn token(&self, audience: String) -> Result<String> {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&Claims {
+            aud: audience,
+            exp: (Utc::now() + Duration::hours(12)).timestamp(),
+            sub: self.subject.as_str(),
+        })?);
+        let unsigned = format!("{}.{}", header, claims);
+        let signature: Signature = self.key.sign(unsigned.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            unsigned,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+}
+
+/// Encrypts a payload for a browser as described in RFC 8291, a single aes128gcm record keyed
+/// from an ECDH exchange between a throwaway key and the browser's.
+fn encrypt(p256dh: &[u8], auth: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    // the delimiter and the tag have to fit in the record as well
+    if payload.len() + 17 > RECORD_SIZE as usize {
+        return Err(anyhow::Error::msg("push payload too large"));
+    }
+    let browser = PublicKey::from_sec1_bytes(p256dh)
+        .map_err(|_| anyhow::Error::msg("bad push subscription key"))?;
+    let secret = EphemeralSecret::random(&mut OsRng);
+    let server = secret.public_key().to_encoded_point(false);
+    let shared = secret.diffie_hellman(&browser);
+    let length = |_| anyhow::Error::msg("bad hkdf length");
+
+    let mut info = b"WebPush: info\0".to_vec();
+    info.extend_from_slice(p256dh);
+    info.extend_from_slice(server.as_bytes());
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(auth), &shared.raw_secret_bytes()[..])
+        .expand(&info, &mut ikm)
+        .map_err(length)?;
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let prk = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+    let mut cek = [0u8; 16];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .map_err(length)?;
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+        .map_err(length)?;
+
+    // 2 marks the last record, there is no padding after it
+    let mut plaintext = payload.to_vec();
+    plaintext.push(2);
+    let ciphertext = Aes128Gcm::new_from_slice(&cek)?
+        .encrypt(&Nonce::from(nonce), plaintext.as_slice())
+        .map_err(|_| anyhow::Error::msg("could not encrypt push payload"))?;
+
+    let mut body = salt.to_vec();
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(server.as_bytes().len() as u8);
+    body.extend_from_slice(server.as_bytes());
+    body.extend_from_slice(&ciphertext);
+    Ok(body)
+}
@@ -0,0 +1,13 @@
+// Shows the notifications pushed for feeds with push turned on, opening the article when clicked.
+self.addEventListener("push", function (event) {
+    var data = event.data ? event.data.json() : {};
+    event.waitUntil(self.registration.showNotification(data.title || "New article", {
+        body: data.body,
+        data: { url: data.url || "/" },
+    }));
+});
+
+self.addEventListener("notificationclick", function (event) {
+    event.notification.close();
+    event.waitUntil(clients.openWindow(event.notification.data.url));
+});